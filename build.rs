@@ -0,0 +1,50 @@
+#![allow(clippy::needless_return)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+RFX_BUILD_HASH, the hash of the sources rfx is built from. it names this
+build in the keys of the build cache, so a rebuilt rfx never takes what an
+older one left there, whatever the version in Cargo.toml says
+*/
+
+//the files under dir, sorted
+fn files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Err(_) => return,
+        Ok(x) => x.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+    };
+    entries.sort();
+    for p in entries {
+        match p.is_dir() {
+            true => files(&p, out),
+            false => out.push(p),
+        }
+    }
+}
+
+fn main() {
+    let mut paths = vec![PathBuf::from("Cargo.toml"), PathBuf::from("build.rs")];
+    files(Path::new("src"), &mut paths);
+
+    //FNV-1a over each path and its contents
+    let mut h: u64 = 0xcbf29ce484222325;
+    for p in paths.iter() {
+        let name = p.to_string_lossy();
+        let bytes = fs::read(p).unwrap_or_default();
+        for b in name
+            .as_bytes()
+            .iter()
+            .chain([0u8].iter())
+            .chain(bytes.iter())
+        {
+            h ^= *b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+    }
+    println!("cargo:rustc-env=RFX_BUILD_HASH={:016x}", h);
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,156 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::port::{home_path, NO_HOME, RFX_HOME_DIR};
+
+/*
+content addressed store of build outputs in RFX_HOME_DIR/cache. an entry is
+named by the hash of everything it was made from, this build of rfx among
+it, so it never goes stale; `rfx cache-clean` empties it. the parse of each
+module is kept too, so a build parses only the modules that changed
+*/
+pub struct Cache {
+    dir: PathBuf,
+}
+
+//FNV-1a over the parts, stable across runs and rust versions
+fn hash(parts: &[&[u8]]) -> String {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for p in parts.iter() {
+        //the length first, so ("ab", "c") and ("a", "bc") differ
+        for b in (p.len() as u64).to_le_bytes().iter().chain(p.iter()) {
+            h ^= *b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+    }
+    return format!("{:016x}", h);
+}
+
+//this build of rfx: its version and the hash of its sources, from build.rs.
+//every key starts with it, so a rebuilt rfx misses what an older one cached
+pub const BUILD_ID: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("RFX_BUILD_HASH"));
+
+//the key of an entry made from the parts by this build
+pub fn key(parts: &[&[u8]]) -> String {
+    return key_of(BUILD_ID, parts);
+}
+
+fn key_of(build: &str, parts: &[&[u8]]) -> String {
+    let mut all: Vec<&[u8]> = vec![build.as_bytes()];
+    all.extend_from_slice(parts);
+    return hash(&all);
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    return Some(Path::new(&home_path(RFX_HOME_DIR)?).join("cache"));
+}
+
+//binaries built by `rfx run`, kept to run again unchanged. None without a
+//home directory
+pub fn run_dir() -> Option<PathBuf> {
+    return Some(Path::new(&home_path(RFX_HOME_DIR)?).join("run"));
+}
+
+impl Cache {
+    //None if the cache directory cannot be made, builds then go uncached
+    pub fn open() -> Option<Self> {
+        return match cache_dir() {
+            None => {
+                warn!("cache disabled, {}", NO_HOME);
+                None
+            }
+            Some(x) => Self::at(x),
+        };
+    }
+
+    pub fn at(dir: PathBuf) -> Option<Self> {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            warn!("cache disabled, cannot create {}: {}", dir.display(), e);
+            return None;
+        }
+        return Some(Cache { dir });
+    }
+
+    fn path(&self, key: &str, ext: &str) -> PathBuf {
+        return self.dir.join(format!("{}.{}", key, ext));
+    }
+
+    pub fn get(&self, key: &str, ext: &str) -> Option<PathBuf> {
+        let p = self.path(key, ext);
+        return match p.exists() {
+            true => Some(p),
+            false => None,
+        };
+    }
+
+    //copy a file in. it is written aside and renamed, so a concurrent build
+    //never sees half an entry
+    pub fn put(&self, key: &str, ext: &str, from: &Path) {
+        let p = self.path(key, ext);
+        let part = self.path(key, &format!("{}.{}", ext, std::process::id()));
+        if let Err(e) = fs::copy(from, &part).and_then(|_| fs::rename(&part, &p)) {
+            warn!("cannot cache {}: {}", from.display(), e);
+            let _ = fs::remove_file(&part);
+        }
+    }
+
+    pub fn put_bytes(&self, key: &str, ext: &str, data: &[u8]) {
+        let p = self.path(key, ext);
+        let part = self.path(key, &format!("{}.{}", ext, std::process::id()));
+        if let Err(e) = fs::write(&part, data).and_then(|_| fs::rename(&part, &p)) {
+            warn!("cannot cache {}: {}", p.display(), e);
+            let _ = fs::remove_file(&part);
+        }
+    }
+}
+
+//remove every entry, and the binaries of `rfx run`. returns how many there
+//were
+pub fn clean() -> io::Result<usize> {
+    let mut n = 0;
+    for dir in [cache_dir(), run_dir()].into_iter().flatten() {
+        if !dir.exists() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            fs::remove_file(entry?.path())?;
+            n += 1;
+        }
+    }
+    return Ok(n);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, key, key_of, Cache, BUILD_ID};
+    use std::fs;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(&[b"ab", b"c"]), hash(&[b"ab", b"c"]));
+        assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+        assert_eq!(hash(&[]).len(), 16);
+    }
+
+    #[test]
+    fn test_put_get() {
+        let dir = std::env::temp_dir().join(format!("rfx_cache_test_{}", std::process::id()));
+        let cache = Cache::at(dir.clone()).unwrap();
+        assert!(cache.get("k", "c").is_none());
+        cache.put_bytes("k", "c", b"int main(void) {}");
+        let hit = cache.get("k", "c").unwrap();
+        assert_eq!(fs::read_to_string(hit).unwrap(), "int main(void) {}");
+
+        //what another build of rfx cached is not taken
+        let parts: [&[u8]; 2] = [b"ast", b"print(1);"];
+        cache.put_bytes(&key(&parts), "ast", b"[]");
+        assert!(cache.get(&key(&parts), "ast").is_some());
+        let other = format!("{}.dev", BUILD_ID);
+        assert!(cache.get(&key_of(&other, &parts), "ast").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,932 @@
+use std::collections::HashMap;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::diag::{self, Diagnostic, Warnings};
+use super::lex::{self, Token, TokenType};
+use super::parser::SymbolTable;
+use super::target::Target;
+use super::{lint, ownership, syntax, typeck};
+
+/*
+what an editor asks of one source, for `rfx lsp`: its diagnostics, the
+declarations in it, where a name is declared, the type of what is under the
+cursor and the names that can be written there.
+
+the source is checked on its own, as a program of its own. what parses is
+type checked, also around the syntax errors the parser recovered from, so
+types are known while the source is being edited; only the diagnostics of
+the first stage that fails are reported. positions are (line, col), 1-based like those
+of tokens, col counted in chars
+*/
+
+pub type Pos = (u64, u64);
+
+const START: Pos = (0, 0);
+const END: Pos = (u64::MAX, u64::MAX);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Trait,
+    Impl,
+    Struct,
+    Enum,
+    Alias,
+    Type, // built in
+    Variable,
+    Constant,
+    Argument,
+    Field,
+    Variant,
+}
+
+//a declaration in the outline of a source
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub detail: String,
+    pub start: Pos, // the whole declaration
+    pub end: Pos,
+    pub at: Pos, // its name
+    pub len: u64,
+    pub children: Vec<Symbol>,
+}
+
+//a name that can be written at a position
+#[derive(Clone, Debug)]
+pub struct Completion {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub detail: String,
+}
+
+//a declared name, visible from scope.0 to scope.1. members of a type or a
+//trait have an owner and are only reached through it
+struct Decl {
+    name: String,
+    kind: SymbolKind,
+    detail: String, // how it is shown: its signature or name and type
+    at: Pos,
+    owner: Option<String>,
+    scope: (Pos, Pos),
+    tp: Option<usize>,
+}
+
+pub struct Analysis {
+    pub diags: Vec<Diagnostic>,
+    lines: Vec<String>,
+    root: AST,
+    table: SymbolTable,
+    closing: HashMap<Pos, Pos>, // `{` to its `}`
+    decls: Vec<Decl>,
+}
+
+fn tok_pos(t: &Token) -> Pos {
+    return (t.line, t.col);
+}
+
+fn tok_end(t: &Token) -> Pos {
+    return (t.line, t.col + t.s.chars().count() as u64);
+}
+
+//false for tokens standing for something not written in the source
+fn is_written(t: &Token) -> bool {
+    return t.tp != TokenType::Null && !t.s.is_empty();
+}
+
+fn covers(t: &Token, pos: Pos) -> bool {
+    return is_written(t) && tok_pos(t) <= pos && pos < tok_end(t);
+}
+
+fn is_ident_char(c: char) -> bool {
+    return c.is_alphanumeric() || c == '_';
+}
+
+//a type as written, e.g. Vec<i64>
+fn type_text(t: &AST) -> String {
+    if t.next.is_empty() {
+        return t.token.s.clone();
+    }
+    let args: Vec<String> = t.next.iter().map(type_text).collect();
+    return match t.token.s.as_str() {
+        "template" => format!("template{{{}}}", args.join(", ")),
+        _ => format!("{}<{}>", t.token.s, args.join(", ")),
+    };
+}
+
+//a function as declared, from its tree
+fn signature(f: &AST, owner: Option<&str>) -> String {
+    let args: Vec<String> = f.next[2]
+        .next
+        .iter()
+        .map(
+            |a| match (a.token.s.as_str(), type_text(&a.next[1]).as_str()) {
+                ("self", "self") => "self".to_string(),
+                (name, tp) => format!("{}: {}", name, tp),
+            },
+        )
+        .collect();
+    let mut s = String::new();
+    if f.next[0].token.tp == TokenType::Pub {
+        s += "pub ";
+    }
+    s += "fn ";
+    if let Some(owner) = owner {
+        s += &format!("{}::", owner);
+    }
+    s += &format!("{}({})", f.next[1].token.s, args.join(", "));
+    if let Some(r) = f.next[3].next.first() {
+        s += &format!(" -> {}", type_text(r));
+    }
+    return s;
+}
+
+//the bindings of a pattern
+fn bindings<'a>(pat: &'a AST, out: &mut Vec<&'a AST>) {
+    if pat.tp == ASTtype::PatBinding {
+        out.push(pat);
+    }
+    for n in pat.next.iter() {
+        bindings(n, out);
+    }
+}
+
+impl Analysis {
+    pub fn new(src: &str, target: Target, warnings: Warnings) -> Self {
+        let toks = lex::lex_all(src);
+        let mut closing: HashMap<Pos, Pos> = HashMap::new();
+        let mut open: Vec<Pos> = Vec::new();
+        for t in toks.iter() {
+            match t.tp {
+                TokenType::LeftBrace => open.push(tok_pos(t)),
+                TokenType::RightBrace => {
+                    if let Some(o) = open.pop() {
+                        closing.insert(o, tok_end(t));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (mut root, mut diags) = syntax::parse(toks);
+        let mut table = SymbolTable::for_target(target);
+        let checked = typeck::check(&mut root, &mut table);
+        if !diag::has_error(&diags) {
+            diags.extend(checked);
+            if !diag::has_error(&diags) {
+                diags.extend(ownership::check(&root, &table));
+            }
+            if !diag::has_error(&diags) {
+                diags.extend(lint::check(&root, &table, warnings.lints));
+            }
+        }
+
+        let mut a = Analysis {
+            diags: diag::apply_warnings(diags, warnings),
+            lines: src.lines().map(|x| x.to_string()).collect(),
+            root: AST::new(),
+            table,
+            closing,
+            decls: Vec::new(),
+        };
+        for item in root.next.iter() {
+            a.collect(item, END);
+        }
+        a.root = root;
+        return a;
+    }
+
+    //the first and past the last position of the source of a node
+    fn span(&self, ast: &AST) -> (Pos, Pos) {
+        let mut start = END;
+        let mut end = START;
+        if is_written(&ast.token) {
+            start = tok_pos(&ast.token);
+            end = tok_end(&ast.token);
+        }
+        if ast.tp == ASTtype::CodeBlock {
+            end = end.max(self.block_end(ast));
+        }
+        for n in ast.next.iter() {
+            let (s, e) = self.span(n);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        return (start, end);
+    }
+
+    fn block_end(&self, block: &AST) -> Pos {
+        return *self.closing.get(&tok_pos(&block.token)).unwrap_or(&END);
+    }
+
+    fn type_name(&self, tp: Option<usize>) -> Option<String> {
+        return tp.map(|x| self.table.type_name(x).to_string());
+    }
+
+    //the name of the type a type expression resolves to, as written if it
+    //does not
+    fn resolved(&self, t: &AST) -> String {
+        return match self.table.find_type(&type_text(t)) {
+            Some(tp) => self.table.type_name(tp).to_string(),
+            None => type_text(t),
+        };
+    }
+
+    fn declare(
+        &mut self,
+        t: &Token,
+        kind: SymbolKind,
+        detail: String,
+        owner: Option<String>,
+        scope: (Pos, Pos),
+        tp: Option<usize>,
+    ) {
+        self.decls.push(Decl {
+            name: t.s.clone(),
+            kind,
+            detail,
+            at: tok_pos(t),
+            owner,
+            scope,
+            tp,
+        });
+    }
+
+    //a variable, constant or argument with its type
+    fn declare_value(&mut self, name: &AST, kind: SymbolKind, written: Option<&AST>, end: Pos) {
+        let tp = self
+            .type_name(name.vtype)
+            .or_else(|| written.filter(|x| is_written(&x.token)).map(type_text));
+        let keyword = match kind {
+            SymbolKind::Variable => "var ",
+            SymbolKind::Constant => "const ",
+            _ => "",
+        };
+        let detail = match tp {
+            Some(tp) => format!("{}{}: {}", keyword, name.token.s, tp),
+            None => format!("{}{}", keyword, name.token.s),
+        };
+        let start = tok_end(&name.token);
+        self.declare(&name.token, kind, detail, None, (start, end), name.vtype);
+    }
+
+    //the declarations in ast, end is that of the block it is in
+    fn collect(&mut self, ast: &AST, end: Pos) {
+        match ast.tp {
+            ASTtype::Function => self.collect_function(ast, None),
+            ASTtype::Impl => {
+                let owner = self.resolved(&ast.next[0]);
+                for f in ast.next[1..].iter() {
+                    self.collect_function(f, Some(&owner));
+                }
+            }
+            ASTtype::Trait => {
+                let detail = format!("trait {}", ast.token.s);
+                self.declare(
+                    &ast.token,
+                    SymbolKind::Trait,
+                    detail,
+                    None,
+                    (START, END),
+                    None,
+                );
+                for f in ast.next.iter() {
+                    self.collect_function(f, Some(&ast.token.s));
+                }
+            }
+            ASTtype::Struct => {
+                let detail = format!("struct {}", ast.token.s);
+                self.declare(
+                    &ast.token,
+                    SymbolKind::Struct,
+                    detail,
+                    None,
+                    (START, END),
+                    None,
+                );
+                for f in ast.next.iter() {
+                    let detail =
+                        format!("{}.{}: {}", ast.token.s, f.token.s, type_text(&f.next[0]));
+                    let owner = Some(ast.token.s.clone());
+                    self.declare(
+                        &f.token,
+                        SymbolKind::Field,
+                        detail,
+                        owner,
+                        (START, END),
+                        None,
+                    );
+                }
+            }
+            ASTtype::Enum => {
+                let detail = format!("enum {}", ast.token.s);
+                self.declare(
+                    &ast.token,
+                    SymbolKind::Enum,
+                    detail,
+                    None,
+                    (START, END),
+                    None,
+                );
+                for v in ast.next.iter() {
+                    let mut detail = format!("{}::{}", ast.token.s, v.token.s);
+                    if !v.next.is_empty() {
+                        let fields: Vec<String> = v.next.iter().map(type_text).collect();
+                        detail += &format!("({})", fields.join(", "));
+                    }
+                    let owner = Some(ast.token.s.clone());
+                    self.declare(
+                        &v.token,
+                        SymbolKind::Variant,
+                        detail,
+                        owner,
+                        (START, END),
+                        None,
+                    );
+                }
+            }
+            ASTtype::TypeAlias => {
+                let detail = format!("type {} = {}", ast.token.s, type_text(&ast.next[0]));
+                self.declare(
+                    &ast.token,
+                    SymbolKind::Alias,
+                    detail,
+                    None,
+                    (START, END),
+                    None,
+                );
+            }
+            ASTtype::Variable => {
+                self.collect(&ast.next[3], end);
+                let kind = match ast.next[0].token.tp {
+                    TokenType::Const => SymbolKind::Constant,
+                    _ => SymbolKind::Variable,
+                };
+                self.declare_value(&ast.next[1], kind, Some(&ast.next[2]), end);
+                //visible after its value
+                let after = self.span(ast).1;
+                self.decls.last_mut().unwrap().scope.0 = after;
+            }
+            ASTtype::For => {
+                self.collect(&ast.next[1], end);
+                let body_end = self.block_end(&ast.next[2]);
+                self.declare_value(&ast.next[0], SymbolKind::Constant, None, body_end);
+                self.collect(&ast.next[2], end);
+            }
+            ASTtype::MatchCase => {
+                let case_end = self.span(&ast.next[1]).1;
+                let mut found: Vec<&AST> = Vec::new();
+                bindings(&ast.next[0], &mut found);
+                for b in found {
+                    self.declare_value(b, SymbolKind::Constant, None, case_end);
+                }
+                self.collect(&ast.next[1], end);
+            }
+            ASTtype::CodeBlock => {
+                let block_end = self.block_end(ast);
+                for n in ast.next.iter() {
+                    self.collect(n, block_end);
+                }
+            }
+            _ => {
+                for n in ast.next.iter() {
+                    self.collect(n, end);
+                }
+            }
+        }
+    }
+
+    fn collect_function(&mut self, f: &AST, owner: Option<&str>) {
+        let kind = match owner {
+            None => SymbolKind::Function,
+            Some(_) => SymbolKind::Method,
+        };
+        let owned = owner.map(|x| x.to_string());
+        self.declare(
+            &f.next[1].token,
+            kind,
+            signature(f, owner),
+            owned,
+            (START, END),
+            None,
+        );
+
+        let body = match f.next.get(4) {
+            None => return,
+            Some(x) => x,
+        };
+        let body_end = self.block_end(body);
+        for arg in f.next[2].next.iter() {
+            let mut name = arg.next[0].clone();
+            name.vtype = arg.vtype;
+            self.declare_value(&name, SymbolKind::Argument, Some(&arg.next[1]), body_end);
+        }
+        self.collect(body, body_end);
+    }
+
+    //the nodes from the root to the innermost one whose token is at pos
+    fn path_at(&self, pos: Pos) -> Vec<&AST> {
+        fn walk<'a>(ast: &'a AST, pos: Pos, path: &mut Vec<&'a AST>) -> bool {
+            path.push(ast);
+            for n in ast.next.iter() {
+                if walk(n, pos, path) {
+                    return true;
+                }
+            }
+            if covers(&ast.token, pos) {
+                return true;
+            }
+            path.pop();
+            return false;
+        }
+        let mut path: Vec<&AST> = Vec::new();
+        walk(&self.root, pos, &mut path);
+        return path;
+    }
+
+    //the variable, constant or argument name resolves to at pos
+    fn local(&self, name: &str, pos: Pos) -> Option<&Decl> {
+        return self
+            .decls
+            .iter()
+            .filter(|d| d.owner.is_none() && d.name == name)
+            .filter(|d| d.scope.0 <= pos && pos < d.scope.1)
+            .max_by_key(|d| d.at);
+    }
+
+    //the item or module level variable called name
+    fn item(&self, name: &str) -> Option<&Decl> {
+        return self
+            .decls
+            .iter()
+            .find(|d| d.owner.is_none() && d.name == name && d.scope.1 == END);
+    }
+
+    //the field, variant or method name of the type or trait owner
+    fn member(&self, owner: &str, name: &str) -> Option<&Decl> {
+        let owner = match self.table.find_type(owner) {
+            Some(tp) => self.table.type_name(tp),
+            None => owner,
+        };
+        return self
+            .decls
+            .iter()
+            .find(|d| d.owner.as_deref() == Some(owner) && d.name == name);
+    }
+
+    fn resolve(&self, path: &[&AST], pos: Pos) -> Option<&Decl> {
+        let node = path.last()?;
+        let name = node.token.s.as_str();
+        return match node.tp {
+            ASTtype::Ident | ASTtype::CallFunction | ASTtype::Variant | ASTtype::PatVariant => {
+                match name.rsplit_once("::") {
+                    Some((owner, name)) => self.member(owner, name),
+                    None => self.local(name, pos).or_else(|| self.item(name)),
+                }
+            }
+            ASTtype::CallMethod | ASTtype::Field => {
+                let owner = self.type_name(node.next[0].vtype)?;
+                self.member(&owner, name)
+            }
+            ASTtype::FieldInit => {
+                let lit = path.get(path.len().wrapping_sub(2))?;
+                let owner = self
+                    .type_name(lit.vtype)
+                    .unwrap_or_else(|| lit.token.s.clone());
+                self.member(&owner, name)
+            }
+            ASTtype::VariableType | ASTtype::StructLiteral | ASTtype::Impl => {
+                let tp = self.table.find_type(name);
+                let name = tp.map(|x| self.table.type_name(x)).unwrap_or(name);
+                self.item(name)
+            }
+            //a declaration itself
+            _ => {
+                let at = tok_pos(&node.token);
+                self.decls.iter().find(|d| d.at == at && d.name == name)
+            }
+        };
+    }
+
+    //where the name at pos is declared
+    pub fn definition(&self, pos: Pos) -> Option<Pos> {
+        let path = self.path_at(pos);
+        return self.resolve(&path, pos).map(|d| d.at);
+    }
+
+    //what is at pos: the declaration of a name, the type of an expression
+    pub fn hover(&self, pos: Pos) -> Option<String> {
+        let path = self.path_at(pos);
+        let node = path.last()?;
+        if let Some(d) = self.resolve(&path, pos) {
+            //a use may know the type its declaration left to be inferred
+            return match (d.tp, self.type_name(node.vtype)) {
+                (None, Some(tp)) if d.kind == SymbolKind::Variable => {
+                    Some(format!("var {}: {}", d.name, tp))
+                }
+                _ => Some(d.detail.clone()),
+            };
+        }
+        return self.type_name(node.vtype);
+    }
+
+    //the declarations of the source, items with their members and the
+    //variables of the functions
+    pub fn symbols(&self) -> Vec<Symbol> {
+        return self
+            .root
+            .next
+            .iter()
+            .filter_map(|x| self.outline(x, None))
+            .collect();
+    }
+
+    fn symbol(&self, ast: &AST, name: &Token, kind: SymbolKind, detail: String) -> Symbol {
+        let (start, end) = self.span(ast);
+        return Symbol {
+            name: name.s.clone(),
+            kind,
+            detail,
+            start,
+            end,
+            at: tok_pos(name),
+            len: name.s.chars().count() as u64,
+            children: Vec::new(),
+        };
+    }
+
+    fn outline(&self, ast: &AST, owner: Option<&str>) -> Option<Symbol> {
+        let decl = |t: &Token| self.decls.iter().find(|d| d.at == tok_pos(t));
+        let detail = |t: &Token| decl(t).map(|d| d.detail.clone()).unwrap_or_default();
+        let mut s = match ast.tp {
+            ASTtype::Function => {
+                let kind = match owner {
+                    None => SymbolKind::Function,
+                    Some(_) => SymbolKind::Method,
+                };
+                let name = &ast.next[1].token;
+                let mut s = self.symbol(ast, name, kind, signature(ast, owner));
+                if let Some(body) = ast.next.get(4) {
+                    self.locals(body, &mut s.children);
+                }
+                s
+            }
+            ASTtype::Impl => {
+                let tp = self.resolved(&ast.next[0]);
+                let (name, mut s) = match is_written(&ast.token) {
+                    true => (
+                        format!("impl {} for {}", ast.token.s, tp),
+                        self.symbol(ast, &ast.token, SymbolKind::Impl, String::new()),
+                    ),
+                    false => (
+                        format!("impl {}", tp),
+                        self.symbol(ast, &ast.next[0].token, SymbolKind::Impl, String::new()),
+                    ),
+                };
+                s.name = name;
+                for f in ast.next[1..].iter() {
+                    s.children.extend(self.outline(f, Some(&tp)));
+                }
+                s
+            }
+            ASTtype::Trait => {
+                let mut s = self.symbol(ast, &ast.token, SymbolKind::Trait, detail(&ast.token));
+                for f in ast.next.iter() {
+                    s.children.extend(self.outline(f, Some(&ast.token.s)));
+                }
+                s
+            }
+            ASTtype::Struct | ASTtype::Enum => {
+                let (kind, member) = match ast.tp {
+                    ASTtype::Struct => (SymbolKind::Struct, SymbolKind::Field),
+                    _ => (SymbolKind::Enum, SymbolKind::Variant),
+                };
+                let mut s = self.symbol(ast, &ast.token, kind, detail(&ast.token));
+                for m in ast.next.iter() {
+                    let d = detail(&m.token);
+                    s.children.push(self.symbol(m, &m.token, member, d));
+                }
+                s
+            }
+            ASTtype::TypeAlias => {
+                self.symbol(ast, &ast.token, SymbolKind::Alias, detail(&ast.token))
+            }
+            ASTtype::Variable => {
+                let name = &ast.next[1].token;
+                let kind = decl(name).map(|d| d.kind)?;
+                self.symbol(ast, name, kind, detail(name))
+            }
+            _ => return None,
+        };
+        s.children.sort_by_key(|x| x.start);
+        return Some(s);
+    }
+
+    //the variables and constants declared in a function body
+    fn locals(&self, ast: &AST, out: &mut Vec<Symbol>) {
+        if ast.tp == ASTtype::Variable {
+            out.extend(self.outline(ast, None));
+        }
+        for n in ast.next.iter() {
+            self.locals(n, out);
+        }
+    }
+
+    //the names that can be written at pos: after `x.` the fields and methods
+    //of x, after `T::` the variants and methods of T, otherwise the
+    //variables in scope, the items and what the symbol table knows of
+    pub fn completions(&self, pos: Pos) -> Vec<Completion> {
+        let line: Vec<char> = match self.lines.get(pos.0.wrapping_sub(1) as usize) {
+            None => Vec::new(),
+            Some(x) => x.chars().collect(),
+        };
+        let mut word = (pos.1.saturating_sub(1) as usize).min(line.len());
+        while word > 0 && is_ident_char(line[word - 1]) {
+            word -= 1;
+        }
+        let before: String = line[..word].iter().collect();
+        //the name before a `.` or `::`
+        let owner = |sep: &str| {
+            let rest = before.strip_suffix(sep)?;
+            let start = rest.trim_end_matches(is_ident_char).len();
+            return Some(rest[start..].to_string()).filter(|x| !x.is_empty());
+        };
+
+        let of = |d: &Decl| Completion {
+            name: d.name.clone(),
+            kind: d.kind,
+            detail: d.detail.clone(),
+        };
+        if let Some(recv) = owner(".") {
+            let tp = self
+                .local(&recv, pos)
+                .or_else(|| self.item(&recv))
+                .and_then(|d| self.type_name(d.tp));
+            return match tp {
+                None => Vec::new(),
+                Some(tp) => self
+                    .decls
+                    .iter()
+                    .filter(|d| d.owner.as_deref() == Some(tp.as_str()))
+                    .filter(|d| d.kind != SymbolKind::Variant)
+                    .map(of)
+                    .collect(),
+            };
+        }
+        if let Some(owner) = owner("::") {
+            let owner = match self.table.find_type(&owner) {
+                Some(tp) => self.table.type_name(tp).to_string(),
+                None => owner,
+            };
+            return self
+                .decls
+                .iter()
+                .filter(|d| d.owner.as_deref() == Some(owner.as_str()))
+                .filter(|d| d.kind != SymbolKind::Field)
+                .map(of)
+                .collect();
+        }
+
+        let mut out: Vec<Completion> = Vec::new();
+        let seen = |name: &str, out: &Vec<Completion>| out.iter().any(|x| x.name == name);
+        let mut visible: Vec<&Decl> = self
+            .decls
+            .iter()
+            .filter(|d| d.owner.is_none() && d.scope.0 <= pos && pos < d.scope.1)
+            .filter(|d| d.scope.1 != END)
+            .collect();
+        visible.sort_by_key(|d| std::cmp::Reverse(d.at));
+        for d in visible {
+            if !seen(&d.name, &out) {
+                out.push(of(d));
+            }
+        }
+        for d in self.decls.iter() {
+            if d.owner.is_none() && d.scope.1 == END && !seen(&d.name, &out) {
+                out.push(of(d));
+            }
+        }
+        for t in self.table.variable_type.iter() {
+            if !t.name.contains('<') && !seen(&t.name, &out) {
+                out.push(Completion {
+                    name: t.name.clone(),
+                    kind: SymbolKind::Type,
+                    detail: t.name.clone(),
+                });
+            }
+        }
+        for (name, tp) in self.table.alias.iter() {
+            if !seen(name, &out) {
+                out.push(Completion {
+                    name: name.clone(),
+                    kind: SymbolKind::Alias,
+                    detail: format!("type {} = {}", name, self.table.type_name(*tp)),
+                });
+            }
+        }
+        for t in self.table.trait_name.iter() {
+            if !seen(&t.name, &out) {
+                out.push(Completion {
+                    name: t.name.clone(),
+                    kind: SymbolKind::Trait,
+                    detail: format!("trait {}", t.name),
+                });
+            }
+        }
+        for f in self.table.function_name.iter().filter(|x| x.free) {
+            if !seen(&f.name, &out) {
+                out.push(Completion {
+                    name: f.name.clone(),
+                    kind: SymbolKind::Function,
+                    detail: format!("fn {}", f.name),
+                });
+            }
+        }
+        let builtins = [
+            ("print", "fn print(value)"),
+            ("assert", "fn assert(cond: bool)"),
+            ("assert_eq", "fn assert_eq(left, right)"),
+        ];
+        for (name, detail) in builtins {
+            if !seen(name, &out) {
+                out.push(Completion {
+                    name: name.to_string(),
+                    kind: SymbolKind::Function,
+                    detail: detail.to_string(),
+                });
+            }
+        }
+        return out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Analysis, SymbolKind};
+    use crate::compile::{Lints, Target, WarningLevel, Warnings};
+
+    const SRC: &str = "\
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+enum Shape {
+    Dot(Point),
+    Empty,
+}
+
+trait Area {
+    fn area(self) -> i64;
+}
+
+impl Area for Point {
+    fn area(self) -> i64 {
+        return self.x * self.y;
+    }
+}
+
+fn scale(p: Point, by: i64) -> Point {
+    var q = Point { x: p.x * by, y: p.y * by };
+    const unused: bool = true;
+    return q;
+}
+
+const origin = Point { x: 0, y: 0 };
+var s = Shape::Dot(scale(origin, 2));
+match s {
+    Shape::Dot(d) => print(d.area()),
+    Shape::Empty => print(0),
+}
+";
+
+    fn analysis(src: &str) -> Analysis {
+        return Analysis::new(
+            src,
+            Target::with_bits(64),
+            Warnings {
+                level: WarningLevel::Warn,
+                lints: Lints::NONE,
+            },
+        );
+    }
+
+    //the position of the n-th occurrence of pat in SRC
+    fn find(pat: &str, n: usize) -> (u64, u64) {
+        let at = SRC.match_indices(pat).nth(n).unwrap().0;
+        let line = SRC[..at].matches('\n').count() as u64 + 1;
+        let start = SRC[..at].rfind('\n').map(|x| x + 1).unwrap_or(0);
+        return (line, SRC[start..at].chars().count() as u64 + 1);
+    }
+
+    #[test]
+    fn test_analysis() {
+        let a = analysis(SRC);
+        assert!(a.diags.is_empty(), "{:?}", a.diags);
+
+        let symbols = a.symbols();
+        let names: Vec<&str> = symbols.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Point",
+                "Shape",
+                "Area",
+                "impl Area for Point",
+                "scale",
+                "origin",
+                "s"
+            ]
+        );
+        assert_eq!(symbols[0].children.len(), 2);
+        assert_eq!(symbols[3].children[0].kind, SymbolKind::Method);
+        let locals: Vec<(&str, SymbolKind)> = symbols[4]
+            .children
+            .iter()
+            .map(|x| (x.name.as_str(), x.kind))
+            .collect();
+        assert_eq!(
+            locals,
+            [
+                ("q", SymbolKind::Variable),
+                ("unused", SymbolKind::Constant)
+            ]
+        );
+        assert_eq!(symbols[4].at, find("scale", 0));
+        assert_eq!(symbols[5].detail, "const origin: Point");
+
+        //variables, arguments, functions, methods, fields and types
+        assert_eq!(a.definition(find("q;", 0)), Some(find("q =", 0)));
+        assert_eq!(a.definition(find("p.x", 0)), Some(find("p: Point", 0)));
+        assert_eq!(a.definition(find("scale(", 1)), Some(find("scale", 0)));
+        assert_eq!(a.definition(find("area()", 0)), Some(find("area", 1)));
+        assert_eq!(a.definition(find("x * by", 0)), Some(find("x: i64", 0)));
+        assert_eq!(a.definition(find("x: p.x", 0)), Some(find("x: i64", 0)));
+        assert_eq!(a.definition(find("Point {", 1)), Some(find("Point {", 0)));
+        assert_eq!(a.definition(find("Dot(d)", 0)), Some(find("Dot(Point)", 0)));
+        assert_eq!(a.definition(find("d.area", 0)), Some(find("d)", 0)));
+        assert_eq!(
+            a.definition(find("origin, 2", 0)),
+            Some(find("origin =", 0))
+        );
+        assert_eq!(a.definition(find("print", 0)), None);
+
+        assert_eq!(a.hover(find("q;", 0)).unwrap(), "var q: Point");
+        assert_eq!(
+            a.hover(find("scale(", 1)).unwrap(),
+            "fn scale(p: Point, by: i64) -> Point"
+        );
+        assert_eq!(
+            a.hover(find("area()", 0)).unwrap(),
+            "fn Point::area(self) -> i64"
+        );
+        assert_eq!(a.hover(find("d.area", 0)).unwrap(), "const d: Point");
+        assert_eq!(a.hover(find("Dot(s", 0)).unwrap(), "Shape::Dot(Point)");
+        assert_eq!(a.hover(find("* by", 0)).unwrap(), "i64");
+
+        let names = |pos| {
+            let mut v: Vec<String> = a.completions(pos).into_iter().map(|x| x.name).collect();
+            v.sort();
+            return v;
+        };
+        let in_scale = names(find("return q", 0));
+        for n in [
+            "q", "unused", "p", "by", "scale", "Point", "Area", "i64", "print",
+        ] {
+            assert!(in_scale.iter().any(|x| x == n), "{} missing", n);
+        }
+        assert!(!in_scale.iter().any(|x| x == "d"));
+        let mut after_dot = find("x * by", 0);
+        after_dot.1 += 1;
+        assert_eq!(names(after_dot), ["area", "x", "y"]);
+        let mut after_colons = find("Dot(s", 0);
+        after_colons.1 += 1;
+        assert_eq!(names(after_colons), ["Dot", "Empty"]);
+    }
+
+    #[test]
+    fn test_analysis_errors() {
+        //what is around a syntax error is still known, type errors are not
+        //reported with it
+        let a = analysis("fn f(a: i64) -> bool {\n    return a;\n}\nvar x = ;\n");
+        assert_eq!(a.diags.len(), 1);
+        assert_eq!((a.diags[0].line, a.diags[0].col), (4, 9));
+        assert_eq!(a.symbols()[0].name, "f");
+        assert_eq!(a.definition((2, 12)), Some((1, 6)));
+
+        let a = analysis("fn f(a: i64) {\n    var b = a *\n    print(a);\n}\n");
+        assert_eq!(a.diags.len(), 1);
+        assert_eq!(a.hover((3, 11)).unwrap(), "a: i64");
+        let names: Vec<String> = a.completions((3, 5)).into_iter().map(|x| x.name).collect();
+        assert!(names.contains(&"a".to_string()) && names.contains(&"f".to_string()));
+
+        let a = analysis("fn f(a: i64) -> bool {\n    return a;\n}\n");
+        assert_eq!(a.diags.len(), 1);
+        assert_eq!(a.diags[0].line, 2);
+    }
+}
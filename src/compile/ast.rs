@@ -1,33 +1,65 @@
-use std::fs::File;
-use std::io::BufReader;
-
+use json::JsonValue;
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
 
-use super::lex::Lex;
-
 use super::lex::Token;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Debug, strum_macros::EnumString)]
 pub enum ASTtype {
     Super,
-    CallFunction,
-    CallFunction_arg,
-    Bunch,
-    Fucntion,
+    Use,
+    TypeAlias,
+    Function,
+    FnVisibility,
     FnIdent,
+    FnArgs,
+    FnArg,
     FnRetType,
     CodeBlock,
-    Match,
-    MatchCase,
-    If,
-    For,
-    While,
-    Return,
     Trait,
-    VariableAttr, // const, static...
+    Impl,
+    Struct,
+    StructField,
+    Enum,
+    EnumVariant,
+
+    Variable,
+    VariableAttr, // const, var
     VariableType,
     VariableName,
+    Assign,
+
+    If,
+    While,
+    For,
+    Match,
+    MatchCase,
+    Return,
+    Break,
+    Continue,
+
+    Literal,
+    Ident,
+    Binary,
+    Unary,
+    CallFunction,
+    CallMethod,
+    Index,
+    Array,
+    Field,
+    StructLiteral,
+    FieldInit,
+    Variant,
+
+    PatWildcard,
+    PatBinding,
+    PatLiteral,
+    PatRange,
+    PatOr,
+    PatVariant,
+
+    Attribute,
+    Error,
 }
 
 #[derive(Clone)]
@@ -35,6 +67,11 @@ pub struct AST {
     pub token: Token,
     pub tp: ASTtype,
     pub next: Vec<AST>,
+    pub vtype: Option<usize>, // index into the type table, filled by typeck
+    pub sym: Option<usize>,   // resolved function, filled by typeck
+    pub attrs: Vec<AST>,      // Attribute..., of an item
+    pub doc: Option<String>,  // the doc comments before an item, a line each
+    pub public: bool,         // `pub` before a struct, enum, trait, type alias, var or const
 }
 
 impl AST {
@@ -43,24 +80,155 @@ impl AST {
             token: Token::new(),
             tp: ASTtype::Super,
             next: Vec::new(),
+            vtype: None,
+            sym: None,
+            attrs: Vec::new(),
+            doc: None,
+            public: false,
         }
     }
 
-    //pub fn push_next(self, tp: ASTtype, t: Token) -> &'static mut AST {}
+    pub fn with(tp: ASTtype, t: Token) -> Self {
+        AST {
+            token: t,
+            tp,
+            next: Vec::new(),
+            vtype: None,
+            sym: None,
+            attrs: Vec::new(),
+            doc: None,
+            public: false,
+        }
+    }
+
+    //true if an item has the attribute, e.g. #[test]
+    pub fn has_attr(&self, name: &str) -> bool {
+        return self.attrs.iter().any(|x| x.token.s == name);
+    }
+
+    pub fn push_next(&mut self, tp: ASTtype, t: Token) -> &mut AST {
+        self.next.push(AST::with(tp, t));
+        return self.next.last_mut().unwrap();
+    }
+
+    //the tree as json, to cache the parse of a module: [type, token type,
+    //text, line, col, doc, attrs, next, public]. vtype and sym are left out,
+    //typeck fills them in
+    pub fn to_json(&self) -> JsonValue {
+        let doc = match &self.doc {
+            None => JsonValue::Null,
+            Some(x) => x.as_str().into(),
+        };
+        return JsonValue::Array(vec![
+            format!("{:?}", self.tp).into(),
+            self.token.tp.to_string().into(),
+            self.token.s.as_str().into(),
+            self.token.line.into(),
+            self.token.col.into(),
+            doc,
+            JsonValue::Array(self.attrs.iter().map(|x| x.to_json()).collect()),
+            JsonValue::Array(self.next.iter().map(|x| x.to_json()).collect()),
+            self.public.into(),
+        ]);
+    }
+
+    //the tree of to_json, its tokens in the module file. None if v is not one
+    pub fn from_json(v: &JsonValue, file: usize) -> Option<AST> {
+        let mut t = Token::new();
+        t.tp = v[1].as_str()?.parse().ok()?;
+        t.s = v[2].as_str()?.to_string();
+        t.line = v[3].as_u64()?;
+        t.col = v[4].as_u64()?;
+        t.file = file;
+        let mut ast = AST::with(v[0].as_str()?.parse().ok()?, t);
+        ast.doc = v[5].as_str().map(|x| x.to_string());
+        ast.public = v[8].as_bool()?;
+        for x in v[6].members() {
+            ast.attrs.push(AST::from_json(x, file)?);
+        }
+        for x in v[7].members() {
+            ast.next.push(AST::from_json(x, file)?);
+        }
+        return Some(ast);
+    }
+
+    //the tree a node a line, its type and token, indented by depth
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        self.dump_into(0, &mut out);
+        return out;
+    }
+
+    fn dump_into(&self, depth: usize, out: &mut String) {
+        for a in self.attrs.iter() {
+            a.dump_into(depth, out);
+        }
+        out.push_str(&"  ".repeat(depth));
+        match self.token.s.is_empty() {
+            true => out.push_str(&format!("{:?}\n", self.tp)),
+            false => out.push_str(&format!("{:?} {:?}\n", self.tp, self.token.s)),
+        }
+        for n in self.next.iter() {
+            n.dump_into(depth + 1, out);
+        }
+    }
 }
 
 /*
-CallFunction -> name, args..., next
+Super     -> ? entry (items and top level statements...)
+Use       -> 0 entry, token is the path
+TypeAlias -> 1 entry (VariableType), token is the name
 
+Function  -> 5 entry (FnVisibility, FnIdent, FnArgs, FnRetType, CodeBlock)
+             a trait method signature has no CodeBlock
+FnArgs    -> ? entry (FnArg...)
+FnArg     -> 2 entry (VariableName, VariableType)
+FnRetType -> 0 or 1 entry (VariableType)
+Trait     -> ? entry (Function...), token is the name
+Impl      -> ? entry (VariableType, Function...), token is the trait name or Null
+VariableType -> ? entry (generic args: VariableType...), token is the name
+Struct    -> ? entry (StructField...), token is the name
+             public, as Enum, Trait and TypeAlias, if `pub` is before it
+StructField -> 1 entry (VariableType), token is the field name
+Enum      -> ? entry (EnumVariant...), token is the name
+EnumVariant -> ? entry (payload: VariableType...), token is the variant name
 
-Fucntion      -> 4 entry (name, args, return type, code, next)
 CodeBlock -> ? entry (codes...)
-Match   -> ? entry (value, cases...)
-MatchCase    -> 3 entry (value, code)
-If      -> 2 entry (code, next)
-For     -> 2 entry (variable, range, code)
-While   -> 2 entry (condition, code)
-Return  -> 1 entry (value)
-
-etc     -> 1 entry (next)
+Variable  -> 4 entry (VariableAttr, VariableName, VariableType, value)
+             public at module level if `pub` is before it
+             VariableType has a Null token if the type is inferred
+Assign    -> 2 entry (place, value), token is the operator
+If        -> 2 or 3 entry (condition, CodeBlock, [CodeBlock or If])
+While     -> 2 entry (condition, code)
+For       -> 3 entry (VariableName, iterable, code)
+Match     -> ? entry (value, MatchCase...)
+MatchCase -> 2 entry (pattern, code), code is an expression or a CodeBlock
+Return    -> 0 or 1 entry (value)
+
+Literal   -> 0 entry, token is Number, StringLiteral, CharLiteral, True or False
+Ident     -> 0 entry
+Binary    -> 2 entry (left, right), token is the operator. '..' and '..=' are ranges
+Unary     -> 1 entry (value), token is the operator
+CallFunction -> ? entry (args...), token is the name. a path is joined by "::"
+CallMethod   -> ? entry (receiver, args...), token is the method name
+Index     -> 2 entry (value, index)
+Array     -> ? entry (elements...)
+Field     -> 1 entry (value), token is the field name
+StructLiteral -> ? entry (FieldInit...), token is the struct name
+FieldInit -> 1 entry (value), token is the field name
+Variant   -> ? entry (payload...), token is "Enum::Variant". made by typeck from
+             CallFunction or Ident, `sym` is the variant index
+
+PatWildcard, PatBinding -> 0 entry
+PatLiteral -> 0 entry, token is the literal. a negative number keeps its '-'
+PatRange   -> 2 entry (PatLiteral, PatLiteral), token is '..' or '..='
+PatOr      -> ? entry (patterns...)
+PatVariant -> ? entry (payload patterns...), token is "Enum::Variant"
+
+Attribute -> ? entry (Ident...), token is the name. `#[name(args)]` before an
+             item or a function of an impl or trait, kept in its attrs
+the `///` and `/** */` comments before the same, attributes or not, are its doc
+
+Error     -> 0 entry, an item or statement that did not parse, token is its
+             first. the parser reports it and goes on after it
 */
@@ -0,0 +1,867 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::bytecode::{
+    BinOp, CmpOp, Const, Function, IntTy, Op, Place, PrintKind, Program, Step, Type, TypeDesc, Var,
+};
+use super::interp::runs_drop;
+use super::lex::{number_value, TokenType};
+use super::matching::{domain_of, pattern_of, Pat};
+use super::parser::{SymbolTable, TypeKind, TYPE_STRING, TYPE_U64, TYPE_VOID};
+use super::typeck::assert_message;
+
+/*
+compile a checked tree to bytecode. the code does what the C of codegen
+does, step for step as the interpreter walks the tree: a variable gets a
+slot of its own, an owned value is moved out of a variable and copied out of
+a field or an element, an rvalue only borrowed is kept in a temporary, and
+the slots of a scope are dropped when it is left, by its end, a `break`, a
+`continue` or a `return`. only slots of types whose drop runs a user `drop`
+are dropped, for the rest going away is enough.
+
+the top level statements are the entry function. its module level variables
+are globals, dropped after everything else
+*/
+
+//a loop being compiled: the jumps out of it, patched at its end
+struct Loop {
+    depth: usize, // scopes outside the loop, left alone by break and continue
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Gen<'a> {
+    table: &'a SymbolTable,
+    consts: Vec<Const>,
+    functions: Vec<Option<u32>>, // by sym, the index of its compiled function
+    dropping: Vec<bool>,         // by type, true if dropping it runs a user `drop`
+    globals: Vec<(String, u32)>,
+
+    //the function being compiled
+    locals: Vec<u32>,
+    scopes: Vec<Vec<(String, u32)>>, // the slots of each scope, temporaries unnamed
+    code: Vec<Op>,
+    places: Vec<Place>,
+    pats: Vec<Pat>,
+    loops: Vec<Loop>,
+    drop_self: bool, // a `drop`, whose `self` only drops its fields
+}
+
+//the declarations of the functions with a body, in source order
+fn bodies(root: &AST) -> Vec<&AST> {
+    let mut fs: Vec<&AST> = Vec::new();
+    for item in root.next.iter() {
+        let items = match item.tp {
+            ASTtype::Function => std::slice::from_ref(item),
+            ASTtype::Impl => &item.next[1..],
+            _ => continue,
+        };
+        fs.extend(
+            items
+                .iter()
+                .filter(|f| f.next.len() == 5 && f.sym.is_some()),
+        );
+    }
+    return fs;
+}
+
+fn int_ty(table: &SymbolTable, tp: usize) -> IntTy {
+    let v = &table.variable_type[tp];
+    return IntTy {
+        bits: v.size as u8 * 8,
+        signed: matches!(v.kind, TypeKind::Integer { signed: true }),
+    };
+}
+
+fn type_desc(table: &SymbolTable, tp: usize) -> TypeDesc {
+    return match table.kind(tp) {
+        TypeKind::Void => TypeDesc::Void,
+        TypeKind::Integer { .. } => TypeDesc::Int(int_ty(table, tp)),
+        TypeKind::Bool => TypeDesc::Bool,
+        TypeKind::Char => TypeDesc::Char,
+        TypeKind::String => TypeDesc::Str,
+        TypeKind::Vec(x) => TypeDesc::Vec(*x as u32),
+        TypeKind::Struct(fields) => TypeDesc::Struct(fields.iter().map(|x| x.tp as u32).collect()),
+        TypeKind::Enum(variants) => TypeDesc::Enum(
+            variants
+                .iter()
+                .map(|v| v.fields.iter().map(|x| *x as u32).collect())
+                .collect(),
+        ),
+    };
+}
+
+//compile the program of a checked tree
+pub fn compile(root: &AST, table: &SymbolTable) -> Program {
+    let bodies = bodies(root);
+    let mut functions: Vec<Option<u32>> = vec![None; table.function_name.len()];
+    for (i, f) in bodies.iter().enumerate() {
+        functions[f.sym.unwrap()] = Some(i as u32);
+    }
+    let types: Vec<Type> = (0..table.variable_type.len())
+        .map(|tp| Type {
+            name: table.type_name(tp).to_string(),
+            desc: type_desc(table, tp),
+            drop: table.drop_impl(tp).and_then(|f| functions[f]),
+        })
+        .collect();
+    let globals: Vec<(String, u32)> = root
+        .next
+        .iter()
+        .filter(|x| x.tp == ASTtype::Variable)
+        .map(|x| (x.next[1].token.s.clone(), x.vtype.unwrap() as u32))
+        .collect();
+
+    let mut g = Gen {
+        table,
+        consts: Vec::new(),
+        functions,
+        dropping: (0..table.variable_type.len())
+            .map(|tp| runs_drop(table, tp, &mut Vec::new()))
+            .collect(),
+        globals,
+        locals: Vec::new(),
+        scopes: Vec::new(),
+        code: Vec::new(),
+        places: Vec::new(),
+        pats: Vec::new(),
+        loops: Vec::new(),
+        drop_self: false,
+    };
+    let mut compiled: Vec<Function> = bodies.iter().map(|f| g.function(f)).collect();
+    compiled.push(g.entry(root));
+    return Program {
+        consts: g.consts,
+        types,
+        globals: g.globals,
+        entry: compiled.len() as u32 - 1,
+        functions: compiled,
+    };
+}
+
+impl<'a> Gen<'a> {
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        return self.code.len() - 1;
+    }
+
+    fn here(&self) -> u32 {
+        return self.code.len() as u32;
+    }
+
+    //point the jump at `at` to target
+    fn patch(&mut self, at: usize, target: u32) {
+        self.code[at] = match self.code[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            op => unreachable!("{:?} is not a jump", op),
+        };
+    }
+
+    fn constant(&mut self, c: Const) -> u32 {
+        let i = match self.consts.iter().position(|x| *x == c) {
+            Some(i) => i,
+            None => {
+                self.consts.push(c);
+                self.consts.len() - 1
+            }
+        };
+        return i as u32;
+    }
+
+    //the compiled function and its tables, the Gen ready for the next
+    fn finish(&mut self, name: String, args: usize) -> Function {
+        self.emit(Op::Zero(TYPE_VOID as u32));
+        self.emit(Op::Return);
+        return Function {
+            name,
+            args: args as u32,
+            locals: std::mem::take(&mut self.locals),
+            places: std::mem::take(&mut self.places),
+            pats: std::mem::take(&mut self.pats),
+            code: std::mem::take(&mut self.code),
+        };
+    }
+
+    fn function(&mut self, f: &AST) -> Function {
+        let sym = f.sym.unwrap();
+        let decl = &self.table.function_name[sym];
+        self.drop_self = self.table.drop_impl.iter().any(|(_, x)| *x == sym);
+        self.scopes = vec![Vec::new()];
+        for a in decl.args.iter() {
+            self.declare(&a.name, a.tp);
+        }
+        self.stmts(&f.next[4].next);
+        self.close_scope();
+        let name = match decl.self_tp {
+            Some(tp) => format!("{}::{}", self.table.type_name(tp), decl.name),
+            None => decl.name.clone(),
+        };
+        return self.finish(name, decl.args.len());
+    }
+
+    //the top level statements. the module level variables are set in source
+    //order and dropped last, after the temporaries of the statements
+    fn entry(&mut self, root: &AST) -> Function {
+        self.drop_self = false;
+        self.scopes = vec![Vec::new()];
+        let mut global = 0;
+        for item in root.next.iter() {
+            match item.tp {
+                ASTtype::Function
+                | ASTtype::Impl
+                | ASTtype::Trait
+                | ASTtype::TypeAlias
+                | ASTtype::Struct
+                | ASTtype::Enum
+                | ASTtype::Use => {}
+                ASTtype::Variable => {
+                    self.value(&item.next[3]);
+                    self.emit(Op::Store(Var::Global(global)));
+                    global += 1;
+                }
+                _ => self.stmt(item),
+            }
+        }
+        self.close_scope();
+        for (i, (_, tp)) in self.globals.iter().enumerate().rev() {
+            if self.dropping[*tp as usize] {
+                self.code.push(Op::Drop(Var::Global(i as u32)));
+            }
+        }
+        return self.finish("<main>".to_string(), 0);
+    }
+
+    fn slot(&mut self, tp: usize) -> u32 {
+        self.locals.push(tp as u32);
+        return self.locals.len() as u32 - 1;
+    }
+
+    //a slot for a variable of the innermost scope, or unnamed a temporary
+    fn declare(&mut self, name: &str, tp: usize) -> u32 {
+        let slot = self.slot(tp);
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((name.to_string(), slot));
+        return slot;
+    }
+
+    fn var(&self, name: &str) -> Var {
+        for scope in self.scopes.iter().rev() {
+            if let Some((_, slot)) = scope.iter().rev().find(|(n, _)| n == name) {
+                return Var::Local(*slot);
+            }
+        }
+        let g = self.globals.iter().rposition(|(n, _)| n == name);
+        return Var::Global(g.unwrap_or_else(|| unreachable!("no variable {}", name)) as u32);
+    }
+
+    fn open_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    //drop the slots of the scopes from depth on, innermost and last declared
+    //first. the scopes stay, for a jump out of them
+    fn drop_scopes(&mut self, depth: usize) {
+        let mut ops: Vec<Op> = Vec::new();
+        for scope in self.scopes[depth..].iter().rev() {
+            for (_, slot) in scope.iter().rev() {
+                if self.drop_self && *slot == 0 {
+                    ops.push(Op::DropFields(Var::Local(0)));
+                } else if self.dropping[self.locals[*slot as usize] as usize] {
+                    ops.push(Op::Drop(Var::Local(*slot)));
+                }
+            }
+        }
+        self.code.extend(ops);
+    }
+
+    fn close_scope(&mut self) {
+        self.drop_scopes(self.scopes.len() - 1);
+        self.scopes.pop();
+    }
+
+    fn is_place(ast: &AST) -> bool {
+        return match ast.tp {
+            ASTtype::Ident => true,
+            ASTtype::Field | ASTtype::Index => Self::is_place(&ast.next[0]),
+            _ => false,
+        };
+    }
+
+    fn field_index(&self, base: &AST, name: &str) -> u32 {
+        return match self.table.kind(base.vtype.unwrap()) {
+            TypeKind::Struct(fields) => fields.iter().position(|x| x.name == name).unwrap() as u32,
+            _ => unreachable!(),
+        };
+    }
+
+    //the variable and the steps into it a place expression names, and the
+    //index expressions of its Index steps in order
+    fn path<'t>(&self, ast: &'t AST, steps: &mut Vec<Step>, indices: &mut Vec<&'t AST>) -> Var {
+        return match ast.tp {
+            ASTtype::Ident => self.var(&ast.token.s),
+            ASTtype::Field => {
+                let v = self.path(&ast.next[0], steps, indices);
+                steps.push(Step::Field(self.field_index(&ast.next[0], &ast.token.s)));
+                v
+            }
+            _ => {
+                let v = self.path(&ast.next[0], steps, indices);
+                steps.push(Step::Index);
+                indices.push(&ast.next[1]);
+                v
+            }
+        };
+    }
+
+    fn place_index(&mut self, place: Place) -> u32 {
+        let i = match self.places.iter().position(|x| *x == place) {
+            Some(i) => i,
+            None => {
+                self.places.push(place);
+                self.places.len() - 1
+            }
+        };
+        return i as u32;
+    }
+
+    //the place of a place expression, its indices pushed
+    fn place(&mut self, ast: &AST) -> u32 {
+        let (mut steps, mut indices) = (Vec::new(), Vec::new());
+        let var = self.path(ast, &mut steps, &mut indices);
+        for x in indices {
+            self.expr(x);
+        }
+        return self.place_index(Place { var, steps });
+    }
+
+    //an expression whose value is taken over: a variable is moved out of, a
+    //field or an element is copied
+    fn value(&mut self, ast: &AST) {
+        if ast.tp == ASTtype::Ident && self.table.is_owned(ast.vtype.unwrap()) {
+            let v = self.var(&ast.token.s);
+            self.emit(Op::Move(v));
+            return;
+        }
+        self.expr(ast);
+    }
+
+    //an expression that is only read. an rvalue that runs a user `drop` is
+    //kept until the end of the block
+    fn borrow(&mut self, ast: &AST) {
+        self.expr(ast);
+        let tp = ast.vtype.unwrap();
+        let named = matches!(
+            ast.tp,
+            ASTtype::Ident | ASTtype::Field | ASTtype::Index | ASTtype::Literal
+        );
+        if !named && self.dropping[tp] {
+            let t = self.declare("", tp);
+            self.emit(Op::Dup);
+            self.emit(Op::Store(Var::Local(t)));
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[AST]) {
+        self.open_scope();
+        for stmt in stmts.iter() {
+            self.stmt(stmt);
+        }
+        self.close_scope();
+    }
+
+    fn stmt(&mut self, ast: &AST) {
+        match ast.tp {
+            ASTtype::Variable => {
+                self.value(&ast.next[3]);
+                let slot = self.declare(&ast.next[1].token.s, ast.vtype.unwrap());
+                self.emit(Op::Store(Var::Local(slot)));
+            }
+            ASTtype::Assign => self.assign(ast),
+            ASTtype::CodeBlock => self.stmts(&ast.next),
+            ASTtype::If => self.if_chain(ast),
+            ASTtype::While => self.while_loop(ast),
+            ASTtype::For => self.for_loop(ast),
+            ASTtype::Match => self.match_value(ast, false),
+            ASTtype::Return => {
+                match ast.next.first() {
+                    None => self.code.push(Op::Zero(TYPE_VOID as u32)),
+                    Some(x) => self.value(x),
+                }
+                self.drop_scopes(0);
+                self.emit(Op::Return);
+            }
+            ASTtype::Break | ASTtype::Continue => {
+                self.drop_scopes(self.loops.last().unwrap().depth);
+                let at = self.emit(Op::Jump(0));
+                let lp = self.loops.last_mut().unwrap();
+                match ast.tp {
+                    ASTtype::Break => lp.breaks.push(at),
+                    _ => lp.continues.push(at),
+                }
+            }
+            _ => {
+                self.borrow(ast);
+                self.emit(Op::Pop);
+            }
+        }
+    }
+
+    fn if_chain(&mut self, ast: &AST) {
+        self.expr(&ast.next[0]);
+        let skip = self.emit(Op::JumpIfFalse(0));
+        self.stmts(&ast.next[1].next);
+        let other = match ast.next.get(2) {
+            None => {
+                self.patch(skip, self.here());
+                return;
+            }
+            Some(x) => x,
+        };
+        let end = self.emit(Op::Jump(0));
+        self.patch(skip, self.here());
+        match other.tp {
+            ASTtype::If => self.if_chain(other),
+            _ => self.stmts(&other.next),
+        }
+        self.patch(end, self.here());
+    }
+
+    //the jumps out of the loop just compiled to their targets
+    fn end_loop(&mut self, brk: u32, cont: u32) {
+        let lp = self.loops.pop().unwrap();
+        for at in lp.breaks {
+            self.patch(at, brk);
+        }
+        for at in lp.continues {
+            self.patch(at, cont);
+        }
+    }
+
+    fn while_loop(&mut self, ast: &AST) {
+        //temporaries of the condition are made and dropped each pass
+        let start = self.here();
+        self.open_scope();
+        self.expr(&ast.next[0]);
+        let exit = self.emit(Op::JumpIfFalse(0));
+        self.loops.push(Loop {
+            depth: self.scopes.len() - 1,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        self.stmts(&ast.next[1].next);
+        self.drop_scopes(self.scopes.len() - 1);
+        self.emit(Op::Jump(start));
+        self.patch(exit, self.here());
+        self.close_scope();
+        let end = self.here();
+        self.end_loop(end, start);
+    }
+
+    fn for_loop(&mut self, ast: &AST) {
+        let iter = &ast.next[1];
+        let tp = ast.next[0].vtype.unwrap();
+        let range = iter.tp == ASTtype::Binary
+            && matches!(iter.token.tp, TokenType::DotDot | TokenType::DotDotEqu);
+
+        self.open_scope();
+        let i = self.slot(if range { tp } else { TYPE_U64 });
+        //a range is counted from its start to its end, a Vec by the index of
+        //the element, which is copied out of a copy of the Vec
+        let (start, exit) = match range {
+            true => {
+                let end = self.slot(tp);
+                self.expr(&iter.next[0]);
+                self.emit(Op::Store(Var::Local(i)));
+                self.expr(&iter.next[1]);
+                self.emit(Op::Store(Var::Local(end)));
+                let start = self.here();
+                self.emit(Op::Load(Var::Local(i)));
+                self.emit(Op::Load(Var::Local(end)));
+                match iter.token.tp {
+                    TokenType::DotDot => self.emit(Op::Cmp(CmpOp::Lt)),
+                    _ => self.emit(Op::Cmp(CmpOp::Le)),
+                };
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.open_scope();
+                self.emit(Op::Load(Var::Local(i)));
+                (start, exit)
+            }
+            false => {
+                self.borrow(iter);
+                let v = Var::Local(self.slot(iter.vtype.unwrap()));
+                self.emit(Op::Store(v));
+                let zero = self.constant(Const::Int(0));
+                self.emit(Op::Const(zero));
+                self.emit(Op::Store(Var::Local(i)));
+                let start = self.here();
+                self.emit(Op::Load(Var::Local(i)));
+                let whole = self.place_index(Place {
+                    var: v,
+                    steps: Vec::new(),
+                });
+                self.emit(Op::LenPlace(whole));
+                self.emit(Op::Cmp(CmpOp::Lt));
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.open_scope();
+                self.emit(Op::Load(Var::Local(i)));
+                let element = self.place_index(Place {
+                    var: v,
+                    steps: vec![Step::Index],
+                });
+                self.emit(Op::GetPlace(element));
+                (start, exit)
+            }
+        };
+        let x = self.declare(&ast.next[0].token.s, tp);
+        self.emit(Op::Store(Var::Local(x)));
+        self.loops.push(Loop {
+            depth: self.scopes.len() - 1,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        self.stmts(&ast.next[2].next);
+        self.close_scope();
+        let cont = self.here();
+        self.emit(Op::Inc(Var::Local(i)));
+        self.emit(Op::Jump(start));
+        let end = self.here();
+        self.patch(exit, end);
+        self.close_scope();
+        self.end_loop(end, cont);
+    }
+
+    fn assign(&mut self, ast: &AST) {
+        let tp = ast.vtype.unwrap();
+        let (place, value) = (&ast.next[0], &ast.next[1]);
+
+        if ast.token.tp == TokenType::Equal {
+            self.value(value);
+            match place.tp {
+                ASTtype::Ident => {
+                    let v = self.var(&place.token.s);
+                    self.emit(Op::Assign(v));
+                }
+                _ => {
+                    let p = self.place(place);
+                    self.emit(Op::SetPlace(p, tp as u32));
+                }
+            }
+            return;
+        }
+
+        //`place op= x`: x first, then the place, its indices computed once
+        let op = match tp == TYPE_STRING {
+            true => {
+                self.borrow(value);
+                Op::Concat
+            }
+            false => {
+                self.expr(value);
+                let b = BinOp::from_symbol(ast.token.s.trim_end_matches('='));
+                Op::Bin(b.unwrap(), int_ty(self.table, tp))
+            }
+        };
+        let x = Var::Local(self.slot(value.vtype.unwrap()));
+        self.emit(Op::Store(x));
+        if place.tp == ASTtype::Ident {
+            let v = self.var(&place.token.s);
+            self.emit(Op::Load(v));
+            self.emit(Op::Load(x));
+            self.emit(op);
+            self.emit(Op::Assign(v));
+            return;
+        }
+
+        let (mut steps, mut indices) = (Vec::new(), Vec::new());
+        let var = self.path(place, &mut steps, &mut indices);
+        let mut saved: Vec<Var> = Vec::new();
+        for i in indices {
+            self.expr(i);
+            let s = Var::Local(self.slot(i.vtype.unwrap()));
+            self.emit(Op::Store(s));
+            saved.push(s);
+        }
+        let p = self.place_index(Place { var, steps });
+        for s in saved.iter() {
+            self.emit(Op::Load(*s));
+        }
+        self.emit(Op::GetPlace(p));
+        self.emit(Op::Load(x));
+        self.emit(op);
+        for s in saved.iter() {
+            self.emit(Op::Load(*s));
+        }
+        self.emit(Op::SetPlace(p, tp as u32));
+    }
+
+    fn expr(&mut self, ast: &AST) {
+        match ast.tp {
+            ASTtype::Literal => {
+                let c = match ast.token.tp {
+                    TokenType::Number => Const::Int(number_value(&ast.token.s).unwrap()),
+                    TokenType::StringLiteral => Const::Str(ast.token.s.clone()),
+                    TokenType::CharLiteral => {
+                        Const::Int(ast.token.s.chars().next().unwrap() as i128)
+                    }
+                    TokenType::True => Const::Bool(true),
+                    _ => Const::Bool(false),
+                };
+                let c = self.constant(c);
+                self.emit(Op::Const(c));
+            }
+            ASTtype::Ident => {
+                let v = self.var(&ast.token.s);
+                self.emit(Op::Load(v));
+            }
+            ASTtype::Unary => {
+                let x = &ast.next[0];
+                if ast.token.tp == TokenType::Minus && x.tp == ASTtype::Literal {
+                    let c = self.constant(Const::Int(-number_value(&x.token.s).unwrap()));
+                    self.emit(Op::Const(c));
+                    return;
+                }
+                let tp = ast.vtype.unwrap();
+                self.expr(x);
+                match (ast.token.tp, self.table.kind(tp)) {
+                    (TokenType::Bang, TypeKind::Bool) => self.emit(Op::Not),
+                    (TokenType::Bang, _) => self.emit(Op::BitNot(int_ty(self.table, tp))),
+                    _ => self.emit(Op::Neg(int_ty(self.table, tp))),
+                };
+            }
+            ASTtype::Binary => self.binary(ast),
+            ASTtype::CallFunction => self.call_function(ast),
+            ASTtype::CallMethod => self.call_method(ast),
+            ASTtype::Index | ASTtype::Field if Self::is_place(ast) => {
+                //copied out of the variable, which stays where it is
+                let p = self.place(ast);
+                self.emit(Op::GetPlace(p));
+            }
+            ASTtype::Index => {
+                self.borrow(&ast.next[0]);
+                self.expr(&ast.next[1]);
+                self.emit(Op::Index);
+            }
+            ASTtype::Field => {
+                self.borrow(&ast.next[0]);
+                let i = self.field_index(&ast.next[0], &ast.token.s);
+                self.emit(Op::Field(i));
+            }
+            ASTtype::Array => {
+                for x in ast.next.iter() {
+                    self.value(x);
+                }
+                self.emit(Op::MakeVec(ast.next.len() as u32));
+            }
+            ASTtype::StructLiteral => {
+                let tp = ast.vtype.unwrap();
+                self.emit(Op::Zero(tp as u32));
+                for init in ast.next.iter() {
+                    self.value(&init.next[0]);
+                    let i = match self.table.kind(tp) {
+                        TypeKind::Struct(fields) => {
+                            fields.iter().position(|x| x.name == init.token.s)
+                        }
+                        _ => None,
+                    };
+                    self.emit(Op::SetField(i.unwrap() as u32));
+                }
+            }
+            ASTtype::Variant => {
+                for x in ast.next.iter() {
+                    self.value(x);
+                }
+                let n = ast.next.len() as u32;
+                self.emit(Op::MakeEnum(ast.sym.unwrap() as u32, n));
+            }
+            ASTtype::Match => self.match_value(ast, true),
+            _ => unreachable!("{:?} is not an expression", ast.tp),
+        }
+    }
+
+    fn binary(&mut self, ast: &AST) {
+        //the right side only if the left does not decide
+        if matches!(ast.token.tp, TokenType::LogicAND | TokenType::LogicOR) {
+            self.borrow(&ast.next[0]);
+            self.emit(Op::Dup);
+            if ast.token.tp == TokenType::LogicOR {
+                self.emit(Op::Not);
+            }
+            let decided = self.emit(Op::JumpIfFalse(0));
+            self.emit(Op::Pop);
+            self.borrow(&ast.next[1]);
+            self.patch(decided, self.here());
+            return;
+        }
+
+        self.borrow(&ast.next[0]);
+        self.borrow(&ast.next[1]);
+        let op = match ast.token.tp {
+            TokenType::LogicEqual => Op::Cmp(CmpOp::Eq),
+            TokenType::LogicNotEqual => Op::Cmp(CmpOp::Ne),
+            TokenType::LeftABrack => Op::Cmp(CmpOp::Lt),
+            TokenType::RightABrack => Op::Cmp(CmpOp::Gt),
+            TokenType::LessEqual => Op::Cmp(CmpOp::Le),
+            TokenType::GreaterEqual => Op::Cmp(CmpOp::Ge),
+            _ if matches!(
+                self.table.kind(ast.next[0].vtype.unwrap()),
+                TypeKind::String
+            ) =>
+            {
+                Op::Concat
+            }
+            _ => {
+                let b = BinOp::from_symbol(&ast.token.s);
+                Op::Bin(b.unwrap(), int_ty(self.table, ast.vtype.unwrap()))
+            }
+        };
+        self.emit(op);
+    }
+
+    fn call(&mut self, sym: usize, args: &[AST]) {
+        for x in args.iter() {
+            self.value(x);
+        }
+        let f = self.functions[sym].unwrap_or_else(|| unreachable!("no body for {}", sym));
+        self.emit(Op::Call(f, args.len() as u32));
+    }
+
+    //how print shows a value of tp
+    fn print_kind(&self, tp: usize) -> PrintKind {
+        return match self.table.kind(tp) {
+            TypeKind::Char => PrintKind::Char,
+            TypeKind::Bool => PrintKind::Bool,
+            TypeKind::String => PrintKind::Str,
+            _ => PrintKind::Int,
+        };
+    }
+
+    fn call_function(&mut self, ast: &AST) {
+        if let Some(sym) = ast.sym {
+            return self.call(sym, &ast.next);
+        }
+        match ast.token.s.as_str() {
+            "assert" => {
+                self.expr(&ast.next[0]);
+                let msg = self.constant(Const::Str(assert_message(self.table, ast)));
+                self.emit(Op::Assert(msg));
+            }
+            "assert_eq" => {
+                self.borrow(&ast.next[0]);
+                self.borrow(&ast.next[1]);
+                let kind = self.print_kind(ast.next[0].vtype.unwrap());
+                let msg = self.constant(Const::Str(assert_message(self.table, ast)));
+                self.emit(Op::AssertEq(msg, kind));
+            }
+            "print" => {
+                let x = &ast.next[0];
+                self.borrow(x);
+                let kind = self.print_kind(x.vtype.unwrap());
+                self.emit(Op::Print(kind));
+            }
+            "Vec::new" => {
+                self.emit(Op::MakeVec(0));
+            }
+            //Vec::new_init
+            _ => self.value(&ast.next[0]),
+        }
+    }
+
+    fn call_method(&mut self, ast: &AST) {
+        if let Some(sym) = ast.sym {
+            return self.call(sym, &ast.next);
+        }
+        let recv = &ast.next[0];
+        if ast.token.s == "len" {
+            match Self::is_place(recv) {
+                true => {
+                    let p = self.place(recv);
+                    self.emit(Op::LenPlace(p));
+                }
+                false => {
+                    self.borrow(recv);
+                    self.emit(Op::Len);
+                }
+            }
+            return;
+        }
+        //push
+        self.value(&ast.next[1]);
+        let p = self.place(recv);
+        self.emit(Op::PushPlace(p));
+    }
+
+    //a match, kept in a slot the arms are tested against in order. a match
+    //expression leaves the value of its arm
+    fn match_value(&mut self, ast: &AST, expr: bool) {
+        let tp = ast.next[0].vtype.unwrap();
+        let dom = domain_of(self.table, tp).unwrap();
+
+        self.open_scope();
+        self.borrow(&ast.next[0]);
+        let m = self.slot(tp);
+        self.emit(Op::Store(Var::Local(m)));
+        let mut ends: Vec<usize> = Vec::new();
+        for case in ast.next[1..].iter() {
+            self.pats
+                .push(pattern_of(&case.next[0], &dom, self.table).unwrap());
+            self.emit(Op::Test(Var::Local(m), self.pats.len() as u32 - 1));
+            let next = self.emit(Op::JumpIfFalse(0));
+
+            self.open_scope();
+            self.bind(&case.next[0], m, &mut Vec::new());
+            let body = &case.next[1];
+            match body.tp {
+                ASTtype::CodeBlock => {
+                    self.stmts(&body.next);
+                    if expr {
+                        self.emit(Op::Zero(TYPE_VOID as u32));
+                    }
+                }
+                _ if expr => self.value(body),
+                _ => {
+                    self.borrow(body);
+                    self.emit(Op::Pop);
+                }
+            }
+            self.close_scope();
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(next, self.here());
+        }
+        //no arm matched, ruled out by the checker. a value all the same, so
+        //the stack is as deep on every path
+        if expr {
+            self.emit(Op::Zero(ast.vtype.unwrap() as u32));
+        }
+        for at in ends {
+            self.patch(at, self.here());
+        }
+        self.close_scope();
+    }
+
+    //declare the bindings of a pattern, copied out of the matched value
+    fn bind(&mut self, pat: &AST, m: u32, steps: &mut Vec<Step>) {
+        match pat.tp {
+            ASTtype::PatBinding => {
+                let p = self.place_index(Place {
+                    var: Var::Local(m),
+                    steps: steps.clone(),
+                });
+                self.emit(Op::GetPlace(p));
+                let slot = self.declare(&pat.token.s, pat.vtype.unwrap());
+                self.emit(Op::Store(Var::Local(slot)));
+            }
+            ASTtype::PatVariant => {
+                for (i, sub) in pat.next.iter().enumerate() {
+                    steps.push(Step::Payload(i as u32));
+                    self.bind(sub, m, steps);
+                    steps.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
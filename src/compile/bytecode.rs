@@ -0,0 +1,1074 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::matching::Pat;
+
+/*
+the bytecode of a program, as compiled by bcgen and run by vm, and its .rfxc
+file format. everything is little endian:
+
+    "RFXC"  u16 version  u16 flags (0)
+    constants   u32 count, each a tag byte and the value
+    types       u32 count, each a name, a description and its `drop` function
+    globals     u32 count, each a name and a type
+    functions   u32 count, each a name, the number of args, the types of its
+                slots, the places and patterns its code refers to, the code
+    u32 entry   the function of the top level statements
+
+a function keeps its arguments and variables in numbered slots. an
+instruction takes its operands from the stack of the function and pushes its
+result there. a slot or a place (a variable and a path into it, a field, an
+element or an enum payload) is only named by index, the instruction says what
+is done with it: copied out (Load, GetPlace), moved out (Move), set (Store),
+set and the old value dropped (Assign, SetPlace) or dropped (Drop)
+*/
+
+pub const MAGIC: &[u8; 4] = b"RFXC";
+pub const VERSION: u16 = 1;
+pub const EXTENSION: &str = "rfxc";
+
+//width and signedness integer operations wrap at
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct IntTy {
+    pub bits: u8,
+    pub signed: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+//how print shows a value
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PrintKind {
+    Int,
+    Bool,
+    Char,
+    Str,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Const {
+    Int(i128),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Var {
+    Local(u32),
+    Global(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    Field(u32),
+    Index, // the index is taken from the stack
+    Payload(u32),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Place {
+    pub var: Var,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TypeDesc {
+    Void,
+    Int(IntTy),
+    Bool,
+    Char,
+    Str,
+    Vec(u32),
+    Struct(Vec<u32>),
+    Enum(Vec<Vec<u32>>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Type {
+    pub name: String,
+    pub desc: TypeDesc,
+    pub drop: Option<u32>, // the user `drop` function
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Const(u32),
+    Pop,
+    Dup,
+    Load(Var),
+    Move(Var),
+    Store(Var),
+    Assign(Var),
+    Drop(Var),
+    DropFields(Var), // the `self` of `drop`
+    Inc(Var),        // a loop counter, does not wrap
+    GetPlace(u32),
+    SetPlace(u32, u32), // place, type of the value
+    PushPlace(u32),
+    LenPlace(u32),
+    Zero(u32), // a value of the type with everything zero
+    SetField(u32),
+    MakeVec(u32),
+    MakeEnum(u32, u32), // variant, number of payload values
+    Field(u32),
+    Index,
+    Len,
+    Bin(BinOp, IntTy),
+    Cmp(CmpOp),
+    Neg(IntTy),
+    BitNot(IntTy),
+    Not,
+    Concat,
+    Test(Var, u32), // push whether the variable matches the pattern
+    Jump(u32),
+    JumpIfFalse(u32),
+    Call(u32, u32), // function, number of args
+    Return,
+    Print(PrintKind),
+    Assert(u32),              // pop a bool, if false panic with the string constant
+    AssertEq(u32, PrintKind), // pop two values, if they differ panic with the constant and them
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Function {
+    pub name: String,
+    pub args: u32,
+    pub locals: Vec<u32>, // the type of each slot, the args first
+    pub places: Vec<Place>,
+    pub pats: Vec<Pat>,
+    pub code: Vec<Op>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Program {
+    pub consts: Vec<Const>,
+    pub types: Vec<Type>,
+    pub globals: Vec<(String, u32)>,
+    pub functions: Vec<Function>,
+    pub entry: u32,
+}
+
+//true if the bytes start like a .rfxc file
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    return bytes.starts_with(MAGIC);
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        return match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::And => "&",
+            BinOp::Or => "|",
+            BinOp::Xor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+        };
+    }
+
+    pub fn from_symbol(s: &str) -> Option<Self> {
+        return [
+            BinOp::Add,
+            BinOp::Sub,
+            BinOp::Mul,
+            BinOp::Div,
+            BinOp::Rem,
+            BinOp::And,
+            BinOp::Or,
+            BinOp::Xor,
+            BinOp::Shl,
+            BinOp::Shr,
+        ]
+        .into_iter()
+        .find(|x| x.symbol() == s);
+    }
+}
+
+impl CmpOp {
+    pub fn symbol(self) -> &'static str {
+        return match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Gt => ">",
+            CmpOp::Le => "<=",
+            CmpOp::Ge => ">=",
+        };
+    }
+}
+
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, x: u8) {
+        self.out.push(x);
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.out.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    fn list(&mut self, xs: &[u32]) {
+        self.u32(xs.len() as u32);
+        for x in xs.iter() {
+            self.u32(*x);
+        }
+    }
+
+    fn var(&mut self, v: Var) {
+        match v {
+            Var::Local(x) => {
+                self.u8(0);
+                self.u32(x);
+            }
+            Var::Global(x) => {
+                self.u8(1);
+                self.u32(x);
+            }
+        }
+    }
+
+    fn int_ty(&mut self, t: IntTy) {
+        self.u8(t.bits);
+        self.u8(t.signed as u8);
+    }
+
+    fn pat(&mut self, p: &Pat) {
+        match p {
+            Pat::Wild => self.u8(0),
+            Pat::Range(a, b) => {
+                self.u8(1);
+                self.out.extend_from_slice(&a.to_le_bytes());
+                self.out.extend_from_slice(&b.to_le_bytes());
+            }
+            Pat::Str(s) => {
+                self.u8(2);
+                self.str(s);
+            }
+            Pat::Or(alts) => {
+                self.u8(3);
+                self.u32(alts.len() as u32);
+                for x in alts.iter() {
+                    self.pat(x);
+                }
+            }
+            Pat::Variant(i, subs) => {
+                self.u8(4);
+                self.u32(*i as u32);
+                self.u32(subs.len() as u32);
+                for x in subs.iter() {
+                    self.pat(x);
+                }
+            }
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        self.u8(op.code());
+        match *op {
+            Op::Pop | Op::Dup | Op::Index | Op::Len | Op::Not | Op::Concat | Op::Return => {}
+            Op::Const(x)
+            | Op::GetPlace(x)
+            | Op::PushPlace(x)
+            | Op::LenPlace(x)
+            | Op::Zero(x)
+            | Op::SetField(x)
+            | Op::MakeVec(x)
+            | Op::Field(x)
+            | Op::Jump(x)
+            | Op::JumpIfFalse(x)
+            | Op::Assert(x) => self.u32(x),
+            Op::SetPlace(x, y) | Op::MakeEnum(x, y) | Op::Call(x, y) => {
+                self.u32(x);
+                self.u32(y);
+            }
+            Op::Load(v)
+            | Op::Move(v)
+            | Op::Store(v)
+            | Op::Assign(v)
+            | Op::Drop(v)
+            | Op::DropFields(v)
+            | Op::Inc(v) => self.var(v),
+            Op::Test(v, x) => {
+                self.var(v);
+                self.u32(x);
+            }
+            Op::Bin(b, t) => {
+                self.u8(b as u8);
+                self.int_ty(t);
+            }
+            Op::Cmp(c) => self.u8(c as u8),
+            Op::Neg(t) | Op::BitNot(t) => self.int_ty(t),
+            Op::Print(k) => self.u8(k as u8),
+            Op::AssertEq(x, k) => {
+                self.u32(x);
+                self.u8(k as u8);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+const TRUNCATED: &str = "truncated bytecode";
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < n {
+            return Err(TRUNCATED.to_string());
+        }
+        self.pos += n;
+        return Ok(&self.bytes[self.pos - n..self.pos]);
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        return Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()));
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn i128(&mut self) -> Result<i128, String> {
+        return Ok(i128::from_le_bytes(self.take(16)?.try_into().unwrap()));
+    }
+
+    //a count of things at least min bytes each, checked against what is left
+    //so a broken file cannot make us allocate much
+    fn count(&mut self, min: usize) -> Result<usize, String> {
+        let n = self.u32()? as usize;
+        if n.saturating_mul(min) > self.bytes.len() - self.pos {
+            return Err(TRUNCATED.to_string());
+        }
+        return Ok(n);
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let n = self.count(1)?;
+        return String::from_utf8(self.take(n)?.to_vec())
+            .map_err(|_| "invalid string in bytecode".to_string());
+    }
+
+    fn list(&mut self) -> Result<Vec<u32>, String> {
+        let n = self.count(4)?;
+        return (0..n).map(|_| self.u32()).collect();
+    }
+
+    fn var(&mut self) -> Result<Var, String> {
+        return match self.u8()? {
+            0 => Ok(Var::Local(self.u32()?)),
+            1 => Ok(Var::Global(self.u32()?)),
+            x => Err(format!("invalid variable kind {}", x)),
+        };
+    }
+
+    fn int_ty(&mut self) -> Result<IntTy, String> {
+        let bits = self.u8()?;
+        if ![8, 16, 32, 64].contains(&bits) {
+            return Err(format!("invalid integer width {}", bits));
+        }
+        return Ok(IntTy {
+            bits,
+            signed: self.u8()? != 0,
+        });
+    }
+
+    fn print_kind(&mut self) -> Result<PrintKind, String> {
+        let kinds = [
+            PrintKind::Int,
+            PrintKind::Bool,
+            PrintKind::Char,
+            PrintKind::Str,
+        ];
+        let k = self.u8()? as usize;
+        return Ok(*kinds.get(k).ok_or(format!("invalid print kind {}", k))?);
+    }
+
+    fn pat(&mut self) -> Result<Pat, String> {
+        return match self.u8()? {
+            0 => Ok(Pat::Wild),
+            1 => Ok(Pat::Range(self.i128()?, self.i128()?)),
+            2 => Ok(Pat::Str(self.str()?)),
+            3 => {
+                let n = self.count(1)?;
+                Ok(Pat::Or(
+                    (0..n).map(|_| self.pat()).collect::<Result<_, _>>()?,
+                ))
+            }
+            4 => {
+                let i = self.u32()? as usize;
+                let n = self.count(1)?;
+                Ok(Pat::Variant(
+                    i,
+                    (0..n).map(|_| self.pat()).collect::<Result<_, _>>()?,
+                ))
+            }
+            x => Err(format!("invalid pattern kind {}", x)),
+        };
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        let code = self.u8()?;
+        let op = match code {
+            0 => Op::Const(self.u32()?),
+            1 => Op::Pop,
+            2 => Op::Dup,
+            3 => Op::Load(self.var()?),
+            4 => Op::Move(self.var()?),
+            5 => Op::Store(self.var()?),
+            6 => Op::Assign(self.var()?),
+            7 => Op::Drop(self.var()?),
+            8 => Op::DropFields(self.var()?),
+            9 => Op::Inc(self.var()?),
+            10 => Op::GetPlace(self.u32()?),
+            11 => Op::SetPlace(self.u32()?, self.u32()?),
+            12 => Op::PushPlace(self.u32()?),
+            13 => Op::LenPlace(self.u32()?),
+            14 => Op::Zero(self.u32()?),
+            15 => Op::SetField(self.u32()?),
+            16 => Op::MakeVec(self.u32()?),
+            17 => Op::MakeEnum(self.u32()?, self.u32()?),
+            18 => Op::Field(self.u32()?),
+            19 => Op::Index,
+            20 => Op::Len,
+            21 => {
+                let ops = [
+                    BinOp::Add,
+                    BinOp::Sub,
+                    BinOp::Mul,
+                    BinOp::Div,
+                    BinOp::Rem,
+                    BinOp::And,
+                    BinOp::Or,
+                    BinOp::Xor,
+                    BinOp::Shl,
+                    BinOp::Shr,
+                ];
+                let b = self.u8()? as usize;
+                let b = *ops.get(b).ok_or(format!("invalid operator {}", b))?;
+                Op::Bin(b, self.int_ty()?)
+            }
+            22 => {
+                let ops = [
+                    CmpOp::Eq,
+                    CmpOp::Ne,
+                    CmpOp::Lt,
+                    CmpOp::Gt,
+                    CmpOp::Le,
+                    CmpOp::Ge,
+                ];
+                let c = self.u8()? as usize;
+                Op::Cmp(*ops.get(c).ok_or(format!("invalid comparison {}", c))?)
+            }
+            23 => Op::Neg(self.int_ty()?),
+            24 => Op::BitNot(self.int_ty()?),
+            25 => Op::Not,
+            26 => Op::Concat,
+            27 => Op::Test(self.var()?, self.u32()?),
+            28 => Op::Jump(self.u32()?),
+            29 => Op::JumpIfFalse(self.u32()?),
+            30 => Op::Call(self.u32()?, self.u32()?),
+            31 => Op::Return,
+            32 => Op::Print(self.print_kind()?),
+            33 => Op::Assert(self.u32()?),
+            34 => Op::AssertEq(self.u32()?, self.print_kind()?),
+            x => return Err(format!("invalid opcode {}", x)),
+        };
+        return Ok(op);
+    }
+}
+
+impl Op {
+    fn code(&self) -> u8 {
+        return match self {
+            Op::Const(_) => 0,
+            Op::Pop => 1,
+            Op::Dup => 2,
+            Op::Load(_) => 3,
+            Op::Move(_) => 4,
+            Op::Store(_) => 5,
+            Op::Assign(_) => 6,
+            Op::Drop(_) => 7,
+            Op::DropFields(_) => 8,
+            Op::Inc(_) => 9,
+            Op::GetPlace(_) => 10,
+            Op::SetPlace(..) => 11,
+            Op::PushPlace(_) => 12,
+            Op::LenPlace(_) => 13,
+            Op::Zero(_) => 14,
+            Op::SetField(_) => 15,
+            Op::MakeVec(_) => 16,
+            Op::MakeEnum(..) => 17,
+            Op::Field(_) => 18,
+            Op::Index => 19,
+            Op::Len => 20,
+            Op::Bin(..) => 21,
+            Op::Cmp(_) => 22,
+            Op::Neg(_) => 23,
+            Op::BitNot(_) => 24,
+            Op::Not => 25,
+            Op::Concat => 26,
+            Op::Test(..) => 27,
+            Op::Jump(_) => 28,
+            Op::JumpIfFalse(_) => 29,
+            Op::Call(..) => 30,
+            Op::Return => 31,
+            Op::Print(_) => 32,
+            Op::Assert(_) => 33,
+            Op::AssertEq(..) => 34,
+        };
+    }
+}
+
+impl Program {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { out: Vec::new() };
+        w.out.extend_from_slice(MAGIC);
+        w.out.extend_from_slice(&VERSION.to_le_bytes());
+        w.out.extend_from_slice(&0u16.to_le_bytes());
+
+        w.u32(self.consts.len() as u32);
+        for c in self.consts.iter() {
+            match c {
+                Const::Int(x) => {
+                    w.u8(0);
+                    w.out.extend_from_slice(&x.to_le_bytes());
+                }
+                Const::Bool(x) => {
+                    w.u8(1);
+                    w.u8(*x as u8);
+                }
+                Const::Str(s) => {
+                    w.u8(2);
+                    w.str(s);
+                }
+            }
+        }
+
+        w.u32(self.types.len() as u32);
+        for t in self.types.iter() {
+            w.str(&t.name);
+            match &t.desc {
+                TypeDesc::Void => w.u8(0),
+                TypeDesc::Int(x) => {
+                    w.u8(1);
+                    w.int_ty(*x);
+                }
+                TypeDesc::Bool => w.u8(2),
+                TypeDesc::Char => w.u8(3),
+                TypeDesc::Str => w.u8(4),
+                TypeDesc::Vec(x) => {
+                    w.u8(5);
+                    w.u32(*x);
+                }
+                TypeDesc::Struct(fields) => {
+                    w.u8(6);
+                    w.list(fields);
+                }
+                TypeDesc::Enum(variants) => {
+                    w.u8(7);
+                    w.u32(variants.len() as u32);
+                    for v in variants.iter() {
+                        w.list(v);
+                    }
+                }
+            }
+            match t.drop {
+                None => w.u8(0),
+                Some(f) => {
+                    w.u8(1);
+                    w.u32(f);
+                }
+            }
+        }
+
+        w.u32(self.globals.len() as u32);
+        for (name, tp) in self.globals.iter() {
+            w.str(name);
+            w.u32(*tp);
+        }
+
+        w.u32(self.functions.len() as u32);
+        for f in self.functions.iter() {
+            w.str(&f.name);
+            w.u32(f.args);
+            w.list(&f.locals);
+            w.u32(f.places.len() as u32);
+            for p in f.places.iter() {
+                w.var(p.var);
+                w.u32(p.steps.len() as u32);
+                for s in p.steps.iter() {
+                    match s {
+                        Step::Field(x) => {
+                            w.u8(0);
+                            w.u32(*x);
+                        }
+                        Step::Index => w.u8(1),
+                        Step::Payload(x) => {
+                            w.u8(2);
+                            w.u32(*x);
+                        }
+                    }
+                }
+            }
+            w.u32(f.pats.len() as u32);
+            for p in f.pats.iter() {
+                w.pat(p);
+            }
+            w.u32(f.code.len() as u32);
+            for op in f.code.iter() {
+                w.op(op);
+            }
+        }
+        w.u32(self.entry);
+        return w.out;
+    }
+
+    //read a .rfxc file. the indices in it are checked, so running a broken
+    //or hand made file fails here rather than in the VM
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !is_bytecode(bytes) {
+            return Err("not an rfx bytecode file".to_string());
+        }
+        let mut r = Reader { bytes, pos: 4 };
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(format!(
+                "bytecode version {}, this rfx runs version {}",
+                version, VERSION
+            ));
+        }
+        r.u16()?;
+
+        let mut consts: Vec<Const> = Vec::new();
+        for _ in 0..r.count(2)? {
+            consts.push(match r.u8()? {
+                0 => Const::Int(r.i128()?),
+                1 => Const::Bool(r.u8()? != 0),
+                2 => Const::Str(r.str()?),
+                x => return Err(format!("invalid constant kind {}", x)),
+            });
+        }
+
+        let mut types: Vec<Type> = Vec::new();
+        for _ in 0..r.count(6)? {
+            let name = r.str()?;
+            let desc = match r.u8()? {
+                0 => TypeDesc::Void,
+                1 => TypeDesc::Int(r.int_ty()?),
+                2 => TypeDesc::Bool,
+                3 => TypeDesc::Char,
+                4 => TypeDesc::Str,
+                5 => TypeDesc::Vec(r.u32()?),
+                6 => TypeDesc::Struct(r.list()?),
+                7 => {
+                    let n = r.count(4)?;
+                    TypeDesc::Enum((0..n).map(|_| r.list()).collect::<Result<_, _>>()?)
+                }
+                x => return Err(format!("invalid type kind {}", x)),
+            };
+            let drop = match r.u8()? {
+                0 => None,
+                _ => Some(r.u32()?),
+            };
+            types.push(Type { name, desc, drop });
+        }
+
+        let mut globals: Vec<(String, u32)> = Vec::new();
+        for _ in 0..r.count(8)? {
+            globals.push((r.str()?, r.u32()?));
+        }
+
+        let mut functions: Vec<Function> = Vec::new();
+        for _ in 0..r.count(24)? {
+            let name = r.str()?;
+            let (args, locals) = (r.u32()?, r.list()?);
+            let mut places: Vec<Place> = Vec::new();
+            for _ in 0..r.count(9)? {
+                let var = r.var()?;
+                let mut steps: Vec<Step> = Vec::new();
+                for _ in 0..r.count(1)? {
+                    steps.push(match r.u8()? {
+                        0 => Step::Field(r.u32()?),
+                        1 => Step::Index,
+                        2 => Step::Payload(r.u32()?),
+                        x => return Err(format!("invalid place step {}", x)),
+                    });
+                }
+                places.push(Place { var, steps });
+            }
+            let n = r.count(1)?;
+            let pats = (0..n).map(|_| r.pat()).collect::<Result<_, _>>()?;
+            let n = r.count(1)?;
+            let code = (0..n).map(|_| r.op()).collect::<Result<_, _>>()?;
+            functions.push(Function {
+                name,
+                args,
+                locals,
+                places,
+                pats,
+                code,
+            });
+        }
+        let entry = r.u32()?;
+        if r.pos != bytes.len() {
+            return Err("trailing bytes after the bytecode".to_string());
+        }
+
+        let p = Program {
+            consts,
+            types,
+            globals,
+            functions,
+            entry,
+        };
+        p.validate()?;
+        return Ok(p);
+    }
+
+    //every index in range, every jump inside its function
+    fn validate(&self) -> Result<(), String> {
+        let nf = self.functions.len() as u32;
+        let nt = self.types.len() as u32;
+        if self.entry >= nf {
+            return Err("the entry function is missing".to_string());
+        }
+        for t in self.types.iter() {
+            let parts: Vec<u32> = match &t.desc {
+                TypeDesc::Vec(x) => vec![*x],
+                TypeDesc::Struct(fields) => fields.clone(),
+                TypeDesc::Enum(variants) => variants.iter().flatten().copied().collect(),
+                _ => Vec::new(),
+            };
+            if parts.iter().any(|x| *x >= nt) || t.drop.is_some_and(|f| f >= nf) {
+                return Err(format!("type `{}` refers past the tables", t.name));
+            }
+        }
+        if self.globals.iter().any(|(_, tp)| *tp >= nt) {
+            return Err("a global has an unknown type".to_string());
+        }
+
+        for f in self.functions.iter() {
+            let var_ok = |v: &Var| match v {
+                Var::Local(x) => (*x as usize) < f.locals.len(),
+                Var::Global(x) => (*x as usize) < self.globals.len(),
+            };
+            let bad = |what: &str| Err(format!("`{}` refers to a missing {}", f.name, what));
+            let bad_types = f.locals.iter().any(|t| *t >= nt);
+            if bad_types
+                || f.args as usize > f.locals.len()
+                || f.places.iter().any(|p| !var_ok(&p.var))
+            {
+                return bad("slot");
+            }
+            let n = f.code.len() as u32;
+            for op in f.code.iter() {
+                let ok = match *op {
+                    Op::Const(x) => (x as usize) < self.consts.len(),
+                    Op::Load(v)
+                    | Op::Move(v)
+                    | Op::Store(v)
+                    | Op::Assign(v)
+                    | Op::Drop(v)
+                    | Op::DropFields(v)
+                    | Op::Inc(v) => var_ok(&v),
+                    Op::Test(v, p) => var_ok(&v) && (p as usize) < f.pats.len(),
+                    Op::GetPlace(p) | Op::PushPlace(p) | Op::LenPlace(p) => {
+                        (p as usize) < f.places.len()
+                    }
+                    Op::SetPlace(p, t) => (p as usize) < f.places.len() && t < nt,
+                    Op::Zero(t) => t < nt,
+                    Op::Jump(x) | Op::JumpIfFalse(x) => x <= n,
+                    Op::Call(x, argc) => x < nf && self.functions[x as usize].args == argc,
+                    Op::Assert(x) | Op::AssertEq(x, _) => {
+                        matches!(self.consts.get(x as usize), Some(Const::Str(_)))
+                    }
+                    _ => true,
+                };
+                if !ok {
+                    return bad(&format!("operand in {:?}", op));
+                }
+            }
+            if let Some(pc) = underflow(f) {
+                return Err(format!("`{}` pops an empty stack at {}", f.name, pc));
+            }
+        }
+        return Ok(());
+    }
+}
+
+//the values an op pops and pushes
+fn stack_effect(f: &Function, op: Op) -> (usize, usize) {
+    let indices = |p: u32| {
+        let steps = &f.places[p as usize].steps;
+        return steps.iter().filter(|x| **x == Step::Index).count();
+    };
+    return match op {
+        Op::Const(_) | Op::Load(_) | Op::Move(_) | Op::Zero(_) | Op::Test(..) => (0, 1),
+        Op::Drop(_) | Op::DropFields(_) | Op::Inc(_) | Op::Jump(_) => (0, 0),
+        Op::Pop | Op::Store(_) | Op::Assign(_) | Op::JumpIfFalse(_) | Op::Return => (1, 0),
+        Op::Dup => (1, 2),
+        Op::Field(_) | Op::Len | Op::Neg(_) | Op::BitNot(_) | Op::Not => (1, 1),
+        Op::Print(_) | Op::Assert(_) => (1, 1),
+        Op::SetField(_) | Op::Index | Op::Bin(..) | Op::Cmp(_) | Op::Concat => (2, 1),
+        Op::AssertEq(..) => (2, 1),
+        Op::GetPlace(p) | Op::LenPlace(p) => (indices(p), 1),
+        Op::SetPlace(p, _) => (indices(p) + 1, 0),
+        Op::PushPlace(p) => (indices(p) + 1, 1),
+        Op::MakeVec(n) | Op::MakeEnum(_, n) | Op::Call(_, n) => (n as usize, 1),
+    };
+}
+
+//the first op of f that may pop more than its operand stack holds, None if
+//there is none. each op is checked against the least depth any path reaches
+//it with
+fn underflow(f: &Function) -> Option<usize> {
+    let mut depth: Vec<Option<usize>> = vec![None; f.code.len() + 1];
+    let mut work = vec![(0, 0)];
+    while let Some((pc, d)) = work.pop() {
+        if depth[pc].is_some_and(|x| x <= d) {
+            continue;
+        }
+        depth[pc] = Some(d);
+        let op = match f.code.get(pc) {
+            None => continue,
+            Some(x) => *x,
+        };
+        let (pops, pushes) = stack_effect(f, op);
+        if d < pops {
+            return Some(pc);
+        }
+        let next = d - pops + pushes;
+        match op {
+            Op::Return => {}
+            Op::Jump(x) => work.push((x as usize, next)),
+            Op::JumpIfFalse(x) => {
+                work.push((x as usize, next));
+                work.push((pc + 1, next));
+            }
+            _ => work.push((pc + 1, next)),
+        }
+    }
+    return None;
+}
+
+fn var_name(p: &Program, f: &Function, v: Var) -> String {
+    return match v {
+        Var::Local(x) if x < f.args => format!("arg{}", x),
+        Var::Local(x) => format!("s{}", x),
+        Var::Global(x) => format!("g{} {}", x, p.globals[x as usize].0),
+    };
+}
+
+fn place_text(p: &Program, f: &Function, i: u32) -> String {
+    let place = &f.places[i as usize];
+    let mut s = var_name(p, f, place.var);
+    for step in place.steps.iter() {
+        match step {
+            Step::Field(x) => s += &format!(".{}", x),
+            Step::Index => s += "[_]",
+            Step::Payload(x) => s += &format!(".payload{}", x),
+        }
+    }
+    return s;
+}
+
+fn int_ty_text(t: IntTy) -> String {
+    return format!("{}{}", if t.signed { "i" } else { "u" }, t.bits);
+}
+
+fn const_text(c: &Const) -> String {
+    return match c {
+        Const::Int(x) => x.to_string(),
+        Const::Bool(x) => x.to_string(),
+        Const::Str(s) => format!("{:?}", s),
+    };
+}
+
+//a listing of the program: its tables, then each function an instruction a
+//line, with its offset, the jump targets and what the operands refer to
+pub fn disassemble(p: &Program) -> String {
+    let mut out = format!("rfxc version {}\n", VERSION);
+    out += &format!("\nconstants: {}\n", p.consts.len());
+    for (i, c) in p.consts.iter().enumerate() {
+        out += &format!("  #{:<4} {}\n", i, const_text(c));
+    }
+    out += &format!("\ntypes: {}\n", p.types.len());
+    for (i, t) in p.types.iter().enumerate() {
+        let drop = match t.drop {
+            None => String::new(),
+            Some(f) => format!(", drop fn{} {}", f, p.functions[f as usize].name),
+        };
+        out += &format!("  t{:<4} {}{}\n", i, t.name, drop);
+    }
+    out += &format!("\nglobals: {}\n", p.globals.len());
+    for (i, (name, tp)) in p.globals.iter().enumerate() {
+        out += &format!("  g{:<4} {}: {}\n", i, name, p.types[*tp as usize].name);
+    }
+
+    for (fi, f) in p.functions.iter().enumerate() {
+        let entry = if fi as u32 == p.entry { ", entry" } else { "" };
+        out += &format!(
+            "\nfn{} {} ({} args, {} slots{})\n",
+            fi,
+            f.name,
+            f.args,
+            f.locals.len(),
+            entry
+        );
+        for (i, op) in f.code.iter().enumerate() {
+            let tname = |t: u32| p.types[t as usize].name.clone();
+            let text = match *op {
+                Op::Const(x) => format!("Const #{}  ; {}", x, const_text(&p.consts[x as usize])),
+                Op::Load(v) => format!("Load {}", var_name(p, f, v)),
+                Op::Move(v) => format!("Move {}", var_name(p, f, v)),
+                Op::Store(v) => format!("Store {}", var_name(p, f, v)),
+                Op::Inc(v) => format!("Inc {}", var_name(p, f, v)),
+                Op::Assign(v) => format!("Assign {}", var_name(p, f, v)),
+                Op::Drop(v) => format!("Drop {}", var_name(p, f, v)),
+                Op::DropFields(v) => format!("DropFields {}", var_name(p, f, v)),
+                Op::Test(v, x) => format!("Test {} {:?}", var_name(p, f, v), f.pats[x as usize]),
+                Op::GetPlace(x) => format!("GetPlace {}", place_text(p, f, x)),
+                Op::SetPlace(x, t) => format!("SetPlace {} : {}", place_text(p, f, x), tname(t)),
+                Op::PushPlace(x) => format!("PushPlace {}", place_text(p, f, x)),
+                Op::LenPlace(x) => format!("LenPlace {}", place_text(p, f, x)),
+                Op::Zero(t) => format!("Zero {}", tname(t)),
+                Op::Bin(b, t) => format!("Bin {} {}", b.symbol(), int_ty_text(t)),
+                Op::Neg(t) => format!("Neg {}", int_ty_text(t)),
+                Op::BitNot(t) => format!("BitNot {}", int_ty_text(t)),
+                Op::Jump(x) => format!("Jump {:04}", x),
+                Op::JumpIfFalse(x) => format!("JumpIfFalse {:04}", x),
+                Op::Call(x, n) => {
+                    format!("Call fn{} {} ({} args)", x, p.functions[x as usize].name, n)
+                }
+                Op::SetField(x) => format!("SetField {}", x),
+                Op::MakeVec(n) => format!("MakeVec {}", n),
+                Op::MakeEnum(x, n) => format!("MakeEnum variant {} ({} values)", x, n),
+                Op::Field(x) => format!("Field {}", x),
+                Op::Cmp(c) => format!("Cmp {}", c.symbol()),
+                Op::Print(k) => format!("Print {}", format!("{:?}", k).to_lowercase()),
+                Op::Assert(x) => format!("Assert #{}  ; {}", x, const_text(&p.consts[x as usize])),
+                Op::AssertEq(x, k) => format!(
+                    "AssertEq {} #{}  ; {}",
+                    format!("{:?}", k).to_lowercase(),
+                    x,
+                    const_text(&p.consts[x as usize])
+                ),
+                other => format!("{:?}", other),
+            };
+            out += &format!("  {:04}  {}\n", i, text);
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{bcgen, check_modules, Lints, Target};
+    use super::{disassemble, Program, VERSION};
+
+    fn compile(src: &str) -> Program {
+        let modules = [(String::new(), src.to_string())];
+        let (checked, diags) = check_modules(&modules, Target::host(), 1, Lints::NONE, None);
+        let (root, table) = checked.unwrap_or_else(|| panic!("{:?}", diags[0].msg));
+        return bcgen::compile(&root, &table);
+    }
+
+    #[test]
+    fn test_file_format() {
+        let p = compile(
+            "enum E { A(i64), B }\nfn f(e: E) -> bool {\n    return match e {\n        E::A(x) => x > 1 || x < -1,\n        E::B => false,\n    };\n}\nvar s: String = \"hi\";\nprint(f(E::A(2)));\nprint(s + \"hi\");",
+        );
+        let bytes = p.to_bytes();
+        assert_eq!(&bytes[..6], b"RFXC\x01\x00");
+        assert_eq!(Program::from_bytes(&bytes).unwrap(), p);
+
+        //the constant pool has each value once
+        let text = disassemble(&p);
+        assert!(text.contains("\nconstants: "));
+        let pool = &text[..text.find("\ntypes:").unwrap()];
+        assert_eq!(pool.matches("\"hi\"").count(), 1);
+        assert!(text.contains("fn0 f (1 args, 3 slots)\n  0000  Load arg0\n"));
+        assert!(text.contains("Test s1 Variant(0, [Wild])"));
+        assert!(text.contains("globals: 1\n  g0    s: String\n"));
+
+        let mut newer = bytes.clone();
+        newer[4] = VERSION as u8 + 1;
+        assert_eq!(
+            Program::from_bytes(&newer).err().unwrap(),
+            format!(
+                "bytecode version {}, this rfx runs version {}",
+                VERSION + 1,
+                VERSION
+            )
+        );
+        for n in [3, 8, bytes.len() / 2, bytes.len() - 1] {
+            assert!(Program::from_bytes(&bytes[..n]).is_err());
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(Program::from_bytes(&longer).is_err());
+        //a call past the functions is caught before anything runs
+        let mut broken = p.clone();
+        broken.functions[0].code.push(super::Op::Call(99, 0));
+        assert!(Program::from_bytes(&broken.to_bytes()).is_err());
+        //so is an op that pops more than its stack holds, on any path
+        let mut broken = p.clone();
+        broken.functions[0].code.insert(0, super::Op::SetField(0));
+        assert_eq!(
+            Program::from_bytes(&broken.to_bytes()).err().unwrap(),
+            "`f` pops an empty stack at 0"
+        );
+        let mut broken = p.clone();
+        broken.functions[0].code = vec![
+            super::Op::Const(0),
+            super::Op::JumpIfFalse(4),
+            super::Op::Const(0),
+            super::Op::Return,
+            super::Op::Return,
+        ];
+        assert_eq!(
+            Program::from_bytes(&broken.to_bytes()).err().unwrap(),
+            "`f` pops an empty stack at 4"
+        );
+    }
+}
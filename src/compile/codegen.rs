@@ -0,0 +1,1656 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::lex::{number_value, TokenType};
+use super::matching::{check_arms, domain_of, pattern_of, Domain, Pat};
+use super::ownership::is_projection;
+use super::parser::{SymbolTable, TypeKind, TYPE_BOOL, TYPE_CHAR, TYPE_STRING};
+use super::typeck::{assert_message, block_diverges, mangle};
+
+static RUNTIME: &str = r#"#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    const char *ptr;
+    uint64_t len;
+    uint64_t cap; // 0 if ptr is not owned, e.g. a literal
+} rfx_String;
+
+static inline void rfx_panic(const char *msg) {
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n", msg);
+    exit(101);
+}
+
+static inline void rfx_assert(bool ok, const char *msg) {
+    if (!ok)
+        rfx_panic(msg);
+}
+
+// integer division, checked as the interpreter does it. MIN / -1 wraps
+static inline int64_t rfx_div_i64(int64_t a, int64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    if (b == -1)
+        return (int64_t)(0 - (uint64_t)a);
+    return a / b;
+}
+
+static inline int64_t rfx_rem_i64(int64_t a, int64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    if (b == -1)
+        return 0;
+    return a % b;
+}
+
+static inline uint64_t rfx_div_u64(uint64_t a, uint64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    return a / b;
+}
+
+static inline uint64_t rfx_rem_u64(uint64_t a, uint64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    return a % b;
+}
+
+static inline rfx_String rfx_string_lit(const char *s, uint64_t len) {
+    rfx_String r = {s, len, 0};
+    return r;
+}
+
+static inline void rfx_drop_String(rfx_String s) {
+    if (s.cap)
+        free((char *)s.ptr);
+}
+
+static inline rfx_String rfx_clone_String(rfx_String s) {
+    if (s.cap == 0)
+        return s;
+    char *p = malloc(s.len + 1);
+    if (p == NULL)
+        rfx_panic("out of memory");
+    memcpy(p, s.ptr, s.len);
+    p[s.len] = '\0';
+    rfx_String r = {p, s.len, s.len + 1};
+    return r;
+}
+
+static inline rfx_String rfx_string_concat(rfx_String a, rfx_String b) {
+    char *p = malloc(a.len + b.len + 1);
+    if (p == NULL)
+        rfx_panic("out of memory");
+    memcpy(p, a.ptr, a.len);
+    memcpy(p + a.len, b.ptr, b.len);
+    p[a.len + b.len] = '\0';
+    rfx_String r = {p, a.len + b.len, a.len + b.len + 1};
+    return r;
+}
+
+static inline bool rfx_string_eq(rfx_String a, rfx_String b) {
+    return a.len == b.len && memcmp(a.ptr, b.ptr, a.len) == 0;
+}
+
+static inline void rfx_print_String(rfx_String s) {
+    fwrite(s.ptr, 1, s.len, stdout);
+    putchar('\n');
+}
+
+static inline void rfx_print_i64(int64_t x) {
+    printf("%lld\n", (long long)x);
+}
+
+static inline void rfx_print_u64(uint64_t x) {
+    printf("%llu\n", (unsigned long long)x);
+}
+
+static inline void rfx_print_bool(bool x) {
+    puts(x ? "true" : "false");
+}
+
+// c as utf-8 into buf, which ends with a NUL
+static inline void rfx_utf8(uint32_t c, char buf[5]) {
+    memset(buf, 0, 5);
+    if (c < 0x80) {
+        buf[0] = (char)c;
+    } else if (c < 0x800) {
+        buf[0] = (char)(0xC0 | (c >> 6));
+        buf[1] = (char)(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        buf[0] = (char)(0xE0 | (c >> 12));
+        buf[1] = (char)(0x80 | ((c >> 6) & 0x3F));
+        buf[2] = (char)(0x80 | (c & 0x3F));
+    } else {
+        buf[0] = (char)(0xF0 | (c >> 18));
+        buf[1] = (char)(0x80 | ((c >> 12) & 0x3F));
+        buf[2] = (char)(0x80 | ((c >> 6) & 0x3F));
+        buf[3] = (char)(0x80 | (c & 0x3F));
+    }
+}
+
+static inline void rfx_print_char(uint32_t c) {
+    char buf[5];
+    rfx_utf8(c, buf);
+    puts(buf);
+}
+
+// a failed assert_eq panics with its message and the values as print shows
+// them, the same as rfx_panic
+static inline void rfx_assert_eq_i64(int64_t l, int64_t r, const char *msg) {
+    if (l == r)
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %lld\n right: %lld\n", msg, (long long)l,
+            (long long)r);
+    exit(101);
+}
+
+static inline void rfx_assert_eq_u64(uint64_t l, uint64_t r, const char *msg) {
+    if (l == r)
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %llu\n right: %llu\n", msg, (unsigned long long)l,
+            (unsigned long long)r);
+    exit(101);
+}
+
+static inline void rfx_assert_eq_bool(bool l, bool r, const char *msg) {
+    if (l == r)
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %s\n right: %s\n", msg, l ? "true" : "false",
+            r ? "true" : "false");
+    exit(101);
+}
+
+static inline void rfx_assert_eq_char(uint32_t l, uint32_t r, const char *msg) {
+    if (l == r)
+        return;
+    char a[5], b[5];
+    rfx_utf8(l, a);
+    rfx_utf8(r, b);
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %s\n right: %s\n", msg, a, b);
+    exit(101);
+}
+
+static inline void rfx_assert_eq_String(rfx_String l, rfx_String r, const char *msg) {
+    if (rfx_string_eq(l, r))
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: ", msg);
+    fwrite(l.ptr, 1, l.len, stderr);
+    fprintf(stderr, "\n right: ");
+    fwrite(r.ptr, 1, r.len, stderr);
+    fprintf(stderr, "\n");
+    exit(101);
+}
+"#;
+
+//the runner of `rfx test`. each test runs in a child of its own, so a panic
+//or a crash fails only that test. those whose name has filter in it run
+static TEST_RUNTIME: &str = r#"
+#include <sys/wait.h>
+#include <unistd.h>
+
+typedef struct {
+    const char *name;
+    void (*run)(void);
+} rfx_Test;
+
+static int rfx_run_tests(const rfx_Test *tests, const char *filter) {
+    int total = 0, run = 0, passed = 0;
+    while (tests[total].name != NULL)
+        total++;
+    bool *failed = calloc(total + 1, sizeof(bool));
+    for (int i = 0; i < total; i++)
+        run += strstr(tests[i].name, filter) != NULL;
+    printf("running %d test%s\n", run, run == 1 ? "" : "s");
+    for (int i = 0; i < total; i++) {
+        if (strstr(tests[i].name, filter) == NULL)
+            continue;
+        fflush(stdout);
+        fflush(stderr);
+        pid_t pid = fork();
+        if (pid < 0)
+            rfx_panic("cannot fork");
+        if (pid == 0) {
+            tests[i].run();
+            exit(0);
+        }
+        int status = 0;
+        waitpid(pid, &status, 0);
+        if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+            printf("test %s ... ok\n", tests[i].name);
+            passed++;
+        } else if (WIFSIGNALED(status)) {
+            printf("test %s ... FAILED (signal %d)\n", tests[i].name, WTERMSIG(status));
+            failed[i] = true;
+        } else {
+            printf("test %s ... FAILED\n", tests[i].name);
+            failed[i] = true;
+        }
+    }
+    if (passed < run) {
+        printf("\nfailures:\n");
+        for (int i = 0; i < total; i++) {
+            if (failed[i])
+                printf("    %s\n", tests[i].name);
+        }
+    }
+    printf("\ntest result: %s. %d passed; %d failed; %d filtered out\n",
+           passed == run ? "ok" : "FAILED", passed, run - passed, total - run);
+    free(failed);
+    return passed == run ? 0 : 101;
+}
+"#;
+
+//switch case labels generated for one range pattern at most
+const MAX_CASE_RANGE: i128 = 16;
+
+//a C variable in scope. an owned one has a flag telling if it still holds
+//its value, and is dropped with the function when its scope ends
+struct Local {
+    name: String, //empty for temporaries
+    cname: String,
+    owned: Option<(String, String)>, // (flag, drop function)
+}
+
+struct Gen<'a> {
+    table: &'a SymbolTable,
+    out: String,
+    indent: usize,
+    tmp: usize,
+    scopes: Vec<Vec<Local>>,
+    pending: Vec<String>, // declarations of temporaries, written before the next line
+    loops: Vec<usize>,    // first scope inside each enclosing loop
+    fn_base: usize,       // first scope of the current function
+}
+
+pub fn generate(root: &AST, table: &SymbolTable) -> String {
+    return program(root, table, false);
+}
+
+//the program of `rfx test`: main runs the #[test] functions with the test
+//runner, after the module level variables are set. the other top level
+//statements do not run. the first argument is the filter
+pub fn generate_tests(root: &AST, table: &SymbolTable) -> String {
+    return program(root, table, true);
+}
+
+fn program(root: &AST, table: &SymbolTable, tests: bool) -> String {
+    let mut g = Gen {
+        table,
+        out: String::new(),
+        indent: 0,
+        tmp: 0,
+        scopes: vec![Vec::new()],
+        pending: Vec::new(),
+        loops: Vec::new(),
+        fn_base: 0,
+    };
+
+    g.out.push_str(RUNTIME);
+    if tests {
+        g.out.push_str(TEST_RUNTIME);
+    }
+    g.gen_types();
+    g.gen_prototypes();
+    g.gen_glue();
+    g.gen_globals(root);
+
+    for item in root.next.iter() {
+        match item.tp {
+            ASTtype::Function => g.gen_function(item),
+            ASTtype::Impl => {
+                for f in item.next[1..].iter() {
+                    g.gen_function(f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if tests {
+        g.line("static const rfx_Test rfx_tests[] = {");
+        for f in root.next.iter().filter(|x| x.has_attr("test")) {
+            let name = c_string(&f.next[1].token.s);
+            let cname = &table.function_name[f.sym.unwrap()].cname;
+            g.line(&format!("    {{{}, {}}},", name, cname));
+        }
+        g.line("    {NULL, NULL},");
+        g.line("};");
+        g.out.push('\n');
+        g.line("int main(int argc, char **argv) {");
+    } else {
+        g.line("int main(void) {");
+    }
+    g.indent += 1;
+    for item in root.next.iter() {
+        match item.tp {
+            ASTtype::Function
+            | ASTtype::Impl
+            | ASTtype::Trait
+            | ASTtype::TypeAlias
+            | ASTtype::Struct
+            | ASTtype::Enum
+            | ASTtype::Use => {}
+            ASTtype::Variable => g.gen_global_init(item),
+            _ if tests => {}
+            _ => g.gen_stmt(item),
+        }
+    }
+    if tests {
+        g.line("int failed = rfx_run_tests(rfx_tests, argc > 1 ? argv[1] : \"\");");
+        g.drop_scopes(0);
+        g.line("return failed;");
+    } else {
+        g.drop_scopes(0);
+        g.line("return 0;");
+    }
+    g.indent -= 1;
+    g.line("}");
+    return g.out;
+}
+
+//C string literal for the bytes of s
+fn c_string(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => ret.push_str("\\\""),
+            b'\\' => ret.push_str("\\\\"),
+            b'\n' => ret.push_str("\\n"),
+            b'\t' => ret.push_str("\\t"),
+            0x20..=0x7e => ret.push(b as char),
+            _ => ret.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    ret.push('"');
+    return ret;
+}
+
+fn has_loop_break(ast: &AST) -> bool {
+    return match ast.tp {
+        ASTtype::Break => true,
+        ASTtype::While | ASTtype::For => false,
+        _ => ast.next.iter().any(has_loop_break),
+    };
+}
+
+impl<'a> Gen<'a> {
+    fn line(&mut self, s: &str) {
+        for decl in std::mem::take(&mut self.pending) {
+            self.line(&decl);
+        }
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(s);
+        self.out.push('\n');
+    }
+
+    fn new_tmp(&mut self) -> String {
+        self.tmp += 1;
+        return format!("_t{}", self.tmp);
+    }
+
+    fn drop_fn(&self, tp: usize) -> String {
+        return format!("rfx_drop_{}", mangle(self.table.type_name(tp)));
+    }
+
+    fn clone_fn(&self, tp: usize) -> String {
+        return format!("rfx_clone_{}", mangle(self.table.type_name(tp)));
+    }
+
+    //add a local of type tp. an owned one gets its flag declared here
+    fn declare(&mut self, name: &str, tp: usize) -> String {
+        let shadow = self.scopes.iter().flatten().any(|l| l.name == name);
+        let cname = match shadow {
+            false => format!("v_{}", name),
+            true => format!("v_{}_{}", name, self.new_tmp()),
+        };
+        self.add_local(name, &cname, tp, self.drop_fn(tp));
+        return cname;
+    }
+
+    fn add_local(&mut self, name: &str, cname: &str, tp: usize, drop: String) {
+        let owned = match self.table.is_owned(tp) {
+            false => None,
+            true => {
+                let flag = self.new_tmp();
+                self.line(&format!("bool {} = true;", flag));
+                Some((flag, drop))
+            }
+        };
+        self.scopes.last_mut().unwrap().push(Local {
+            name: name.to_string(),
+            cname: cname.to_string(),
+            owned,
+        });
+    }
+
+    fn find_local(&self, name: &str) -> Option<&Local> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(l) = scope.iter().rev().find(|l| l.name == name) {
+                return Some(l);
+            }
+        }
+        return None;
+    }
+
+    fn lookup(&self, name: &str) -> String {
+        return match self.find_local(name) {
+            None => format!("v_{}", name),
+            Some(l) => l.cname.clone(),
+        };
+    }
+
+    //an owned value that nothing takes over lives in a temporary until the end of the block
+    fn temp(&mut self, value: String, tp: usize) -> String {
+        let (t, flag) = (self.new_tmp(), self.new_tmp());
+        self.pending
+            .push(format!("{} {} = {{0}};", self.ctype(tp), t));
+        self.pending.push(format!("bool {} = false;", flag));
+        let owned = Some((flag.clone(), self.drop_fn(tp)));
+        self.scopes.last_mut().unwrap().push(Local {
+            name: String::new(),
+            cname: t.clone(),
+            owned,
+        });
+        return format!("({} = {}, {} = true, {})", t, value, flag, t);
+    }
+
+    fn open_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn close_scope(&mut self, diverged: bool) {
+        if !diverged {
+            self.drop_scopes(self.scopes.len() - 1);
+        }
+        self.scopes.pop();
+    }
+
+    //drop the owned locals of scopes[from..], innermost first
+    fn drop_scopes(&mut self, from: usize) {
+        let mut lines: Vec<String> = Vec::new();
+        for scope in self.scopes[from..].iter().rev() {
+            for l in scope.iter().rev() {
+                if let Some((flag, drop)) = &l.owned {
+                    lines.push(format!("if ({}) {}({});", flag, drop, l.cname));
+                }
+            }
+        }
+        for x in lines.iter() {
+            self.line(x);
+        }
+    }
+
+    fn has_drops(&self, from: usize) -> bool {
+        return self.scopes[from..]
+            .iter()
+            .flatten()
+            .any(|l| l.owned.is_some());
+    }
+
+    //an expression whose value is taken over: a variable is moved out of, a
+    //field or an element is copied
+    fn gen_value(&mut self, ast: &AST) -> String {
+        let tp = ast.vtype.unwrap();
+        if !self.table.is_owned(tp) {
+            return self.gen_expr(ast);
+        }
+        if ast.tp == ASTtype::Ident {
+            if let Some(Local {
+                cname,
+                owned: Some((flag, _)),
+                ..
+            }) = self.find_local(&ast.token.s)
+            {
+                return format!("({} = false, {})", flag, cname);
+            }
+        }
+        if is_projection(ast) {
+            let v = self.gen_expr(ast);
+            return format!("{}({})", self.clone_fn(tp), v);
+        }
+        return self.gen_expr(ast);
+    }
+
+    //an expression that is only read. a new owned value goes to a temporary
+    fn gen_borrow(&mut self, ast: &AST) -> String {
+        let v = self.gen_expr(ast);
+        let tp = ast.vtype.unwrap();
+        if !self.table.is_owned(tp)
+            || matches!(
+                ast.tp,
+                ASTtype::Ident | ASTtype::Field | ASTtype::Index | ASTtype::Literal
+            )
+        {
+            return v;
+        }
+        return self.temp(v, tp);
+    }
+
+    fn ctype(&self, tp: usize) -> String {
+        let v = &self.table.variable_type[tp];
+        return match v.kind {
+            TypeKind::Void => "void".to_string(),
+            TypeKind::Integer { signed: true } => format!("int{}_t", v.size * 8),
+            TypeKind::Integer { signed: false } => format!("uint{}_t", v.size * 8),
+            TypeKind::Bool => "bool".to_string(),
+            TypeKind::Char => "uint32_t".to_string(),
+            TypeKind::String => "rfx_String".to_string(),
+            TypeKind::Vec(_) | TypeKind::Struct(_) | TypeKind::Enum(_) => {
+                format!("rfx_{}", mangle(&v.name))
+            }
+        };
+    }
+
+    //Vec, struct and enum types. all of them are declared first so they may
+    //refer to each other; a struct or enum comes after the types it holds by value
+    fn gen_types(&mut self) {
+        let mut order: Vec<usize> = Vec::new();
+        for (i, v) in self.table.variable_type.iter().enumerate() {
+            if matches!(
+                v.kind,
+                TypeKind::Vec(_) | TypeKind::Struct(_) | TypeKind::Enum(_)
+            ) {
+                let t = self.ctype(i);
+                self.out.push_str(&format!("typedef struct {t} {t};\n"));
+            }
+            self.type_order(i, &mut order);
+        }
+
+        for (i, v) in self.table.variable_type.iter().enumerate() {
+            if let TypeKind::Vec(x) = v.kind {
+                self.out.push_str(&format!(
+                    "\nstruct {} {{\n    {} *ptr;\n    uint64_t len;\n    uint64_t cap;\n}};\n",
+                    self.ctype(i),
+                    self.ctype(x)
+                ));
+            }
+        }
+        for i in order.iter() {
+            self.gen_user_type(*i);
+        }
+        for i in 0..self.table.variable_type.len() {
+            self.gen_vec_functions(i);
+        }
+        for i in order.iter() {
+            self.gen_constructors(*i);
+        }
+        self.out.push('\n');
+    }
+
+    //user types in an order where each comes after the types it holds by value
+    fn type_order(&self, tp: usize, order: &mut Vec<usize>) {
+        if order.contains(&tp) {
+            return;
+        }
+        let held: Vec<usize> = match self.table.kind(tp) {
+            TypeKind::Struct(fields) => fields.iter().map(|x| x.tp).collect(),
+            TypeKind::Enum(variants) => variants.iter().flat_map(|x| x.fields.clone()).collect(),
+            _ => return,
+        };
+        for x in held {
+            self.type_order(x, order);
+        }
+        order.push(tp);
+    }
+
+    fn gen_user_type(&mut self, tp: usize) {
+        let t = self.ctype(tp);
+        self.out.push_str(&format!("\nstruct {} {{\n", t));
+        match self.table.kind(tp) {
+            TypeKind::Struct(fields) => {
+                for f in fields.iter() {
+                    let s = format!("    {} f_{};\n", self.ctype(f.tp), f.name);
+                    self.out.push_str(&s);
+                }
+                if fields.is_empty() {
+                    self.out.push_str("    char _unused;\n");
+                }
+            }
+            TypeKind::Enum(variants) => {
+                self.out.push_str("    uint32_t tag;\n");
+                if variants.iter().any(|v| !v.fields.is_empty()) {
+                    self.out.push_str("    union {\n");
+                    for v in variants.iter().filter(|v| !v.fields.is_empty()) {
+                        self.out.push_str("        struct {\n");
+                        for (k, f) in v.fields.iter().enumerate() {
+                            let s = format!("            {} f{};\n", self.ctype(*f), k);
+                            self.out.push_str(&s);
+                        }
+                        self.out.push_str(&format!("        }} v_{};\n", v.name));
+                    }
+                    self.out.push_str("    } u;\n");
+                }
+            }
+            _ => {}
+        }
+        self.out.push_str("};\n");
+    }
+
+    //a function per enum variant building the tagged value
+    fn gen_constructors(&mut self, tp: usize) {
+        let variants = match self.table.kind(tp) {
+            TypeKind::Enum(x) => x,
+            _ => return,
+        };
+        let t = self.ctype(tp);
+        for (i, v) in variants.iter().enumerate() {
+            let args: Vec<String> = v
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(k, f)| format!("{} f{}", self.ctype(*f), k))
+                .collect();
+            let args = match args.is_empty() {
+                true => "void".to_string(),
+                false => args.join(", "),
+            };
+            let mut s = format!(
+                "\nstatic inline {t} {t}__{}({}) {{\n    {t} r = {{0}};\n    r.tag = {};\n",
+                v.name, args, i
+            );
+            for k in 0..v.fields.len() {
+                s.push_str(&format!("    r.u.v_{}.f{k} = f{k};\n", v.name));
+            }
+            s.push_str("    return r;\n}\n");
+            self.out.push_str(&s);
+        }
+    }
+
+    fn gen_vec_functions(&mut self, tp: usize) {
+        let elem = match self.table.kind(tp) {
+            TypeKind::Vec(x) => self.ctype(*x),
+            _ => return,
+        };
+        let vt = self.ctype(tp);
+
+        self.out.push_str(&format!(
+            r#"
+static inline {v} {v}_new(void) {{
+    {v} r = {{NULL, 0, 0}};
+    return r;
+}}
+
+static inline void {v}_push({v} *v, {e} x) {{
+    if (v->len == v->cap) {{
+        v->cap = v->cap ? v->cap * 2 : 4;
+        v->ptr = realloc(v->ptr, v->cap * sizeof({e}));
+        if (v->ptr == NULL)
+            rfx_panic("out of memory");
+    }}
+    v->ptr[v->len++] = x;
+}}
+
+static inline {v} {v}_from(const {e} *x, uint64_t n) {{
+    {v} r = {v}_new();
+    for (uint64_t i = 0; i < n; i++)
+        {v}_push(&r, x[i]);
+    return r;
+}}
+
+static inline {e} *{v}_at({v} v, uint64_t i) {{
+    if (i >= v.len)
+        rfx_panic("index out of bounds");
+    return &v.ptr[i];
+}}
+"#,
+            e = elem,
+            v = vt
+        ));
+    }
+
+    fn signature(&self, sym: usize) -> String {
+        let f = &self.table.function_name[sym];
+        let args: Vec<String> = f
+            .args
+            .iter()
+            .map(|a| format!("{} v_{}", self.ctype(a.tp), a.name))
+            .collect();
+        let args = match args.is_empty() {
+            true => "void".to_string(),
+            false => args.join(", "),
+        };
+        return format!("{} {}({})", self.ctype(f.return_tp), f.cname, args);
+    }
+
+    fn gen_prototypes(&mut self) {
+        for sym in 0..self.table.function_name.len() {
+            let s = self.signature(sym) + ";";
+            self.line(&s);
+        }
+        if !self.table.function_name.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    //drop and clone functions of every owned type but String, which the
+    //runtime has
+    fn gen_glue(&mut self) {
+        let owned: Vec<usize> = (0..self.table.variable_type.len())
+            .filter(|x| *x != TYPE_STRING && self.table.is_owned(*x))
+            .collect();
+        for tp in owned.iter() {
+            let (t, drop, clone) = (self.ctype(*tp), self.drop_fn(*tp), self.clone_fn(*tp));
+            self.line(&format!("static inline void {}({} v);", drop, t));
+            self.line(&format!("static inline {} {}({} v);", t, clone, t));
+        }
+        for tp in owned.iter() {
+            self.gen_glue_functions(*tp);
+        }
+        if !owned.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    //`f` applied to each owned part of `v`, as lines of C
+    fn owned_parts(&self, tp: usize, f: &dyn Fn(&str, usize) -> String) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        match self.table.kind(tp) {
+            TypeKind::Struct(fields) => {
+                for x in fields.iter().filter(|x| self.table.is_owned(x.tp)) {
+                    lines.push(format!("    {}", f(&format!("f_{}", x.name), x.tp)));
+                }
+            }
+            TypeKind::Enum(variants) => {
+                lines.push("    switch (v.tag) {".to_string());
+                for (i, v) in variants.iter().enumerate() {
+                    let parts: Vec<(usize, &usize)> = v
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, x)| self.table.is_owned(**x))
+                        .collect();
+                    if parts.is_empty() {
+                        continue;
+                    }
+                    lines.push(format!("    case {}:", i));
+                    for (k, x) in parts {
+                        lines.push(format!(
+                            "        {}",
+                            f(&format!("u.v_{}.f{}", v.name, k), *x)
+                        ));
+                    }
+                    lines.push("        break;".to_string());
+                }
+                lines.push("    default:".to_string());
+                lines.push("        break;".to_string());
+                lines.push("    }".to_string());
+            }
+            _ => {}
+        }
+        return lines;
+    }
+
+    fn gen_glue_functions(&mut self, tp: usize) {
+        let (t, drop, clone) = (self.ctype(tp), self.drop_fn(tp), self.clone_fn(tp));
+
+        if let TypeKind::Vec(x) = self.table.kind(tp) {
+            let x = *x;
+            let (dx, cx) = match self.table.is_owned(x) {
+                true => (
+                    format!(
+                        "    for (uint64_t i = 0; i < v.len; i++)\n        {}(v.ptr[i]);\n",
+                        self.drop_fn(x)
+                    ),
+                    format!("{}(v.ptr[i])", self.clone_fn(x)),
+                ),
+                false => (String::new(), "v.ptr[i]".to_string()),
+            };
+            self.out.push_str(&format!(
+                r#"
+static inline void {drop}({t} v) {{
+{dx}    free(v.ptr);
+}}
+
+static inline {t} {clone}({t} v) {{
+    {t} r = {t}_new();
+    for (uint64_t i = 0; i < v.len; i++)
+        {t}_push(&r, {cx});
+    return r;
+}}
+"#
+            ));
+            return;
+        }
+
+        //the fields alone, also run after a user `Drop::drop`
+        let mut s = format!("\nstatic inline void {}_fields({} v) {{\n", drop, t);
+        let parts = self.owned_parts(tp, &|m, x| format!("{}(v.{});", self.drop_fn(x), m));
+        match parts.is_empty() {
+            true => s.push_str("    (void)v;\n"),
+            false => s.push_str(&(parts.join("\n") + "\n")),
+        }
+        s.push_str("}\n");
+
+        s.push_str(&format!("\nstatic inline void {}({} v) {{\n", drop, t));
+        match self.table.drop_impl(tp) {
+            Some(f) => s.push_str(&format!("    {}(v);\n", self.table.function_name[f].cname)),
+            None => s.push_str(&format!("    {}_fields(v);\n", drop)),
+        }
+        s.push_str("}\n");
+
+        s.push_str(&format!(
+            "\nstatic inline {t} {clone}({t} v) {{\n    {t} r = v;\n"
+        ));
+        let parts = self.owned_parts(tp, &|m, x| {
+            format!("r.{} = {}(v.{});", m, self.clone_fn(x), m)
+        });
+        if !parts.is_empty() {
+            s.push_str(&(parts.join("\n") + "\n"));
+        }
+        s.push_str("    return r;\n}\n");
+        self.out.push_str(&s);
+    }
+
+    //module level variables are C globals, so functions see them. main sets
+    //them in source order
+    fn gen_globals(&mut self, root: &AST) {
+        let mut any = false;
+        for item in root.next.iter().filter(|x| x.tp == ASTtype::Variable) {
+            let tp = item.vtype.unwrap();
+            let name = item.next[1].token.s.clone();
+            let cname = format!("v_{}", name);
+            self.line(&format!("static {} {};", self.ctype(tp), cname));
+            let owned = match self.table.is_owned(tp) {
+                false => None,
+                true => {
+                    let flag = self.new_tmp();
+                    self.line(&format!("static bool {} = false;", flag));
+                    Some((flag, self.drop_fn(tp)))
+                }
+            };
+            self.scopes[0].push(Local { name, cname, owned });
+            any = true;
+        }
+        if any {
+            self.out.push('\n');
+        }
+    }
+
+    fn gen_global_init(&mut self, item: &AST) {
+        let value = self.gen_value(&item.next[3]);
+        let l = self.scopes[0]
+            .iter()
+            .find(|l| l.name == item.next[1].token.s)
+            .unwrap();
+        let mut lines = vec![format!("{} = {};", l.cname, value)];
+        if let Some((flag, _)) = &l.owned {
+            lines.push(format!("{} = true;", flag));
+        }
+        for x in lines.iter() {
+            self.line(x);
+        }
+    }
+
+    fn gen_function(&mut self, f: &AST) {
+        let sym = match f.sym {
+            None => return,
+            Some(x) => x,
+        };
+        if f.next.len() < 5 {
+            return;
+        }
+
+        let s = self.signature(sym) + " {";
+        self.line(&s);
+        self.indent += 1;
+        self.fn_base = self.scopes.len();
+        self.open_scope();
+        let dropping = self.table.drop_impl.iter().find(|(_, f)| *f == sym);
+        for (i, a) in self.table.function_name[sym].args.iter().enumerate() {
+            let c = format!("v_{}", a.name);
+            //`self` of `Drop::drop` only drops its fields at the end
+            let drop = match dropping {
+                Some((tp, _)) if i == 0 => format!("{}_fields", self.drop_fn(*tp)),
+                _ => self.drop_fn(a.tp),
+            };
+            self.add_local(&a.name, &c, a.tp, drop);
+        }
+        let body = &f.next[4].next;
+        self.gen_stmts(body);
+        self.close_scope(block_diverges(body));
+        self.indent -= 1;
+        self.line("}");
+        self.out.push('\n');
+    }
+
+    fn gen_stmts(&mut self, stmts: &[AST]) {
+        self.open_scope();
+        for stmt in stmts.iter() {
+            self.gen_stmt(stmt);
+        }
+        self.close_scope(block_diverges(stmts));
+    }
+
+    fn gen_block(&mut self, ast: &AST) {
+        self.indent += 1;
+        self.gen_stmts(&ast.next);
+        self.indent -= 1;
+    }
+
+    fn gen_stmt(&mut self, ast: &AST) {
+        match ast.tp {
+            ASTtype::Variable => {
+                let tp = ast.vtype.unwrap();
+                let value = self.gen_value(&ast.next[3]);
+                let name = self.declare(&ast.next[1].token.s, tp);
+                let s = format!("{} {} = {};", self.ctype(tp), name, value);
+                self.line(&s);
+            }
+            ASTtype::Assign => self.gen_assign(ast),
+            ASTtype::CodeBlock => {
+                self.line("{");
+                self.gen_block(ast);
+                self.line("}");
+            }
+            ASTtype::If => {
+                let cond = self.gen_expr(&ast.next[0]);
+                self.gen_if(ast, &cond);
+            }
+            ASTtype::While => self.gen_while(ast),
+            ASTtype::For => self.gen_for(ast),
+            ASTtype::Match => self.gen_match(ast, None),
+            ASTtype::Return => match ast.next.first() {
+                None => {
+                    self.drop_scopes(self.fn_base);
+                    self.line("return;");
+                }
+                Some(x) => {
+                    let v = self.gen_value(x);
+                    if !self.has_drops(self.fn_base) {
+                        self.line(&format!("return {};", v));
+                        return;
+                    }
+                    //the value is computed before the locals go away
+                    let r = self.new_tmp();
+                    let s = format!("{} {} = {};", self.ctype(x.vtype.unwrap()), r, v);
+                    self.line(&s);
+                    self.drop_scopes(self.fn_base);
+                    self.line(&format!("return {};", r));
+                }
+            },
+            ASTtype::Break => {
+                self.drop_scopes(*self.loops.last().unwrap());
+                self.line("break;");
+            }
+            ASTtype::Continue => {
+                self.drop_scopes(*self.loops.last().unwrap());
+                self.line("continue;");
+            }
+            _ => {
+                let s = self.gen_borrow(ast) + ";";
+                self.line(&s);
+            }
+        }
+    }
+
+    fn gen_if(&mut self, ast: &AST, cond: &str) {
+        self.line(&format!("if ({}) {{", cond));
+        self.gen_else_chain(ast);
+        self.line("}");
+    }
+
+    //body of an if, then its else branch if any
+    fn gen_else_chain(&mut self, ast: &AST) {
+        self.gen_block(&ast.next[1]);
+        let other = match ast.next.get(2) {
+            None => return,
+            Some(x) => x,
+        };
+
+        if other.tp != ASTtype::If {
+            self.line("} else {");
+            self.gen_block(other);
+            return;
+        }
+
+        self.open_scope();
+        let cond = self.gen_expr(&other.next[0]);
+        if self.scopes.last().unwrap().is_empty() {
+            self.scopes.pop();
+            self.line(&format!("}} else if ({}) {{", cond));
+            self.gen_else_chain(other);
+            return;
+        }
+        //temporaries of the condition live in the else block
+        let decls = std::mem::take(&mut self.pending);
+        self.line("} else {");
+        self.indent += 1;
+        self.pending = decls;
+        self.gen_if(other, &cond);
+        self.close_scope(false);
+        self.indent -= 1;
+    }
+
+    fn gen_while(&mut self, ast: &AST) {
+        self.open_scope();
+        self.loops.push(self.scopes.len() - 1);
+        let cond = self.gen_expr(&ast.next[0]);
+        if self.scopes.last().unwrap().is_empty() {
+            self.line(&format!("while ({}) {{", cond));
+            self.gen_block(&ast.next[1]);
+        } else {
+            //temporaries of the condition are made and dropped each pass
+            let decls = std::mem::take(&mut self.pending);
+            self.line("while (1) {");
+            self.indent += 1;
+            self.pending = decls;
+            self.line(&format!("if (!({})) {{", cond));
+            self.indent += 1;
+            self.drop_scopes(self.scopes.len() - 1);
+            self.line("break;");
+            self.indent -= 1;
+            self.line("}");
+            self.gen_stmts(&ast.next[1].next);
+            self.drop_scopes(self.scopes.len() - 1);
+            self.indent -= 1;
+        }
+        self.line("}");
+        self.loops.pop();
+        self.scopes.pop();
+    }
+
+    fn gen_assign(&mut self, ast: &AST) {
+        let tp = ast.vtype.unwrap();
+        if self.table.is_owned(tp) {
+            self.gen_assign_owned(ast);
+            return;
+        }
+        let place = self.gen_expr(&ast.next[0]);
+        let value = self.gen_expr(&ast.next[1]);
+
+        if ast.token.tp == TokenType::Equal {
+            self.line(&format!("{} = {};", place, value));
+        } else if tp == TYPE_STRING {
+            self.line(&format!(
+                "{} = rfx_string_concat({}, {});",
+                place, place, value
+            ));
+        } else {
+            let op = ast.token.s.trim_end_matches('=');
+            let s = format!(
+                "{} = ({})({} {} {});",
+                place,
+                self.ctype(tp),
+                place,
+                op,
+                value
+            );
+            self.line(&s);
+        }
+    }
+
+    //the old value of the place is dropped once the new one is ready
+    fn gen_assign_owned(&mut self, ast: &AST) {
+        let tp = ast.vtype.unwrap();
+        let t = self.ctype(tp);
+        let (place, value) = (&ast.next[0], &ast.next[1]);
+        let n = self.new_tmp();
+
+        if place.tp == ASTtype::Ident {
+            let (cname, flag, drop) = match self.find_local(&place.token.s) {
+                Some(Local {
+                    cname,
+                    owned: Some((flag, drop)),
+                    ..
+                }) => (cname.clone(), flag.clone(), drop.clone()),
+                _ => unreachable!(),
+            };
+            let v = match ast.token.tp {
+                TokenType::Equal => self.gen_value(value),
+                _ => format!("rfx_string_concat({}, {})", cname, self.gen_borrow(value)),
+            };
+            self.line(&format!("{} {} = {};", t, n, v));
+            self.line(&format!("if ({}) {}({});", flag, drop, cname));
+            self.line(&format!("{} = {};", cname, n));
+            self.line(&format!("{} = true;", flag));
+            return;
+        }
+
+        let p = self.new_tmp();
+        if ast.token.tp == TokenType::Equal {
+            let v = self.gen_value(value);
+            self.line(&format!("{} {} = {};", t, n, v));
+            let s = format!("{} *{} = &{};", t, p, self.gen_expr(place));
+            self.line(&s);
+        } else {
+            let s = format!("{} *{} = &{};", t, p, self.gen_expr(place));
+            self.line(&s);
+            let v = self.gen_borrow(value);
+            self.line(&format!("{} {} = rfx_string_concat(*{}, {});", t, n, p, v));
+        }
+        self.line(&format!("{}(*{});", self.drop_fn(tp), p));
+        self.line(&format!("*{} = {};", p, n));
+    }
+
+    fn gen_for(&mut self, ast: &AST) {
+        let iter = &ast.next[1];
+        let tp = ast.next[0].vtype.unwrap();
+        let ct = self.ctype(tp);
+        let i = self.new_tmp();
+
+        self.line("{");
+        self.indent += 1;
+        self.open_scope();
+        if iter.tp == ASTtype::Binary && iter.token.tp == TokenType::DotDot {
+            let (a, b) = (self.gen_expr(&iter.next[0]), self.gen_expr(&iter.next[1]));
+            let e = self.new_tmp();
+            self.line(&format!(
+                "for ({ct} {i} = {a}, {e} = {b}; {i} < {e}; {i}++) {{"
+            ));
+        } else if iter.tp == ASTtype::Binary {
+            //inclusive, must not overflow past the end
+            let (a, b) = (self.gen_expr(&iter.next[0]), self.gen_expr(&iter.next[1]));
+            let (e, go) = (self.new_tmp(), self.new_tmp());
+            self.line(&format!(
+                "for ({ct} {i} = {a}, {e} = {b}, {go} = ({i} <= {e}); {go}; {go} = ({i} != {e}), {i} += {go}) {{"
+            ));
+        } else {
+            let v = self.new_tmp();
+            let vt = self.ctype(iter.vtype.unwrap());
+            let s = format!("{} {} = {};", vt, v, self.gen_borrow(iter));
+            self.line(&s);
+            let idx = self.new_tmp();
+            self.line(&format!(
+                "for (uint64_t {idx} = 0; {idx} < {v}.len; {idx}++) {{"
+            ));
+            //each element is copied out, the Vec keeps its own
+            let x = match self.table.is_owned(tp) {
+                true => format!("{}({}.ptr[{}])", self.clone_fn(tp), v, idx),
+                false => format!("{}.ptr[{}]", v, idx),
+            };
+            self.line(&format!("    {ct} {i} = {x};"));
+        }
+
+        self.indent += 1;
+        self.open_scope();
+        self.loops.push(self.scopes.len() - 1);
+        let name = self.declare(&ast.next[0].token.s, tp);
+        self.line(&format!("const {} {} = {};", ct, name, i));
+        let body = &ast.next[2].next;
+        self.gen_stmts(body);
+        self.loops.pop();
+        self.close_scope(block_diverges(body));
+        self.indent -= 1;
+        self.line("}");
+        self.close_scope(false);
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn int_literal(&self, value: i128, tp: usize) -> String {
+        return match self.table.kind(tp) {
+            TypeKind::Bool => (value != 0).to_string(),
+            TypeKind::Char => format!("((uint32_t){})", value),
+            TypeKind::Integer { signed: false } => format!("(({}){}ULL)", self.ctype(tp), value),
+            _ if value == i64::MIN as i128 => "INT64_MIN".to_string(),
+            _ => format!("(({}){}LL)", self.ctype(tp), value),
+        };
+    }
+
+    fn gen_expr(&mut self, ast: &AST) -> String {
+        match ast.tp {
+            ASTtype::Literal => match ast.token.tp {
+                TokenType::Number => {
+                    let value = number_value(&ast.token.s).unwrap();
+                    return self.int_literal(value, ast.vtype.unwrap());
+                }
+                TokenType::StringLiteral => {
+                    let s = &ast.token.s;
+                    return format!("rfx_string_lit({}, {})", c_string(s), s.len());
+                }
+                TokenType::CharLiteral => {
+                    let c = ast.token.s.chars().next().unwrap() as i128;
+                    return self.int_literal(c, TYPE_CHAR);
+                }
+                TokenType::True => return "true".to_string(),
+                _ => return "false".to_string(),
+            },
+            ASTtype::Ident => {
+                return self.lookup(&ast.token.s);
+            }
+            ASTtype::Unary => {
+                let x = &ast.next[0];
+                if ast.token.tp == TokenType::Minus && x.tp == ASTtype::Literal {
+                    let value = number_value(&x.token.s).unwrap();
+                    return self.int_literal(-value, ast.vtype.unwrap());
+                }
+                let v = self.gen_expr(x);
+                let tp = ast.vtype.unwrap();
+                return match (ast.token.tp, tp == TYPE_BOOL) {
+                    (TokenType::Bang, true) => format!("(!{})", v),
+                    (TokenType::Bang, false) => format!("(({})~{})", self.ctype(tp), v),
+                    _ => format!("(({})-{})", self.ctype(tp), v),
+                };
+            }
+            ASTtype::Binary => {
+                return self.gen_binary(ast);
+            }
+            ASTtype::CallFunction => {
+                return self.gen_call(ast);
+            }
+            ASTtype::CallMethod => {
+                return self.gen_method(ast);
+            }
+            ASTtype::Index => {
+                let vt = self.ctype(ast.next[0].vtype.unwrap());
+                let base = self.gen_borrow(&ast.next[0]);
+                let index = self.gen_expr(&ast.next[1]);
+                return format!("(*{}_at({}, (uint64_t)({})))", vt, base, index);
+            }
+            ASTtype::Array => {
+                let vt = self.ctype(ast.vtype.unwrap());
+                if ast.next.is_empty() {
+                    return format!("{}_new()", vt);
+                }
+                let elem = match self.table.kind(ast.vtype.unwrap()) {
+                    TypeKind::Vec(x) => self.ctype(*x),
+                    _ => unreachable!(),
+                };
+                let xs: Vec<String> = ast.next.iter().map(|x| self.gen_value(x)).collect();
+                return format!(
+                    "{}_from(({}[]){{{}}}, {})",
+                    vt,
+                    elem,
+                    xs.join(", "),
+                    xs.len()
+                );
+            }
+            ASTtype::Field => {
+                let base = self.gen_borrow(&ast.next[0]);
+                return format!("({}).f_{}", base, ast.token.s);
+            }
+            ASTtype::StructLiteral => {
+                let t = self.ctype(ast.vtype.unwrap());
+                let xs: Vec<String> = ast
+                    .next
+                    .iter()
+                    .map(|x| format!(".f_{} = {}", x.token.s, self.gen_value(&x.next[0])))
+                    .collect();
+                return match xs.is_empty() {
+                    true => format!("(({}){{0}})", t),
+                    false => format!("(({}){{{}}})", t, xs.join(", ")),
+                };
+            }
+            ASTtype::Variant => {
+                let tp = ast.vtype.unwrap();
+                let v = self.table.variant(tp, ast.sym.unwrap());
+                let args = self.gen_args(&ast.next);
+                return format!("{}__{}({})", self.ctype(tp), v.name, args);
+            }
+            ASTtype::Match => {
+                //GNU statement expression holding the lowered match
+                let r = self.new_tmp();
+                let decl = format!("{} {};", self.ctype(ast.vtype.unwrap()), r);
+                let save = std::mem::take(&mut self.out);
+                let pending = std::mem::take(&mut self.pending);
+                let indent = self.indent;
+                self.indent = 1;
+                self.line(&decl);
+                self.gen_match(ast, Some(&r));
+                self.line(&format!("{};", r));
+                self.indent = indent;
+                self.pending = pending;
+                let body = std::mem::replace(&mut self.out, save);
+                return format!("({{\n{}}})", body);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn gen_binary(&mut self, ast: &AST) -> String {
+        let l = self.gen_borrow(&ast.next[0]);
+        let r = self.gen_borrow(&ast.next[1]);
+        let operand = ast.next[0].vtype.unwrap();
+
+        if operand == TYPE_STRING {
+            return match ast.token.tp {
+                TokenType::Plus => format!("rfx_string_concat({}, {})", l, r),
+                TokenType::LogicEqual => format!("rfx_string_eq({}, {})", l, r),
+                _ => format!("(!rfx_string_eq({}, {}))", l, r),
+            };
+        }
+
+        let op = ast.token.s.as_str();
+        let t = self.ctype(ast.vtype.unwrap());
+        return match ast.token.tp {
+            TokenType::Plus | TokenType::Minus | TokenType::Multify => {
+                //wraps: unsigned arithmetic does not overflow, signed does
+                format!("(({})((uint64_t)({}) {} (uint64_t)({})))", t, l, op, r)
+            }
+            TokenType::Divide | TokenType::Mod => {
+                let sign = match self.table.kind(operand) {
+                    TypeKind::Integer { signed: true } => "i",
+                    _ => "u",
+                };
+                let f = match ast.token.tp {
+                    TokenType::Divide => "div",
+                    _ => "rem",
+                };
+                format!("(({})rfx_{}_{}64({}, {}))", t, f, sign, l, r)
+            }
+            TokenType::BitAND
+            | TokenType::BitOR
+            | TokenType::BitXOR
+            | TokenType::LeftBShift
+            | TokenType::RightBShift => {
+                //cast back, C promotes narrow integers to int
+                format!("(({})({} {} {}))", t, l, op, r)
+            }
+            _ => format!("({} {} {})", l, op, r),
+        };
+    }
+
+    fn gen_args(&mut self, args: &[AST]) -> String {
+        let xs: Vec<String> = args.iter().map(|x| self.gen_value(x)).collect();
+        return xs.join(", ");
+    }
+
+    fn gen_call(&mut self, ast: &AST) -> String {
+        if let Some(sym) = ast.sym {
+            let args = self.gen_args(&ast.next);
+            return format!("{}({})", self.table.function_name[sym].cname, args);
+        }
+
+        match ast.token.s.as_str() {
+            "assert" => {
+                let c = self.gen_expr(&ast.next[0]);
+                let msg = c_string(&assert_message(self.table, ast));
+                return format!("rfx_assert({}, {})", c, msg);
+            }
+            "assert_eq" => {
+                let l = self.gen_borrow(&ast.next[0]);
+                let r = self.gen_borrow(&ast.next[1]);
+                let msg = c_string(&assert_message(self.table, ast));
+                let f = match self.table.kind(ast.next[0].vtype.unwrap()) {
+                    TypeKind::Integer { signed: true } => {
+                        return format!(
+                            "rfx_assert_eq_i64((int64_t){}, (int64_t){}, {})",
+                            l, r, msg
+                        );
+                    }
+                    TypeKind::Integer { signed: false } => {
+                        return format!(
+                            "rfx_assert_eq_u64((uint64_t){}, (uint64_t){}, {})",
+                            l, r, msg
+                        );
+                    }
+                    TypeKind::Bool => "bool",
+                    TypeKind::Char => "char",
+                    _ => "String",
+                };
+                return format!("rfx_assert_eq_{}({}, {}, {})", f, l, r, msg);
+            }
+            "print" => {
+                let x = &ast.next[0];
+                let tp = x.vtype.unwrap();
+                let v = self.gen_borrow(x);
+                return match self.table.kind(tp) {
+                    TypeKind::Integer { signed: true } => format!("rfx_print_i64((int64_t){})", v),
+                    TypeKind::Integer { signed: false } => {
+                        format!("rfx_print_u64((uint64_t){})", v)
+                    }
+                    TypeKind::Bool => format!("rfx_print_bool({})", v),
+                    TypeKind::Char => format!("rfx_print_char({})", v),
+                    _ => format!("rfx_print_String({})", v),
+                };
+            }
+            "Vec::new" => {
+                return format!("{}_new()", self.ctype(ast.vtype.unwrap()));
+            }
+            _ => {
+                //Vec::new_init
+                return self.gen_value(&ast.next[0]);
+            }
+        }
+    }
+
+    fn gen_method(&mut self, ast: &AST) -> String {
+        if let Some(sym) = ast.sym {
+            let mut args: Vec<String> = Vec::new();
+            for x in ast.next.iter() {
+                args.push(self.gen_value(x));
+            }
+            return format!(
+                "{}({})",
+                self.table.function_name[sym].cname,
+                args.join(", ")
+            );
+        }
+
+        return match ast.token.s.as_str() {
+            "len" => format!("({}).len", self.gen_borrow(&ast.next[0])),
+            _ => {
+                //push
+                let vt = self.ctype(ast.next[0].vtype.unwrap());
+                let recv = self.gen_expr(&ast.next[0]);
+                let x = self.gen_value(&ast.next[1]);
+                format!("{}_push(&{}, {})", vt, recv, x)
+            }
+        };
+    }
+
+    //C condition testing value `m` against a pattern
+    fn pat_cond(&self, p: &Pat, m: &str, tp: usize, dom: &Domain) -> String {
+        return match p {
+            Pat::Variant(i, subs) => {
+                let v = self.table.variant(tp, *i);
+                let mut conds = vec![format!("{}.tag == {}", m, i)];
+                for (k, (sub, f)) in subs.iter().zip(v.fields.iter()).enumerate() {
+                    let fdom = domain_of(self.table, *f).unwrap();
+                    let fm = format!("{}.u.v_{}.f{}", m, v.name, k);
+                    let c = self.pat_cond(sub, &fm, *f, &fdom);
+                    if c != "1" {
+                        conds.push(c);
+                    }
+                }
+                format!("({})", conds.join(" && "))
+            }
+            Pat::Wild => "1".to_string(),
+            Pat::Range(a, b) if a == b => format!("{} == {}", m, self.int_literal(*a, tp)),
+            Pat::Range(a, b) => {
+                let mut conds: Vec<String> = Vec::new();
+                let (lo, hi) = match dom {
+                    Domain::Int(lo, hi) => (*lo, *hi),
+                    _ => (0, 0x10FFFF),
+                };
+                if *a > lo {
+                    conds.push(format!("{} >= {}", m, self.int_literal(*a, tp)));
+                }
+                if *b < hi {
+                    conds.push(format!("{} <= {}", m, self.int_literal(*b, tp)));
+                }
+                match conds.is_empty() {
+                    true => "1".to_string(),
+                    false => format!("({})", conds.join(" && ")),
+                }
+            }
+            Pat::Str(s) => format!(
+                "rfx_string_eq({}, rfx_string_lit({}, {}))",
+                m,
+                c_string(s),
+                s.len()
+            ),
+            Pat::Or(alts) => {
+                let xs: Vec<String> = alts.iter().map(|x| self.pat_cond(x, m, tp, dom)).collect();
+                format!("({})", xs.join(" || "))
+            }
+        };
+    }
+
+    //values a pattern needs as case labels, None for a default label
+    fn case_values(p: &Pat, out: &mut Vec<i128>) -> Option<()> {
+        match p {
+            Pat::Wild => return None,
+            Pat::Range(a, b) => {
+                for x in *a..=*b {
+                    out.push(x);
+                }
+            }
+            Pat::Or(alts) => {
+                for alt in alts.iter() {
+                    Self::case_values(alt, out)?;
+                }
+            }
+            Pat::Variant(i, _) => out.push(*i as i128),
+            Pat::Str(_) => {}
+        }
+        return Some(());
+    }
+
+    fn can_switch(p: &Pat) -> bool {
+        return match p {
+            Pat::Wild => true,
+            Pat::Range(a, b) => b - a < MAX_CASE_RANGE,
+            Pat::Or(alts) => alts.iter().all(Self::can_switch),
+            Pat::Variant(_, subs) => subs.iter().all(|x| *x == Pat::Wild),
+            Pat::Str(_) => false,
+        };
+    }
+
+    //declare the bindings of a pattern matched by the value `m`
+    fn gen_bindings(&mut self, pat: &AST, m: &str) {
+        match pat.tp {
+            ASTtype::PatBinding => {
+                let tp = pat.vtype.unwrap();
+                let name = self.declare(&pat.token.s, tp);
+                let value = match self.table.is_owned(tp) {
+                    true => format!("{}({})", self.clone_fn(tp), m),
+                    false => m.to_string(),
+                };
+                self.line(&format!("const {} {} = {};", self.ctype(tp), name, value));
+                self.line(&format!("(void){};", name));
+            }
+            ASTtype::PatVariant => {
+                let v = self.table.variant(pat.vtype.unwrap(), pat.sym.unwrap());
+                let vname = v.name.clone();
+                for (k, sub) in pat.next.iter().enumerate() {
+                    self.gen_bindings(sub, &format!("{}.u.v_{}.f{}", m, vname, k));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn gen_case_body(&mut self, case: &AST, m: &str, sink: Option<&str>) {
+        self.open_scope();
+        self.gen_bindings(&case.next[0], m);
+
+        let body = &case.next[1];
+        let mut diverged = false;
+        if body.tp == ASTtype::CodeBlock {
+            self.gen_stmts(&body.next);
+            diverged = block_diverges(&body.next);
+        } else {
+            match sink {
+                None => {
+                    let v = self.gen_borrow(body);
+                    self.line(&format!("{};", v));
+                }
+                Some(r) => {
+                    let v = self.gen_value(body);
+                    self.line(&format!("{} = {};", r, v));
+                }
+            }
+        }
+        self.close_scope(diverged);
+    }
+
+    //lower to a switch if every arm is a few values, to an if chain otherwise.
+    //`sink` receives the value of a match expression
+    fn gen_match(&mut self, ast: &AST, sink: Option<&str>) {
+        let tp = ast.next[0].vtype.unwrap();
+        let dom = domain_of(self.table, tp).unwrap();
+        let cases = &ast.next[1..];
+        let pats: Vec<Pat> = cases
+            .iter()
+            .map(|c| pattern_of(&c.next[0], &dom, self.table).unwrap())
+            .collect();
+        let unreachable = check_arms(&pats, &dom, self.table).unreachable;
+        let arms: Vec<usize> = (0..pats.len())
+            .filter(|i| !unreachable.contains(i))
+            .collect();
+
+        let m = self.new_tmp();
+        self.line("{");
+        self.indent += 1;
+        self.open_scope();
+        let s = format!(
+            "{} {} = {};",
+            self.ctype(tp),
+            m,
+            self.gen_borrow(&ast.next[0])
+        );
+        self.line(&s);
+
+        let is_enum = matches!(dom, Domain::Enum(_));
+        let switch = matches!(
+            dom,
+            Domain::Int(..) | Domain::Char | Domain::Bool | Domain::Enum(_)
+        ) && arms.iter().all(|i| Self::can_switch(&pats[*i]))
+            && !cases.iter().any(has_loop_break);
+
+        if switch {
+            let mut done: Vec<i128> = Vec::new();
+            match is_enum {
+                true => self.line(&format!("switch ({}.tag) {{", m)),
+                false => self.line(&format!("switch ({}) {{", m)),
+            }
+            for i in arms.iter() {
+                let mut values: Vec<i128> = Vec::new();
+                match Self::case_values(&pats[*i], &mut values) {
+                    None => self.line("default: {"),
+                    Some(_) => {
+                        values.retain(|x| !done.contains(x));
+                        for x in values.iter() {
+                            let s = match is_enum {
+                                true => format!("case {}:", x),
+                                false => format!("case {}:", self.int_literal(*x, tp)),
+                            };
+                            self.line(&s);
+                        }
+                        done.extend(values);
+                        self.line("{");
+                    }
+                }
+                self.indent += 1;
+                self.gen_case_body(&cases[*i], &m, sink);
+                self.line("break;");
+                self.indent -= 1;
+                self.line("}");
+            }
+            self.line("}");
+        } else {
+            for (n, i) in arms.iter().enumerate() {
+                let cond = self.pat_cond(&pats[*i], &m, tp, &dom);
+                if n == 0 {
+                    self.line(&format!("if ({}) {{", cond));
+                } else if pats[*i] == Pat::Wild {
+                    self.line("} else {");
+                } else {
+                    self.line(&format!("}} else if ({}) {{", cond));
+                }
+                self.indent += 1;
+                self.gen_case_body(&cases[*i], &m, sink);
+                self.indent -= 1;
+            }
+            self.line("}");
+        }
+
+        self.close_scope(false);
+        self.indent -= 1;
+        self.line("}");
+    }
+}
@@ -0,0 +1,146 @@
+use super::lex::Token;
+use super::lint::Lints;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub line: u64,
+    pub col: u64,
+    pub file: usize, // index into the linked modules
+    pub msg: String,
+}
+
+impl Diagnostic {
+    //[error, line, col, msg], to cache it with what it is about
+    pub fn to_json(&self) -> json::JsonValue {
+        return json::array![
+            self.level == Level::Error,
+            self.line,
+            self.col,
+            self.msg.as_str()
+        ];
+    }
+
+    pub fn from_json(v: &json::JsonValue, file: usize) -> Option<Self> {
+        let level = match v[0].as_bool()? {
+            true => Level::Error,
+            false => Level::Warning,
+        };
+        return Some(Diagnostic {
+            level,
+            line: v[1].as_u64()?,
+            col: v[2].as_u64()?,
+            file,
+            msg: v[3].as_str()?.to_string(),
+        });
+    }
+
+    pub fn error(t: &Token, msg: String) -> Self {
+        Diagnostic {
+            level: Level::Error,
+            line: t.line,
+            col: t.col,
+            file: t.file,
+            msg,
+        }
+    }
+
+    pub fn warning(t: &Token, msg: String) -> Self {
+        Diagnostic {
+            level: Level::Warning,
+            line: t.line,
+            col: t.col,
+            file: t.file,
+            msg,
+        }
+    }
+
+    //about a file as a whole, e.g. reported by the C compiler
+    pub fn file(level: Level, msg: String) -> Self {
+        Diagnostic {
+            level,
+            line: 0,
+            col: 0,
+            file: 0,
+            msg,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+
+    pub fn format(&self, file: &str) -> String {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        if self.line == 0 {
+            return format!("{}: {}: {}", file, level, self.msg);
+        }
+        return format!(
+            "{}:{}:{}: {}: {}",
+            file, self.line, self.col, level, self.msg
+        );
+    }
+}
+
+pub fn has_error(diags: &[Diagnostic]) -> bool {
+    return diags.iter().any(|d| d.is_error());
+}
+
+//what becomes of warnings, the WARNINGS setting
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WarningLevel {
+    Allow, // dropped
+    Warn,
+    Deny, // made errors
+}
+
+//the WARNINGS and LINTS settings
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Warnings {
+    pub level: WarningLevel,
+    pub lints: Lints,
+}
+
+impl Warnings {
+    //with every lint on
+    pub fn new(level: WarningLevel) -> Self {
+        return Warnings {
+            level,
+            lints: Lints::all(),
+        };
+    }
+}
+
+pub fn apply_warnings(diags: Vec<Diagnostic>, w: Warnings) -> Vec<Diagnostic> {
+    return match w.level {
+        WarningLevel::Warn => diags,
+        WarningLevel::Allow => diags.into_iter().filter(|d| d.is_error()).collect(),
+        WarningLevel::Deny => diags
+            .into_iter()
+            .map(|d| Diagnostic {
+                level: Level::Error,
+                ..d
+            })
+            .collect(),
+    };
+}
+
+//the diagnostics as printed, a line each. files are the names of the linked
+//modules they point into
+pub fn render(files: &[String], diags: &[Diagnostic]) -> String {
+    let mut s = String::new();
+    for d in diags {
+        s += &d.format(&files[d.file]);
+        s.push('\n');
+    }
+    return s;
+}
@@ -0,0 +1,493 @@
+use std::collections::HashMap;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::lex::TokenType;
+
+/*
+the pages of `rfx doc`, one per module: its pub functions, structs, enums,
+traits, type aliases, constants and variables, and its impls, with their
+signatures and doc comments. a type
+named in a signature links to where it is declared, in whichever module. an
+impl lists the functions of its trait, or its own pub ones
+*/
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DocFormat {
+    Html,
+    Markdown,
+}
+
+impl DocFormat {
+    pub fn extension(self) -> &'static str {
+        return match self {
+            DocFormat::Html => "html",
+            DocFormat::Markdown => "md",
+        };
+    }
+}
+
+//a piece of a signature, a type declared somewhere links to it
+enum Seg {
+    Text(String),
+    Type(String),
+}
+
+//an item of a page
+struct Entry {
+    kind: &'static str, // of the anchor, e.g. "struct"
+    name: String,
+    title: Vec<Seg>,
+    sig: Vec<Seg>,
+    doc: Option<String>,
+    methods: Vec<Entry>,
+}
+
+const SECTIONS: &[(&str, &str)] = &[
+    ("fn", "Functions"),
+    ("struct", "Structs"),
+    ("enum", "Enums"),
+    ("trait", "Traits"),
+    ("type", "Type aliases"),
+    ("const", "Constants"),
+    ("var", "Variables"),
+    ("impl", "Impls"),
+];
+
+struct Pages<'a> {
+    format: DocFormat,
+    links: HashMap<String, (&'a str, String)>, // type name to (page, anchor)
+}
+
+//the pages of the (page name, tree) modules, in their order
+pub fn pages(modules: &[(String, &AST)], format: DocFormat) -> Vec<String> {
+    let mut p = Pages {
+        format,
+        links: HashMap::new(),
+    };
+    for (page, root) in modules.iter() {
+        for item in root.next.iter().filter(|x| x.public) {
+            let kind = match item.tp {
+                ASTtype::Struct => "struct",
+                ASTtype::Enum => "enum",
+                ASTtype::Trait => "trait",
+                ASTtype::TypeAlias => "type",
+                _ => continue,
+            };
+            let anchor = format!("{}.{}", kind, item.token.s);
+            p.links
+                .entry(item.token.s.clone())
+                .or_insert((page.as_str(), anchor));
+        }
+    }
+    return modules
+        .iter()
+        .map(|(page, root)| p.page(page, root))
+        .collect();
+}
+
+fn text(s: &str) -> Seg {
+    return Seg::Text(s.to_string());
+}
+
+fn type_segs(ast: &AST, out: &mut Vec<Seg>) {
+    out.push(Seg::Type(ast.token.s.clone()));
+    if ast.next.is_empty() {
+        return;
+    }
+    let template = ast.token.s == "template";
+    out.push(text(if template { "{" } else { "<" }));
+    for (i, a) in ast.next.iter().enumerate() {
+        if i > 0 {
+            out.push(text(", "));
+        }
+        type_segs(a, out);
+    }
+    out.push(text(if template { "}" } else { ">" }));
+}
+
+fn fn_segs(f: &AST, out: &mut Vec<Seg>) {
+    if f.next[0].token.tp == TokenType::Pub {
+        out.push(text("pub "));
+    }
+    out.push(text(&format!("fn {}(", f.next[1].token.s)));
+    for (i, arg) in f.next[2].next.iter().enumerate() {
+        if i > 0 {
+            out.push(text(", "));
+        }
+        //`self` alone has its name for a type, at the same place
+        let (name, tp) = (&arg.next[0].token, &arg.next[1].token);
+        if name.s == "self" && (name.line, name.col) == (tp.line, tp.col) {
+            out.push(text("self"));
+            continue;
+        }
+        out.push(text(&format!("{}: ", name.s)));
+        type_segs(&arg.next[1], out);
+    }
+    out.push(text(")"));
+    if let Some(ret) = f.next[3].next.first() {
+        out.push(text(" -> "));
+        type_segs(ret, out);
+    }
+}
+
+fn function(f: &AST) -> Entry {
+    let mut sig: Vec<Seg> = Vec::new();
+    fn_segs(f, &mut sig);
+    return Entry {
+        kind: "fn",
+        name: f.next[1].token.s.clone(),
+        title: vec![text(&format!("fn {}", f.next[1].token.s))],
+        sig,
+        doc: f.doc.clone(),
+        methods: Vec::new(),
+    };
+}
+
+//what a page shows of an item, None if nothing
+fn entry(item: &AST) -> Option<Entry> {
+    let mut name = item.token.s.clone();
+    let mut sig: Vec<Seg> = Vec::new();
+    let mut methods: Vec<Entry> = Vec::new();
+    let (kind, title) = match item.tp {
+        ASTtype::Function if item.next[0].token.tp == TokenType::Pub => {
+            return Some(function(item));
+        }
+        ASTtype::Struct | ASTtype::Enum | ASTtype::Trait | ASTtype::TypeAlias if !item.public => {
+            return None;
+        }
+        ASTtype::Struct => {
+            sig.push(text(&format!("pub struct {} {{\n", name)));
+            for field in item.next.iter() {
+                sig.push(text(&format!("    {}: ", field.token.s)));
+                type_segs(&field.next[0], &mut sig);
+                sig.push(text(",\n"));
+            }
+            sig.push(text("}"));
+            ("struct", vec![text(&format!("struct {}", name))])
+        }
+        ASTtype::Enum => {
+            sig.push(text(&format!("pub enum {} {{\n", name)));
+            for variant in item.next.iter() {
+                sig.push(text(&format!("    {}", variant.token.s)));
+                if !variant.next.is_empty() {
+                    sig.push(text("("));
+                    for (i, tp) in variant.next.iter().enumerate() {
+                        if i > 0 {
+                            sig.push(text(", "));
+                        }
+                        type_segs(tp, &mut sig);
+                    }
+                    sig.push(text(")"));
+                }
+                sig.push(text(",\n"));
+            }
+            sig.push(text("}"));
+            ("enum", vec![text(&format!("enum {}", name))])
+        }
+        ASTtype::Trait => {
+            sig.push(text(&format!("pub trait {} {{\n", name)));
+            for f in item.next.iter() {
+                sig.push(text("    "));
+                fn_segs(f, &mut sig);
+                sig.push(text(";\n"));
+                methods.push(function(f));
+            }
+            sig.push(text("}"));
+            ("trait", vec![text(&format!("trait {}", name))])
+        }
+        //the type, not the value, is what others rely on
+        ASTtype::Variable if item.public => {
+            let kind = match item.next[0].token.tp {
+                TokenType::Const => "const",
+                _ => "var",
+            };
+            name = item.next[1].token.s.clone();
+            sig.push(text(&format!("pub {} {}", kind, name)));
+            if item.next[2].token.tp != TokenType::Null {
+                sig.push(text(": "));
+                type_segs(&item.next[2], &mut sig);
+            }
+            sig.push(text(";"));
+            (kind, vec![text(&format!("{} {}", kind, name))])
+        }
+        ASTtype::TypeAlias => {
+            sig.push(text(&format!("pub type {} = ", name)));
+            type_segs(&item.next[0], &mut sig);
+            sig.push(text(";"));
+            ("type", vec![text(&format!("type {}", name))])
+        }
+        //named Trait.Type, or Type for its own functions
+        ASTtype::Impl => {
+            let own = item.token.tp == TokenType::Null;
+            name = match own {
+                true => item.next[0].token.s.clone(),
+                false => format!("{}.{}", item.token.s, item.next[0].token.s),
+            };
+            sig.push(text("impl "));
+            if !own {
+                sig.push(Seg::Type(item.token.s.clone()));
+                sig.push(text(" for "));
+            }
+            type_segs(&item.next[0], &mut sig);
+            methods = item.next[1..]
+                .iter()
+                .filter(|f| !own || f.next[0].token.tp == TokenType::Pub)
+                .map(function)
+                .collect();
+            if methods.is_empty() {
+                return None;
+            }
+            let title = std::mem::take(&mut sig);
+            ("impl", title)
+        }
+        _ => return None,
+    };
+    return Some(Entry {
+        kind,
+        name,
+        title,
+        sig,
+        doc: item.doc.clone(),
+        methods,
+    });
+}
+
+fn escape(s: &str) -> String {
+    return s
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+//doc comment text as html: paragraphs split by blank lines, `code` spans
+fn doc_html(doc: &str) -> String {
+    let mut out = String::new();
+    for para in doc.split("\n\n").filter(|x| !x.trim().is_empty()) {
+        let mut p = String::new();
+        for (i, part) in escape(para.trim()).split('`').enumerate() {
+            match i % 2 {
+                0 => p += part,
+                _ => p += &format!("<code>{}</code>", part),
+            }
+        }
+        out += &format!("<p>{}</p>\n", p);
+    }
+    return out;
+}
+
+impl<'a> Pages<'a> {
+    fn href(&self, page: &str, name: &str) -> Option<String> {
+        let (at, anchor) = self.links.get(name)?;
+        return match *at == page {
+            true => Some(format!("#{}", anchor)),
+            false => Some(format!("{}.{}#{}", at, self.format.extension(), anchor)),
+        };
+    }
+
+    //segments as html, a linked type an <a>
+    fn html(&self, page: &str, segs: &[Seg]) -> String {
+        let mut out = String::new();
+        for s in segs.iter() {
+            match s {
+                Seg::Text(x) => out += &escape(x),
+                Seg::Type(x) => match self.href(page, x) {
+                    None => out += &escape(x),
+                    Some(h) => out += &format!("<a href=\"{}\">{}</a>", h, escape(x)),
+                },
+            }
+        }
+        return out;
+    }
+
+    //segments as plain text, and the links of the types among them
+    fn markdown(&self, page: &str, segs: &[Seg]) -> (String, Vec<String>) {
+        let mut out = String::new();
+        let mut links: Vec<String> = Vec::new();
+        for s in segs.iter() {
+            match s {
+                Seg::Text(x) => out += x,
+                Seg::Type(x) => {
+                    out += x;
+                    if let Some(h) = self.href(page, x) {
+                        let link = format!("[`{}`]({})", x, h);
+                        if !links.contains(&link) {
+                            links.push(link);
+                        }
+                    }
+                }
+            }
+        }
+        return (out, links);
+    }
+
+    //a function of a trait or impl has the anchor of it as prefix
+    fn entry(&self, page: &str, e: &Entry, prefix: &str, depth: usize, out: &mut String) {
+        let anchor = format!("{}{}.{}", prefix, e.kind, e.name);
+        match self.format {
+            DocFormat::Html => {
+                *out += &format!(
+                    "<h{} id=\"{}\">{}</h{}>\n",
+                    depth,
+                    escape(&anchor),
+                    self.html(page, &e.title),
+                    depth
+                );
+                if e.kind != "impl" {
+                    *out += &format!("<pre><code>{}</code></pre>\n", self.html(page, &e.sig));
+                }
+                if let Some(doc) = &e.doc {
+                    *out += &doc_html(doc);
+                }
+            }
+            DocFormat::Markdown => {
+                let (title, _) = self.markdown(page, &e.title);
+                *out += &format!(
+                    "{} <a id=\"{}\"></a>`{}`\n\n",
+                    "#".repeat(depth),
+                    anchor,
+                    title
+                );
+                let (sig, mut links) = self.markdown(page, &e.sig);
+                if e.kind == "impl" {
+                    links = self.markdown(page, &e.title).1;
+                } else {
+                    *out += &format!("```rfx\n{}\n```\n\n", sig);
+                }
+                if !links.is_empty() {
+                    *out += &format!("Types: {}\n\n", links.join(", "));
+                }
+                if let Some(doc) = &e.doc {
+                    *out += &format!("{}\n\n", doc);
+                }
+            }
+        }
+        for m in e.methods.iter() {
+            self.entry(page, m, &format!("{}.", anchor), depth + 1, out);
+        }
+    }
+
+    fn page(&self, page: &str, root: &AST) -> String {
+        let entries: Vec<Entry> = root.next.iter().filter_map(entry).collect();
+        let mut body = String::new();
+        for (kind, heading) in SECTIONS.iter() {
+            let of: Vec<&Entry> = entries.iter().filter(|x| x.kind == *kind).collect();
+            if of.is_empty() {
+                continue;
+            }
+            body += &match self.format {
+                DocFormat::Html => format!("<h2>{}</h2>\n", heading),
+                DocFormat::Markdown => format!("## {}\n\n", heading),
+            };
+            for e in of {
+                self.entry(page, e, "", 3, &mut body);
+            }
+        }
+
+        return match self.format {
+            DocFormat::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n{1}</body>\n</html>\n",
+                escape(page),
+                body
+            ),
+            DocFormat::Markdown => format!("# {}\n\n{}", page, body),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pages, DocFormat};
+    use crate::compile::{lex, syntax};
+
+    #[test]
+    fn test_pages() {
+        let shapes = "\
+/// A point on the plane.
+///
+/// Made by `origin`.
+pub struct Point {
+    x: i64,
+    y: i64,
+}
+/** What `area` is taken of. */
+pub trait Area {
+    /// The area, never negative.
+    fn area(self) -> i64;
+}
+impl Area for Point {
+    fn area(self) -> i64 {
+        return 0;
+    }
+}
+/// The origin.
+pub fn origin() -> Point {
+    return Point { x: 0, y: 0 };
+}
+fn hidden() {}
+/// Not pub, not on the page.
+struct Secret {
+    n: i64,
+}
+/// The unit of `area`.
+pub const UNIT: i64 = 1;
+pub var made = 0;
+var count: i64 = 0;
+";
+        let app = "\
+pub type Points = Vec<Point>;
+// not a doc comment
+pub fn first(p: Points) -> Point {
+    return p[0];
+}
+";
+        let (a, _) = syntax::parse(lex::lex_all(shapes));
+        let (b, _) = syntax::parse(lex::lex_all(app));
+        assert_eq!(
+            a.next[0].doc.as_deref(),
+            Some("A point on the plane.\n\nMade by `origin`.")
+        );
+        assert_eq!(
+            a.next[1].next[0].doc.as_deref(),
+            Some("The area, never negative.")
+        );
+        assert_eq!(b.next[1].doc, None);
+
+        let modules = [("shapes".to_string(), &a), ("app".to_string(), &b)];
+        let html = pages(&modules, DocFormat::Html);
+        assert!(html[0].contains("<h3 id=\"struct.Point\">struct Point</h3>"));
+        assert!(html[0].contains("<p>Made by <code>origin</code>.</p>"));
+        assert!(html[0].contains("pub fn origin() -&gt; <a href=\"#struct.Point\">Point</a>"));
+        assert!(html[0].contains(
+            "impl <a href=\"#trait.Area\">Area</a> for <a href=\"#struct.Point\">Point</a>"
+        ));
+        assert!(html[0].contains("<h4 id=\"trait.Area.fn.area\">fn area</h4>"));
+        assert!(html[0].contains("<h4 id=\"impl.Area.Point.fn.area\">fn area</h4>"));
+        assert!(!html[0].contains("hidden") && !html[0].contains("Secret"));
+        assert!(html[0].contains("<pre><code>pub struct Point {\n"));
+        assert!(html[0].contains(
+            "<h2>Constants</h2>\n<h3 id=\"const.UNIT\">const UNIT</h3>\n\
+             <pre><code>pub const UNIT: i64;</code></pre>\n<p>The unit of <code>area</code>.</p>\n"
+        ));
+        assert!(html[0].contains(
+            "<h2>Variables</h2>\n<h3 id=\"var.made\">var made</h3>\n\
+             <pre><code>pub var made;</code></pre>\n"
+        ));
+        assert!(!html[0].contains("count"));
+        assert!(html[1].contains("Vec&lt;<a href=\"shapes.html#struct.Point\">Point</a>&gt;"));
+        assert!(html[1].contains("first(p: <a href=\"#type.Points\">Points</a>)"));
+
+        let md = pages(&modules, DocFormat::Markdown);
+        assert!(
+            md[1].starts_with("# app\n\n## Functions\n\n### <a id=\"fn.first\"></a>`fn first`\n\n")
+        );
+        assert!(md[1].contains(
+            "```rfx\npub fn first(p: Points) -> Point\n```\n\n\
+             Types: [`Points`](#type.Points), [`Point`](shapes.md#struct.Point)\n\n"
+        ));
+    }
+}
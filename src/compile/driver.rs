@@ -0,0 +1,233 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::super::cache::{self, Cache};
+use super::diag::{self, Diagnostic, Level};
+use super::target::Target;
+
+pub const DEFAULT_CC: &str = "cc";
+
+//how the C compiler is run
+#[derive(Clone, Debug)]
+pub struct Cc {
+    pub cmd: String,
+    pub target: Option<Target>, // None when no width was asked for, cc picks its own
+    pub flags: Vec<String>,     // extra flags, e.g. cflags of rfx.json
+    pub warnings: bool,         // print cc's warnings, not with -q
+}
+
+impl Cc {
+    pub fn new(cmd: &str, target: Option<Target>) -> Self {
+        return Cc {
+            cmd: cmd.to_string(),
+            target,
+            flags: Vec::new(),
+            warnings: true,
+        };
+    }
+}
+
+//what the C compiler makes out of the generated C
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stage {
+    Asm,
+    Obj,
+    Bin,
+}
+
+impl Stage {
+    //appended to the source stem when no output file is given
+    pub fn extension(self) -> &'static str {
+        return match self {
+            Stage::Asm => ".s",
+            Stage::Obj => ".o",
+            Stage::Bin => "",
+        };
+    }
+}
+
+//arguments for cc. target is None when no -m flag was given, cc picks its own
+pub fn cc_args(stage: Stage, target: Option<Target>, cfile: &str, out: &str) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    match stage {
+        Stage::Asm => args.push("-S".to_string()),
+        Stage::Obj => args.push("-c".to_string()),
+        Stage::Bin => {}
+    }
+    if let Some(t) = target {
+        args.push(t.cc_flag());
+    }
+    args.push("-o".to_string());
+    args.push(out.to_string());
+    args.push(cfile.to_string());
+    return args;
+}
+
+//cc's stderr, a diagnostic per line, then its exit status if it failed
+pub fn cc_diagnostics(cc: &str, stderr: &str, status: Option<i32>) -> Vec<Diagnostic> {
+    let failed = status != Some(0);
+    let mut diags: Vec<Diagnostic> = Vec::new();
+    for l in stderr.lines().filter(|x| !x.trim().is_empty()) {
+        let level = match failed && !l.contains("warning:") {
+            true => Level::Error,
+            false => Level::Warning,
+        };
+        diags.push(Diagnostic::file(level, format!("{}: {}", cc, l)));
+    }
+    if failed {
+        let msg = match status {
+            Some(x) => format!("`{}` failed with exit status {}", cc, x),
+            None => format!("`{}` was terminated by a signal", cc),
+        };
+        diags.push(Diagnostic::file(Level::Error, msg));
+    }
+    return diags;
+}
+
+//run cc on cfile, or take its output from the cache if this C was built the
+//same way before. returns false if it failed, and the problems reported
+//against rfxfile as they are to be printed
+pub fn run_cc(
+    cc: &Cc,
+    stage: Stage,
+    cfile: &str,
+    out: &str,
+    rfxfile: &str,
+    cache: Option<&Cache>,
+) -> (bool, String) {
+    let mut args = cc.flags.clone();
+    args.extend(cc_args(stage, cc.target, cfile, out));
+    let key = match (cache, fs::read(cfile)) {
+        (Some(_), Ok(c)) => {
+            let mut flags = cc.flags.clone();
+            flags.extend(cc_args(stage, cc.target, "", ""));
+            flags.insert(0, cc.cmd.clone());
+            let flags = flags.join(" ");
+            cache::key(&[flags.as_bytes(), &c])
+        }
+        _ => String::new(),
+    };
+    let ext = match stage {
+        Stage::Asm => "s",
+        Stage::Obj => "o",
+        Stage::Bin => "bin",
+    };
+    if let Some(hit) = cache.and_then(|x| x.get(&key, ext)) {
+        info!("{}: using cached {}", rfxfile, hit.display());
+        if fs::copy(&hit, out).is_ok() {
+            return (true, String::new());
+        }
+    }
+
+    info!("{} {}", cc.cmd, args.join(" "));
+
+    let diags = match Command::new(&cc.cmd).args(&args).output() {
+        Err(e) => vec![Diagnostic::file(
+            Level::Error,
+            format!("cannot run `{}`: {}", cc.cmd, e),
+        )],
+        Ok(x) => cc_diagnostics(
+            &cc.cmd,
+            &String::from_utf8_lossy(&x.stderr),
+            x.status.code(),
+        ),
+    };
+    let shown: Vec<Diagnostic> = match cc.warnings {
+        true => diags.clone(),
+        false => diags.iter().filter(|x| x.is_error()).cloned().collect(),
+    };
+    let log = diag::render(&[rfxfile.to_string()], &shown);
+    if diag::has_error(&diags) {
+        return (false, log);
+    }
+    //only clean builds are cached, a hit has no warnings to replay
+    if let Some(cache) = cache.filter(|_| diags.is_empty()) {
+        cache.put(&key, ext, Path::new(out));
+    }
+    return (true, log);
+}
+
+//build the C with the system cc and run it, for tests: its stdout, exit
+//code, 128 + the signal if one killed it, and stderr. None without a C
+//compiler
+#[cfg(test)]
+pub fn run_c(c: &str) -> Option<(String, i32, String)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    if Command::new(DEFAULT_CC).arg("--version").output().is_err() {
+        eprintln!("no `{}`, not run", DEFAULT_CC);
+        return None;
+    }
+    let mut temps = crate::temp::Temps::new(false).unwrap();
+    let cfile = temps.create(".c").unwrap();
+    let bin = temps.create("").unwrap();
+    fs::write(&cfile, c).unwrap();
+    let (built, log) = run_cc(
+        &Cc::new(DEFAULT_CC, None),
+        Stage::Bin,
+        &cfile.to_string_lossy(),
+        &bin.to_string_lossy(),
+        "test",
+        None,
+    );
+    assert!(built, "{}", log);
+    let out = Command::new(&bin).output().unwrap();
+    temps.finish(true);
+    let code = out
+        .status
+        .code()
+        .unwrap_or_else(|| 128 + out.status.signal().unwrap_or(0));
+    let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+    return Some((
+        stdout,
+        code,
+        String::from_utf8_lossy(&out.stderr).to_string(),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cc_args, cc_diagnostics, Stage};
+    use crate::compile::Target;
+
+    #[test]
+    fn test_cc_args() {
+        assert_eq!(
+            cc_args(
+                Stage::Asm,
+                Some(Target::with_bits(32)),
+                "/tmp/rfx/a.c",
+                "m.s"
+            ),
+            vec!["-S", "-m32", "-o", "m.s", "/tmp/rfx/a.c"]
+        );
+        assert_eq!(
+            cc_args(Stage::Bin, None, "m.c", "m"),
+            vec!["-o", "m", "m.c"]
+        );
+    }
+
+    #[test]
+    fn test_cc_diagnostics() {
+        assert!(cc_diagnostics("cc", "", Some(0)).is_empty());
+
+        let diags = cc_diagnostics(
+            "gcc",
+            "a.c:3:1: warning: unused\na.c:4:2: error: bad\n",
+            Some(1),
+        );
+        let msgs: Vec<String> = diags.iter().map(|d| d.format("m.lang")).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "m.lang: warning: gcc: a.c:3:1: warning: unused",
+                "m.lang: error: gcc: a.c:4:2: error: bad",
+                "m.lang: error: `gcc` failed with exit status 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,1022 @@
+use std::collections::HashMap;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::diag::{self, Diagnostic};
+use super::lex::{self, Comment, Token, TokenType};
+use super::syntax;
+
+/*
+the formatter, `rfx fmt`. the source is parsed and its tree printed again:
+four spaces a level, `{` on the line of what it opens, one space around
+binary operators and after commas. an expression longer than the width has
+its argument lists broken, an argument a line, and a chain of method calls a
+call a line. comments are kept where they were, between items, statements,
+fields and match cases as inside expressions: one after code on its line
+stays after it, one on its own line goes on its own line. an expression with
+a comment inside is never put on one line. of the blank lines one is kept
+where there were some
+*/
+
+pub const DEFAULT_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+//the source formatted, or the diagnostics if it does not parse. nothing is
+//formatted that might lose code
+pub fn format(src: &str, width: usize) -> Result<String, Vec<Diagnostic>> {
+    let lexed = lex::lex_source(src);
+    let (root, diags) = syntax::parse(lexed.toks.clone());
+    if diag::has_error(&diags) {
+        return Err(diags);
+    }
+
+    let mut p = Printer::new(&lexed, width);
+    if src.starts_with("#!") {
+        p.out
+            .push_str(src.lines().next().unwrap_or_default().trim_end());
+        p.out.push('\n');
+        p.last_line = 1;
+    }
+    p.list(&root.next, None, 0, Printer::item);
+    p.comments_before((u64::MAX, u64::MAX), 0);
+
+    let out = p.out.trim_end();
+    return match out.is_empty() {
+        true => Ok(String::new()),
+        false => Ok(out.to_string() + "\n"),
+    };
+}
+
+type Element<'a> = fn(&mut Printer<'a>, &AST, usize);
+
+struct Printer<'a> {
+    toks: &'a [Token],
+    raw: &'a [String],
+    comments: &'a [Comment],
+    at: HashMap<(u64, u64), usize>, // token index by position
+    partner: Vec<Option<usize>>,    // the closing bracket of each opening one, and back
+    trailing: Vec<bool>,            // a comment after code on its line
+    leading: Vec<bool>,             // a comment before code on its line
+    next_comment: usize,
+    width: usize,
+    out: String,
+    last_line: u64, // source line of what was printed last
+}
+
+impl<'a> Printer<'a> {
+    fn new(lexed: &'a lex::Source, width: usize) -> Self {
+        let toks = &lexed.toks;
+        let mut at = HashMap::new();
+        let mut partner = vec![None; toks.len()];
+        let mut open: Vec<usize> = Vec::new();
+        let mut first_col: HashMap<u64, u64> = HashMap::new();
+        let mut last_col: HashMap<u64, u64> = HashMap::new();
+        for (i, t) in toks.iter().enumerate() {
+            at.insert((t.line, t.col), i);
+            first_col.entry(t.line).or_insert(t.col);
+            last_col.insert(t.line, t.col);
+            match t.tp {
+                TokenType::LeftBrace | TokenType::LeftParen | TokenType::LeftBrack => open.push(i),
+                TokenType::RightBrace | TokenType::RightParen | TokenType::RightBrack => {
+                    if let Some(o) = open.pop() {
+                        partner[o] = Some(i);
+                        partner[i] = Some(o);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let trailing = lexed
+            .comments
+            .iter()
+            .map(|c| first_col.get(&c.line).is_some_and(|x| *x < c.col))
+            .collect();
+        let leading = lexed
+            .comments
+            .iter()
+            .map(|c| last_col.get(&c.line).is_some_and(|x| *x > c.col))
+            .collect();
+
+        return Printer {
+            toks,
+            raw: &lexed.raw,
+            comments: &lexed.comments,
+            at,
+            partner,
+            trailing,
+            leading,
+            next_comment: 0,
+            width,
+            out: String::new(),
+            last_line: 0,
+        };
+    }
+
+    fn index(&self, t: &Token) -> Option<usize> {
+        return self.at.get(&(t.line, t.col)).copied();
+    }
+
+    //the token an element starts at: the first of its tree, or the keyword
+    //before its name
+    fn first(&self, ast: &AST) -> usize {
+        //`#[` before the name of the first attribute
+        if let Some(i) = ast.attrs.first().and_then(|x| self.index(&x.token)) {
+            return i.saturating_sub(2);
+        }
+        let first = self.min_index(ast).unwrap_or(0);
+        let keyword = match ast.tp {
+            ASTtype::Struct | ASTtype::Enum | ASTtype::Trait | ASTtype::TypeAlias => true,
+            ASTtype::Use => true,
+            ASTtype::Impl => ast.token.tp != TokenType::Null,
+            _ => false,
+        };
+        //the keyword of a variable is its token, the first of its tree
+        if ast.tp == ASTtype::Variable && ast.public {
+            return first.saturating_sub(1);
+        }
+        return match (keyword, ast.public) {
+            (true, true) => first.saturating_sub(2),
+            (true, false) => first.saturating_sub(1),
+            (false, _) => first,
+        };
+    }
+
+    fn min_index(&self, ast: &AST) -> Option<usize> {
+        let below = ast.next.iter().filter_map(|x| self.min_index(x)).min();
+        return match (self.index(&ast.token), below) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    fn max_index(&self, ast: &AST) -> Option<usize> {
+        let below = ast.next.iter().filter_map(|x| self.max_index(x)).max();
+        return self.index(&ast.token).max(below);
+    }
+
+    //the last token of an expression, the brackets it closes after its tree
+    //among it, and the `()` of a call without arguments
+    fn last_index(&self, ast: &AST) -> Option<usize> {
+        let first = self.min_index(ast)?;
+        let mut last = self.max_index(ast)?;
+        while let Some(t) = self.toks.get(last + 1) {
+            let partner = self.partner[last + 1];
+            match t.tp {
+                TokenType::RightParen | TokenType::RightBrack | TokenType::RightBrace
+                    if partner.is_some_and(|x| x >= first) =>
+                {
+                    last += 1
+                }
+                TokenType::LeftParen if partner == Some(last + 2) => last += 2,
+                _ => break,
+            }
+        }
+        return Some(last);
+    }
+
+    //true if a comment not yet printed comes before the end of ast
+    fn commented(&self, ast: &AST) -> bool {
+        let end = self.pos(self.last_index(ast));
+        return self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| (c.line, c.col) < end);
+    }
+
+    //the `{` after what ast was parsed from, e.g. the type of an impl or the
+    //value of a match
+    fn open_after(&self, ast: &AST) -> Option<usize> {
+        let mut depth: i64 = 0;
+        for i in self.max_index(ast)? + 1..self.toks.len() {
+            match self.toks[i].tp {
+                TokenType::LeftParen | TokenType::LeftBrack => depth += 1,
+                TokenType::RightParen | TokenType::RightBrack => depth -= 1,
+                TokenType::LeftBrace if depth <= 0 => return Some(i),
+                _ => {}
+            }
+        }
+        return None;
+    }
+
+    fn close_of(&self, open: Option<usize>) -> Option<usize> {
+        return open.and_then(|x| self.partner[x]);
+    }
+
+    //source line a token ends on, a string literal may span lines
+    fn end_line(&self, i: usize) -> u64 {
+        let newlines = self.raw[i].matches('\n').count() as u64;
+        return self.toks[i].line + newlines;
+    }
+
+    fn pos(&self, i: Option<usize>) -> (u64, u64) {
+        return match i.and_then(|x| self.toks.get(x)) {
+            None => (u64::MAX, u64::MAX),
+            Some(t) => (t.line, t.col),
+        };
+    }
+
+    //the start of the source line of a token. the comments before it are
+    //those of the lines above, one before the token on its line stays with it
+    fn line_of(&self, i: Option<usize>) -> (u64, u64) {
+        return (self.pos(i).0, 0);
+    }
+
+    //the column the output is at
+    fn col(&self) -> usize {
+        let line = match self.out.rfind('\n') {
+            None => &self.out[..],
+            Some(i) => &self.out[i + 1..],
+        };
+        return line.chars().count();
+    }
+
+    fn indent(&mut self, indent: usize) {
+        self.out.push_str(&INDENT.repeat(indent));
+    }
+
+    //a blank line before what is on source line `line` if there was one,
+    //never first in a block
+    fn gap(&mut self, line: u64) {
+        let opened = self.out.is_empty() || self.out.ends_with("{\n");
+        if !opened && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+    }
+
+    //print the comments not yet printed before pos
+    fn comments_before(&mut self, pos: (u64, u64), indent: usize) {
+        while let Some(c) = self.comments.get(self.next_comment) {
+            if (c.line, c.col) >= pos {
+                return;
+            }
+            if self.trailing[self.next_comment] && self.out.ends_with('\n') {
+                self.out.pop();
+                self.out.push(' ');
+            } else {
+                self.gap(c.line);
+                self.indent(indent);
+            }
+            self.out.push_str(&c.text);
+            self.out.push('\n');
+            let end = c.line + c.text.matches('\n').count() as u64;
+            self.last_line = self.last_line.max(end);
+            self.next_comment += 1;
+        }
+    }
+
+    /*
+    print the comments not yet printed before pos inside an expression. one
+    alone on its line stays alone, one next to code stays next to it, and a
+    `//` comment ends its line. the expression goes on at indent + 1 after
+    a comment ending a line
+    */
+    fn comments_inline(&mut self, pos: (u64, u64), indent: usize) {
+        while let Some(c) = self.comments.get(self.next_comment) {
+            if (c.line, c.col) >= pos {
+                return;
+            }
+            let own_line = !self.trailing[self.next_comment] && !self.leading[self.next_comment];
+            if own_line {
+                self.out.truncate(self.out.trim_end_matches(' ').len());
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.indent(indent + 1);
+            } else if !self.out.ends_with(' ') {
+                self.out.push(' ');
+            }
+            self.out.push_str(&c.text);
+            match own_line || c.text.starts_with("//") {
+                true => {
+                    self.out.push('\n');
+                    self.indent(indent + 1);
+                }
+                false => self.out.push(' '),
+            }
+            let end = c.line + c.text.matches('\n').count() as u64;
+            self.last_line = self.last_line.max(end);
+            self.next_comment += 1;
+        }
+    }
+
+    /*
+    elements a line each at indent, with the comments and blank lines around
+    them. close is the `}` after the last one, None at the end of the file
+    */
+    fn list(&mut self, elems: &[AST], close: Option<usize>, indent: usize, print: Element<'a>) {
+        let starts: Vec<usize> = elems.iter().map(|x| self.first(x)).collect();
+        for (i, e) in elems.iter().enumerate() {
+            self.comments_before(self.pos(Some(starts[i])), indent);
+            self.gap(self.toks[starts[i]].line);
+            self.indent(indent);
+            for a in e.attrs.iter() {
+                self.out.push_str(&attribute(a));
+                self.out.push('\n');
+                self.indent(indent);
+            }
+            print(self, e, indent);
+            self.out.push('\n');
+
+            let end = match starts.get(i + 1) {
+                Some(x) => *x,
+                None => close.unwrap_or(self.toks.len()),
+            };
+            self.last_line = self.end_line(end - 1);
+        }
+        self.comments_before(self.pos(close), indent);
+    }
+
+    //`{`, the elements and `}`, or `{}` if there are none
+    fn braced(&mut self, elems: &[AST], open: Option<usize>, indent: usize, print: Element<'a>) {
+        let close = self.close_of(open);
+        let commented = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| (c.line, c.col) < self.pos(close));
+        if elems.is_empty() && !commented {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        if let Some(o) = open {
+            self.last_line = self.toks[o].line;
+        }
+        self.list(elems, close, indent + 1, print);
+        self.indent(indent);
+        self.out.push('}');
+    }
+
+    fn block(&mut self, ast: &AST, indent: usize) {
+        let open = self.index(&ast.token);
+        self.braced(&ast.next, open, indent, Printer::statement);
+    }
+
+    fn item(&mut self, ast: &AST, indent: usize) {
+        if ast.public {
+            self.out.push_str("pub ");
+        }
+        match ast.tp {
+            ASTtype::Use => {
+                self.out.push_str(&format!("use {};", ast.token.s));
+            }
+            ASTtype::TypeAlias => {
+                let tp = type_name(&ast.next[0]);
+                self.out
+                    .push_str(&format!("type {} = {};", ast.token.s, tp));
+            }
+            ASTtype::Function => self.function(ast, indent),
+            ASTtype::Struct => {
+                self.out.push_str(&format!("struct {} ", ast.token.s));
+                let open = self.index(&ast.token).map(|x| x + 1);
+                self.braced(&ast.next, open, indent, Printer::field);
+            }
+            ASTtype::Enum => {
+                self.out.push_str(&format!("enum {} ", ast.token.s));
+                let open = self.index(&ast.token).map(|x| x + 1);
+                self.braced(&ast.next, open, indent, Printer::variant);
+            }
+            ASTtype::Trait => {
+                self.out.push_str(&format!("trait {} ", ast.token.s));
+                let open = self.index(&ast.token).map(|x| x + 1);
+                self.braced(&ast.next, open, indent, Printer::function);
+            }
+            ASTtype::Impl => {
+                let tp = type_name(&ast.next[0]);
+                match ast.token.tp {
+                    TokenType::Null => self.out.push_str(&format!("impl {} ", tp)),
+                    _ => self
+                        .out
+                        .push_str(&format!("impl {} for {} ", ast.token.s, tp)),
+                }
+                let open = self.open_after(&ast.next[0]);
+                self.braced(&ast.next[1..], open, indent, Printer::function);
+            }
+            _ => self.statement(ast, indent),
+        }
+    }
+
+    fn function(&mut self, ast: &AST, indent: usize) {
+        match ast.next[0].token.tp {
+            TokenType::Pub => self.out.push_str("pub "),
+            TokenType::Private => self.out.push_str("private "),
+            _ => {}
+        }
+        self.out.push_str(&format!("fn {}(", ast.next[1].token.s));
+        let params: Vec<String> = ast.next[2].next.iter().map(param).collect();
+        let ret = match ast.next[3].next.first() {
+            None => String::new(),
+            Some(x) => format!(" -> {}", type_name(x)),
+        };
+
+        let flat = params.join(", ");
+        let tail = ret.chars().count() + 3;
+        if params.is_empty() || self.col() + flat.chars().count() + tail <= self.width {
+            self.out.push_str(&flat);
+        } else {
+            self.out.push('\n');
+            for (i, x) in params.iter().enumerate() {
+                self.indent(indent + 1);
+                self.out.push_str(x);
+                if i + 1 < params.len() {
+                    self.out.push(',');
+                }
+                self.out.push('\n');
+            }
+            self.indent(indent);
+        }
+        self.out.push(')');
+        self.out.push_str(&ret);
+
+        match ast.next.get(4) {
+            None => self.out.push(';'),
+            Some(body) => {
+                self.out.push(' ');
+                self.block(body, indent);
+            }
+        }
+    }
+
+    fn field(&mut self, ast: &AST, _indent: usize) {
+        let tp = type_name(&ast.next[0]);
+        self.out.push_str(&format!("{}: {},", ast.token.s, tp));
+    }
+
+    fn variant(&mut self, ast: &AST, _indent: usize) {
+        self.out.push_str(&ast.token.s);
+        if !ast.next.is_empty() {
+            let payload: Vec<String> = ast.next.iter().map(type_name).collect();
+            self.out.push_str(&format!("({})", payload.join(", ")));
+        }
+        self.out.push(',');
+    }
+
+    fn statement(&mut self, ast: &AST, indent: usize) {
+        match ast.tp {
+            ASTtype::Variable => {
+                let attr = &ast.next[0].token.s;
+                self.out
+                    .push_str(&format!("{} {}", attr, ast.next[1].token.s));
+                if ast.next[2].token.tp != TokenType::Null {
+                    self.out.push_str(&format!(": {}", type_name(&ast.next[2])));
+                }
+                self.out.push_str(" = ");
+                self.expr(&ast.next[3], indent, 1);
+                self.out.push(';');
+            }
+            ASTtype::Assign => {
+                self.expr(&ast.next[0], indent, 0);
+                self.out.push_str(&format!(" {} ", ast.token.s));
+                self.expr(&ast.next[1], indent, 1);
+                self.out.push(';');
+            }
+            ASTtype::If => {
+                self.out.push_str("if ");
+                self.header(&ast.next[0], indent);
+                self.block(&ast.next[1], indent);
+                if let Some(other) = ast.next.get(2) {
+                    self.out.push_str(" else ");
+                    match other.tp {
+                        ASTtype::If => self.statement(other, indent),
+                        _ => self.block(other, indent),
+                    }
+                }
+            }
+            ASTtype::While => {
+                self.out.push_str("while ");
+                self.header(&ast.next[0], indent);
+                self.block(&ast.next[1], indent);
+            }
+            ASTtype::For => {
+                self.out
+                    .push_str(&format!("for {} in ", ast.next[0].token.s));
+                self.header(&ast.next[1], indent);
+                self.block(&ast.next[2], indent);
+            }
+            ASTtype::Match => self.expr(ast, indent, 0),
+            ASTtype::Return => {
+                self.out.push_str("return");
+                if let Some(value) = ast.next.first() {
+                    self.out.push(' ');
+                    self.expr(value, indent, 1);
+                }
+                self.out.push(';');
+            }
+            ASTtype::Break => self.out.push_str("break;"),
+            ASTtype::Continue => self.out.push_str("continue;"),
+            ASTtype::CodeBlock => self.block(ast, indent),
+            _ => {
+                self.expr(ast, indent, 1);
+                self.out.push(';');
+            }
+        }
+    }
+
+    //the expression of an if, while, for or match, then a space. a struct
+    //literal there would take the `{` of the block, it is put in parentheses
+    fn header(&mut self, ast: &AST, indent: usize) {
+        match bare_struct(ast) {
+            true => {
+                self.out.push('(');
+                self.expr(ast, indent, 3);
+                self.out.push(')');
+            }
+            false => self.expr(ast, indent, 2),
+        }
+        self.out.push(' ');
+    }
+
+    fn match_case(&mut self, ast: &AST, indent: usize) {
+        let pat = pattern(&ast.next[0], self);
+        self.out.push_str(&pat);
+        self.out.push_str(" => ");
+        match ast.next[1].tp {
+            ASTtype::CodeBlock => self.block(&ast.next[1], indent),
+            _ => {
+                self.expr(&ast.next[1], indent, 1);
+                self.out.push(',');
+            }
+        }
+    }
+
+    /*
+    print an expression at the output's column, on one line if it fits with
+    tail more columns after it and has no comment inside. otherwise the
+    argument lists in it are broken, an argument a line at indent + 1, and a
+    chain of method calls a call a line
+    */
+    fn expr(&mut self, ast: &AST, indent: usize, tail: usize) {
+        self.comments_inline(self.pos(self.min_index(ast)), indent);
+        if !self.commented(ast) {
+            if let Some(s) = self.flat(ast) {
+                if self.col() + s.chars().count() + tail <= self.width {
+                    self.out.push_str(&s);
+                    return;
+                }
+            }
+        }
+        if chain(ast).len() > 1 {
+            self.chain(ast, indent, tail);
+            return;
+        }
+
+        match ast.tp {
+            ASTtype::Binary => {
+                let prec = precedence(ast);
+                self.operand(&ast.next[0], precedence(&ast.next[0]) < prec, indent, 0);
+                match range(ast) {
+                    true => self.out.push_str(&ast.token.s),
+                    false => self.out.push_str(&format!(" {} ", ast.token.s)),
+                }
+                self.operand(&ast.next[1], precedence(&ast.next[1]) <= prec, indent, tail);
+            }
+            ASTtype::Unary => {
+                self.out.push_str(&ast.token.s);
+                let value = &ast.next[0];
+                self.operand(value, unary_paren(ast), indent, tail);
+            }
+            ASTtype::CallFunction => {
+                self.out.push_str(&ast.token.s);
+                self.args(ast, &ast.next, "(", ")", indent, tail);
+            }
+            ASTtype::CallMethod => {
+                self.receiver(&ast.next[0], indent);
+                self.out.push_str(&format!(".{}", ast.token.s));
+                self.args(ast, &ast.next[1..], "(", ")", indent, tail);
+            }
+            ASTtype::Field => {
+                self.receiver(&ast.next[0], indent);
+                self.out.push_str(&format!(".{}", ast.token.s));
+            }
+            ASTtype::Index => {
+                self.receiver(&ast.next[0], indent);
+                self.out.push('[');
+                self.expr(&ast.next[1], indent, tail + 1);
+                self.out.push(']');
+            }
+            ASTtype::Array => self.args(ast, &ast.next, "[", "]", indent, tail),
+            ASTtype::StructLiteral => {
+                self.out.push_str(&format!("{} {{\n", ast.token.s));
+                for f in ast.next.iter() {
+                    self.comments_before(self.line_of(self.index(&f.token)), indent + 1);
+                    self.indent(indent + 1);
+                    self.out.push_str(&format!("{}: ", f.token.s));
+                    self.expr(&f.next[0], indent + 1, 1);
+                    self.out.push_str(",\n");
+                }
+                self.comments_before(self.pos(self.last_index(ast)), indent + 1);
+                self.indent(indent);
+                self.out.push('}');
+            }
+            ASTtype::Match => {
+                self.out.push_str("match ");
+                self.header(&ast.next[0], indent);
+                let open = self.open_after(&ast.next[0]);
+                self.braced(&ast.next[1..], open, indent, Printer::match_case);
+            }
+            //a literal, as written even if it spans lines
+            _ => {
+                let s = self.flat(ast).unwrap_or_else(|| self.raw_text(&ast.token));
+                self.out.push_str(&s);
+            }
+        }
+    }
+
+    fn operand(&mut self, ast: &AST, paren: bool, indent: usize, tail: usize) {
+        match paren {
+            true => {
+                self.out.push('(');
+                self.expr(ast, indent, tail + 1);
+                self.out.push(')');
+            }
+            false => self.expr(ast, indent, tail),
+        }
+    }
+
+    //the value a method, field or index is taken of
+    fn receiver(&mut self, ast: &AST, indent: usize) {
+        let paren = matches!(ast.tp, ASTtype::Binary | ASTtype::Unary);
+        self.operand(ast, paren, indent, 0);
+    }
+
+    //the arguments of a call or elements of an array, ast: on the line if
+    //they fit with tail after them and have no comment, otherwise broken
+    //with open, an element a line at indent + 1, and close
+    fn args(
+        &mut self,
+        ast: &AST,
+        elems: &[AST],
+        open: &str,
+        close: &str,
+        indent: usize,
+        tail: usize,
+    ) {
+        let flat: Option<Vec<String>> = elems.iter().map(|x| self.flat(x)).collect();
+        let commented = elems.iter().any(|x| self.commented(x));
+        if let (Some(flat), false) = (flat, commented) {
+            let flat = flat.join(", ");
+            if self.col() + open.len() + flat.chars().count() + close.len() + tail <= self.width {
+                self.out.push_str(&format!("{}{}{}", open, flat, close));
+                return;
+            }
+        }
+
+        self.out.push_str(open);
+        if elems.is_empty() {
+            self.out.push_str(close);
+            return;
+        }
+        self.out.push('\n');
+        let end = self.last_index(ast);
+        if let Some(open) = end.and_then(|x| self.partner[x]) {
+            self.last_line = self.toks[open].line;
+        }
+        for (i, e) in elems.iter().enumerate() {
+            self.comments_before(self.line_of(self.min_index(e)), indent + 1);
+            self.indent(indent + 1);
+            let last = i + 1 == elems.len();
+            self.expr(e, indent + 1, !last as usize);
+            if !last {
+                self.out.push(',');
+            }
+            self.out.push('\n');
+            if let Some(x) = self.last_index(e) {
+                self.last_line = self.end_line(x);
+            }
+        }
+        self.comments_before(self.pos(end), indent + 1);
+        self.indent(indent);
+        self.out.push_str(close);
+    }
+
+    //a chain of method calls, its receiver then a call a line at indent + 1
+    fn chain(&mut self, ast: &AST, indent: usize, tail: usize) {
+        let links = chain(ast);
+        let start = &links.last().unwrap().next[0];
+        self.receiver(start, indent);
+        if let Some(x) = self.last_index(start) {
+            self.last_line = self.end_line(x);
+        }
+        for (i, link) in links.iter().rev().enumerate() {
+            self.out.push('\n');
+            self.comments_before(self.line_of(self.index(&link.token)), indent + 1);
+            self.indent(indent + 1);
+            self.out.push_str(&format!(".{}", link.token.s));
+            let last = i + 1 == links.len();
+            let tail = if last { tail } else { 0 };
+            if link.tp == ASTtype::CallMethod {
+                self.args(link, &link.next[1..], "(", ")", indent + 1, tail);
+            }
+            if let Some(x) = self.last_index(link) {
+                self.last_line = self.end_line(x);
+            }
+        }
+    }
+
+    //the expression on one line, None if it cannot be: it holds a match or
+    //a string literal spanning lines
+    fn flat(&self, ast: &AST) -> Option<String> {
+        let list = |elems: &[AST]| -> Option<String> {
+            let parts: Option<Vec<String>> = elems.iter().map(|x| self.flat(x)).collect();
+            return Some(parts?.join(", "));
+        };
+        let paren = |ast: &AST, paren: bool| -> Option<String> {
+            let s = self.flat(ast)?;
+            return match paren {
+                true => Some(format!("({})", s)),
+                false => Some(s),
+            };
+        };
+        let receiver = |ast: &AST| -> Option<String> {
+            return paren(ast, matches!(ast.tp, ASTtype::Binary | ASTtype::Unary));
+        };
+
+        return match ast.tp {
+            ASTtype::Literal => {
+                let raw = self.raw_text(&ast.token);
+                match raw.contains('\n') {
+                    true => None,
+                    false => Some(raw),
+                }
+            }
+            ASTtype::Ident => Some(ast.token.s.clone()),
+            ASTtype::Binary => {
+                let prec = precedence(ast);
+                let left = paren(&ast.next[0], precedence(&ast.next[0]) < prec)?;
+                let right = paren(&ast.next[1], precedence(&ast.next[1]) <= prec)?;
+                match range(ast) {
+                    true => Some(format!("{}{}{}", left, ast.token.s, right)),
+                    false => Some(format!("{} {} {}", left, ast.token.s, right)),
+                }
+            }
+            ASTtype::Unary => {
+                let value = paren(&ast.next[0], unary_paren(ast))?;
+                Some(format!("{}{}", ast.token.s, value))
+            }
+            ASTtype::CallFunction => Some(format!("{}({})", ast.token.s, list(&ast.next)?)),
+            ASTtype::CallMethod => {
+                let recv = receiver(&ast.next[0])?;
+                Some(format!(
+                    "{}.{}({})",
+                    recv,
+                    ast.token.s,
+                    list(&ast.next[1..])?
+                ))
+            }
+            ASTtype::Field => Some(format!("{}.{}", receiver(&ast.next[0])?, ast.token.s)),
+            ASTtype::Index => {
+                let base = receiver(&ast.next[0])?;
+                Some(format!("{}[{}]", base, self.flat(&ast.next[1])?))
+            }
+            ASTtype::Array => Some(format!("[{}]", list(&ast.next)?)),
+            ASTtype::StructLiteral => {
+                if ast.next.is_empty() {
+                    return Some(format!("{} {{}}", ast.token.s));
+                }
+                let mut fields: Vec<String> = Vec::new();
+                for f in ast.next.iter() {
+                    let value = &f.next[0];
+                    //`name` alone is `name: name`
+                    let short = value.tp == ASTtype::Ident
+                        && (value.token.line, value.token.col) == (f.token.line, f.token.col);
+                    match short {
+                        true => fields.push(f.token.s.clone()),
+                        false => fields.push(format!("{}: {}", f.token.s, self.flat(value)?)),
+                    }
+                }
+                Some(format!("{} {{ {} }}", ast.token.s, fields.join(", ")))
+            }
+            _ => None,
+        };
+    }
+
+    //a token as written, e.g. a string literal with its quotes and escapes
+    fn raw_text(&self, t: &Token) -> String {
+        return match self.index(t) {
+            None => t.s.clone(),
+            Some(i) => self.raw[i].clone(),
+        };
+    }
+}
+
+fn type_name(ast: &AST) -> String {
+    if ast.next.is_empty() {
+        return ast.token.s.clone();
+    }
+    let args: Vec<String> = ast.next.iter().map(type_name).collect();
+    return match ast.token.s.as_str() {
+        "template" => format!("template{{{}}}", args.join(", ")),
+        _ => format!("{}<{}>", ast.token.s, args.join(", ")),
+    };
+}
+
+fn attribute(ast: &AST) -> String {
+    if ast.next.is_empty() {
+        return format!("#[{}]", ast.token.s);
+    }
+    let args: Vec<&str> = ast.next.iter().map(|x| x.token.s.as_str()).collect();
+    return format!("#[{}({})]", ast.token.s, args.join(", "));
+}
+
+//`self` alone has its name for a type, at the same place
+fn param(ast: &AST) -> String {
+    let (name, tp) = (&ast.next[0].token, &ast.next[1].token);
+    if name.s == "self" && (name.line, name.col) == (tp.line, tp.col) {
+        return "self".to_string();
+    }
+    return format!("{}: {}", name.s, type_name(&ast.next[1]));
+}
+
+fn pattern(ast: &AST, p: &Printer) -> String {
+    return match ast.tp {
+        ASTtype::PatOr => {
+            let alts: Vec<String> = ast.next.iter().map(|x| pattern(x, p)).collect();
+            alts.join(" | ")
+        }
+        ASTtype::PatRange => {
+            let (lo, hi) = (pattern(&ast.next[0], p), pattern(&ast.next[1], p));
+            format!("{}{}{}", lo, ast.token.s, hi)
+        }
+        ASTtype::PatVariant if !ast.next.is_empty() => {
+            let subs: Vec<String> = ast.next.iter().map(|x| pattern(x, p)).collect();
+            format!("{}({})", ast.token.s, subs.join(", "))
+        }
+        //a negative number is at its `-`, the number follows
+        ASTtype::PatLiteral if ast.token.s.starts_with('-') => match p.index(&ast.token) {
+            None => ast.token.s.clone(),
+            Some(i) => format!("-{}", p.raw[i + 1]),
+        },
+        ASTtype::PatLiteral => p.raw_text(&ast.token),
+        _ => ast.token.s.clone(),
+    };
+}
+
+//the method calls of a chain, the last first. a field between them is a
+//link too. empty unless there are two calls or more
+fn chain(ast: &AST) -> Vec<&AST> {
+    let mut links: Vec<&AST> = Vec::new();
+    let mut at = ast;
+    while matches!(at.tp, ASTtype::CallMethod | ASTtype::Field) {
+        links.push(at);
+        at = &at.next[0];
+    }
+    let calls = links.iter().filter(|x| x.tp == ASTtype::CallMethod).count();
+    if calls < 2 {
+        links.clear();
+    }
+    return links;
+}
+
+fn range(ast: &AST) -> bool {
+    return matches!(ast.token.tp, TokenType::DotDot | TokenType::DotDotEqu);
+}
+
+//the operand of a unary operator in parentheses: a binary expression, or
+//a negation negated, which would read as `--`
+fn unary_paren(ast: &AST) -> bool {
+    let value = &ast.next[0];
+    return match value.tp {
+        ASTtype::Binary => true,
+        ASTtype::Unary => ast.token.s == "-" && value.token.s == "-",
+        _ => false,
+    };
+}
+
+//how tightly an expression binds, as the parser reads it
+fn precedence(ast: &AST) -> u8 {
+    return match ast.tp {
+        ASTtype::Binary => syntax::binary_precedence(ast.token.tp).unwrap_or(0),
+        ASTtype::Unary => 12,
+        _ => 13,
+    };
+}
+
+//a struct literal not inside parentheses or brackets
+fn bare_struct(ast: &AST) -> bool {
+    return match ast.tp {
+        ASTtype::StructLiteral => true,
+        ASTtype::Binary => bare_struct(&ast.next[0]) || bare_struct(&ast.next[1]),
+        ASTtype::Unary | ASTtype::CallMethod | ASTtype::Field | ASTtype::Index => {
+            bare_struct(&ast.next[0])
+        }
+        _ => false,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, DEFAULT_WIDTH};
+
+    fn fmt(src: &str) -> String {
+        let out = format(src, DEFAULT_WIDTH).unwrap();
+        assert_eq!(format(&out, DEFAULT_WIDTH).unwrap(), out, "not idempotent");
+        return out;
+    }
+
+    #[test]
+    fn test_format() {
+        let src = "fn add(a:i64,b:i64)->i64{return a+b*2;}\n\n\n\nvar x=add(1,-(2+3));\n";
+        assert_eq!(
+            fmt(src),
+            "fn add(a: i64, b: i64) -> i64 {\n    return a + b * 2;\n}\n\nvar x = add(1, -(2 + 3));\n"
+        );
+
+        //parentheses are kept where they are needed
+        assert_eq!(
+            fmt("var x=(1+2)*(3-(4-5));"),
+            "var x = (1 + 2) * (3 - (4 - 5));\n"
+        );
+        assert_eq!(
+            fmt("var x=((a.b)).c[0]+(-x).f;"),
+            "var x = a.b.c[0] + (-x).f;\n"
+        );
+        assert_eq!(fmt("x = -(-1) - -1;"), "x = -(-1) - -1;\n");
+
+        let src = "struct P{x:i64,y:Vec<i64>}enum E{A,B(i64,P),}\n\
+                   impl Show for P{fn show(self)->String{return \"p\";}}\
+                   trait Show{fn show(self)->String;}";
+        assert_eq!(
+            fmt(src),
+            "struct P {\n    x: i64,\n    y: Vec<i64>,\n}\nenum E {\n    A,\n    B(i64, P),\n}\n\
+             impl Show for P {\n    fn show(self) -> String {\n        return \"p\";\n    }\n}\n\
+             trait Show {\n    fn show(self) -> String;\n}\n"
+        );
+        assert_eq!(
+            fmt("// a point\npub struct P{x:i64}\npub type Q=P;"),
+            "// a point\npub struct P {\n    x: i64,\n}\npub type Q = P;\n"
+        );
+
+        let src = "if (P{x:1}).x==1{}else if !b{x+=1;}else{}\n\
+                   match e{E::A|E::B(_, -1..=5)=>print(\"a\\n\"),_=>{break;}}\
+                   for i in 0..10{continue;}";
+        assert_eq!(
+            fmt(src),
+            "if (P { x: 1 }.x == 1) {} else if !b {\n    x += 1;\n} else {}\n\
+             match e {\n    E::A | E::B(_, -1..=5) => print(\"a\\n\"),\n    _ => {\n        break;\n    }\n}\n\
+             for i in 0..10 {\n    continue;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let src = "#!/usr/bin/env rfx\n// head\n\nfn f(){ // opens\n  // inside\n  g(); /* after */\n\n\n  h();\n  // last\n}\nvar x = 1;   // I hope\n// tail\n";
+        assert_eq!(
+            fmt(src),
+            "#!/usr/bin/env rfx\n// head\n\nfn f() { // opens\n    // inside\n    g(); /* after */\n\n    h();\n    // last\n}\nvar x = 1; // I hope\n// tail\n"
+        );
+        assert_eq!(
+            fmt("fn f() {\n    // todo\n}"),
+            "fn f() {\n    // todo\n}\n"
+        );
+        //a comment inside an expression stays where it is, the expression is
+        //broken around it
+        assert_eq!(
+            fmt("f(a, // a\n  b);\ng();"),
+            "f(\n    a, // a\n    b\n);\ng();\n"
+        );
+        assert_eq!(
+            fmt("var x = 1 + // one\n2 + /* two */ 2;"),
+            "var x = 1 + // one\n    2 + /* two */ 2;\n"
+        );
+        assert_eq!(
+            fmt("g(/* none */ 1, [2, // two\n3]);"),
+            "g(\n    /* none */ 1,\n    [\n        2, // two\n        3\n    ]\n);\n"
+        );
+        assert!(format("fn f( {", DEFAULT_WIDTH).is_err());
+
+        let src =
+            "// lone\n#[allow(shadowing,unused_variable)] #[allow(unused_function)]\nfn f(){}";
+        assert_eq!(
+            fmt(src),
+            "// lone\n#[allow(shadowing, unused_variable)]\n#[allow(unused_function)]\nfn f() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_width() {
+        let src = "fn f(){print(\n\"two\nlines\"\n);}\nvar long = call(first_argument, second_argument, third_argument);";
+        assert_eq!(
+            format(src, 40).unwrap(),
+            "fn f() {\n    print(\n        \"two\nlines\"\n    );\n}\nvar long = call(\n    first_argument,\n    second_argument,\n    third_argument\n);\n"
+        );
+        //a chain of calls is broken before each call, not inside them
+        let src = "var s = builder.with_name(the_name).with_price(price).build(x, y);";
+        assert_eq!(
+            format(src, 40).unwrap(),
+            "var s = builder\n    .with_name(the_name)\n    .with_price(price)\n    .build(x, y);\n"
+        );
+
+        for f in std::fs::read_dir("language").unwrap() {
+            let path = f.unwrap().path();
+            if path.extension().is_none_or(|x| x != "lang") {
+                continue;
+            }
+            let src = std::fs::read_to_string(path).unwrap();
+            let out = fmt(&src);
+            let words = |s: &str| s.split_whitespace().collect::<String>();
+            assert_eq!(words(&out), words(&src));
+        }
+    }
+}
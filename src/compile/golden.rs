@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use super::{compile_modules, diag, dump_source, format, Target, WarningLevel, Warnings};
+use common::{root, sources};
+
+/*
+golden-file tests. every .lang file under tests/, and every sample in
+language/, is lexed, parsed and compiled, and each output is compared with
+its snapshot: NAME.tokens, NAME.ast, NAME.c and NAME.stderr next to the
+source, in tests/language/ for the samples. the files in tests/fmt/ are
+formatted too, into NAME.fmt. an output that is not made (the tree of a file
+that does not parse, the C of one that does not check, no diagnostics) must
+have no snapshot. with RFX_BLESS=1 the snapshots are written instead of
+compared:
+
+    RFX_BLESS=1 cargo test golden
+*/
+
+//snapshots of the C are for this target, whatever rfx runs on
+const GOLDEN_BITS: usize = 64;
+
+//the sources whose formatting is a snapshot
+const FMT_DIR: &str = "tests/fmt/";
+
+const BLESS_VAR: &str = "RFX_BLESS";
+
+//the snapshot of the source named name, without its extension
+fn snapshot_base(name: &str) -> PathBuf {
+    let stem = name.strip_suffix(".lang").unwrap_or(name);
+    return match stem.strip_prefix("language/") {
+        Some(x) => root().join("tests").join("language").join(x),
+        None => root().join(stem),
+    };
+}
+
+//each output of the source named name: the snapshot extension and the text,
+//None if it is not made
+fn outputs(name: &str, src: &str) -> Vec<(&'static str, Option<String>)> {
+    let (tokens, _) = dump_source(name, src, false);
+    let (ast, _) = dump_source(name, src, true);
+    let modules = [(name.to_string(), src.to_string())];
+    let warnings = Warnings::new(WarningLevel::Warn);
+    let target = Target::with_bits(GOLDEN_BITS);
+    let (c, diags) = compile_modules(&modules, target, 1, warnings.lints, None);
+    let diags = diag::apply_warnings(diags, warnings);
+    let c = c.filter(|_| !diag::has_error(&diags));
+    let stderr = diag::render(&[name.to_string()], &diags);
+    let stderr = Some(stderr).filter(|x| !x.is_empty());
+    let formatted = match name.starts_with(FMT_DIR) {
+        true => format::format(src, format::DEFAULT_WIDTH).ok(),
+        false => None,
+    };
+    return vec![
+        ("tokens", tokens),
+        ("ast", ast),
+        ("c", c),
+        ("stderr", stderr),
+        ("fmt", formatted),
+    ];
+}
+
+//why got does not match the snapshot at path, None if it does
+fn compare(path: &Path, got: &Option<String>) -> Option<String> {
+    let want = fs::read_to_string(path).ok();
+    let (want, got) = match (want, got) {
+        (None, None) => return None,
+        (Some(_), None) => return Some("is not made, but has a snapshot".to_string()),
+        (None, Some(_)) => return Some("has no snapshot".to_string()),
+        (Some(w), Some(g)) if w == *g => return None,
+        (Some(w), Some(g)) => (w, g.clone()),
+    };
+    let mut want_lines = want.lines();
+    let mut got_lines = got.lines();
+    let mut line = 1;
+    loop {
+        match (want_lines.next(), got_lines.next()) {
+            (Some(w), Some(g)) if w == g => line += 1,
+            (w, g) => {
+                return Some(format!(
+                    "differs at line {}\n  want: {}\n  got:  {}",
+                    line,
+                    w.unwrap_or("<end>"),
+                    g.unwrap_or("<end>")
+                ))
+            }
+        }
+    }
+}
+
+//write got as the snapshot at path, or remove the snapshot if it is None
+fn bless(path: &Path, got: &Option<String>) {
+    match got {
+        Some(x) => {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, x).unwrap();
+        }
+        None if path.exists() => fs::remove_file(path).unwrap(),
+        None => {}
+    }
+}
+
+#[test]
+fn test_golden() {
+    let blessing = std::env::var(BLESS_VAR).is_ok_and(|x| !x.is_empty() && x != "0");
+    let mut files = Vec::new();
+    sources(&root().join("tests"), &mut files);
+    sources(&root().join("language"), &mut files);
+    assert!(!files.is_empty());
+
+    let mut failures = Vec::new();
+    for path in files.iter() {
+        let name = path.strip_prefix(root()).unwrap().to_string_lossy();
+        let src = fs::read_to_string(path).unwrap();
+        let base = snapshot_base(&name);
+        for (ext, got) in outputs(&name, &src) {
+            let snapshot = base.with_extension(ext);
+            if blessing {
+                bless(&snapshot, &got);
+            } else if let Some(why) = compare(&snapshot, &got) {
+                failures.push(format!("{} ({}): {}", name, ext, why));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} golden outputs do not match, run with {}=1 to update them:\n{}",
+        failures.len(),
+        BLESS_VAR,
+        failures.join("\n")
+    );
+}
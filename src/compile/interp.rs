@@ -0,0 +1,1146 @@
+use std::io::Write;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::lex::{number_value, TokenType};
+use super::matching::{domain_of, pattern_of, Pat};
+use super::parser::{SymbolTable, TypeKind, TYPE_STRING};
+use super::typeck::{assert_eq_message, assert_message};
+
+/*
+a tree walking interpreter over the checked tree. it runs a program the way
+the C that codegen makes for it does: integers wrap at the width of their
+type, an index out of bounds panics, owned values are moved, copied out of
+fields and elements, and dropped at the end of their scope, innermost first,
+so `Drop` impls run in the same order. the output of the two is meant to be
+the same, which makes each a check of the other.
+
+one difference: dividing by zero is a panic here, where the C program dies
+of SIGFPE
+*/
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Void,
+    Int(i128), // integers and chars
+    Bool(bool),
+    Str(String),
+    Vec(Vec<Value>),
+    Struct(Vec<Value>),      // the fields in declaration order
+    Enum(usize, Vec<Value>), // variant index, payload
+}
+
+//how a block, a loop or a function body was left
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+//a panic of the program, its message
+pub(super) type Panic = String;
+
+//a variable, or an unnamed temporary kept until the end of its block. None
+//once it is moved out of. `fields` is the `self` of `Drop::drop`, of which
+//only the fields are dropped
+struct Local {
+    name: String,
+    value: Option<Value>,
+    tp: usize,
+    fields: bool,
+}
+
+//a step from a variable into one of its parts
+enum Step {
+    Field(usize),
+    Index(i128),
+}
+
+struct Interp<'a> {
+    table: &'a SymbolTable,
+    functions: Vec<Option<&'a AST>>, // the declaration of each function, by sym
+    dropping: Vec<bool>,             // by type, true if dropping it runs a user `drop`
+    frames: Vec<Vec<Vec<Local>>>, // scopes of each call; frames[0][0] has the module level variables
+    out: &'a mut dyn Write,
+}
+
+//run the program of a checked tree, writing what it prints to out. Err is
+//the message of a panic
+pub fn run(root: &AST, table: &SymbolTable, out: &mut dyn Write) -> Result<(), Panic> {
+    let mut it = Interp::new(root, table, out);
+    for item in root.next.iter() {
+        it.item(item)?;
+    }
+    it.close_scope()?;
+    it.out.flush().ok();
+    return Ok(());
+}
+
+/*
+run the top level statements of a checked tree after source line `from`,
+those of a REPL entry. globals has the values of the module level variables
+declared before, in order, and gets those of all of them after; the entry's
+temporaries are dropped, its variables kept. after a panic globals is as it
+was
+*/
+pub fn run_entry(
+    root: &AST,
+    table: &SymbolTable,
+    from: u64,
+    globals: &mut Vec<Option<Value>>,
+    out: &mut dyn Write,
+) -> Result<(), Panic> {
+    let mut it = Interp::new(root, table, out);
+    for (l, v) in it.frames[0][0].iter_mut().zip(globals.iter()) {
+        l.value = v.clone();
+    }
+    let n = it.frames[0][0].len();
+    for item in root.next.iter().filter(|x| x.token.line > from) {
+        it.item(item)?;
+    }
+    let temps = it.frames[0][0].split_off(n);
+    it.scopes().push(temps);
+    it.close_scope()?;
+    it.out.flush().ok();
+    *globals = it.frames[0][0].iter().map(|l| l.value.clone()).collect();
+    return Ok(());
+}
+
+impl<'a> Interp<'a> {
+    //an interpreter for the program, its module level variables declared
+    //but not set
+    fn new(root: &'a AST, table: &'a SymbolTable, out: &'a mut dyn Write) -> Self {
+        let mut functions: Vec<Option<&AST>> = vec![None; table.function_name.len()];
+        for item in root.next.iter() {
+            let fs = match item.tp {
+                ASTtype::Function => std::slice::from_ref(item),
+                ASTtype::Impl => &item.next[1..],
+                _ => continue,
+            };
+            for f in fs.iter().filter(|f| f.next.len() == 5) {
+                if let Some(sym) = f.sym {
+                    functions[sym] = Some(f);
+                }
+            }
+        }
+
+        let mut it = Interp {
+            table,
+            functions,
+            dropping: Vec::new(),
+            frames: vec![vec![Vec::new()]],
+            out,
+        };
+        it.dropping = (0..table.variable_type.len())
+            .map(|tp| runs_drop(table, tp, &mut Vec::new()))
+            .collect();
+
+        //module level variables are there from the start, set in source order
+        for item in root.next.iter().filter(|x| x.tp == ASTtype::Variable) {
+            it.frames[0][0].push(Local {
+                name: item.next[1].token.s.clone(),
+                value: None,
+                tp: item.vtype.unwrap(),
+                fields: false,
+            });
+        }
+        return it;
+    }
+
+    //run a top level item or statement
+    fn item(&mut self, item: &AST) -> Result<(), Panic> {
+        match item.tp {
+            ASTtype::Function
+            | ASTtype::Impl
+            | ASTtype::Trait
+            | ASTtype::TypeAlias
+            | ASTtype::Struct
+            | ASTtype::Enum
+            | ASTtype::Use => {}
+            ASTtype::Variable => {
+                let v = self.value(&item.next[3])?;
+                let name = &item.next[1].token.s;
+                let l = self.frames[0][0].iter_mut().find(|l| l.name == *name);
+                l.unwrap().value = Some(v);
+            }
+            _ => {
+                self.stmt(item)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+//the width in bits and signedness of the integer type tp
+fn int_ty(table: &SymbolTable, tp: usize) -> (u32, bool) {
+    let v = &table.variable_type[tp];
+    return (
+        v.size as u32 * 8,
+        matches!(v.kind, TypeKind::Integer { signed: true }),
+    );
+}
+
+//x as an integer of that many bits, wrapped like a C cast
+pub(super) fn wrap_bits(x: i128, bits: u32, signed: bool) -> i128 {
+    let x = x & ((1i128 << bits) - 1);
+    return match signed && x >> (bits - 1) != 0 {
+        true => x - (1i128 << bits),
+        false => x,
+    };
+}
+
+fn wrap(table: &SymbolTable, x: i128, tp: usize) -> i128 {
+    let (bits, signed) = int_ty(table, tp);
+    return wrap_bits(x, bits, signed);
+}
+
+//`l op r` on integers of that many bits
+pub(super) fn int_op(op: &str, l: i128, r: i128, bits: u32, signed: bool) -> Result<i128, Panic> {
+    let x = match op {
+        "+" => l.wrapping_add(r),
+        "-" => l.wrapping_sub(r),
+        "*" => l.wrapping_mul(r),
+        "/" | "%" if r == 0 => return Err("division by zero".to_string()),
+        "/" => l / r,
+        "%" => l % r,
+        "&" => l & r,
+        "|" => l | r,
+        "^" => l ^ r,
+        "<<" => l.wrapping_shl(r as u32),
+        ">>" => l.wrapping_shr(r as u32),
+        _ => unreachable!("operator {}", op),
+    };
+    return Ok(wrap_bits(x, bits, signed));
+}
+
+//`l op r` on integers of type tp
+fn arith(table: &SymbolTable, op: &str, l: i128, r: i128, tp: usize) -> Result<i128, Panic> {
+    let (bits, signed) = int_ty(table, tp);
+    return int_op(op, l, r, bits, signed);
+}
+
+//true if dropping a value of the type may run a user `drop`. only then is
+//dropping more than letting go of the value
+pub(super) fn runs_drop(table: &SymbolTable, tp: usize, visiting: &mut Vec<usize>) -> bool {
+    if table.drop_impl(tp).is_some() {
+        return true;
+    }
+    if visiting.contains(&tp) {
+        return false;
+    }
+    visiting.push(tp);
+    let parts: Vec<usize> = match table.kind(tp) {
+        TypeKind::Vec(x) => vec![*x],
+        TypeKind::Struct(fields) => fields.iter().map(|x| x.tp).collect(),
+        TypeKind::Enum(variants) => variants.iter().flat_map(|x| x.fields.clone()).collect(),
+        _ => Vec::new(),
+    };
+    let ret = parts.iter().any(|x| runs_drop(table, *x, visiting));
+    visiting.pop();
+    return ret;
+}
+
+//the bytes rfx_print_char writes for c
+pub(super) fn utf8(c: u32) -> Vec<u8> {
+    return match c {
+        //puts stops at the NUL of '\0'
+        0 => Vec::new(),
+        1..=0x7F => vec![c as u8],
+        0x80..=0x7FF => vec![0xC0 | (c >> 6) as u8, 0x80 | (c & 0x3F) as u8],
+        0x800..=0xFFFF => vec![
+            0xE0 | (c >> 12) as u8,
+            0x80 | ((c >> 6) & 0x3F) as u8,
+            0x80 | (c & 0x3F) as u8,
+        ],
+        _ => vec![
+            0xF0 | (c >> 18) as u8,
+            0x80 | ((c >> 12) & 0x3F) as u8,
+            0x80 | ((c >> 6) & 0x3F) as u8,
+            0x80 | (c & 0x3F) as u8,
+        ],
+    };
+}
+
+pub(super) fn matches_pat(p: &Pat, v: &Value) -> bool {
+    return match (p, v) {
+        (Pat::Wild, _) => true,
+        (Pat::Range(a, b), Value::Int(x)) => a <= x && x <= b,
+        (Pat::Range(a, b), Value::Bool(x)) => *a <= *x as i128 && *x as i128 <= *b,
+        (Pat::Str(s), Value::Str(x)) => s == x,
+        (Pat::Or(alts), _) => alts.iter().any(|x| matches_pat(x, v)),
+        (Pat::Variant(i, subs), Value::Enum(j, fields)) => {
+            i == j
+                && subs
+                    .iter()
+                    .zip(fields.iter())
+                    .all(|(p, f)| matches_pat(p, f))
+        }
+        _ => false,
+    };
+}
+
+impl Value {
+    pub(super) fn int(&self) -> i128 {
+        return match self {
+            Value::Int(x) => *x,
+            Value::Bool(x) => *x as i128,
+            _ => unreachable!("{:?} is not an integer", self),
+        };
+    }
+
+    pub(super) fn bool(&self) -> bool {
+        return match self {
+            Value::Bool(x) => *x,
+            _ => unreachable!("{:?} is not a bool", self),
+        };
+    }
+
+    pub(super) fn len(&self) -> usize {
+        return match self {
+            Value::Str(s) => s.len(),
+            Value::Vec(xs) => xs.len(),
+            _ => unreachable!("{:?} has no len", self),
+        };
+    }
+}
+
+impl<'a> Interp<'a> {
+    //the value a C variable of the type starts with, all zero
+    fn zero(&self, tp: usize) -> Value {
+        return match self.table.kind(tp) {
+            TypeKind::Void => Value::Void,
+            TypeKind::Integer { .. } | TypeKind::Char => Value::Int(0),
+            TypeKind::Bool => Value::Bool(false),
+            TypeKind::String => Value::Str(String::new()),
+            TypeKind::Vec(_) => Value::Vec(Vec::new()),
+            TypeKind::Struct(fields) => {
+                Value::Struct(fields.iter().map(|x| self.zero(x.tp)).collect())
+            }
+            TypeKind::Enum(variants) => {
+                let fields = variants
+                    .first()
+                    .map(|v| v.fields.clone())
+                    .unwrap_or_default();
+                Value::Enum(0, fields.iter().map(|x| self.zero(*x)).collect())
+            }
+        };
+    }
+
+    //the value as print shows it, without the newline
+    fn shown(&self, v: &Value, tp: usize) -> Vec<u8> {
+        return match (self.table.kind(tp), v) {
+            (TypeKind::Char, Value::Int(c)) => utf8(*c as u32),
+            (_, Value::Str(s)) => s.as_bytes().to_vec(),
+            (_, Value::Bool(x)) => x.to_string().into_bytes(),
+            (_, x) => x.int().to_string().into_bytes(),
+        };
+    }
+
+    fn print(&mut self, v: &Value, tp: usize) {
+        let mut bytes = self.shown(v, tp);
+        bytes.push(b'\n');
+        self.out.write_all(&bytes).ok();
+    }
+
+    fn scopes(&mut self) -> &mut Vec<Vec<Local>> {
+        return self.frames.last_mut().unwrap();
+    }
+
+    fn open_scope(&mut self) {
+        self.scopes().push(Vec::new());
+    }
+
+    //drop what the innermost scope still holds, last declared first
+    fn close_scope(&mut self) -> Result<(), Panic> {
+        let scope = self.scopes().pop().unwrap();
+        for l in scope.into_iter().rev() {
+            if let Some(v) = l.value {
+                match l.fields {
+                    true => self.drop_fields(v, l.tp)?,
+                    false => self.drop(v, l.tp)?,
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    fn declare(&mut self, name: &str, v: Value, tp: usize) {
+        self.scopes().last_mut().unwrap().push(Local {
+            name: name.to_string(),
+            value: Some(v),
+            tp,
+            fields: false,
+        });
+    }
+
+    //an owned value nothing takes over is dropped with the block, as codegen
+    //keeps it in a temporary
+    fn keep(&mut self, v: &Value, tp: usize) {
+        if self.dropping[tp] {
+            self.scopes().last_mut().unwrap().push(Local {
+                name: String::new(),
+                value: Some(v.clone()),
+                tp,
+                fields: false,
+            });
+        }
+    }
+
+    fn drop(&mut self, v: Value, tp: usize) -> Result<(), Panic> {
+        if !self.dropping[tp] {
+            return Ok(());
+        }
+        return match self.table.drop_impl(tp) {
+            Some(f) => self.call(f, vec![v]).map(|_| ()),
+            None => self.drop_fields(v, tp),
+        };
+    }
+
+    fn drop_fields(&mut self, v: Value, tp: usize) -> Result<(), Panic> {
+        let types: Vec<usize> = match (self.table.kind(tp), &v) {
+            (TypeKind::Vec(x), Value::Vec(xs)) => vec![*x; xs.len()],
+            (TypeKind::Struct(fields), _) => fields.iter().map(|x| x.tp).collect(),
+            (TypeKind::Enum(_), Value::Enum(i, _)) => self.table.variant(tp, *i).fields.clone(),
+            _ => return Ok(()),
+        };
+        let parts = match v {
+            Value::Vec(xs) | Value::Struct(xs) | Value::Enum(_, xs) => xs,
+            _ => return Ok(()),
+        };
+        for (x, t) in parts.into_iter().zip(types) {
+            self.drop(x, t)?;
+        }
+        return Ok(());
+    }
+
+    //the variable named name: a local of the running function or a module
+    //level one
+    fn local(&mut self, name: &str) -> &mut Local {
+        let frame = self.frames.len() - 1;
+        let found = self.frames[frame]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, s)| s.iter().rposition(|l| l.name == name).map(|k| (i, k)));
+        let (f, i, k) = match found {
+            Some((i, k)) => (frame, i, k),
+            None => {
+                let k = self.frames[0][0].iter().rposition(|l| l.name == name);
+                (
+                    0,
+                    0,
+                    k.unwrap_or_else(|| unreachable!("no variable {}", name)),
+                )
+            }
+        };
+        return &mut self.frames[f][i][k];
+    }
+
+    fn read_local(&mut self, name: &str) -> Value {
+        let l = self.local(name);
+        return match &l.value {
+            Some(v) => v.clone(),
+            //a module level variable not set yet is zero, as in C
+            None => {
+                let tp = l.tp;
+                self.zero(tp)
+            }
+        };
+    }
+
+    fn is_place(ast: &AST) -> bool {
+        return match ast.tp {
+            ASTtype::Ident => true,
+            ASTtype::Field | ASTtype::Index => Self::is_place(&ast.next[0]),
+            _ => false,
+        };
+    }
+
+    //the variable and the steps into it a place expression names
+    fn path(&mut self, ast: &AST, steps: &mut Vec<Step>) -> Result<String, Panic> {
+        return match ast.tp {
+            ASTtype::Ident => Ok(ast.token.s.clone()),
+            ASTtype::Field => {
+                let name = self.path(&ast.next[0], steps)?;
+                steps.push(Step::Field(self.field_index(&ast.next[0], &ast.token.s)));
+                Ok(name)
+            }
+            _ => {
+                let name = self.path(&ast.next[0], steps)?;
+                let i = self.expr(&ast.next[1])?.int();
+                steps.push(Step::Index(i));
+                Ok(name)
+            }
+        };
+    }
+
+    fn field_index(&self, base: &AST, name: &str) -> usize {
+        return match self.table.kind(base.vtype.unwrap()) {
+            TypeKind::Struct(fields) => fields.iter().position(|x| x.name == name).unwrap(),
+            _ => unreachable!(),
+        };
+    }
+
+    //the part of a variable a place expression names
+    fn place(&mut self, ast: &AST) -> Result<&mut Value, Panic> {
+        let mut steps: Vec<Step> = Vec::new();
+        let name = self.path(ast, &mut steps)?;
+        let l = self.local(&name);
+        if l.value.is_none() {
+            let tp = l.tp;
+            let z = self.zero(tp);
+            self.local(&name).value = Some(z);
+        }
+        let mut v = self.local(&name).value.as_mut().unwrap();
+        for s in steps {
+            v = match (s, v) {
+                (Step::Field(i), Value::Struct(fields)) => &mut fields[i],
+                (Step::Index(i), Value::Vec(xs)) => {
+                    if i < 0 || i >= xs.len() as i128 {
+                        return Err("index out of bounds".to_string());
+                    }
+                    &mut xs[i as usize]
+                }
+                _ => unreachable!(),
+            };
+        }
+        return Ok(v);
+    }
+
+    //an expression whose value is taken over: a variable is moved out of, a
+    //field or an element is copied
+    fn value(&mut self, ast: &AST) -> Result<Value, Panic> {
+        let tp = ast.vtype.unwrap();
+        if ast.tp == ASTtype::Ident && self.table.is_owned(tp) {
+            let l = self.local(&ast.token.s);
+            return Ok(match l.value.take() {
+                Some(v) => v,
+                None => self.zero(tp),
+            });
+        }
+        return self.expr(ast);
+    }
+
+    //an expression that is only read
+    fn borrow(&mut self, ast: &AST) -> Result<Value, Panic> {
+        let v = self.expr(ast)?;
+        let tp = ast.vtype.unwrap();
+        if !matches!(
+            ast.tp,
+            ASTtype::Ident | ASTtype::Field | ASTtype::Index | ASTtype::Literal
+        ) {
+            self.keep(&v, tp);
+        }
+        return Ok(v);
+    }
+
+    fn stmts(&mut self, stmts: &[AST]) -> Result<Flow, Panic> {
+        self.open_scope();
+        let mut flow = Flow::Next;
+        for stmt in stmts.iter() {
+            flow = self.stmt(stmt)?;
+            if !matches!(flow, Flow::Next) {
+                break;
+            }
+        }
+        self.close_scope()?;
+        return Ok(flow);
+    }
+
+    fn stmt(&mut self, ast: &AST) -> Result<Flow, Panic> {
+        match ast.tp {
+            ASTtype::Variable => {
+                let v = self.value(&ast.next[3])?;
+                self.declare(&ast.next[1].token.s, v, ast.vtype.unwrap());
+            }
+            ASTtype::Assign => self.assign(ast)?,
+            ASTtype::CodeBlock => return self.stmts(&ast.next),
+            ASTtype::If => return self.if_chain(ast),
+            ASTtype::While => return self.while_loop(ast),
+            ASTtype::For => return self.for_loop(ast),
+            ASTtype::Match => return self.match_value(ast, false).map(|x| x.1),
+            ASTtype::Return => {
+                let v = match ast.next.first() {
+                    None => Value::Void,
+                    Some(x) => self.value(x)?,
+                };
+                return Ok(Flow::Return(v));
+            }
+            ASTtype::Break => return Ok(Flow::Break),
+            ASTtype::Continue => return Ok(Flow::Continue),
+            _ => {
+                self.borrow(ast)?;
+            }
+        }
+        return Ok(Flow::Next);
+    }
+
+    fn if_chain(&mut self, ast: &AST) -> Result<Flow, Panic> {
+        if self.expr(&ast.next[0])?.bool() {
+            return self.stmts(&ast.next[1].next);
+        }
+        return match ast.next.get(2) {
+            None => Ok(Flow::Next),
+            Some(x) if x.tp == ASTtype::If => self.if_chain(x),
+            Some(x) => self.stmts(&x.next),
+        };
+    }
+
+    //run a loop body, the flow says whether to go on
+    fn loop_body(&mut self, body: &AST) -> Result<Option<Flow>, Panic> {
+        return Ok(match self.stmts(&body.next)? {
+            Flow::Break => Some(Flow::Next),
+            Flow::Return(v) => Some(Flow::Return(v)),
+            _ => None,
+        });
+    }
+
+    fn while_loop(&mut self, ast: &AST) -> Result<Flow, Panic> {
+        loop {
+            //temporaries of the condition are made and dropped each pass
+            self.open_scope();
+            let go = self.expr(&ast.next[0])?.bool();
+            let stop = match go {
+                false => Some(Flow::Next),
+                true => self.loop_body(&ast.next[1])?,
+            };
+            self.close_scope()?;
+            if let Some(flow) = stop {
+                return Ok(flow);
+            }
+        }
+    }
+
+    fn for_loop(&mut self, ast: &AST) -> Result<Flow, Panic> {
+        let iter = &ast.next[1];
+        let tp = ast.next[0].vtype.unwrap();
+        let name = &ast.next[0].token.s;
+
+        self.open_scope();
+        let items: Box<dyn Iterator<Item = Value>> = match iter.tp {
+            ASTtype::Binary
+                if matches!(iter.token.tp, TokenType::DotDot | TokenType::DotDotEqu) =>
+            {
+                let a = self.expr(&iter.next[0])?.int();
+                let b = self.expr(&iter.next[1])?.int();
+                match iter.token.tp {
+                    TokenType::DotDot => Box::new((a..b).map(Value::Int)),
+                    _ => Box::new((a..=b).map(Value::Int)),
+                }
+            }
+            _ => match self.borrow(iter)? {
+                Value::Vec(xs) => Box::new(xs.into_iter()),
+                _ => unreachable!(),
+            },
+        };
+        let mut flow = Flow::Next;
+        for x in items {
+            self.open_scope();
+            self.declare(name, x, tp);
+            let stop = self.loop_body(&ast.next[2])?;
+            self.close_scope()?;
+            if let Some(f) = stop {
+                flow = f;
+                break;
+            }
+        }
+        self.close_scope()?;
+        return Ok(flow);
+    }
+
+    fn assign(&mut self, ast: &AST) -> Result<(), Panic> {
+        let tp = ast.vtype.unwrap();
+        let (place, value) = (&ast.next[0], &ast.next[1]);
+
+        let new = match ast.token.tp {
+            TokenType::Equal => self.value(value)?,
+            _ if tp == TYPE_STRING => {
+                let x = self.borrow(value)?;
+                match (self.place(place)?, x) {
+                    (Value::Str(s), Value::Str(x)) => Value::Str(s.clone() + &x),
+                    _ => unreachable!(),
+                }
+            }
+            _ => {
+                let x = self.expr(value)?.int();
+                let old = self.place(place)?.int();
+                let op = ast.token.s.trim_end_matches('=');
+                Value::Int(arith(self.table, op, old, x, tp)?)
+            }
+        };
+        //the old value goes away once the new one is ready. a variable moved
+        //out of has none
+        if place.tp == ASTtype::Ident {
+            return match self.local(&place.token.s).value.replace(new) {
+                None => Ok(()),
+                Some(old) => self.drop(old, tp),
+            };
+        }
+        let old = std::mem::replace(self.place(place)?, new);
+        return self.drop(old, tp);
+    }
+
+    fn expr(&mut self, ast: &AST) -> Result<Value, Panic> {
+        match ast.tp {
+            ASTtype::Literal => {
+                return Ok(match ast.token.tp {
+                    TokenType::Number => Value::Int(number_value(&ast.token.s).unwrap()),
+                    TokenType::StringLiteral => Value::Str(ast.token.s.clone()),
+                    TokenType::CharLiteral => {
+                        Value::Int(ast.token.s.chars().next().unwrap() as i128)
+                    }
+                    TokenType::True => Value::Bool(true),
+                    _ => Value::Bool(false),
+                });
+            }
+            ASTtype::Ident => return Ok(self.read_local(&ast.token.s)),
+            ASTtype::Unary => {
+                let x = &ast.next[0];
+                if ast.token.tp == TokenType::Minus && x.tp == ASTtype::Literal {
+                    return Ok(Value::Int(-number_value(&x.token.s).unwrap()));
+                }
+                let tp = ast.vtype.unwrap();
+                return Ok(match (ast.token.tp, self.expr(x)?) {
+                    (TokenType::Bang, Value::Bool(b)) => Value::Bool(!b),
+                    (TokenType::Bang, v) => Value::Int(wrap(self.table, !v.int(), tp)),
+                    (_, v) => Value::Int(wrap(self.table, v.int().wrapping_neg(), tp)),
+                });
+            }
+            ASTtype::Binary => return self.binary(ast),
+            ASTtype::CallFunction => return self.call_function(ast),
+            ASTtype::CallMethod => return self.call_method(ast),
+            ASTtype::Index | ASTtype::Field if Self::is_place(ast) => {
+                //copied out of the variable, which stays where it is
+                return Ok(self.place(ast)?.clone());
+            }
+            ASTtype::Index => {
+                let base = self.borrow(&ast.next[0])?;
+                let i = self.expr(&ast.next[1])?.int();
+                return match base {
+                    Value::Vec(mut xs) if i >= 0 && i < xs.len() as i128 => {
+                        Ok(xs.swap_remove(i as usize))
+                    }
+                    _ => Err("index out of bounds".to_string()),
+                };
+            }
+            ASTtype::Field => {
+                let i = self.field_index(&ast.next[0], &ast.token.s);
+                return match self.borrow(&ast.next[0])? {
+                    Value::Struct(mut fields) => Ok(fields.swap_remove(i)),
+                    _ => unreachable!(),
+                };
+            }
+            ASTtype::Array => {
+                let mut xs: Vec<Value> = Vec::new();
+                for x in ast.next.iter() {
+                    xs.push(self.value(x)?);
+                }
+                return Ok(Value::Vec(xs));
+            }
+            ASTtype::StructLiteral => {
+                let tp = ast.vtype.unwrap();
+                let mut v = match self.zero(tp) {
+                    Value::Struct(x) => x,
+                    _ => unreachable!(),
+                };
+                for init in ast.next.iter() {
+                    let i = match self.table.kind(tp) {
+                        TypeKind::Struct(fields) => {
+                            fields.iter().position(|x| x.name == init.token.s)
+                        }
+                        _ => None,
+                    };
+                    v[i.unwrap()] = self.value(&init.next[0])?;
+                }
+                return Ok(Value::Struct(v));
+            }
+            ASTtype::Variant => {
+                let mut xs: Vec<Value> = Vec::new();
+                for x in ast.next.iter() {
+                    xs.push(self.value(x)?);
+                }
+                return Ok(Value::Enum(ast.sym.unwrap(), xs));
+            }
+            ASTtype::Match => {
+                //the arms are a scope of their own, as codegen's statement expression
+                return self.match_value(ast, true).map(|x| x.0);
+            }
+            _ => unreachable!("{:?} is not an expression", ast.tp),
+        }
+    }
+
+    fn binary(&mut self, ast: &AST) -> Result<Value, Panic> {
+        match ast.token.tp {
+            TokenType::LogicAND => {
+                let l = self.borrow(&ast.next[0])?.bool();
+                return Ok(Value::Bool(l && self.borrow(&ast.next[1])?.bool()));
+            }
+            TokenType::LogicOR => {
+                let l = self.borrow(&ast.next[0])?.bool();
+                return Ok(Value::Bool(l || self.borrow(&ast.next[1])?.bool()));
+            }
+            _ => {}
+        }
+
+        let l = self.borrow(&ast.next[0])?;
+        let r = self.borrow(&ast.next[1])?;
+        if let (Value::Str(a), Value::Str(b)) = (&l, &r) {
+            return Ok(match ast.token.tp {
+                TokenType::Plus => Value::Str(a.clone() + b),
+                TokenType::LogicEqual => Value::Bool(a == b),
+                _ => Value::Bool(a != b),
+            });
+        }
+        let (l, r) = (l.int(), r.int());
+        return Ok(match ast.token.tp {
+            TokenType::LogicEqual => Value::Bool(l == r),
+            TokenType::LogicNotEqual => Value::Bool(l != r),
+            TokenType::LeftABrack => Value::Bool(l < r),
+            TokenType::RightABrack => Value::Bool(l > r),
+            TokenType::LessEqual => Value::Bool(l <= r),
+            TokenType::GreaterEqual => Value::Bool(l >= r),
+            _ => Value::Int(arith(self.table, &ast.token.s, l, r, ast.vtype.unwrap())?),
+        });
+    }
+
+    fn call_function(&mut self, ast: &AST) -> Result<Value, Panic> {
+        if let Some(sym) = ast.sym {
+            let mut args: Vec<Value> = Vec::new();
+            for x in ast.next.iter() {
+                args.push(self.value(x)?);
+            }
+            return self.call(sym, args);
+        }
+
+        return match ast.token.s.as_str() {
+            "assert" => match self.expr(&ast.next[0])?.bool() {
+                true => Ok(Value::Void),
+                false => Err(assert_message(self.table, ast)),
+            },
+            "assert_eq" => {
+                let (l, r) = (self.borrow(&ast.next[0])?, self.borrow(&ast.next[1])?);
+                if l == r {
+                    return Ok(Value::Void);
+                }
+                let tp = ast.next[0].vtype.unwrap();
+                let (l, r) = (self.shown(&l, tp), self.shown(&r, tp));
+                let msg = assert_message(self.table, ast);
+                let (l, r) = (String::from_utf8_lossy(&l), String::from_utf8_lossy(&r));
+                Err(assert_eq_message(&msg, &l, &r))
+            }
+            "print" => {
+                let x = &ast.next[0];
+                let v = self.borrow(x)?;
+                self.print(&v, x.vtype.unwrap());
+                Ok(Value::Void)
+            }
+            "Vec::new" => Ok(Value::Vec(Vec::new())),
+            //Vec::new_init
+            _ => self.value(&ast.next[0]),
+        };
+    }
+
+    fn call_method(&mut self, ast: &AST) -> Result<Value, Panic> {
+        if let Some(sym) = ast.sym {
+            let mut args: Vec<Value> = Vec::new();
+            for x in ast.next.iter() {
+                args.push(self.value(x)?);
+            }
+            return self.call(sym, args);
+        }
+
+        let recv = &ast.next[0];
+        if ast.token.s == "len" {
+            let n = match Self::is_place(recv) {
+                true => self.place(recv)?.len(),
+                false => self.borrow(recv)?.len(),
+            };
+            return Ok(Value::Int(n as i128));
+        }
+        //push
+        let x = self.value(&ast.next[1])?;
+        match self.place(recv)? {
+            Value::Vec(xs) => xs.push(x),
+            _ => unreachable!(),
+        }
+        return Ok(Value::Void);
+    }
+
+    fn call(&mut self, sym: usize, args: Vec<Value>) -> Result<Value, Panic> {
+        let table = self.table;
+        let f = self.functions[sym].unwrap_or_else(|| unreachable!("no body for {}", sym));
+        let decl = &table.function_name[sym];
+        //`self` of `Drop::drop` only drops its fields at the end
+        let dropping = table.drop_impl.iter().any(|(_, f)| *f == sym);
+        let scope: Vec<Local> = decl
+            .args
+            .iter()
+            .zip(args)
+            .enumerate()
+            .map(|(i, (a, v))| Local {
+                name: a.name.clone(),
+                value: Some(v),
+                tp: a.tp,
+                fields: dropping && i == 0,
+            })
+            .collect();
+
+        self.frames.push(vec![scope]);
+        let flow = self.stmts(&f.next[4].next);
+        let flow = flow.and_then(|x| self.close_scope().map(|_| x));
+        self.frames.pop();
+        return Ok(match flow? {
+            Flow::Return(v) => v,
+            _ => Value::Void,
+        });
+    }
+
+    //run a match. the value is that of the arm for a match expression
+    fn match_value(&mut self, ast: &AST, expr: bool) -> Result<(Value, Flow), Panic> {
+        let tp = ast.next[0].vtype.unwrap();
+        let dom = domain_of(self.table, tp).unwrap();
+
+        self.open_scope();
+        let m = self.borrow(&ast.next[0])?;
+        let case = ast.next[1..].iter().find(|c| {
+            let p = pattern_of(&c.next[0], &dom, self.table).unwrap();
+            matches_pat(&p, &m)
+        });
+        let case = case.unwrap_or_else(|| unreachable!("no arm matched"));
+
+        self.open_scope();
+        self.bind(&case.next[0], &m);
+        let body = &case.next[1];
+        let ret = match body.tp {
+            ASTtype::CodeBlock => (Value::Void, self.stmts(&body.next)?),
+            _ if expr => (self.value(body)?, Flow::Next),
+            _ => {
+                self.borrow(body)?;
+                (Value::Void, Flow::Next)
+            }
+        };
+        self.close_scope()?;
+        self.close_scope()?;
+        return Ok(ret);
+    }
+
+    //declare the bindings of a pattern matched by m
+    fn bind(&mut self, pat: &AST, m: &Value) {
+        match (pat.tp, m) {
+            (ASTtype::PatBinding, _) => self.declare(&pat.token.s, m.clone(), pat.vtype.unwrap()),
+            (ASTtype::PatVariant, Value::Enum(_, fields)) => {
+                for (sub, x) in pat.next.iter().zip(fields.iter()) {
+                    self.bind(sub, x);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::driver::run_c;
+    use super::super::{check_modules, compile_modules, Lints, Target};
+
+    //what src prints when interpreted, and how it ended
+    fn interpret(src: &str) -> (String, Result<(), String>) {
+        let modules = [(String::new(), src.to_string())];
+        let (checked, diags) = check_modules(&modules, Target::host(), 1, Lints::NONE, None);
+        let (root, table) = checked.unwrap_or_else(|| panic!("{:?}", diags[0].msg));
+        let mut out: Vec<u8> = Vec::new();
+        let ended = super::run(&root, &table, &mut out);
+        return (String::from_utf8(out).unwrap(), ended);
+    }
+
+    const PROGRAMS: &[&str] = &[
+        r#"
+var b: u8 = 250;
+b += 10;
+print(b);
+var i: i8 = 127;
+i = i + 1;
+print(i);
+var u: u64 = 0;
+u = u - 1;
+print(u);
+print(-7 / 2);
+print(-7 % 2);
+var z: u16 = 0;
+print(!z);
+print('é');
+var s: String = "ab";
+s += "cd";
+print(s + "!");
+print(s.len() == 4);
+"#,
+        r#"
+struct Point { x: i64, y: i64 }
+enum Shape { Circle(i64), Rect(Point, Point), Empty }
+trait Area {
+    fn area(s: self) -> i64;
+}
+impl Area for Shape {
+    fn area(s: self) -> i64 {
+        return match s {
+            Shape::Circle(r) => 3 * r * r,
+            Shape::Rect(a, b) => (b.x - a.x) * (b.y - a.y),
+            Shape::Empty => 0,
+        };
+    }
+}
+fn fib(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+print(Shape::Rect(Point { x: 0, y: 0 }, Point { x: 2, y: 3 }).area());
+print(Shape::Empty.area());
+print(fib(15));
+for k in 0..=3 {
+    match k {
+        0 => print("zero"),
+        1 | 2 => print("small"),
+        _ => print("big"),
+    }
+}
+var n: i64 = 0;
+while n < 10 {
+    n += 1;
+    if n % 2 == 0 {
+        continue;
+    }
+    if n > 7 {
+        break;
+    }
+    print(n);
+}
+"#,
+        r#"
+struct G { id: i64 }
+impl Drop for G {
+    fn drop(self) {
+        print(self.id);
+    }
+}
+fn make(id: i64) -> G {
+    var g: G = G { id: id };
+    return g;
+}
+var total: i64 = 0;
+fn add(x: i64) {
+    total += x;
+}
+var v: Vec<i64> = [3, 1, 4];
+v.push(1);
+v[0] = 9;
+for x in v {
+    add(x);
+}
+print(total);
+var gs: Vec<G> = [make(1), make(2)];
+var g: G = make(3);
+g = make(4);
+{
+    var inner: G = make(5);
+}
+var m: Vec<Vec<String>> = [["a"], []];
+m[1].push("b");
+m[1][0] += "c";
+print(m[1][0]);
+print(m[2].len());
+"#,
+        r#"
+var big: i64 = 9223372036854775807;
+print(big + 1);
+var min: i64 = -9223372036854775807 - 1;
+print(min * -1);
+var neg: i64 = -1;
+print(min / neg);
+print(min % neg);
+var w: u16 = 65535;
+print(w * w);
+var n: i32 = -2147483647 - 1;
+print(n - 1);
+var c: i8 = -128;
+var d: i8 = -1;
+print(c / d);
+var zero: u8 = 0;
+print(7 % 3);
+print(1 / zero);
+print(2);
+"#,
+        r#"
+var s: String = "a";
+assert_eq(s + "b", "ab");
+assert_eq('é', 'é');
+var n: u8 = 200;
+assert_eq(n + 100, 44);
+assert_eq(n > 0, true);
+print(1);
+assert_eq(s + "b", "b");
+print(2);
+"#,
+    ];
+
+    #[test]
+    fn test_interpret() {
+        let (out, ended) = interpret(PROGRAMS[0]);
+        assert_eq!(
+            out,
+            "4\n-128\n18446744073709551615\n-3\n-1\n65535\né\nabcd!\ntrue\n"
+        );
+        assert!(ended.is_ok());
+
+        let (out, ended) = interpret(PROGRAMS[2]);
+        //user drops run as the values go away: the old g, then inner. the
+        //panic ends the program before the rest are dropped
+        assert_eq!(out, "15\n3\n5\nbc\n");
+        assert_eq!(ended, Err("index out of bounds".to_string()));
+
+        let (out, _) = interpret("var s: String = \"x\";\nmatch s {\n    \"y\" => print(1),\n    other => print(other + other),\n}");
+        assert_eq!(out, "xx\n");
+
+        let (out, ended) = interpret(PROGRAMS[3]);
+        assert_eq!(
+            out,
+            "-9223372036854775808\n-9223372036854775808\n-9223372036854775808\n0\n1\n2147483647\n-128\n1\n"
+        );
+        assert_eq!(ended, Err("division by zero".to_string()));
+
+        let (out, ended) = interpret(PROGRAMS[4]);
+        assert_eq!(out, "1\n");
+        let msg = "assertion `left == right` failed at 9:1\n  left: ab\n right: b";
+        assert_eq!(ended, Err(msg.to_string()));
+    }
+
+    //the interpreter is the oracle of the C backend: both must print the same
+    //and end the same. needs cc, skipped without one
+    #[test]
+    fn test_differential() {
+        for (i, src) in PROGRAMS.iter().enumerate() {
+            let (want, ended) = interpret(src);
+            let (c, _) = compile_modules(
+                &[(String::new(), src.to_string())],
+                Target::host(),
+                1,
+                Lints::NONE,
+                None,
+            );
+            let (out, code, stderr) = match run_c(&c.unwrap()) {
+                None => return,
+                Some(x) => x,
+            };
+            assert_eq!(out, want, "program {}", i);
+            let (want_code, want_stderr) = match ended {
+                Ok(_) => (0, String::new()),
+                Err(msg) => (101, format!("rfx panic: {}\n", msg)),
+            };
+            assert_eq!((code, stderr), (want_code, want_stderr), "program {}", i);
+        }
+    }
+}
@@ -1,19 +1,23 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufReader, Read};
 use std::str::Chars;
 
 #[allow(dead_code)]
-#[derive(PartialEq, Clone, Copy, strum_macros::Display)]
+#[derive(PartialEq, Debug, Clone, Copy, strum_macros::Display, strum_macros::EnumString)]
 pub enum TokenType {
     Null,
     Broken,
     String,
     Number,
+    StringLiteral,
+    CharLiteral,
 
     LogicEqual,
     LogicNotEqual,
     LogicAND,
     LogicOR,
+    LessEqual,
+    GreaterEqual,
 
     LeftParen,
     RightParen,
@@ -54,20 +58,38 @@ pub enum TokenType {
     Quote,
     DblQuote,
     Dot,
+    DotDot,
+    DotDotEqu,
     Comma,
+    Colon,
     Semicolon,
     DblColon,
     Arrow,
+    FatArrow,
+    Hash,
 
     Const,
+    Var,
     Impl,
     Trait,
+    Type,
+    Struct,
+    Enum,
     Pub,
     Private,
 
     If,
+    Else,
     While,
     For,
+    In,
+    Match,
+    Return,
+    Break,
+    Continue,
+
+    True,
+    False,
 
     Use,
     Fn,
@@ -75,12 +97,16 @@ pub enum TokenType {
     LineComment,
     LongComment,
     BrokenLeftLongComment,
+    DocComment, // `///` or `/** */`, s is the text without the markers
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Token {
     pub s: String,
     pub tp: TokenType,
+    pub line: u64,
+    pub col: u64,
+    pub file: usize, // which of the linked modules, see compile_modules
 }
 
 impl Token {
@@ -88,6 +114,9 @@ impl Token {
         Token {
             s: String::new(),
             tp: TokenType::Null,
+            line: 0,
+            col: 0,
+            file: 0,
         }
     }
 }
@@ -98,7 +127,20 @@ struct TokenDict<'a> {
     tp: TokenType,
 }
 
-static LONG_SP_OPERATOR: &'static [TokenDict] = &[
+// longest operators first, so "<<=" wins over "<<"
+static LONG_SP_OPERATOR: &[TokenDict] = &[
+    TokenDict {
+        s: "<<=",
+        tp: TokenType::LeftBShiftEqu,
+    },
+    TokenDict {
+        s: ">>=",
+        tp: TokenType::RightBShiftEqu,
+    },
+    TokenDict {
+        s: "..=",
+        tp: TokenType::DotDotEqu,
+    },
     TokenDict {
         s: "==",
         tp: TokenType::LogicEqual,
@@ -115,6 +157,14 @@ static LONG_SP_OPERATOR: &'static [TokenDict] = &[
         s: "||",
         tp: TokenType::LogicOR,
     },
+    TokenDict {
+        s: "<=",
+        tp: TokenType::LessEqual,
+    },
+    TokenDict {
+        s: ">=",
+        tp: TokenType::GreaterEqual,
+    },
     TokenDict {
         s: "<<",
         tp: TokenType::LeftBShift,
@@ -143,18 +193,6 @@ static LONG_SP_OPERATOR: &'static [TokenDict] = &[
         s: "%=",
         tp: TokenType::ModEqu,
     },
-    TokenDict {
-        s: "/=",
-        tp: TokenType::DivideEqu,
-    },
-    TokenDict {
-        s: "<<=",
-        tp: TokenType::LeftBShiftEqu,
-    },
-    TokenDict {
-        s: ">>=",
-        tp: TokenType::RightBShiftEqu,
-    },
     TokenDict {
         s: "&=",
         tp: TokenType::BAndEqu,
@@ -175,8 +213,16 @@ static LONG_SP_OPERATOR: &'static [TokenDict] = &[
         s: "->",
         tp: TokenType::Arrow,
     },
+    TokenDict {
+        s: "=>",
+        tp: TokenType::FatArrow,
+    },
+    TokenDict {
+        s: "..",
+        tp: TokenType::DotDot,
+    },
 ];
-static SHORT_SP_OPERATOR: &'static [TokenDict] = &[
+static SHORT_SP_OPERATOR: &[TokenDict] = &[
     TokenDict {
         s: "(",
         tp: TokenType::LeftParen,
@@ -249,14 +295,6 @@ static SHORT_SP_OPERATOR: &'static [TokenDict] = &[
         s: "^",
         tp: TokenType::BitXOR,
     },
-    TokenDict {
-        s: "\'",
-        tp: TokenType::Quote,
-    },
-    TokenDict {
-        s: "\"",
-        tp: TokenType::DblQuote,
-    },
     TokenDict {
         s: ".",
         tp: TokenType::Dot,
@@ -265,16 +303,28 @@ static SHORT_SP_OPERATOR: &'static [TokenDict] = &[
         s: ",",
         tp: TokenType::Comma,
     },
+    TokenDict {
+        s: ":",
+        tp: TokenType::Colon,
+    },
     TokenDict {
         s: ";",
         tp: TokenType::Semicolon,
     },
+    TokenDict {
+        s: "#",
+        tp: TokenType::Hash,
+    },
 ];
-static KEYWORDS: &'static [TokenDict] = &[
+static KEYWORDS: &[TokenDict] = &[
     TokenDict {
         s: "const",
         tp: (TokenType::Const),
     },
+    TokenDict {
+        s: "var",
+        tp: (TokenType::Var),
+    },
     TokenDict {
         s: "impl",
         tp: (TokenType::Impl),
@@ -283,6 +333,18 @@ static KEYWORDS: &'static [TokenDict] = &[
         s: "trait",
         tp: (TokenType::Trait),
     },
+    TokenDict {
+        s: "type",
+        tp: (TokenType::Type),
+    },
+    TokenDict {
+        s: "struct",
+        tp: (TokenType::Struct),
+    },
+    TokenDict {
+        s: "enum",
+        tp: (TokenType::Enum),
+    },
     TokenDict {
         s: "pub",
         tp: (TokenType::Pub),
@@ -295,6 +357,10 @@ static KEYWORDS: &'static [TokenDict] = &[
         s: "if",
         tp: (TokenType::If),
     },
+    TokenDict {
+        s: "else",
+        tp: (TokenType::Else),
+    },
     TokenDict {
         s: "while",
         tp: (TokenType::While),
@@ -303,6 +369,34 @@ static KEYWORDS: &'static [TokenDict] = &[
         s: "for",
         tp: (TokenType::For),
     },
+    TokenDict {
+        s: "in",
+        tp: (TokenType::In),
+    },
+    TokenDict {
+        s: "match",
+        tp: (TokenType::Match),
+    },
+    TokenDict {
+        s: "return",
+        tp: (TokenType::Return),
+    },
+    TokenDict {
+        s: "break",
+        tp: (TokenType::Break),
+    },
+    TokenDict {
+        s: "continue",
+        tp: (TokenType::Continue),
+    },
+    TokenDict {
+        s: "true",
+        tp: (TokenType::True),
+    },
+    TokenDict {
+        s: "false",
+        tp: (TokenType::False),
+    },
     TokenDict {
         s: "use",
         tp: (TokenType::Use),
@@ -313,25 +407,40 @@ static KEYWORDS: &'static [TokenDict] = &[
     },
 ];
 
-struct LexStream {
+pub struct LexStream {
     line: u64,
     col: u64,
     eof: bool,
-    eol: bool,
-    reader: BufReader<File>,
-    linebuf: String,
+    src: String,
+    pos: usize,
 }
 
+#[allow(dead_code)]
 impl LexStream {
     pub fn new(f: File) -> Self {
-        Self {
-            line: 0,
-            col: 0,
-            eof: false,
-            eol: false,
-            reader: BufReader::new(f),
-            linebuf: String::new(),
+        let mut src = String::new();
+        let mut reader = BufReader::new(f);
+
+        if reader.read_to_string(&mut src).is_err() {
+            src.clear();
+        }
+        return Self::from_string(src);
+    }
+
+    pub fn from_string(src: String) -> Self {
+        let mut s = Self {
+            line: 1,
+            col: 1,
+            eof: src.is_empty(),
+            src,
+            pos: 0,
+        };
+        //`#!/usr/bin/env rfx` on the first line makes a script executable
+        if s.src.starts_with("#!") {
+            let len = s.src.find('\n').unwrap_or(s.src.len());
+            s.advance(len);
         }
+        return s;
     }
 
     pub fn line(&self) -> u64 {
@@ -346,21 +455,24 @@ impl LexStream {
         self.eof
     }
 
-    fn is_eol(&self) -> bool {
-        self.eol
-    }
-
-    fn new_line(&mut self) {
-        if self.reader.read_line(&mut self.linebuf).unwrap() == 0 {
+    //move the cursor forward by `len` bytes, keeping line and column in sync
+    fn advance(&mut self, len: usize) {
+        for c in self.src[self.pos..self.pos + len].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        self.pos += len;
+        if self.pos >= self.src.len() {
             self.eof = true;
         }
-
-        self.eol = false;
-        self.line += 1;
-        self.col = 0;
     }
 }
 
+#[allow(dead_code)]
 pub trait Lex {
     fn peek(&mut self) -> Option<Token>;
     fn lex(&mut self) -> Option<Token>;
@@ -368,213 +480,432 @@ pub trait Lex {
 
 impl Lex for LexStream {
     fn lex(&mut self) -> Option<Token> {
-        let token: Option<Token>;
-        let mut siter;
-
-        if self.is_eof() {
-            return None;
-        } else if self.is_eol() {
-            self.new_line();
-        }
+        let rest_len = self.src.len() - self.pos;
 
-        siter = self.linebuf.chars();
-        token = siter.lex();
+        let skipped = {
+            let mut siter = self.src[self.pos..].chars();
+            skip_trivia(&mut siter);
+            rest_len - siter.as_str().len()
+        };
+        self.advance(skipped);
 
-        if token.is_none() {
-            // End of Line
-            self.new_line();
-            return self.lex();
-        }
+        let (line, col) = (self.line, self.col);
+        let (token, consumed) = {
+            let mut siter = self.src[self.pos..].chars();
+            let token = siter.lex();
+            (token, rest_len - skipped - siter.as_str().len())
+        };
+        self.advance(consumed);
 
-        self.col += self.linebuf.chars().count() as u64 - siter.clone().count() as u64;
-        self.linebuf = siter.collect();
-        let unwrap_ret = token.unwrap();
+        let mut unwrap_ret = token?;
+        unwrap_ret.line = line;
+        unwrap_ret.col = col;
         return Some(unwrap_ret);
     }
 
     fn peek(&mut self) -> Option<Token> {
-        return self.linebuf.chars().peek();
+        let (line, col, eof, pos) = (self.line, self.col, self.eof, self.pos);
+        let ret = self.lex();
+
+        self.line = line;
+        self.col = col;
+        self.eof = eof;
+        self.pos = pos;
+        return ret;
     }
 }
 
-impl Lex for Chars<'_> {
-    fn peek(&mut self) -> Option<Token> {
-        return self.clone().lex();
+//lex the whole source, with every token carrying its line and column
+pub fn lex_all(src: &str) -> Vec<Token> {
+    let mut lexs = LexStream::from_string(src.to_string());
+    let mut ret: Vec<Token> = Vec::new();
+
+    while let Some(token) = lexs.lex() {
+        ret.push(token);
     }
+    return ret;
+}
 
-    fn lex(&mut self) -> Option<Token> {
-        let mut ret = Token::new();
-        let mut lex_iter;
+//a comment, which lexing otherwise skips
+#[derive(Clone, Debug)]
+pub struct Comment {
+    pub text: String, // with its `//` or `/* */`
+    pub line: u64,
+    pub col: u64,
+}
 
-        //skip whitespace
-        loop {
-            let c = self.clone().next();
-            if c.is_none() {
-                //<whitespace><end of string>
-                return None;
+//a source lexed for the formatter, with what lex_all drops: each token as
+//written, e.g. a string literal with its quotes and escapes, and the comments.
+//doc comments are among the comments, not the tokens
+pub struct Source {
+    pub toks: Vec<Token>,
+    pub raw: Vec<String>,
+    pub comments: Vec<Comment>,
+}
+
+pub fn lex_source(src: &str) -> Source {
+    let mut lexs = LexStream::from_string(src.to_string());
+    let mut out = Source {
+        toks: Vec::new(),
+        raw: Vec::new(),
+        comments: Vec::new(),
+    };
+
+    loop {
+        let (line, col, from) = (lexs.line, lexs.col, lexs.pos);
+        let start = {
+            let mut siter = lexs.src[from..].chars();
+            skip_trivia(&mut siter);
+            lexs.src.len() - siter.as_str().len()
+        };
+        comments_in(&lexs.src[from..start], line, col, &mut out.comments);
+        match lexs.lex() {
+            None => return out,
+            Some(t) if t.tp == TokenType::DocComment => out.comments.push(Comment {
+                text: lexs.src[start..lexs.pos].trim_end().to_string(),
+                line: t.line,
+                col: t.col,
+            }),
+            Some(t) => {
+                out.raw.push(lexs.src[start..lexs.pos].to_string());
+                out.toks.push(t);
             }
-            if !c.unwrap().is_whitespace() {
-                break;
+        }
+    }
+}
+
+//the comments in trivia starting at line and col
+fn comments_in(trivia: &str, mut line: u64, mut col: u64, out: &mut Vec<Comment>) {
+    let mut rest = trivia;
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            body.find("*/").map(|i| i + 4).unwrap_or(rest.len())
+        } else {
+            0
+        };
+        if len == 0 {
+            match c {
+                '\n' => {
+                    line += 1;
+                    col = 1;
+                }
+                _ => col += 1,
             }
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
 
-            self.next();
+        let text = &rest[..len];
+        out.push(Comment {
+            text: text.trim_end().to_string(),
+            line,
+            col,
+        });
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    line += 1;
+                    col = 1;
+                }
+                _ => col += 1,
+            }
         }
+        rest = &rest[len..];
+    }
+}
 
-        //remove comment
-        if self.clone().count() >= 2 {
-            // checked length
-            let s: String = self.clone().take(2).collect();
-            if s == "//" {
-                //skip '//'
-                self.next();
-                self.next();
+//the length of the doc comment rest starts with. `////` and `/**/` are
+//plain comments, as is a `/**` never closed
+fn doc_comment_len(rest: &str) -> Option<usize> {
+    if rest.starts_with("///") && !rest.starts_with("////") {
+        return Some(rest.find('\n').unwrap_or(rest.len()));
+    }
+    if rest.starts_with("/**") && !rest.starts_with("/***") && !rest.starts_with("/**/") {
+        return rest[3..].find("*/").map(|i| i + 5);
+    }
+    return None;
+}
 
-                //skip one line
-                loop {
-                    let e = self.next();
-                    if e.is_none() {
-                        return None;
-                    }
+//the text of a doc comment: without `///`, or without `/**`, `*/` and the
+//`*` starting each line, and one space after the markers
+fn doc_text(comment: &str) -> String {
+    if let Some(line) = comment.strip_prefix("///") {
+        return line
+            .strip_prefix(' ')
+            .unwrap_or(line)
+            .trim_end()
+            .to_string();
+    }
+    let body = &comment[3..comment.len() - 2];
+    let lines: Vec<&str> = body
+        .lines()
+        .map(|x| {
+            let x = x.trim();
+            let x = x.strip_prefix('*').unwrap_or(x);
+            x.strip_prefix(' ').unwrap_or(x).trim_end()
+        })
+        .collect();
+    let first = lines
+        .iter()
+        .position(|x| !x.is_empty())
+        .unwrap_or(lines.len());
+    let last = lines
+        .iter()
+        .rposition(|x| !x.is_empty())
+        .map_or(first, |i| i + 1);
+    return lines[first..last].join("\n");
+}
 
-                    let c = e.unwrap();
-                    if c == '\n' {
-                        break;
-                    }
-                }
-            } else if s == "/*" {
-                //long comment
-                let self_save = self.clone();
-                let mut iter_long_comment = self.clone().zip(self.clone().skip(1));
-                loop {
-                    let e = iter_long_comment.next();
-                    if e.is_none() {
-                        ret.tp = TokenType::BrokenLeftLongComment;
-                        ret.s = self
-                            .take(self_save.count() - iter_long_comment.count())
-                            .collect();
-                        return Some(ret);
-                    }
+//skip whitespace and comments. an unterminated long comment is left in place
+fn skip_trivia(it: &mut Chars) {
+    loop {
+        while let Some(c) = it.clone().next() {
+            if !c.is_whitespace() {
+                break;
+            }
+            it.next();
+        }
 
-                    let (c1, c2) = e.unwrap();
-                    if c1 == '*' && c2 == '/' {
-                        break;
-                    }
+        let rest = it.as_str();
+        if doc_comment_len(rest).is_some() {
+            return;
+        }
+        if rest.starts_with("//") {
+            match rest.find('\n') {
+                None => {
+                    *it = rest[rest.len()..].chars();
+                }
+                Some(i) => {
+                    *it = rest[i + 1..].chars();
+                }
+            }
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            match body.find("*/") {
+                None => {
+                    return;
+                }
+                Some(i) => {
+                    *it = body[i + 2..].chars();
                 }
             }
+        } else {
+            return;
         }
-        //skip whitespace
-        loop {
-            let c = self.clone().next();
-            if c.is_none() {
-                //<whitespace><end of string>
-                return None;
+    }
+}
+
+fn unescape(c: char) -> Option<char> {
+    return match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        '\'' => Some('\''),
+        '"' => Some('"'),
+        _ => None,
+    };
+}
+
+//lex "..." literal. s is the unescaped content
+fn lex_string_literal(it: &mut Chars, ret: &mut Token) {
+    let save = it.as_str();
+    let mut value = String::new();
+
+    it.next();
+    loop {
+        match it.next() {
+            None => {
+                ret.tp = TokenType::Broken;
+                ret.s = save.to_string();
+                return;
             }
-            if !c.unwrap().is_whitespace() {
+            Some('"') => {
                 break;
             }
-
-            self.next();
+            Some('\\') => match it.next().and_then(unescape) {
+                None => {
+                    ret.tp = TokenType::Broken;
+                    ret.s = save[..save.len() - it.as_str().len()].to_string();
+                    return;
+                }
+                Some(c) => value.push(c),
+            },
+            Some(c) => value.push(c),
         }
+    }
+
+    ret.tp = TokenType::StringLiteral;
+    ret.s = value;
+}
+
+//lex 'c' literal. s is the unescaped char
+fn lex_char_literal(it: &mut Chars, ret: &mut Token) {
+    let save = it.as_str();
 
-        //this is start
-        lex_iter = self.clone();
+    it.next();
+    let value = match it.next() {
+        Some('\\') => it.next().and_then(unescape),
+        Some('\'') | Some('\n') | None => None,
+        Some(c) => Some(c),
+    };
 
-        //start lex
-        'end_lex: while let Some(curr_char) = lex_iter.clone().next() {
-            if curr_char.is_whitespace() {
+    if value.is_none() || it.next() != Some('\'') {
+        //skip to the closing quote on this line, if any
+        while let Some(c) = it.clone().next() {
+            if c == '\n' {
                 break;
             }
+            it.next();
+            if c == '\'' {
+                break;
+            }
+        }
+        ret.tp = TokenType::Broken;
+        ret.s = save[..save.len() - it.as_str().len()].to_string();
+        return;
+    }
 
-            //treat _ as alphabet
-            if !curr_char.is_ascii_alphanumeric() && curr_char != '_' {
-                //special char
-                if !lex_iter.clone().eq(self.clone()) {
-                    //<alphabets><special char(= curr_char)>
-                    break;
-                }
+    ret.tp = TokenType::CharLiteral;
+    ret.s = value.unwrap().to_string();
+}
 
-                let max_len = lex_iter.clone().count();
-                //check for long operator
-                for dict in LONG_SP_OPERATOR.iter() {
-                    if max_len >= dict.s.len()
-                        && lex_iter
-                            .clone()
-                            .take(dict.s.chars().count())
-                            .collect::<String>()
-                            == *dict.s
-                    {
-                        let found = dict;
-                        ret.tp = found.tp;
-                        ret.s = dict.s.to_string();
-                        for _i in 0..dict.s.chars().count() {
-                            lex_iter.next();
-                        }
-                        break 'end_lex;
-                    }
-                }
+//decimal, 0x, 0o or 0b number. '_' may be used as separator
+fn is_number(s: &str) -> bool {
+    let (digits, radix) = if let Some(x) = s.strip_prefix("0x") {
+        (x, 16)
+    } else if let Some(x) = s.strip_prefix("0o") {
+        (x, 8)
+    } else if let Some(x) = s.strip_prefix("0b") {
+        (x, 2)
+    } else {
+        (s, 10)
+    };
 
-                //check for short operator
-                for dict in SHORT_SP_OPERATOR.iter() {
-                    if curr_char == dict.s.chars().nth(0).unwrap() {
-                        let found = dict;
-                        ret.tp = found.tp;
-                        ret.s = dict.s.to_string();
-                        lex_iter.next();
-                        break 'end_lex;
-                    }
-                }
+    return digits.chars().any(|c| c.is_digit(radix))
+        && digits.chars().all(|c| c.is_digit(radix) || c == '_');
+}
 
-                //cannot find matching operator
-                while let Some(spc) = lex_iter.clone().next() {
-                    if spc.is_alphanumeric() || spc.is_whitespace() || spc == '_' {
-                        break;
-                    }
-                    lex_iter.next();
-                }
-                ret.tp = TokenType::Broken;
-                break;
-            }
+//value of a Number token, or of one with a leading '-'
+pub fn number_value(s: &str) -> Option<i128> {
+    let (negative, s) = match s.strip_prefix('-') {
+        None => (false, s),
+        Some(x) => (true, x),
+    };
+    let digits: String = s.chars().filter(|c| *c != '_').collect();
+    let value = if let Some(x) = digits.strip_prefix("0x") {
+        i128::from_str_radix(x, 16)
+    } else if let Some(x) = digits.strip_prefix("0o") {
+        i128::from_str_radix(x, 8)
+    } else if let Some(x) = digits.strip_prefix("0b") {
+        i128::from_str_radix(x, 2)
+    } else {
+        digits.parse::<i128>()
+    };
+
+    return match value {
+        Err(_) => None,
+        Ok(x) if negative => Some(-x),
+        Ok(x) => Some(x),
+    };
+}
 
-            lex_iter.next();
+impl Lex for Chars<'_> {
+    fn peek(&mut self) -> Option<Token> {
+        return self.clone().lex();
+    }
+
+    fn lex(&mut self) -> Option<Token> {
+        let mut ret = Token::new();
+
+        skip_trivia(self);
+
+        let rest = self.as_str();
+        let curr_char = rest.chars().next()?;
+
+        if let Some(len) = doc_comment_len(rest) {
+            ret.tp = TokenType::DocComment;
+            ret.s = doc_text(&rest[..len]);
+            *self = rest[len..].chars();
+            return Some(ret);
+        }
+        if rest.starts_with("/*") {
+            //skip_trivia left it, so it is never closed
+            ret.tp = TokenType::BrokenLeftLongComment;
+            ret.s = rest.to_string();
+            *self = rest[rest.len()..].chars();
+            return Some(ret);
         }
 
-        let result: String = self
-            .take(self.clone().count() - lex_iter.clone().count())
-            .collect();
-        if ret.tp == TokenType::Null {
-            let mut number = true;
-            let mut string = true;
+        if curr_char == '"' {
+            lex_string_literal(self, &mut ret);
+            return Some(ret);
+        }
+        if curr_char == '\'' {
+            lex_char_literal(self, &mut ret);
+            return Some(ret);
+        }
 
-            if result.chars().nth(0).unwrap().is_ascii_digit() {
-                string = false;
-            }
-            for c in result.chars() {
-                if !c.is_ascii_digit() {
-                    number = false;
-                    if !c.is_ascii_alphabetic() && c != '_' {
-                        string = false;
+        //treat _ as alphabet
+        if curr_char.is_ascii_alphanumeric() || curr_char == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let result = &rest[..len];
+
+            if curr_char.is_ascii_digit() {
+                ret.tp = if is_number(result) {
+                    TokenType::Number
+                } else {
+                    TokenType::Broken
+                };
+            } else {
+                ret.tp = TokenType::String;
+
+                //check for keyword
+                for dict in KEYWORDS.iter() {
+                    if result == dict.s {
+                        ret.tp = dict.tp;
+                        break;
                     }
                 }
-                if !number && !string {
-                    break;
-                }
             }
-            if number {
-                ret.tp = TokenType::Number;
-            } else if string {
-                ret.tp = TokenType::String;
+
+            ret.s = result.to_string();
+            *self = rest[len..].chars();
+            return Some(ret);
+        }
+
+        //special char. check for long operator
+        for dict in LONG_SP_OPERATOR.iter() {
+            if let Some(after) = rest.strip_prefix(dict.s) {
+                ret.tp = dict.tp;
+                ret.s = dict.s.to_string();
+                *self = after.chars();
+                return Some(ret);
             }
         }
-        //check for keyword
-        for dict in KEYWORDS.iter() {
-            if result == dict.s {
+
+        //check for short operator
+        for dict in SHORT_SP_OPERATOR.iter() {
+            if let Some(after) = rest.strip_prefix(dict.s) {
                 ret.tp = dict.tp;
-                break;
+                ret.s = dict.s.to_string();
+                *self = after.chars();
+                return Some(ret);
             }
         }
 
-        ret.s = result;
-        *self = lex_iter;
+        //cannot find matching operator
+        let first = curr_char.len_utf8();
+        let len = rest[first..]
+            .find(|c: char| c.is_alphanumeric() || c.is_whitespace() || c == '_')
+            .map_or(rest.len(), |i| i + first);
+        ret.tp = TokenType::Broken;
+        ret.s = rest[..len].to_string();
+        *self = rest[len..].chars();
         return Some(ret);
     }
 }
@@ -586,7 +917,7 @@ mod tests {
     use std::io::stdout;
     use std::io::Write;
 
-    use super::LexStream;
+    use super::{LexStream, TokenType};
 
     #[test]
     fn test_lex_char() {
@@ -624,14 +955,19 @@ mod tests {
             let unwrap_token = token.unwrap();
             println!("{}, {}: \'{}\'", i, unwrap_token.tp, unwrap_token.s);
             stdout().flush().unwrap();
+            assert!(unwrap_token.tp != TokenType::Broken);
         }
+        assert!(i > 1);
     }
 
     #[test]
     fn test_lex_file() {
-        let test_file = File::open("test/lex.test").unwrap();
+        //not relative to the working directory, tests may run from anywhere
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/lex.test");
+        let test_file = File::open(path).unwrap();
         let mut lexs = LexStream::new(test_file);
 
+        let mut lexed = Vec::new();
         loop {
             let token = lexs.lex();
             if token.is_none() || lexs.is_eof() {
@@ -640,7 +976,60 @@ mod tests {
             let unwrap_token = token.unwrap();
             println!("{}, {}: \'{}\'", lexs.line(), lexs.col(), unwrap_token.s);
             stdout().flush().unwrap();
+            lexed.push(unwrap_token.s);
         }
+        assert_eq!(
+            lexed,
+            [
+                "use", "abc", ";", "const", "char", "*", "hello", "(", "i64", "n", ")", "{",
+                "return", "abc", "::", "abcdef", ";"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_shebang() {
+        let toks = super::lex_all("#!/usr/bin/env rfx\nprint(1);");
+        assert_eq!(toks[0].s, "print");
+        assert_eq!((toks[0].line, toks[0].col), (2, 1));
+        assert!(super::lex_all("#!/usr/bin/env rfx").is_empty());
+    }
+
+    #[test]
+    fn test_lex_source() {
+        let src = "// head\nvar s = \"a\\tb\"; /* one\ntwo */ var c = 'x';\n// tail";
+        let lexed = super::lex_source(src);
+        assert_eq!(lexed.toks.len(), 10);
+        assert_eq!(lexed.raw[3], "\"a\\tb\"");
+        assert_eq!(lexed.toks[3].s, "a\tb");
+        assert_eq!(lexed.raw[8], "'x'");
+        let comments: Vec<(&str, u64, u64)> = lexed
+            .comments
+            .iter()
+            .map(|c| (c.text.as_str(), c.line, c.col))
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                ("// head", 1, 1),
+                ("/* one\ntwo */", 2, 17),
+                ("// tail", 4, 1)
+            ]
+        );
+
+        let toks = super::lex_all("/// one\n///two\n//// not\n/**\n * three\n */ fn /**/");
+        let docs: Vec<(&str, u64)> = toks
+            .iter()
+            .filter(|t| t.tp == TokenType::DocComment)
+            .map(|t| (t.s.as_str(), t.line))
+            .collect();
+        assert_eq!(docs, [("one", 1), ("two", 2), ("three", 4)]);
+        assert_eq!(toks.len(), 4);
+        let lexed = super::lex_source("/// doc\nfn f() {}");
+        assert_eq!(
+            (lexed.toks.len(), lexed.comments[0].text.as_str()),
+            (6, "/// doc")
+        );
     }
 }
 /*
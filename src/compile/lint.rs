@@ -0,0 +1,685 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::diag::Diagnostic;
+use super::is_item;
+use super::lex::{number_value, Token, TokenType};
+use super::parser::{SymbolTable, TypeKind};
+use super::typeck::diverges;
+
+/*
+lints: warnings about code that compiles but is likely a mistake. they run
+on the checked tree of a program without errors. each rule is turned on or
+off by the LINTS setting, and `#[allow(rule, ...)]` turns rules off for an
+item and what it holds
+*/
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Rule {
+    UnusedVariable,
+    UnusedImport,
+    UnusedFunction,
+    NeverReassigned,
+    Shadowing,
+    UnreachableCode,
+    NonSnakeCase,
+    UnsignedComparison,
+}
+
+//(name, what it warns about), in the order of Rule
+pub const RULES: &[(&str, &str)] = &[
+    (
+        "unused_variable",
+        "a variable or argument that is never read",
+    ),
+    ("unused_import", "a `use` whose name is never used"),
+    (
+        "unused_function",
+        "a function that is not `pub` and never called",
+    ),
+    (
+        "never_reassigned",
+        "a `var` that is never assigned, it could be `const`",
+    ),
+    ("shadowing", "a variable declared again in an inner scope"),
+    (
+        "unreachable_code",
+        "a statement after `return`, `break` or `continue`",
+    ),
+    ("non_snake_case", "a function name that is not snake_case"),
+    (
+        "unsigned_comparison",
+        "an unsigned value compared with 0 or its largest value, always true or false",
+    ),
+];
+
+//a set of rules
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Lints(u32);
+
+impl Lints {
+    pub const NONE: Lints = Lints(0);
+
+    pub fn all() -> Self {
+        return Lints((1 << RULES.len()) - 1);
+    }
+
+    //the rules of the names, Err with the first unknown one
+    pub fn named(names: &[String]) -> Result<Self, String> {
+        let mut ret = Lints::NONE;
+        for n in names.iter() {
+            match RULES.iter().position(|(x, _)| x == n) {
+                None => return Err(n.clone()),
+                Some(i) => ret.0 |= 1 << i,
+            }
+        }
+        return Ok(ret);
+    }
+
+    fn has(self, rule: Rule) -> bool {
+        return self.0 & (1 << rule as u32) != 0;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LocalKind {
+    Var,
+    Const,
+    Argument,
+    Binding, // of a `for` or a match pattern
+}
+
+struct Local {
+    token: Token,
+    kind: LocalKind,
+    read: bool,
+    assigned: bool,
+    allowed: Lints, // where it was declared
+}
+
+struct Linter<'a> {
+    table: &'a SymbolTable,
+    lints: Lints,
+    diags: Vec<Diagnostic>,
+    allowed: Vec<Lints>, // by the #[allow] of the items being walked
+    locals: Vec<Local>,
+    scopes: Vec<Vec<usize>>, // into locals
+    base: usize,             // first scope of the function being walked
+    called: Vec<usize>,
+    names: Vec<String>, // used by paths, calls and types, for `use`
+}
+
+pub fn check(root: &AST, table: &SymbolTable, lints: Lints) -> Vec<Diagnostic> {
+    let mut l = Linter {
+        table,
+        lints,
+        diags: Vec::new(),
+        allowed: vec![Lints::NONE],
+        locals: Vec::new(),
+        scopes: vec![Vec::new()],
+        base: 0,
+        called: Vec::new(),
+        names: Vec::new(),
+    };
+
+    //top level statements first, as typeck does, so module level variables
+    //are declared before the functions using them are walked
+    let stmts: Vec<&AST> = root.next.iter().filter(|x| !is_item(x)).collect();
+    l.block(&stmts);
+    for item in root.next.iter().filter(|x| is_item(x)) {
+        l.item(item);
+    }
+    l.pop_scope();
+
+    for item in root.next.iter() {
+        l.unused_item(item, Lints::NONE);
+    }
+    l.diags.sort_by_key(|d| (d.file, d.line, d.col));
+    return l.diags;
+}
+
+//the first token of a statement as written
+fn first_token(ast: &AST) -> &Token {
+    let mut first = &ast.token;
+    for n in ast.next.iter() {
+        let t = first_token(n);
+        if t.line > 0 && (first.line == 0 || (t.line, t.col) < (first.line, first.col)) {
+            first = t;
+        }
+    }
+    return first;
+}
+
+fn is_snake_case(name: &str) -> bool {
+    return !name
+        .chars()
+        .any(|c| c.is_uppercase() || !(c.is_alphanumeric() || c == '_'));
+}
+
+fn snake_case(name: &str) -> String {
+    let mut ret = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() && prev_lower {
+            ret.push('_');
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        ret.extend(c.to_lowercase());
+    }
+    return ret;
+}
+
+//the value of an integer literal, None for anything else
+fn literal_value(ast: &AST) -> Option<i128> {
+    if ast.tp != ASTtype::Literal || ast.token.tp != TokenType::Number {
+        return None;
+    }
+    return number_value(&ast.token.s);
+}
+
+//the bindings of a pattern
+fn bindings<'a>(pat: &'a AST, out: &mut Vec<&'a AST>) {
+    match pat.tp {
+        ASTtype::PatBinding => out.push(pat),
+        _ => {
+            for n in pat.next.iter() {
+                bindings(n, out);
+            }
+        }
+    }
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, rule: Rule, allowed: Lints, t: &Token, msg: String) {
+        if !self.lints.has(rule) || allowed.has(rule) {
+            return;
+        }
+        let name = RULES[rule as usize].0;
+        self.diags
+            .push(Diagnostic::warning(t, format!("{} [{}]", msg, name)));
+    }
+
+    fn warn(&mut self, rule: Rule, t: &Token, msg: String) {
+        let allowed = *self.allowed.last().unwrap();
+        self.report(rule, allowed, t, msg);
+    }
+
+    //what the #[allow] among attrs turn off, with what already was
+    fn allow(&self, attrs: &[AST]) -> Lints {
+        let mut ret = *self.allowed.last().unwrap();
+        for a in attrs.iter().filter(|x| x.token.s == "allow") {
+            for n in a.next.iter() {
+                if let Ok(x) = Lints::named(std::slice::from_ref(&n.token.s)) {
+                    ret.0 |= x.0;
+                }
+            }
+        }
+        return ret;
+    }
+
+    //names in the #[allow] among attrs that are no rule
+    fn unknown_lints(&mut self, attrs: &[AST]) {
+        for a in attrs.iter().filter(|x| x.token.s == "allow") {
+            for n in a.next.iter() {
+                if Lints::named(std::slice::from_ref(&n.token.s)).is_err() {
+                    let msg = format!("unknown lint `{}`", n.token.s);
+                    self.diags.push(Diagnostic::warning(&n.token, msg));
+                }
+            }
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    //report what the scope declared and never used
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for i in scope {
+            let v = &self.locals[i];
+            let (name, t, allowed) = (v.token.s.clone(), v.token.clone(), v.allowed);
+            if !v.read && !name.starts_with('_') && name != "self" {
+                let msg = match v.kind {
+                    LocalKind::Argument => format!("unused argument `{}`", name),
+                    _ => format!("unused variable `{}`", name),
+                };
+                self.report(Rule::UnusedVariable, allowed, &t, msg);
+            } else if v.kind == LocalKind::Var && !v.assigned {
+                let msg = format!("`{}` is never reassigned, it could be `const`", name);
+                self.report(Rule::NeverReassigned, allowed, &t, msg);
+            }
+        }
+    }
+
+    fn declare(&mut self, t: &Token, kind: LocalKind) {
+        if kind != LocalKind::Argument {
+            let outer = self.scopes[self.base..]
+                .iter()
+                .flatten()
+                .find(|i| self.locals[**i].token.s == t.s)
+                .map(|i| self.locals[*i].token.line);
+            if let Some(line) = outer {
+                let msg = format!("`{}` shadows the one declared on line {}", t.s, line);
+                self.warn(Rule::Shadowing, t, msg);
+            }
+        }
+        self.locals.push(Local {
+            token: t.clone(),
+            kind,
+            read: false,
+            assigned: false,
+            allowed: *self.allowed.last().unwrap(),
+        });
+        let i = self.locals.len() - 1;
+        self.scopes.last_mut().unwrap().push(i);
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        return self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.iter().rev().find(|i| self.locals[**i].token.s == name))
+            .copied();
+    }
+
+    fn item(&mut self, item: &AST) {
+        self.unknown_lints(&item.attrs);
+        let allowed = self.allow(&item.attrs);
+        self.allowed.push(allowed);
+        match item.tp {
+            ASTtype::Function => self.function(item, true),
+            ASTtype::Trait => {
+                for f in item.next.iter() {
+                    self.unknown_lints(&f.attrs);
+                    self.function(f, true);
+                }
+            }
+            //the names of a trait's functions are the trait's
+            ASTtype::Impl => {
+                let own = item.token.tp == TokenType::Null;
+                for f in item.next[1..].iter() {
+                    self.unknown_lints(&f.attrs);
+                    self.function(f, own);
+                }
+            }
+            _ => {}
+        }
+        if item.tp != ASTtype::Use {
+            self.type_names(item);
+        }
+        self.allowed.pop();
+    }
+
+    fn function(&mut self, f: &AST, named_here: bool) {
+        let allowed = self.allow(&f.attrs);
+        self.allowed.push(allowed);
+        let name = &f.next[1].token;
+        if named_here && !is_snake_case(&name.s) {
+            let msg = format!(
+                "function `{}` should have a snake case name, e.g. `{}`",
+                name.s,
+                snake_case(&name.s)
+            );
+            self.warn(Rule::NonSnakeCase, name, msg);
+        }
+
+        if let Some(body) = f.next.get(4) {
+            let base = self.base;
+            self.base = self.scopes.len();
+            self.push_scope();
+            for arg in f.next[2].next.iter() {
+                self.declare(&arg.next[0].token, LocalKind::Argument);
+            }
+            self.scoped_block(body);
+            self.pop_scope();
+            self.base = base;
+        }
+        self.allowed.pop();
+    }
+
+    //functions never called and imports never used, once everything is seen
+    fn unused_item(&mut self, item: &AST, outer: Lints) {
+        self.allowed.push(outer);
+        let allowed = self.allow(&item.attrs);
+        self.allowed.pop();
+        match item.tp {
+            ASTtype::Function => {
+                let name = &item.next[1].token;
+                let public = item.next[0].token.tp == TokenType::Pub;
+                let called = item.sym.is_some_and(|x| self.called.contains(&x));
+                //`rfx test` calls the tests
+                if !public && !called && !item.has_attr("test") && !name.s.starts_with('_') {
+                    let msg = format!("function `{}` is never called", name.s);
+                    self.report(Rule::UnusedFunction, allowed, name, msg);
+                }
+            }
+            //a trait's functions are called through it
+            ASTtype::Impl if item.token.tp == TokenType::Null => {
+                for f in item.next[1..].iter() {
+                    self.unused_item(f, allowed);
+                }
+            }
+            ASTtype::Use => {
+                let last = item.token.s.rsplit("::").next().unwrap_or_default();
+                if !self.names.iter().any(|x| x == last) {
+                    let msg = format!("unused import `{}`", item.token.s);
+                    self.report(Rule::UnusedImport, allowed, &item.token, msg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    //the type names written in an item, they may name an import
+    fn type_names(&mut self, ast: &AST) {
+        if ast.tp == ASTtype::VariableType {
+            let first = ast.token.s.split("::").next().unwrap_or_default();
+            self.names.push(first.to_string());
+        }
+        for n in ast.next.iter() {
+            self.type_names(n);
+        }
+    }
+
+    fn scoped_block(&mut self, ast: &AST) {
+        self.push_scope();
+        let stmts: Vec<&AST> = ast.next.iter().collect();
+        self.block(&stmts);
+        self.pop_scope();
+    }
+
+    //statements in order. the first one after a `return`, `break` or
+    //`continue` is reported, what follows is still walked for its uses
+    fn block(&mut self, stmts: &[&AST]) {
+        let mut dead = false;
+        let mut reported = false;
+        for s in stmts.iter() {
+            if dead && !reported {
+                let t = first_token(s).clone();
+                self.warn(
+                    Rule::UnreachableCode,
+                    &t,
+                    "unreachable statement".to_string(),
+                );
+                reported = true;
+            }
+            self.stmt(s);
+            dead |= diverges(s);
+        }
+    }
+
+    fn stmt(&mut self, ast: &AST) {
+        match ast.tp {
+            ASTtype::Variable => {
+                self.type_names(&ast.next[2]);
+                self.expr(&ast.next[3]);
+                let kind = match ast.next[0].token.tp {
+                    TokenType::Const => LocalKind::Const,
+                    _ => LocalKind::Var,
+                };
+                self.declare(&ast.next[1].token, kind);
+                //other modules may read and assign a pub one
+                if ast.public {
+                    let v = self.locals.last_mut().unwrap();
+                    (v.read, v.assigned) = (true, true);
+                }
+            }
+            ASTtype::Assign => {
+                self.place(&ast.next[0], ast.token.tp != TokenType::Equal);
+                self.expr(&ast.next[1]);
+            }
+            ASTtype::If => {
+                self.expr(&ast.next[0]);
+                self.scoped_block(&ast.next[1]);
+                if let Some(other) = ast.next.get(2) {
+                    match other.tp {
+                        ASTtype::If => self.stmt(other),
+                        _ => self.scoped_block(other),
+                    }
+                }
+            }
+            ASTtype::While => {
+                self.expr(&ast.next[0]);
+                self.scoped_block(&ast.next[1]);
+            }
+            ASTtype::For => {
+                self.expr(&ast.next[1]);
+                self.push_scope();
+                self.declare(&ast.next[0].token, LocalKind::Binding);
+                self.scoped_block(&ast.next[2]);
+                self.pop_scope();
+            }
+            ASTtype::CodeBlock => self.scoped_block(ast),
+            ASTtype::Break | ASTtype::Continue => {}
+            _ => self.expr(ast),
+        }
+    }
+
+    //an assigned place. compound assignment reads it too
+    fn place(&mut self, ast: &AST, read: bool) {
+        match ast.tp {
+            ASTtype::Ident => {
+                if let Some(i) = self.find(&ast.token.s) {
+                    self.locals[i].assigned = true;
+                    self.locals[i].read |= read;
+                }
+            }
+            ASTtype::Field => self.place(&ast.next[0], read),
+            ASTtype::Index => {
+                self.place(&ast.next[0], read);
+                self.expr(&ast.next[1]);
+            }
+            _ => self.expr(ast),
+        }
+    }
+
+    fn expr(&mut self, ast: &AST) {
+        match ast.tp {
+            ASTtype::Ident => {
+                match self.find(&ast.token.s) {
+                    Some(i) => self.locals[i].read = true,
+                    None => self.path(&ast.token.s),
+                }
+                return;
+            }
+            ASTtype::CallFunction | ASTtype::StructLiteral | ASTtype::Variant => {
+                self.path(&ast.token.s);
+            }
+            //push changes the Vec it is called on
+            ASTtype::CallMethod if ast.token.s == "push" => {
+                self.place(&ast.next[0], true);
+                for n in ast.next[1..].iter() {
+                    self.expr(n);
+                }
+                return;
+            }
+            ASTtype::Binary => self.compare_unsigned(ast),
+            ASTtype::Match => {
+                self.expr(&ast.next[0]);
+                for case in ast.next[1..].iter() {
+                    self.push_scope();
+                    let mut names: Vec<&AST> = Vec::new();
+                    bindings(&case.next[0], &mut names);
+                    for n in names {
+                        self.declare(&n.token, LocalKind::Binding);
+                    }
+                    self.path_patterns(&case.next[0]);
+                    match case.next[1].tp {
+                        ASTtype::CodeBlock => self.scoped_block(&case.next[1]),
+                        _ => self.expr(&case.next[1]),
+                    }
+                    self.pop_scope();
+                }
+                return;
+            }
+            _ => {}
+        }
+        if let Some(sym) = ast.sym {
+            if matches!(ast.tp, ASTtype::CallFunction | ASTtype::CallMethod) {
+                self.called.push(sym);
+            }
+        }
+        for n in ast.next.iter() {
+            self.expr(n);
+        }
+    }
+
+    //the first part of a path, or a name called or built, may be imported
+    fn path(&mut self, name: &str) {
+        let first = name.split("::").next().unwrap_or_default();
+        self.names.push(first.to_string());
+    }
+
+    fn path_patterns(&mut self, pat: &AST) {
+        if pat.tp == ASTtype::PatVariant {
+            self.path(&pat.token.s);
+        }
+        for n in pat.next.iter() {
+            self.path_patterns(n);
+        }
+    }
+
+    //`x >= 0` of an unsigned x is always true, `x < 0` always false, and so
+    //are `x <= 255` and `x > 255` of a u8
+    fn compare_unsigned(&mut self, ast: &AST) {
+        let (left, right) = (&ast.next[0], &ast.next[1]);
+        //as `value op literal`
+        let (value, op, lit) = match (literal_value(left), literal_value(right)) {
+            (_, Some(x)) => (left, ast.token.tp, x),
+            (Some(x), None) => {
+                let op = match ast.token.tp {
+                    TokenType::GreaterEqual => TokenType::LessEqual,
+                    TokenType::LessEqual => TokenType::GreaterEqual,
+                    TokenType::LeftABrack => TokenType::RightABrack,
+                    TokenType::RightABrack => TokenType::LeftABrack,
+                    _ => return,
+                };
+                (right, op, x)
+            }
+            _ => return,
+        };
+        let tp = match value.vtype {
+            None => return,
+            Some(x) => x,
+        };
+        if *self.table.kind(tp) != (TypeKind::Integer { signed: false }) {
+            return;
+        }
+        let max = (1i128 << (self.table.variable_type[tp].size * 8)) - 1;
+        let name = self.table.type_name(tp);
+        let msg = match (op, lit) {
+            (TokenType::GreaterEqual, 0) => {
+                format!("comparison is always true, `{}` is unsigned", name)
+            }
+            (TokenType::LeftABrack, 0) => {
+                format!("comparison is always false, `{}` is unsigned", name)
+            }
+            (TokenType::LessEqual, x) if x == max => {
+                format!("comparison is always true, `{}` is at most {}", name, max)
+            }
+            (TokenType::RightABrack, x) if x == max => {
+                format!("comparison is always false, `{}` is at most {}", name, max)
+            }
+            _ => return,
+        };
+        self.warn(Rule::UnsignedComparison, &ast.token, msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{snake_case, Lints, RULES};
+    use crate::compile::diag::Diagnostic;
+    use crate::compile::{lex, parser, syntax, typeck};
+
+    //the warnings of src, checked without errors, as line: message
+    fn lint(src: &str, lints: Lints) -> Vec<String> {
+        let (mut root, diags) = syntax::parse(lex::lex_all(src));
+        assert!(diags.is_empty(), "{:?}", diags);
+        let mut table = parser::SymbolTable::for_target(crate::compile::Target::host());
+        let diags = typeck::check(&mut root, &mut table);
+        assert!(diags.is_empty(), "{:?}", diags);
+        let diags: Vec<Diagnostic> = super::check(&root, &table, lints);
+        return diags
+            .iter()
+            .map(|d| format!("{}: {}", d.line, d.msg))
+            .collect();
+    }
+
+    #[test]
+    fn test_lints() {
+        let src = "\
+use io;
+use fmt::show;
+fn unused_fn(a: i64, _b: i64) {
+    return;
+    print(\"never\");
+}
+fn camelCase() {
+    var x: u64 = 1;
+    var y = 2;
+    y += 1;
+    if x >= 0 {
+        const x = 3;
+        print(x);
+    }
+    print(y);
+    show(1 < x);
+    const small: u8 = 2;
+    show(255 >= small);
+    show(small > 255);
+}
+fn show(b: bool) {
+    print(b);
+}
+camelCase();
+";
+        assert_eq!(
+            lint(src, Lints::all()),
+            [
+                "1: unused import `io` [unused_import]",
+                "3: function `unused_fn` is never called [unused_function]",
+                "3: unused argument `a` [unused_variable]",
+                "5: unreachable statement [unreachable_code]",
+                "7: function `camelCase` should have a snake case name, e.g. `camel_case` [non_snake_case]",
+                "8: `x` is never reassigned, it could be `const` [never_reassigned]",
+                "11: comparison is always true, `u64` is unsigned [unsigned_comparison]",
+                "12: `x` shadows the one declared on line 8 [shadowing]",
+                "18: comparison is always true, `u8` is at most 255 [unsigned_comparison]",
+                "19: comparison is always false, `u8` is at most 255 [unsigned_comparison]",
+            ]
+        );
+
+        //rules are turned off by the setting, or for an item by #[allow]
+        let on = Lints::named(&["unused_variable".to_string()]).unwrap();
+        assert_eq!(lint(src, on), ["3: unused argument `a` [unused_variable]"]);
+        let allowed = src.replace(
+            "fn unused_fn",
+            "#[allow(unused_function, unused_variable, bogus)]\nfn unused_fn",
+        );
+        assert_eq!(lint(&allowed, on), ["3: unknown lint `bogus`"]);
+        assert_eq!(
+            Lints::named(&["bogus".to_string()]),
+            Err("bogus".to_string())
+        );
+        assert_eq!(RULES.len(), 8);
+
+        //module level variables used by functions, match bindings
+        let src = "\
+var total = 0;
+enum E { A(i64), B(i64) }
+fn add(e: E) {
+    match e {
+        E::A(n) => { total += n; }
+        E::B(_) => {}
+    }
+}
+add(E::A(1));
+";
+        assert!(lint(src, Lints::all()).is_empty());
+        assert_eq!(snake_case("parseHTTPRequest2"), "parse_httprequest2");
+    }
+}
@@ -0,0 +1,589 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::lex::{number_value, TokenType};
+use super::parser::{SymbolTable, TypeKind};
+
+/*
+exhaustiveness and reachability of match arms.
+
+this is the usual "usefulness" algorithm over a pattern matrix: a pattern
+vector is useful against some rows if there is a value matched by it and by
+none of the rows. an arm is unreachable if its pattern is not useful against
+the arms above it, and a match is exhaustive if `_` is not useful against
+all of its arms. integers, chars and bools are value ranges; the ranges of
+a column are split at their boundaries so every piece is either fully inside
+or fully outside each pattern. enum variants are constructors with their
+payloads as sub-patterns.
+*/
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Pat {
+    Wild,
+    Range(i128, i128),
+    Str(String),
+    Or(Vec<Pat>),
+    Variant(usize, Vec<Pat>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Domain {
+    Int(i128, i128),
+    Char,
+    Bool,
+    Str,
+    Enum(usize),
+    Other, //no patterns but `_` and bindings, e.g. a struct
+}
+
+impl Domain {
+    //the values as ranges, none if they are not. chars skip the surrogates,
+    //which are not scalar values
+    fn ranges(&self) -> Vec<(i128, i128)> {
+        return match self {
+            Domain::Int(lo, hi) => vec![(*lo, *hi)],
+            Domain::Char => vec![(0, 0xD7FF), (0xE000, 0x10FFFF)],
+            Domain::Bool => vec![(0, 1)],
+            _ => Vec::new(),
+        };
+    }
+
+    //the values of [lo, hi] in the domain, split at the column ranges
+    fn pieces(&self, lo: i128, hi: i128, col: &[(i128, i128)]) -> Vec<(i128, i128)> {
+        let mut ret: Vec<(i128, i128)> = Vec::new();
+        for (a, b) in self.ranges() {
+            if a.max(lo) <= b.min(hi) {
+                ret.extend(split_range(a.max(lo), b.min(hi), col));
+            }
+        }
+        return ret;
+    }
+}
+
+pub struct MatchReport {
+    pub unreachable: Vec<usize>,
+    pub missing: Option<String>,
+}
+
+pub fn domain_of(table: &SymbolTable, tp: usize) -> Option<Domain> {
+    return match table.kind(tp) {
+        TypeKind::Integer { .. } => {
+            let (lo, hi) = table.value_range(tp).unwrap();
+            Some(Domain::Int(lo, hi))
+        }
+        TypeKind::Char => Some(Domain::Char),
+        TypeKind::Bool => Some(Domain::Bool),
+        TypeKind::String => Some(Domain::Str),
+        TypeKind::Enum(_) => Some(Domain::Enum(tp)),
+        TypeKind::Struct(_) | TypeKind::Vec(_) => Some(Domain::Other),
+        TypeKind::Void => None,
+    };
+}
+
+fn literal_value(ast: &AST, dom: &Domain) -> Result<Pat, String> {
+    let t = &ast.token;
+    return match (t.tp, dom) {
+        (TokenType::Number, Domain::Int(lo, hi)) => match number_value(&t.s) {
+            Some(x) if x >= *lo && x <= *hi => Ok(Pat::Range(x, x)),
+            _ => Err(format!(
+                "literal `{}` out of range for the matched type",
+                t.s
+            )),
+        },
+        (TokenType::CharLiteral, Domain::Char) => {
+            let c = t.s.chars().next().unwrap() as i128;
+            Ok(Pat::Range(c, c))
+        }
+        (TokenType::True, Domain::Bool) => Ok(Pat::Range(1, 1)),
+        (TokenType::False, Domain::Bool) => Ok(Pat::Range(0, 0)),
+        (TokenType::StringLiteral, Domain::Str) => Ok(Pat::Str(t.s.clone())),
+        _ => Err("pattern type does not match the matched value".to_string()),
+    };
+}
+
+fn variant_of(ast: &AST, dom: &Domain, table: &SymbolTable) -> Result<Pat, String> {
+    let tp = match dom {
+        Domain::Enum(tp) => *tp,
+        _ => return Err("pattern type does not match the matched value".to_string()),
+    };
+    let (tname, vname) = ast.token.s.rsplit_once("::").unwrap();
+    if table.find_type(tname) != Some(tp) {
+        return Err(format!(
+            "expected a variant of `{}`, found `{}`",
+            table.type_name(tp),
+            ast.token.s
+        ));
+    }
+    let i = match table.find_variant(tp, vname) {
+        None => return Err(format!("no variant `{}` in `{}`", vname, tname)),
+        Some(x) => x,
+    };
+
+    let fields = &table.variant(tp, i).fields;
+    if fields.len() != ast.next.len() {
+        return Err(format!(
+            "`{}` has {} field(s) but the pattern has {}",
+            ast.token.s,
+            fields.len(),
+            ast.next.len()
+        ));
+    }
+    let mut subs: Vec<Pat> = Vec::new();
+    for (sub, f) in ast.next.iter().zip(fields.iter()) {
+        subs.push(pattern_of(sub, &domain_of(table, *f).unwrap(), table)?);
+    }
+    return Ok(Pat::Variant(i, subs));
+}
+
+//convert a pattern AST into a Pat of the given domain
+pub fn pattern_of(ast: &AST, dom: &Domain, table: &SymbolTable) -> Result<Pat, String> {
+    return match ast.tp {
+        ASTtype::PatWildcard | ASTtype::PatBinding => Ok(Pat::Wild),
+        ASTtype::PatLiteral => literal_value(ast, dom),
+        ASTtype::PatVariant => variant_of(ast, dom, table),
+        ASTtype::PatRange => {
+            if dom.ranges().is_empty() || *dom == Domain::Bool {
+                return Err("range patterns need an integer or char value".to_string());
+            }
+            let lo = literal_value(&ast.next[0], dom)?;
+            let hi = literal_value(&ast.next[1], dom)?;
+            match (lo, hi) {
+                (Pat::Range(lo, _), Pat::Range(hi, _)) => {
+                    let hi = match ast.token.tp {
+                        TokenType::DotDot => hi - 1,
+                        _ => hi,
+                    };
+                    if lo > hi {
+                        return Err("empty range pattern".to_string());
+                    }
+                    Ok(Pat::Range(lo, hi))
+                }
+                _ => Err("invalid range pattern".to_string()),
+            }
+        }
+        ASTtype::PatOr => {
+            let mut alts: Vec<Pat> = Vec::new();
+            for alt in ast.next.iter() {
+                alts.push(pattern_of(alt, dom, table)?);
+            }
+            Ok(Pat::Or(alts))
+        }
+        _ => Err("invalid pattern".to_string()),
+    };
+}
+
+//split [lo, hi] at the boundaries of the column ranges
+fn split_range(lo: i128, hi: i128, col: &[(i128, i128)]) -> Vec<(i128, i128)> {
+    let mut cuts: Vec<i128> = vec![lo];
+    for (a, b) in col.iter() {
+        if *a > lo && *a <= hi {
+            cuts.push(*a);
+        }
+        if *b >= lo && *b < hi {
+            cuts.push(*b + 1);
+        }
+    }
+    cuts.sort();
+    cuts.dedup();
+
+    let mut ret: Vec<(i128, i128)> = Vec::new();
+    for (i, start) in cuts.iter().enumerate() {
+        let end = match cuts.get(i + 1) {
+            None => hi,
+            Some(x) => x - 1,
+        };
+        ret.push((*start, end));
+    }
+    return ret;
+}
+
+//rows with an or-pattern in the first column become one row per alternative
+fn expand_or(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    let mut ret: Vec<Vec<Pat>> = Vec::new();
+    for row in rows.iter() {
+        match &row[0] {
+            Pat::Or(alts) => {
+                for alt in alts.iter() {
+                    let mut r = vec![alt.clone()];
+                    r.extend_from_slice(&row[1..]);
+                    ret.extend(expand_or(&[r]));
+                }
+            }
+            _ => ret.push(row.clone()),
+        }
+    }
+    return ret;
+}
+
+fn specialize(rows: &[Vec<Pat>], ctor: &Pat) -> Vec<Vec<Pat>> {
+    let mut ret: Vec<Vec<Pat>> = Vec::new();
+    for row in rows.iter() {
+        let keep = match (&row[0], ctor) {
+            (Pat::Wild, _) => true,
+            (Pat::Range(a, b), Pat::Range(lo, hi)) => a <= lo && hi <= b,
+            (Pat::Str(a), Pat::Str(b)) => a == b,
+            _ => false,
+        };
+        if keep {
+            ret.push(row[1..].to_vec());
+        }
+    }
+    return ret;
+}
+
+//rows starting with variant i (or `_`), with its payload spread into n columns
+fn specialize_variant(rows: &[Vec<Pat>], i: usize, n: usize) -> Vec<Vec<Pat>> {
+    let mut ret: Vec<Vec<Pat>> = Vec::new();
+    for row in rows.iter() {
+        let mut r: Vec<Pat> = match &row[0] {
+            Pat::Wild => vec![Pat::Wild; n],
+            Pat::Variant(j, subs) if *j == i => subs.clone(),
+            _ => continue,
+        };
+        r.extend_from_slice(&row[1..]);
+        ret.push(r);
+    }
+    return ret;
+}
+
+fn default_rows(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    return rows
+        .iter()
+        .filter(|r| r[0] == Pat::Wild)
+        .map(|r| r[1..].to_vec())
+        .collect();
+}
+
+fn with_head(head: Pat, mut tail: Vec<Pat>) -> Vec<Pat> {
+    tail.insert(0, head);
+    return tail;
+}
+
+//payload domains of variant i followed by the rest of the columns
+fn variant_doms(table: &SymbolTable, tp: usize, i: usize, rest: &[Domain]) -> Vec<Domain> {
+    let mut ret: Vec<Domain> = table
+        .variant(tp, i)
+        .fields
+        .iter()
+        .map(|f| domain_of(table, *f).unwrap())
+        .collect();
+    ret.extend_from_slice(rest);
+    return ret;
+}
+
+//try variant i of enum tp: a witness with the payload folded back into it
+fn useful_variant(
+    rows: &[Vec<Pat>],
+    i: usize,
+    subs: Vec<Pat>,
+    v: &[Pat],
+    doms: &[Domain],
+    table: &SymbolTable,
+) -> Option<Vec<Pat>> {
+    let tp = match doms[0] {
+        Domain::Enum(tp) => tp,
+        _ => return None,
+    };
+    let n = subs.len();
+    let mut nv = subs;
+    nv.extend_from_slice(&v[1..]);
+
+    let mut w = useful(
+        &specialize_variant(rows, i, n),
+        &nv,
+        &variant_doms(table, tp, i, &doms[1..]),
+        table,
+    )?;
+    let tail = w.split_off(n);
+    return Some(with_head(Pat::Variant(i, w), tail));
+}
+
+//a witness of v being useful, or None
+fn useful(rows: &[Vec<Pat>], v: &[Pat], doms: &[Domain], table: &SymbolTable) -> Option<Vec<Pat>> {
+    if v.is_empty() {
+        return match rows.is_empty() {
+            true => Some(Vec::new()),
+            false => None,
+        };
+    }
+
+    if let Pat::Or(alts) = &v[0] {
+        for alt in alts.iter() {
+            let mut nv = vec![alt.clone()];
+            nv.extend_from_slice(&v[1..]);
+            if let Some(w) = useful(rows, &nv, doms, table) {
+                return Some(w);
+            }
+        }
+        return None;
+    }
+
+    let rows = expand_or(rows);
+    let col_ranges: Vec<(i128, i128)> = rows
+        .iter()
+        .filter_map(|r| match r[0] {
+            Pat::Range(a, b) => Some((a, b)),
+            _ => None,
+        })
+        .collect();
+
+    match &v[0] {
+        Pat::Range(lo, hi) => {
+            for (a, b) in doms[0].pieces(*lo, *hi, &col_ranges) {
+                let ctor = Pat::Range(a, b);
+                if let Some(w) = useful(&specialize(&rows, &ctor), &v[1..], &doms[1..], table) {
+                    return Some(with_head(ctor, w));
+                }
+            }
+            return None;
+        }
+        Pat::Str(_) => {
+            let w = useful(&specialize(&rows, &v[0]), &v[1..], &doms[1..], table)?;
+            return Some(with_head(v[0].clone(), w));
+        }
+        Pat::Variant(i, subs) => {
+            return useful_variant(&rows, *i, subs.clone(), v, doms, table);
+        }
+        _ => {}
+    }
+
+    if let Domain::Enum(tp) = doms[0] {
+        let count = match table.kind(tp) {
+            TypeKind::Enum(variants) => variants.len(),
+            _ => 0,
+        };
+        let present: Vec<usize> = rows
+            .iter()
+            .filter_map(|r| match r[0] {
+                Pat::Variant(i, _) => Some(i),
+                _ => None,
+            })
+            .collect();
+        let missing = (0..count).find(|i| !present.contains(i));
+
+        match missing {
+            None => {
+                //every variant appears; try each with wildcard payloads
+                for i in 0..count {
+                    let subs = vec![Pat::Wild; table.variant(tp, i).fields.len()];
+                    if let Some(w) = useful_variant(&rows, i, subs, v, doms, table) {
+                        return Some(w);
+                    }
+                }
+                return None;
+            }
+            Some(i) => {
+                let w = useful(&default_rows(&rows), &v[1..], &doms[1..], table)?;
+                let subs = vec![Pat::Wild; table.variant(tp, i).fields.len()];
+                return Some(with_head(Pat::Variant(i, subs), w));
+            }
+        }
+    }
+
+    //wildcard
+    if !doms[0].ranges().is_empty() {
+        let segs = doms[0].pieces(i128::MIN, i128::MAX, &col_ranges);
+        let missing: Vec<&(i128, i128)> = segs
+            .iter()
+            .filter(|(a, b)| !col_ranges.iter().any(|(x, y)| x <= a && b <= y))
+            .collect();
+
+        if missing.is_empty() {
+            //every value has a constructor; try each piece
+            for (a, b) in segs.iter() {
+                let ctor = Pat::Range(*a, *b);
+                if let Some(w) = useful(&specialize(&rows, &ctor), &v[1..], &doms[1..], table) {
+                    return Some(with_head(ctor, w));
+                }
+            }
+            return None;
+        }
+
+        let w = useful(&default_rows(&rows), &v[1..], &doms[1..], table)?;
+        let (a, b) = missing[0];
+        return Some(with_head(Pat::Range(*a, *b), w));
+    }
+
+    let w = useful(&default_rows(&rows), &v[1..], &doms[1..], table)?;
+    return Some(with_head(Pat::Wild, w));
+}
+
+fn format_value(x: i128, dom: &Domain) -> String {
+    return match dom {
+        Domain::Bool => (x != 0).to_string(),
+        Domain::Char => match char::from_u32(x as u32) {
+            Some(c) if !c.is_control() => format!("'{}'", c),
+            _ => format!("'\\u{{{:x}}}'", x),
+        },
+        _ => x.to_string(),
+    };
+}
+
+pub fn format_pat(p: &Pat, dom: &Domain, table: &SymbolTable) -> String {
+    return match p {
+        Pat::Wild => "_".to_string(),
+        Pat::Range(a, b) if a == b => format_value(*a, dom),
+        Pat::Range(a, b) => format!("{}..={}", format_value(*a, dom), format_value(*b, dom)),
+        Pat::Str(s) => format!("{:?}", s),
+        Pat::Or(alts) => alts
+            .iter()
+            .map(|x| format_pat(x, dom, table))
+            .collect::<Vec<String>>()
+            .join(" | "),
+        Pat::Variant(i, subs) => {
+            let tp = match dom {
+                Domain::Enum(tp) => *tp,
+                _ => return "_".to_string(),
+            };
+            let v = table.variant(tp, *i);
+            let name = format!("{}::{}", table.type_name(tp), v.name);
+            if subs.is_empty() {
+                return name;
+            }
+            let subs: Vec<String> = subs
+                .iter()
+                .zip(v.fields.iter())
+                .map(|(x, f)| format_pat(x, &domain_of(table, *f).unwrap(), table))
+                .collect();
+            format!("{}({})", name, subs.join(", "))
+        }
+    };
+}
+
+pub fn check_arms(pats: &[Pat], dom: &Domain, table: &SymbolTable) -> MatchReport {
+    let mut ret = MatchReport {
+        unreachable: Vec::new(),
+        missing: None,
+    };
+    let doms = [*dom];
+    let mut rows: Vec<Vec<Pat>> = Vec::new();
+
+    for (i, p) in pats.iter().enumerate() {
+        if useful(&rows, std::slice::from_ref(p), &doms, table).is_none() {
+            ret.unreachable.push(i);
+        }
+        rows.push(vec![p.clone()]);
+    }
+
+    if let Some(w) = useful(&rows, &[Pat::Wild], &doms, table) {
+        ret.missing = Some(format_pat(&w[0], dom, table));
+    }
+    return ret;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_arms, Domain, Pat};
+    use crate::compile::parser::{EnumVariant, SymbolTable, TypeKind, VariableType, TYPE_BOOL};
+    use crate::compile::Target;
+
+    #[test]
+    fn test_match_u8_missing_range() {
+        let pats = vec![Pat::Range(0, 9), Pat::Range(20, 255)];
+        let report = check_arms(
+            &pats,
+            &Domain::Int(0, 255),
+            &SymbolTable::for_target(Target::host()),
+        );
+
+        assert!(report.unreachable.is_empty());
+        assert_eq!(report.missing, Some("10..=19".to_string()));
+    }
+
+    #[test]
+    fn test_match_unreachable_arm() {
+        let pats = vec![
+            Pat::Range(0, 100),
+            Pat::Or(vec![Pat::Range(3, 3), Pat::Range(50, 60)]),
+            Pat::Wild,
+            Pat::Range(7, 7),
+        ];
+        let report = check_arms(
+            &pats,
+            &Domain::Int(-128, 127),
+            &SymbolTable::for_target(Target::host()),
+        );
+
+        assert_eq!(report.unreachable, vec![1, 3]);
+        assert_eq!(report.missing, None);
+    }
+
+    #[test]
+    fn test_match_bool_and_string() {
+        let bools = vec![Pat::Range(1, 1), Pat::Range(0, 0)];
+        assert_eq!(
+            check_arms(
+                &bools,
+                &Domain::Bool,
+                &SymbolTable::for_target(Target::host())
+            )
+            .missing,
+            None
+        );
+
+        let strs = vec![Pat::Str("a".to_string()), Pat::Str("a".to_string())];
+        let report = check_arms(
+            &strs,
+            &Domain::Str,
+            &SymbolTable::for_target(Target::host()),
+        );
+        assert_eq!(report.unreachable, vec![1]);
+        assert_eq!(report.missing, Some("_".to_string()));
+
+        //the surrogates are no chars, nothing is missing between them
+        let chars = vec![Pat::Range(0, 0xD7FF), Pat::Range(0xE000, 0x10FFFF)];
+        let report = check_arms(
+            &chars,
+            &Domain::Char,
+            &SymbolTable::for_target(Target::host()),
+        );
+        assert_eq!(report.missing, None);
+        let chars = vec![Pat::Range(0, 0xD7FF), Pat::Range(0xE001, 0x10FFFF)];
+        let report = check_arms(
+            &chars,
+            &Domain::Char,
+            &SymbolTable::for_target(Target::host()),
+        );
+        assert_eq!(report.missing, Some(format!("'{}'", '\u{e000}')));
+    }
+
+    #[test]
+    fn test_match_enum_payload() {
+        //enum E { A(bool), B }
+        let mut table = SymbolTable::for_target(Target::host());
+        table.variable_type.push(VariableType {
+            name: "E".to_string(),
+            size: 0,
+            align: 1,
+            kind: TypeKind::Enum(vec![
+                EnumVariant {
+                    name: "A".to_string(),
+                    fields: vec![TYPE_BOOL],
+                },
+                EnumVariant {
+                    name: "B".to_string(),
+                    fields: Vec::new(),
+                },
+            ]),
+        });
+        let dom = Domain::Enum(table.variable_type.len() - 1);
+
+        let pats = vec![
+            Pat::Variant(0, vec![Pat::Range(1, 1)]),
+            Pat::Variant(1, Vec::new()),
+        ];
+        let report = check_arms(&pats, &dom, &table);
+        assert!(report.unreachable.is_empty());
+        assert_eq!(report.missing, Some("E::A(false)".to_string()));
+
+        let pats = vec![
+            Pat::Variant(0, vec![Pat::Wild]),
+            Pat::Wild,
+            Pat::Variant(1, Vec::new()),
+        ];
+        let report = check_arms(&pats, &dom, &table);
+        assert_eq!(report.unreachable, vec![2]);
+        assert_eq!(report.missing, None);
+    }
+}
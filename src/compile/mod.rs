@@ -1,39 +1,904 @@
-use super::port::FILENAME_CHARS;
+use super::cache::{self, Cache};
+use super::pool;
+use std::fs;
+use std::io::Write;
 use std::path::Path;
 
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+mod analysis;
 mod ast;
+mod bcgen;
+mod bytecode;
+mod codegen;
+mod diag;
+mod doc;
+mod driver;
+mod format;
+#[cfg(test)]
+mod golden;
+mod interp;
 mod lex;
+mod lint;
+mod matching;
+mod ownership;
 mod parser;
+mod repl;
 mod syntax;
+mod target;
+mod typeck;
+mod vm;
+
+pub use analysis::{Analysis, Completion, Pos, Symbol, SymbolKind};
+pub use bytecode::{is_bytecode, EXTENSION as BYTECODE_EXTENSION};
+use diag::{Diagnostic, Level};
+pub use diag::{WarningLevel, Warnings};
+pub use doc::DocFormat;
+pub use driver::{run_cc, Cc, Stage, DEFAULT_CC};
+pub use format::DEFAULT_WIDTH as DEFAULT_LINE_WIDTH;
+pub use lint::{Lints, RULES as LINT_RULES};
+pub use repl::{compile_repl, is_complete, ReplProgram, ReplState, REPL_MARK};
+pub use target::Target;
+
+//declarations, as opposed to top level statements
+fn is_item(ast: &ast::AST) -> bool {
+    return matches!(
+        ast.tp,
+        ast::ASTtype::Function
+            | ast::ASTtype::Impl
+            | ast::ASTtype::Trait
+            | ast::ASTtype::TypeAlias
+            | ast::ASTtype::Struct
+            | ast::ASTtype::Enum
+            | ast::ASTtype::Use
+    );
+}
+
+//a top level statement that makes its module the entry. module level `var`
+//and `const` declarations may be in any module, a library's among them
+fn is_entry_statement(ast: &ast::AST) -> bool {
+    return !is_item(ast) && ast.tp != ast::ASTtype::Variable;
+}
+
+/*
+lex, parse, type check and generate C for (name, source) modules linked into
+one program. the items of each are seen by all; top level statements, the
+entry point, may only be in one. diagnostics point into modules by index and
+come grouped by module. they are returned even on success. modules are lexed
+and parsed on up to jobs threads. the lints are checked on a program without
+errors. with a cache the modules that did not change are not parsed again
+*/
+pub fn compile_modules(
+    modules: &[(String, String)],
+    target: Target,
+    jobs: usize,
+    lints: Lints,
+    cache: Option<&Cache>,
+) -> (Option<String>, Vec<Diagnostic>) {
+    let (checked, diags) = check_modules(modules, target, jobs, lints, cache);
+    return match checked {
+        None => (None, diags),
+        Some((root, table)) => (Some(codegen::generate(&root, &table)), diags),
+    };
+}
+
+//lex and parse module i, or take its tree from the cache if the same source
+//was parsed before, so only the modules that changed are parsed again
+fn parse_module(i: usize, src: &str, cache: Option<&Cache>) -> (ast::AST, Vec<Diagnostic>) {
+    let key = cache::key(&[b"ast", src.as_bytes()]);
+    let hit = cache
+        .and_then(|x| x.get(&key, "ast"))
+        .and_then(|x| fs::read_to_string(x).ok())
+        .and_then(|x| json::parse(&x).ok());
+    if let Some(v) = hit {
+        let root = ast::AST::from_json(&v["ast"], i);
+        let ds: Option<Vec<Diagnostic>> = v["diags"]
+            .members()
+            .map(|x| Diagnostic::from_json(x, i))
+            .collect();
+        if let (Some(root), Some(ds)) = (root, ds) {
+            return (root, ds);
+        }
+    }
+
+    let mut toks = lex::lex_all(src);
+    for t in toks.iter_mut() {
+        t.file = i;
+    }
+    let (root, ds) = syntax::parse(toks);
+    let ds: Vec<Diagnostic> = ds
+        .into_iter()
+        .map(|d| Diagnostic { file: i, ..d })
+        .collect();
+    if let Some(cache) = cache {
+        let v = json::object! {
+            ast: root.to_json(),
+            diags: json::JsonValue::Array(ds.iter().map(|x| x.to_json()).collect()),
+        };
+        cache.put_bytes(&key, "ast", v.dump().as_bytes());
+    }
+    return (root, ds);
+}
+
+//the front half of compile_modules: the linked, checked tree and its types.
+//with a cache the modules that did not change are not parsed again
+fn check_modules(
+    modules: &[(String, String)],
+    target: Target,
+    jobs: usize,
+    lints: Lints,
+    cache: Option<&Cache>,
+) -> (Option<(ast::AST, parser::SymbolTable)>, Vec<Diagnostic>) {
+    let indexed: Vec<(usize, &str)> = modules.iter().map(|x| x.1.as_str()).enumerate().collect();
+    let parsed = pool::map(&indexed, jobs, |(i, src)| parse_module(*i, src, cache));
+    let mut diags: Vec<Diagnostic> = Vec::new();
+    let mut roots: Vec<ast::AST> = Vec::new();
+    for (root, ds) in parsed {
+        diags.extend(ds);
+        roots.push(root);
+    }
+    if diag::has_error(&diags) {
+        return (None, diags);
+    }
+
+    let entries: Vec<usize> = (0..roots.len())
+        .filter(|i| roots[*i].next.iter().any(is_entry_statement))
+        .collect();
+    for i in entries.iter().skip(1) {
+        let first = roots[*i]
+            .next
+            .iter()
+            .find(|x| is_entry_statement(x))
+            .unwrap();
+        let msg = format!(
+            "`{}` and `{}` both have top level statements; only one linked module may",
+            modules[entries[0]].0, modules[*i].0
+        );
+        diags.push(Diagnostic::error(&first.token, msg));
+    }
+    if diag::has_error(&diags) {
+        return (None, diags);
+    }
+
+    //the entry module last, so its statements keep their order
+    let entry = entries.first().copied().unwrap_or(0);
+    let mut root = roots.remove(entry);
+    let mut items: Vec<ast::AST> = roots.into_iter().flat_map(|x| x.next).collect();
+    items.append(&mut root.next);
+    root.next = items;
+
+    let mut table = parser::SymbolTable::for_target(target);
+    table.files = modules.iter().map(|x| x.0.clone()).collect();
+    diags.extend(typeck::check(&mut root, &mut table));
+    if !diag::has_error(&diags) {
+        diags.extend(ownership::check(&root, &table));
+    }
+    if !diag::has_error(&diags) {
+        diags.extend(lint::check(&root, &table, lints));
+    }
+    diags.sort_by_key(|d| d.file);
+    if diag::has_error(&diags) {
+        return (None, diags);
+    }
+
+    return (Some((root, table)), diags);
+}
+
+//(name, source) of each of the rfxfiles, or the error reading one rendered
+fn read_modules(rfxfiles: &[String]) -> Result<Vec<(String, String)>, String> {
+    let mut modules: Vec<(String, String)> = Vec::new();
+    for (i, f) in rfxfiles.iter().enumerate() {
+        match fs::read_to_string(f) {
+            Err(e) => {
+                let d = Diagnostic::file(Level::Error, format!("cannot read: {}", e));
+                return Err(diag::render(rfxfiles, &[Diagnostic { file: i, ..d }]));
+            }
+            Ok(x) => modules.push((f.clone(), x)),
+        }
+    }
+    return Ok(modules);
+}
 
-pub fn build_c(rfxfile: String, outdir: String, cfile: String) {}
+//check the rfxfiles, linked as one program. the checked tree if there were
+//no errors, and the diagnostics as they are to be printed
+fn check_files(
+    rfxfiles: &[String],
+    target: Target,
+    jobs: usize,
+    warnings: Warnings,
+) -> (Option<(ast::AST, parser::SymbolTable)>, String) {
+    let modules = match read_modules(rfxfiles) {
+        Err(log) => return (None, log),
+        Ok(x) => x,
+    };
+    let (checked, diags) = check_modules(&modules, target, jobs, warnings.lints, None);
+    let diags = diag::apply_warnings(diags, warnings);
+    let log = diag::render(rfxfiles, &diags);
+    return match checked {
+        Some(x) if !diag::has_error(&diags) => (Some(x), log),
+        _ => (None, log),
+    };
+}
+
+//check the rfxfiles, linked as one program, and run it with the interpreter
+//instead of building it, writing what it prints to out. returns the
+//diagnostics as they are to be printed and, if the program ran, how it
+//ended: Err is the message of a panic
+pub fn interpret(
+    rfxfiles: &[String],
+    target: Target,
+    jobs: usize,
+    warnings: Warnings,
+    out: &mut dyn Write,
+) -> (Option<Result<(), String>>, String) {
+    let (checked, log) = check_files(rfxfiles, target, jobs, warnings);
+    return (
+        checked.map(|(root, table)| interp::run(&root, &table, out)),
+        log,
+    );
+}
+
+//compile the rfxfiles, linked as one program, to bytecode: the contents of
+//its .rfxc file, and the diagnostics as they are to be printed
+pub fn build_bytecode(
+    rfxfiles: &[String],
+    target: Target,
+    jobs: usize,
+    warnings: Warnings,
+) -> (Option<Vec<u8>>, String) {
+    let (checked, log) = check_files(rfxfiles, target, jobs, warnings);
+    let bytes = checked.map(|(root, table)| bcgen::compile(&root, &table).to_bytes());
+    return (bytes, log);
+}
+
+//check the rfxfiles, linked as one program, and generate the C of its test
+//harness for `rfx test`: a main that runs each #[test] function in a process
+//of its own. returns the C and the diagnostics as they are to be printed
+pub fn build_tests(
+    rfxfiles: &[String],
+    target: Target,
+    jobs: usize,
+    warnings: Warnings,
+) -> (Option<String>, String) {
+    let (checked, log) = check_files(rfxfiles, target, jobs, warnings);
+    let c = checked.map(|(root, table)| codegen::generate_tests(&root, &table));
+    return (c, log);
+}
+
+//run the bytecode of a .rfxc file in the VM, writing what it prints to out.
+//Err if it is not bytecode this rfx runs, otherwise how the program ended:
+//Err is the message of a panic
+pub fn run_bytecode(bytes: &[u8], out: &mut dyn Write) -> Result<Result<(), String>, String> {
+    let program = bytecode::Program::from_bytes(bytes)?;
+    return Ok(vm::run(&program, out));
+}
+
+//the listing of the bytecode of a .rfxc file
+pub fn disassemble(bytes: &[u8]) -> Result<String, String> {
+    return Ok(bytecode::disassemble(&bytecode::Program::from_bytes(
+        bytes,
+    )?));
+}
+
+//compile the rfxfiles, linked as one program, into outdir/cfile. returns the
+//path of the C file and the diagnostics as they are to be printed, so builds
+//running side by side (-j) can print theirs in order
+pub fn build_c(
+    rfxfiles: &[String],
+    outdir: String,
+    cfile: String,
+    target: Target,
+    cache: Option<&Cache>,
+    jobs: usize,
+    warnings: Warnings,
+) -> (Option<String>, String) {
+    return match read_modules(rfxfiles) {
+        Err(log) => (None, log),
+        Ok(modules) => build_c_modules(&modules, outdir, cfile, target, cache, jobs, warnings),
+    };
+}
+
+//build_c of modules already read, each its file name and its text
+pub fn build_c_modules(
+    modules: &[(String, String)],
+    outdir: String,
+    cfile: String,
+    target: Target,
+    cache: Option<&Cache>,
+    jobs: usize,
+    warnings: Warnings,
+) -> (Option<String>, String) {
+    let rfxfiles: Vec<String> = modules.iter().map(|(f, _)| f.clone()).collect();
+    let write_error = |out: &Path, e: std::io::Error| {
+        let msg = format!("cannot write {}: {}", out.display(), e);
+        return (
+            None,
+            diag::render(&rfxfiles, &[Diagnostic::file(Level::Error, msg)]),
+        );
+    };
 
-pub fn random_cfile(dir: String) -> Option<String> {
-    let mut ret: Option<String> = None;
-    let dir_path = Path::new(&dir);
+    let out = Path::new(&outdir).join(cfile);
+    //the same sources, compiler and target always give the same C. a hit
+    //skips lexing, parsing and checking and replays the warnings. on a miss
+    //the modules that did not change still skip lexing and parsing
+    let key = module_key(modules, target, warnings);
+    if let Some(hit) = cache.and_then(|x| x.get(&key, "c")) {
+        info!("{}: using cached C", rfxfiles[0]);
+        let log = cache.and_then(|x| x.get(&key, "log"));
+        let log = log
+            .and_then(|x| fs::read_to_string(x).ok())
+            .unwrap_or_default();
+        if let Err(e) = fs::copy(&hit, &out) {
+            return write_error(&out, e);
+        }
+        return (Some(out.to_string_lossy().to_string()), log);
+    }
 
-    for seed in 0..FILENAME_CHARS.len().pow(10) {
-        let check = dir_path.clone();
-        let mut filename: String = String::from("0000000000"); //ten chars
+    let (c, diags) = compile_modules(modules, target, jobs, warnings.lints, cache);
+    let diags = diag::apply_warnings(diags, warnings);
+    let log = diag::render(&rfxfiles, &diags);
+    let c = match c {
+        Some(x) if !diag::has_error(&diags) => x,
+        _ => return (None, log),
+    };
 
-        for ci in 1..10 {
-            let x = seed % FILENAME_CHARS.len().pow(ci) / FILENAME_CHARS.len().pow(ci - 1);
+    if let Err(e) = fs::write(&out, &c) {
+        return write_error(&out, e);
+    }
+    if let Some(cache) = cache {
+        cache.put_bytes(&key, "log", log.as_bytes());
+        cache.put_bytes(&key, "c", c.as_bytes());
+    }
+    return (Some(out.to_string_lossy().to_string()), log);
+}
 
-            let index = filename.len() - (ci as usize) + 1;
-            let replace = FILENAME_CHARS[x];
-            filename = filename
-                .chars()
-                .enumerate()
-                .map(|(i, c)| if i == index { replace } else { c })
-                .collect();
+//the tokens (ast false) or syntax tree of rfxfile for --emit=tokens/ast,
+//and the diagnostics as they are to be printed
+pub fn dump(rfxfile: &str, ast: bool) -> (Option<String>, String) {
+    let files = [rfxfile.to_string()];
+    let src = match fs::read_to_string(rfxfile) {
+        Err(e) => {
+            let d = Diagnostic::file(Level::Error, format!("cannot read: {}", e));
+            return (None, diag::render(&files, &[d]));
         }
+        Ok(x) => x,
+    };
+    return dump_source(rfxfile, &src, ast);
+}
 
-        let full = check.join(filename.clone() + ".c");
-        if !full.exists() {
-            ret = Some(filename + ".c");
-            break;
+//dump of src, read from rfxfile
+fn dump_source(rfxfile: &str, src: &str, ast: bool) -> (Option<String>, String) {
+    let files = [rfxfile.to_string()];
+    let toks = lex::lex_all(src);
+    if !ast {
+        let mut out = String::new();
+        for t in toks.iter() {
+            out += &format!("{}:{}: {} {:?}\n", t.line, t.col, t.tp, t.s);
         }
+        return (Some(out), String::new());
+    }
+    let (root, diags) = syntax::parse(toks);
+    let log = diag::render(&files, &diags);
+    if diag::has_error(&diags) {
+        return (None, log);
+    }
+    return (Some(root.dump()), log);
+}
+
+//the source of rfxfile formatted by `rfx fmt`, or the diagnostics as they
+//are to be printed if it does not parse
+pub fn format_source(rfxfile: &str, src: &str, width: usize) -> Result<String, String> {
+    return format::format(src, width).map_err(|d| diag::render(&[rfxfile.to_string()], &d));
+}
+
+//the `rfx doc` pages of the rfxfiles, named by their file stems, each
+//(page, contents), and the diagnostics as they are to be printed. only
+//parsed, so types link across all of them
+pub fn document(rfxfiles: &[String], format: DocFormat) -> (Option<Vec<(String, String)>>, String) {
+    let modules = match read_modules(rfxfiles) {
+        Err(log) => return (None, log),
+        Ok(x) => x,
+    };
+    let mut diags: Vec<Diagnostic> = Vec::new();
+    let mut roots: Vec<(String, ast::AST)> = Vec::new();
+    for (i, (name, src)) in modules.iter().enumerate() {
+        let (root, ds) = syntax::parse(lex::lex_all(src));
+        diags.extend(ds.into_iter().map(|d| Diagnostic { file: i, ..d }));
+        let stem = Path::new(name).file_stem().unwrap_or_default();
+        roots.push((stem.to_string_lossy().to_string(), root));
+    }
+    let log = diag::render(rfxfiles, &diags);
+    if diag::has_error(&diags) {
+        return (None, log);
+    }
+
+    let refs: Vec<(String, &ast::AST)> = roots.iter().map(|(n, r)| (n.clone(), r)).collect();
+    let pages = doc::pages(&refs, format);
+    let named = roots
+        .iter()
+        .zip(pages)
+        .map(|((n, _), page)| (format!("{}.{}", n, format.extension()), page))
+        .collect();
+    return (Some(named), log);
+}
+
+//cache key of the C generated from modules
+fn module_key(modules: &[(String, String)], target: Target, warnings: Warnings) -> String {
+    let bits = target.bits.to_string();
+    let warnings = format!("{:?}", warnings);
+    let mut parts: Vec<&[u8]> = vec![bits.as_bytes(), warnings.as_bytes()];
+    for (name, src) in modules.iter() {
+        parts.push(name.as_bytes());
+        parts.push(src.as_bytes());
     }
+    return cache::key(&parts);
+}
 
-    return ret;
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_modules, codegen, compile_modules, diag, driver, fs, lex, parser, syntax, typeck,
+        Cache, Diagnostic, Lints, Target, WarningLevel, Warnings,
+    };
+
+    fn compile_source(src: &str, target: Target) -> (Option<String>, Vec<Diagnostic>) {
+        return compile_modules(
+            &[(String::new(), src.to_string())],
+            target,
+            1,
+            Lints::NONE,
+            None,
+        );
+    }
+
+    fn errors(src: &str) -> Vec<String> {
+        let (_, diags) = compile_source(src, Target::host());
+        return diags.iter().map(|d| d.msg.clone()).collect();
+    }
+
+    #[test]
+    fn test_match_int_switch() {
+        let src = r#"
+fn name(x: u8) -> String {
+    return match x {
+        0 => "zero",
+        1 | 2 => "small",
+        3..=9 => "digit",
+        _ => "big",
+    };
+}
+print(name(2));
+"#;
+        let (c, diags) = compile_source(src, Target::host());
+        assert!(diags.is_empty());
+        let c = c.unwrap();
+        assert!(c.contains("switch ("));
+        assert!(c.contains("case ((uint8_t)9ULL):"));
+    }
+
+    #[test]
+    fn test_match_string_if_chain() {
+        let src = r#"
+var s: String = "b";
+match s {
+    "a" => print(1),
+    "b" => { print(2); }
+    other => print(other),
+}
+"#;
+        let (c, diags) = compile_source(src, Target::host());
+        assert!(diags.is_empty());
+        let c = c.unwrap();
+        assert!(!c.contains("switch ("));
+        assert!(c.contains("rfx_string_eq("));
+    }
+
+    #[test]
+    fn test_match_diagnostics() {
+        let src = r#"
+var c: char = 'x';
+match c {
+    'a'..='z' => print(1),
+    'q' => print(2),
+}
+"#;
+        assert_eq!(
+            errors(src),
+            vec![
+                "unreachable match arm".to_string(),
+                "non-exhaustive match: `'\\u{0}'..='`'` not covered".to_string(),
+            ]
+        );
+        assert_eq!(
+            errors("var b: bool = true;\nvar x: i8 = match b { true => 1 };"),
+            vec!["non-exhaustive match: `false` not covered".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_warning_levels() {
+        let src = "var c: u8 = 3;\nmatch c { _ => print(1), 2 => print(2) }";
+        let (c, diags) = compile_source(src, Target::host());
+        assert!(c.is_some() && !diag::has_error(&diags) && diags.len() == 1);
+        assert!(diag::apply_warnings(diags.clone(), Warnings::new(WarningLevel::Allow)).is_empty());
+        assert!(diag::has_error(&diag::apply_warnings(
+            diags,
+            Warnings::new(WarningLevel::Deny)
+        )));
+    }
+
+    #[test]
+    fn test_struct_enum_layout() {
+        let src = "struct P { a: u8, b: i64, c: u16 }\nenum E { A(u8), B(P), C }\nenum F { X, Y }\nstruct U {}";
+        let (mut root, _) = syntax::parse(lex::lex_all(src));
+        let mut table = parser::SymbolTable::for_target(Target::host());
+        assert!(typeck::check(&mut root, &mut table).is_empty());
+
+        let layout = |name: &str| {
+            let v = &table.variable_type[table.find_type(name).unwrap()];
+            (v.size, v.align)
+        };
+        assert_eq!(layout("P"), (24, 8));
+        assert_eq!(layout("E"), (32, 8));
+        assert_eq!(layout("F"), (4, 4));
+        assert_eq!(layout("U"), (1, 1));
+    }
+
+    #[test]
+    fn test_target_width() {
+        let src = "struct P { a: u8, b: i64, s: String, n: usize }";
+        let (mut root, _) = syntax::parse(lex::lex_all(src));
+        let mut table = parser::SymbolTable::for_target(Target::with_bits(32));
+        assert!(typeck::check(&mut root, &mut table).is_empty());
+        let p = &table.variable_type[table.find_type("P").unwrap()];
+        //i64 is only 4 aligned on i386, a String is a 4 byte pointer then len and cap
+        assert_eq!((p.size, p.align), (36, 4));
+
+        //-m16 is i386 code, its usize has 32 bits
+        let src = "var a: usize = 65536;\nvar b: usize = 4294967296;\nvar c: isize = -2147483649;";
+        let (_, diags) = compile_source(src, Target::with_bits(16));
+        let msgs: Vec<String> = diags.iter().map(|d| d.msg.clone()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "literal `4294967296` out of range for `usize`".to_string(),
+                "literal `-2147483649` out of range for `isize`".to_string(),
+            ]
+        );
+        let (c, diags) = compile_source(src, Target::with_bits(64));
+        assert!(diags.is_empty());
+        assert!(c.unwrap().contains("v_b = ((uint64_t)4294967296ULL);"));
+    }
+
+    #[test]
+    fn test_target_layout_cc() {
+        use driver::{run_cc, Cc, Stage, DEFAULT_CC};
+        use std::process::Command;
+
+        if Command::new(DEFAULT_CC).arg("--version").output().is_err() {
+            eprintln!("no `{}`, not run", DEFAULT_CC);
+            return;
+        }
+        //what rfx computes for each width must be what cc asked for that
+        //width lays out, checked by cc itself. no headers, those of other
+        //widths may not be installed
+        let src = "struct P { a: u8, b: i64 }\nstruct Q { a: u16, n: usize, c: u8 }";
+        for bits in [16, 32, 64] {
+            let target = Target::with_bits(bits);
+            let (mut root, _) = syntax::parse(lex::lex_all(src));
+            let mut table = parser::SymbolTable::for_target(target);
+            assert!(typeck::check(&mut root, &mut table).is_empty());
+            let layout = |name: &str| {
+                let v = &table.variable_type[table.find_type(name).unwrap()];
+                (v.size, v.align)
+            };
+            let c = format!(
+                "struct P {{ unsigned char a; long long b; }};\n\
+                 struct Q {{ unsigned short a; __SIZE_TYPE__ n; unsigned char c; }};\n\
+                 _Static_assert(sizeof(void *) == {}, \"pointer\");\n\
+                 _Static_assert(sizeof(struct P) == {} && _Alignof(struct P) == {}, \"P\");\n\
+                 _Static_assert(sizeof(struct Q) == {} && _Alignof(struct Q) == {}, \"Q\");\n",
+                target.pointer_size(),
+                layout("P").0,
+                layout("P").1,
+                layout("Q").0,
+                layout("Q").1,
+            );
+            let mut temps = crate::temp::Temps::new(false).unwrap();
+            let cfile = temps.create(".c").unwrap();
+            let obj = temps.create(".o").unwrap();
+            fs::write(&cfile, c).unwrap();
+            let (built, log) = run_cc(
+                &Cc::new(DEFAULT_CC, Some(target)),
+                Stage::Obj,
+                &cfile.to_string_lossy(),
+                &obj.to_string_lossy(),
+                "test",
+                None,
+            );
+            temps.finish(true);
+            assert!(built, "-m{}: {}", bits, log);
+        }
+        assert_eq!(
+            Target::with_bits(16),
+            Target {
+                mode: 16,
+                ..Target::with_bits(32)
+            }
+        );
+    }
+
+    #[test]
+    fn test_enum_match_lowering() {
+        let src = r#"
+struct Point { x: i64, y: i64 }
+enum Shape { Circle(i64), Rect(Point, Point), Empty }
+fn area(s: Shape) -> i64 {
+    return match s {
+        Shape::Circle(r) => 3 * r * r,
+        Shape::Rect(a, b) => (b.x - a.x) * (b.y - a.y),
+        Shape::Empty => 0,
+    };
+}
+print(area(Shape::Rect(Point { x: 0, y: 0 }, Point { x: 2, y: 3 })));
+"#;
+        let (c, diags) = compile_source(src, Target::host());
+        assert!(diags.is_empty());
+        let c = c.unwrap();
+        assert!(c.contains(".tag) {"));
+        assert!(c.contains(".u.v_Rect.f1;"));
+        assert!(c.contains("rfx_Shape__Rect(((rfx_Point){.f_x = "));
+        if let Some(ran) = driver::run_c(&c) {
+            assert_eq!(ran, ("6\n".to_string(), 0, String::new()));
+        }
+
+        let src = "enum L { Cons(i64, L), Nil }\nenum E { A(bool), C }\nvar e = E::C;\nmatch e { E::A(true) => print(1), E::C => print(2) }";
+        assert_eq!(
+            errors(src),
+            vec![
+                "recursive type `L` has infinite size".to_string(),
+                "non-exhaustive match: `E::A(false)` not covered".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_use_after_move() {
+        let src = r#"
+fn take(s: String) {}
+var a: String = "x";
+var b: String = a;
+print(a);
+var c: String = "y";
+if b == "x" {
+    take(c);
+}
+print(c);
+c = "z";
+print(c);
+var v: Vec<String> = [];
+while true {
+    take(b);
+}
+"#;
+        assert_eq!(
+            errors(src),
+            vec![
+                "use of moved value `a`".to_string(),
+                "use of possibly moved value `c`".to_string(),
+                "use of possibly moved value `b`".to_string(),
+            ]
+        );
+
+        let src = r#"
+struct G { id: i64 }
+impl Drop for G {
+    fn drop(self) {
+        var keep: G = self;
+    }
+}
+"#;
+        assert_eq!(
+            errors(src),
+            vec!["cannot move `self` out of `drop`".to_string()]
+        );
+        assert_eq!(
+            errors("struct G { id: i64 }\nimpl Drop for G { fn drop(self) {} }\nvar g = G { id: 1 };\ng.drop();"),
+            vec![
+                "explicit calls to `drop` are not allowed; values are dropped when they go out of scope"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drop_insertion() {
+        let src = r#"
+struct G { id: i64 }
+impl Drop for G {
+    fn drop(self) {
+        print(self.id);
+    }
+}
+fn f(s: String) -> u64 {
+    var t: String = s + "!";
+    return t.len();
+}
+var g: G = G { id: 1 };
+g = G { id: 2 };
+print(f("a"));
+"#;
+        let (c, diags) = compile_source(src, Target::host());
+        assert!(diags.is_empty());
+        let c = c.unwrap();
+        //user drop, then the fields
+        assert!(c.contains("static inline void rfx_drop_G(rfx_G v) {\n    rfx_G_drop(v);\n}"));
+        assert!(c.contains(") rfx_drop_G_fields(v_self);\n"));
+        //locals dropped before returning, innermost first
+        let t = c.find(") rfx_drop_String(v_t);").unwrap();
+        let s = c.find(") rfx_drop_String(v_s);").unwrap();
+        assert!(t < s);
+        //the old g is dropped on reassignment, the new one at the end
+        if let Some(ran) = driver::run_c(&c) {
+            assert_eq!(ran, ("1\n2\n2\n".to_string(), 0, String::new()));
+        }
+    }
+
+    #[test]
+    fn test_module_level_variables() {
+        let src = r#"
+fn show() {
+    print(greeting);
+}
+fn twice() {
+    show();
+    show();
+}
+twice();
+const greeting: String = "hi";
+var n: i64 = 1;
+twice();
+"#;
+        assert_eq!(
+            errors(src),
+            vec!["`twice` uses `greeting`, which is not initialized yet".to_string()]
+        );
+
+        let src = r#"
+fn take(s: String) {}
+fn keep() {
+    take(a);
+}
+var a: String = "a";
+var b: String = "b";
+var c: String = a;
+take(b);
+"#;
+        assert_eq!(
+            errors(src),
+            vec![
+                "cannot move out of module level variable `a`".to_string(),
+                "cannot move `a`, functions use it".to_string(),
+            ]
+        );
+
+        let (c, diags) = compile_source(
+            "fn f() -> i64 { return n; }\nvar n: i64 = 1;\nprint(f());",
+            Target::host(),
+        );
+        assert!(diags.is_empty());
+        let c = c.unwrap();
+        assert!(c.contains("static int64_t v_n;\n"));
+        assert!(c.contains("    return v_n;\n"));
+        assert!(c.contains("int main(void) {\n    v_n = ((int64_t)1LL);\n"));
+    }
+
+    #[test]
+    fn test_linked_modules() {
+        let lib = (
+            "lib.lang".to_string(),
+            "fn twice(x: i64) -> i64 {\n    return 2 * x;\n}".to_string(),
+        );
+        let app = ("app.lang".to_string(), "print(twice(21));".to_string());
+        let (c, diags) = compile_modules(
+            &[app.clone(), lib.clone()],
+            Target::host(),
+            2,
+            Lints::NONE,
+            None,
+        );
+        assert!(diags.is_empty());
+        assert!(c
+            .unwrap()
+            .contains("rfx_print_i64((int64_t)rfx_twice(((int64_t)21LL)));"));
+
+        let other = ("other.lang".to_string(), "fn f() {}\nprint(1);".to_string());
+        let (_, diags) = compile_modules(&[app, lib, other], Target::host(), 1, Lints::NONE, None);
+        assert_eq!(diags.len(), 1);
+        assert_eq!((diags[0].file, diags[0].line), (2, 2));
+        assert_eq!(
+            diags[0].msg,
+            "`app.lang` and `other.lang` both have top level statements; only one linked module may"
+        );
+
+        //a library may declare module level constants and variables
+        let lib = (
+            "lib.lang".to_string(),
+            "const K: i64 = 3;\nvar calls: i64 = 0;\nfn times_k(x: i64) -> i64 {\n    calls += 1;\n    return K * x;\n}".to_string(),
+        );
+        let app = (
+            "app.lang".to_string(),
+            "print(times_k(7));\nprint(K + calls);".to_string(),
+        );
+        let (c, diags) = compile_modules(&[lib, app], Target::host(), 1, Lints::NONE, None);
+        assert!(diags.is_empty(), "{:?}", diags[0].msg);
+        if let Some((out, code, _)) = driver::run_c(&c.unwrap()) {
+            assert_eq!((out.as_str(), code), ("21\n4\n", 0));
+        }
+    }
+
+    #[test]
+    fn test_module_parse_cache() {
+        let dir = std::env::temp_dir().join(format!("rfx_parse_cache_{}", std::process::id()));
+        let cache = Cache::at(dir.clone()).unwrap();
+        let trees = || fs::read_dir(&dir).unwrap().count();
+        let lib = (
+            "lib.lang".to_string(),
+            "/// doubles\nfn twice(x: i64) -> i64 {\n    return 2 * x;\n}".to_string(),
+        );
+        let app = ("app.lang".to_string(), "print(twice(21));".to_string());
+        let modules = [app.clone(), lib.clone()];
+        let (fresh, _) = compile_modules(&modules, Target::host(), 1, Lints::NONE, None);
+        let (first, _) = compile_modules(&modules, Target::host(), 1, Lints::NONE, Some(&cache));
+        assert_eq!(trees(), 2);
+        //the cached trees give the same program
+        let (cached, _) = compile_modules(&modules, Target::host(), 1, Lints::NONE, Some(&cache));
+        assert_eq!((&first, &cached), (&fresh, &fresh));
+
+        //only the edited module is parsed again
+        let app = ("app.lang".to_string(), "print(twice(2));".to_string());
+        let (_, diags) = compile_modules(&[app, lib], Target::host(), 1, Lints::NONE, Some(&cache));
+        assert!(diags.is_empty());
+        assert_eq!(trees(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_test_functions() {
+        let src = r#"
+const n: i64 = 2;
+#[test]
+fn doubles() {
+    assert_eq(n * 2, 4);
+    assert(n > 0);
+}
+print(n);
+"#;
+        let modules = [("t.lang".to_string(), src.to_string())];
+        let (checked, diags) = check_modules(
+            &modules,
+            Target::host(),
+            1,
+            Warnings::new(WarningLevel::Warn).lints,
+            None,
+        );
+        assert!(diags.is_empty());
+        let (root, table) = checked.unwrap();
+        let c = codegen::generate_tests(&root, &table);
+        assert!(c.contains("{\"doubles\", rfx_doubles},"));
+        assert!(c.contains("\"assertion `left == right` failed at t.lang:5:5\""));
+        assert!(c.contains("\"assertion failed at t.lang:6:5\""));
+        //only the variables are set before the tests run
+        assert!(c.contains("    v_n = ((int64_t)2LL);\n    int failed = rfx_run_tests("));
+
+        assert_eq!(
+            errors("#[test]\nfn f(x: i64) {}\nassert_eq(1, true);\nassert(1);"),
+            vec![
+                "test function `f` must take no arguments and return nothing".to_string(),
+                "mismatched types: expected `i64`, found `bool`".to_string(),
+                "mismatched types: expected `bool`, found `i64`".to_string(),
+            ]
+        );
+    }
 }
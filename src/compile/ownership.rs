@@ -0,0 +1,494 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::diag::Diagnostic;
+use super::lex::{Token, TokenType};
+use super::parser::SymbolTable;
+
+/*
+move checking. a value of an owned type (see SymbolTable::is_owned) has one
+owner. using a variable where a value is consumed (initializer, assignment,
+argument, return, struct field, ...) moves it, and the variable cannot be used
+again until it is assigned. everything else only reads the value. reading a
+field or an element where a value is consumed copies it instead of moving.
+
+this walks the typed AST with the state of every variable, merging the states
+of branches, and reports uses of moved or possibly moved variables. codegen
+follows the same rules when it inserts the drops.
+*/
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Live,
+    Moved,
+    MaybeMoved,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Flow {
+    vars: Vec<State>, // by variable id
+    reachable: bool,
+}
+
+impl Flow {
+    fn merge(&self, other: &Flow) -> Flow {
+        if !self.reachable {
+            return other.clone();
+        }
+        if !other.reachable {
+            return self.clone();
+        }
+
+        let vars = self
+            .vars
+            .iter()
+            .zip(other.vars.iter())
+            .map(|(a, b)| if a == b { *a } else { State::MaybeMoved })
+            .collect();
+        return Flow {
+            vars,
+            reachable: true,
+        };
+    }
+
+    fn unreachable(&self) -> Flow {
+        return Flow {
+            vars: self.vars.clone(),
+            reachable: false,
+        };
+    }
+}
+
+struct Var {
+    name: String,
+    id: usize,
+    owned: bool,
+}
+
+struct Checker<'a> {
+    table: &'a SymbolTable,
+    diags: Vec<Diagnostic>,
+    report: bool, //off while looking for the fixed point of a loop
+    scopes: Vec<Vec<Var>>,
+    flow: Flow,
+    loops: Vec<(Vec<Flow>, Vec<Flow>)>, // (break, continue) states
+    drop_self: Option<usize>,           // `self` of a `Drop::drop`
+    globals: Vec<(String, bool)>,       // module level variables, owned or not
+    shared: Vec<String>,                // module level variables functions use
+    in_fn: bool,
+}
+
+pub fn check(root: &AST, table: &SymbolTable) -> Vec<Diagnostic> {
+    let mut c = Checker {
+        table,
+        diags: Vec::new(),
+        report: true,
+        scopes: vec![Vec::new()],
+        flow: Flow {
+            vars: Vec::new(),
+            reachable: true,
+        },
+        loops: Vec::new(),
+        drop_self: None,
+        globals: Vec::new(),
+        shared: Vec::new(),
+        in_fn: false,
+    };
+
+    //functions run at any time, so a module level variable they use must not
+    //be moved out of, neither by them nor by top level code
+    for item in root.next.iter().filter(|x| x.tp == ASTtype::Variable) {
+        let owned = item.vtype.is_some_and(|x| c.table.is_owned(x));
+        c.globals.push((item.next[1].token.s.clone(), owned));
+    }
+    c.in_fn = true;
+    for item in root.next.iter() {
+        match item.tp {
+            ASTtype::Function => c.check_function(item),
+            ASTtype::Impl => {
+                for f in item.next[1..].iter() {
+                    c.check_function(f);
+                }
+            }
+            _ => {}
+        }
+    }
+    c.in_fn = false;
+    for item in root.next.iter() {
+        match item.tp {
+            ASTtype::Function
+            | ASTtype::Impl
+            | ASTtype::Trait
+            | ASTtype::TypeAlias
+            | ASTtype::Struct
+            | ASTtype::Enum
+            | ASTtype::Use => {}
+            _ => c.check_stmt(item),
+        }
+    }
+    return c.diags;
+}
+
+//true if the expression names a place whose value is copied, not moved, when consumed
+pub fn is_projection(ast: &AST) -> bool {
+    return matches!(ast.tp, ASTtype::Field | ASTtype::Index);
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, t: &Token, msg: String) {
+        if !self.report {
+            return;
+        }
+        let d = Diagnostic::error(t, msg);
+        if !self
+            .diags
+            .iter()
+            .any(|x| x.line == d.line && x.col == d.col)
+        {
+            self.diags.push(d);
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    //variables of the scope are gone, and so are their states
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        if let Some(first) = scope.first() {
+            self.flow.vars.truncate(first.id);
+        }
+    }
+
+    fn declare(&mut self, name: &str, tp: Option<usize>) -> usize {
+        let id = self.flow.vars.len();
+        self.flow.vars.push(State::Live);
+        let owned = match tp {
+            None => false,
+            Some(x) => self.table.is_owned(x),
+        };
+        self.scopes.last_mut().unwrap().push(Var {
+            name: name.to_string(),
+            id,
+            owned,
+        });
+        return id;
+    }
+
+    fn find(&self, name: &str) -> Option<(usize, bool)> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(v) = scope.iter().rev().find(|v| v.name == name) {
+                return Some((v.id, v.owned));
+            }
+        }
+        return None;
+    }
+
+    fn check_function(&mut self, f: &AST) {
+        let sym = match f.sym {
+            None => return,
+            Some(x) => x,
+        };
+        if f.next.len() < 5 {
+            return;
+        }
+
+        let saved = std::mem::replace(
+            &mut self.flow,
+            Flow {
+                vars: Vec::new(),
+                reachable: true,
+            },
+        );
+        self.push_scope();
+        for (i, arg) in f.next[2].next.iter().enumerate() {
+            let id = self.declare(&arg.token.s, arg.vtype);
+            if i == 0 && self.table.drop_impl.iter().any(|(_, x)| *x == sym) {
+                self.drop_self = Some(id);
+            }
+        }
+        self.check_block(&f.next[4]);
+        self.pop_scope();
+        self.drop_self = None;
+        self.flow = saved;
+    }
+
+    fn check_block(&mut self, ast: &AST) {
+        self.push_scope();
+        for stmt in ast.next.iter() {
+            self.check_stmt(stmt);
+        }
+        self.pop_scope();
+    }
+
+    fn use_global(&mut self, t: &Token, consume: bool) {
+        let owned = match self.globals.iter().find(|(n, _)| *n == t.s) {
+            None => return,
+            Some((_, x)) => *x,
+        };
+        if !self.shared.contains(&t.s) {
+            self.shared.push(t.s.clone());
+        }
+        if consume && owned {
+            self.error(
+                t,
+                format!("cannot move out of module level variable `{}`", t.s),
+            );
+        }
+    }
+
+    fn use_var(&mut self, t: &Token, consume: bool) {
+        let (id, owned) = match self.find(&t.s) {
+            None if self.in_fn => return self.use_global(t, consume),
+            None => return,
+            Some(x) => x,
+        };
+        if !owned || !self.flow.reachable {
+            return;
+        }
+        if consume && self.shared.contains(&t.s) && self.scopes[0].iter().any(|v| v.id == id) {
+            let msg = format!("cannot move `{}`, functions use it", t.s);
+            self.error(t, msg);
+            return;
+        }
+
+        match self.flow.vars[id] {
+            State::Live => {}
+            State::Moved => {
+                self.error(t, format!("use of moved value `{}`", t.s));
+            }
+            State::MaybeMoved => {
+                self.error(t, format!("use of possibly moved value `{}`", t.s));
+            }
+        }
+        if consume {
+            if self.drop_self == Some(id) {
+                self.error(t, format!("cannot move `{}` out of `drop`", t.s));
+            }
+            self.flow.vars[id] = State::Moved;
+        }
+    }
+
+    //`consume` is true where the value is taken over, e.g. by a function argument
+    fn check_expr(&mut self, ast: &AST, consume: bool) {
+        match ast.tp {
+            ASTtype::Ident => self.use_var(&ast.token, consume),
+            ASTtype::Binary
+                if ast.token.tp == TokenType::LogicAND || ast.token.tp == TokenType::LogicOR =>
+            {
+                //the right side may not run
+                self.check_expr(&ast.next[0], false);
+                let before = self.flow.clone();
+                self.check_expr(&ast.next[1], false);
+                self.flow = self.flow.merge(&before);
+            }
+            ASTtype::Binary | ASTtype::Unary | ASTtype::Field | ASTtype::Index => {
+                for x in ast.next.iter() {
+                    self.check_expr(x, false);
+                }
+            }
+            ASTtype::CallFunction => {
+                let consume_args = ast.sym.is_some() || ast.token.s == "Vec::new_init";
+                for x in ast.next.iter() {
+                    self.check_expr(x, consume_args);
+                }
+            }
+            ASTtype::CallMethod => match ast.sym {
+                //self is taken by value
+                Some(_) => {
+                    for x in ast.next.iter() {
+                        self.check_expr(x, true);
+                    }
+                }
+                None => {
+                    self.check_expr(&ast.next[0], false);
+                    for x in ast.next[1..].iter() {
+                        self.check_expr(x, true);
+                    }
+                }
+            },
+            ASTtype::Array | ASTtype::Variant => {
+                for x in ast.next.iter() {
+                    self.check_expr(x, true);
+                }
+            }
+            ASTtype::StructLiteral => {
+                for init in ast.next.iter() {
+                    self.check_expr(&init.next[0], true);
+                }
+            }
+            ASTtype::Match => self.check_match(ast, true),
+            _ => {}
+        }
+    }
+
+    fn check_stmt(&mut self, ast: &AST) {
+        match ast.tp {
+            ASTtype::Variable => {
+                self.check_expr(&ast.next[3], true);
+                self.declare(&ast.next[1].token.s, ast.vtype);
+            }
+            ASTtype::Assign => self.check_assign(ast),
+            ASTtype::CodeBlock => self.check_block(ast),
+            ASTtype::If => {
+                self.check_expr(&ast.next[0], false);
+                let before = self.flow.clone();
+                self.check_block(&ast.next[1]);
+                let then = std::mem::replace(&mut self.flow, before);
+                if let Some(other) = ast.next.get(2) {
+                    self.check_stmt(other);
+                }
+                self.flow = self.flow.merge(&then);
+            }
+            ASTtype::While => self.check_loop(ast),
+            ASTtype::For => self.check_loop(ast),
+            ASTtype::Match => self.check_match(ast, false),
+            ASTtype::Return => {
+                if let Some(x) = ast.next.first() {
+                    self.check_expr(x, true);
+                }
+                self.flow = self.flow.unreachable();
+            }
+            ASTtype::Break | ASTtype::Continue => {
+                let flow = self.flow.clone();
+                if let Some((breaks, continues)) = self.loops.last_mut() {
+                    match ast.tp {
+                        ASTtype::Break => breaks.push(flow),
+                        _ => continues.push(flow),
+                    }
+                }
+                self.flow = self.flow.unreachable();
+            }
+            _ => self.check_expr(ast, false),
+        }
+    }
+
+    fn check_assign(&mut self, ast: &AST) {
+        self.check_expr(&ast.next[1], true);
+
+        let place = &ast.next[0];
+        if place.tp != ASTtype::Ident {
+            self.check_expr(place, false);
+            return;
+        }
+        if ast.token.tp != TokenType::Equal {
+            //`s += x` reads s
+            self.use_var(&place.token, false);
+        }
+        if let Some((id, _)) = self.find(&place.token.s) {
+            if self.flow.reachable {
+                self.flow.vars[id] = State::Live;
+            }
+        }
+    }
+
+    //one pass over a loop from the state at its head. returns the state after
+    //the body, with `continue` merged in, and the states at `break`
+    fn loop_pass(&mut self, ast: &AST) -> (Flow, Vec<Flow>) {
+        self.loops.push((Vec::new(), Vec::new()));
+        match ast.tp {
+            ASTtype::While => {
+                self.check_block(&ast.next[1]);
+            }
+            _ => {
+                self.push_scope();
+                self.declare(&ast.next[0].token.s, ast.next[0].vtype);
+                self.check_block(&ast.next[2]);
+                self.pop_scope();
+            }
+        }
+        let (breaks, continues) = self.loops.pop().unwrap();
+
+        let mut back = self.flow.clone();
+        for c in continues.iter() {
+            back = back.merge(c);
+        }
+        return (back, breaks);
+    }
+
+    fn check_loop(&mut self, ast: &AST) {
+        //the condition or the iterated value, evaluated once per iteration for `while`
+        let head_expr = match ast.tp {
+            ASTtype::While => &ast.next[0],
+            _ => &ast.next[1],
+        };
+        if ast.tp == ASTtype::For {
+            self.check_expr(head_expr, false);
+        }
+
+        let entry = self.flow.clone();
+        let len = entry.vars.len();
+        let report = self.report;
+        self.report = false;
+
+        //a variable moved in one iteration is moved at the head of the next
+        let mut head = entry.clone();
+        loop {
+            self.flow = head.clone();
+            if ast.tp == ASTtype::While {
+                self.check_expr(head_expr, false);
+            }
+            let (mut back, _) = self.loop_pass(ast);
+            back.vars.truncate(len);
+            let next = entry.merge(&back);
+            if next == head {
+                break;
+            }
+            head = next;
+        }
+
+        self.report = report;
+        self.flow = head;
+        if ast.tp == ASTtype::While {
+            self.check_expr(head_expr, false);
+        }
+        let exit = self.flow.clone();
+        let (_, breaks) = self.loop_pass(ast);
+
+        let mut exit = exit;
+        for b in breaks.iter() {
+            let mut b = b.clone();
+            b.vars.truncate(len);
+            exit = exit.merge(&b);
+        }
+        self.flow = exit;
+    }
+
+    //arms start from the state after the matched value and merge at the end.
+    //the arms of a match used as a value hand their value over
+    fn check_match(&mut self, ast: &AST, is_expr: bool) {
+        self.check_expr(&ast.next[0], false);
+        let start = self.flow.clone();
+        let mut end: Option<Flow> = None;
+
+        for case in ast.next[1..].iter() {
+            self.flow = start.clone();
+            self.push_scope();
+            self.declare_bindings(&case.next[0]);
+            let body = &case.next[1];
+            match body.tp {
+                ASTtype::CodeBlock => self.check_block(body),
+                _ => self.check_expr(body, is_expr),
+            }
+            self.pop_scope();
+
+            end = Some(match end {
+                None => self.flow.clone(),
+                Some(x) => x.merge(&self.flow),
+            });
+        }
+        self.flow = end.unwrap_or(start);
+    }
+
+    //bindings hold copies of the matched parts
+    fn declare_bindings(&mut self, pat: &AST) {
+        if pat.tp == ASTtype::PatBinding {
+            self.declare(&pat.token.s, pat.vtype);
+        }
+        for sub in pat.next.iter() {
+            self.declare_bindings(sub);
+        }
+    }
+}
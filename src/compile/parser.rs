@@ -1,102 +1,431 @@
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
 
-struct VariableType {
-    name: String,
-    size: usize, // into byte
+use super::lex::Token;
+use super::target::Target;
+
+pub const TYPE_I64: usize = 3;
+pub const TYPE_U64: usize = 7;
+pub const TYPE_BOOL: usize = 8;
+pub const TYPE_CHAR: usize = 9;
+pub const TYPE_VOID: usize = 10;
+pub const TYPE_STRING: usize = 11;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TypeKind {
+    Void,
+    Integer { signed: bool },
+    Bool,
+    Char,
+    String,
+    Vec(usize),
+    Struct(Vec<VariableName>),
+    Enum(Vec<EnumVariant>),
 }
 
-struct VariableName<'a> {
-    name: String,
-    tp: &'a VariableType,
+#[derive(Clone, PartialEq, Debug)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Vec<usize>,
 }
 
-struct FunctionName<'a> {
-    name: String,
-    args: Vec<VariableName<'a>>,
-    return_tp: &'a VariableType,
+pub struct VariableType {
+    pub name: String,
+    pub size: usize,  // into byte, as laid out in C
+    pub align: usize, // into byte
+    pub kind: TypeKind,
 }
 
-#[allow(dead_code)]
-enum KeywordType {
-    None,
-    VariableType,
-    VariableName,
+#[derive(Clone, PartialEq, Debug)]
+pub struct VariableName {
+    pub name: String,
+    pub tp: usize,
+    pub constant: bool,
+}
 
-    FunctionName,
+pub struct FunctionName {
+    pub name: String,
+    pub args: Vec<VariableName>,
+    pub return_tp: usize,
+    pub self_tp: Option<usize>, // callable as a method on this type
+    pub free: bool,             // callable by name, not only as a method
+    pub cname: String,
 }
 
-fn init() -> (
-    Vec<VariableType>,
-    Vec<VariableName<'static>>,
-    Vec<FunctionName<'static>>,
-) {
-    let mut variable_type: Vec<VariableType> = Vec::new();
-    let mut variable_name: Vec<VariableName> = Vec::new();
-    let mut function_name: Vec<FunctionName> = Vec::new();
+pub struct TraitName {
+    pub name: String,
+    pub functions: Vec<String>,
+}
 
-    variable_type = vec![
+pub struct SymbolTable {
+    pub variable_type: Vec<VariableType>,
+    pub variable_name: Vec<Vec<VariableName>>, // one Vec per scope
+    pub function_name: Vec<FunctionName>,
+    pub trait_name: Vec<TraitName>,
+    pub alias: Vec<(String, usize)>,
+    pub drop_impl: Vec<(usize, usize)>, // (type, its `Drop::drop` function)
+    pub target: Target,
+    pub files: Vec<String>, // names of the linked modules, for messages
+}
+
+//size and align of String and Vec: a pointer, then u64 len and cap
+fn buffer_layout(target: &Target) -> (usize, usize) {
+    let align = target
+        .align_of(8)
+        .max(target.align_of(target.pointer_size()));
+    return (
+        target.pointer_size().next_multiple_of(target.align_of(8)) + 16,
+        align,
+    );
+}
+
+fn init(target: &Target) -> (Vec<VariableType>, Vec<Vec<VariableName>>, Vec<FunctionName>) {
+    let (buffer_size, buffer_align) = buffer_layout(target);
+    let ptr = target.pointer_size();
+    let variable_type: Vec<VariableType> = vec![
         VariableType {
             name: "i8".to_string(),
             size: 1,
+            align: 1,
+            kind: TypeKind::Integer { signed: true },
         },
         VariableType {
             name: "i16".to_string(),
             size: 2,
+            align: target.align_of(2),
+            kind: TypeKind::Integer { signed: true },
         },
         VariableType {
             name: "i32".to_string(),
             size: 4,
+            align: target.align_of(4),
+            kind: TypeKind::Integer { signed: true },
         },
         VariableType {
             name: "i64".to_string(),
             size: 8,
+            align: target.align_of(8),
+            kind: TypeKind::Integer { signed: true },
         },
         VariableType {
             name: "u8".to_string(),
             size: 1,
+            align: 1,
+            kind: TypeKind::Integer { signed: false },
         },
         VariableType {
             name: "u16".to_string(),
             size: 2,
+            align: target.align_of(2),
+            kind: TypeKind::Integer { signed: false },
         },
         VariableType {
             name: "u32".to_string(),
             size: 4,
+            align: target.align_of(4),
+            kind: TypeKind::Integer { signed: false },
         },
         VariableType {
             name: "u64".to_string(),
             size: 8,
+            align: target.align_of(8),
+            kind: TypeKind::Integer { signed: false },
         },
         VariableType {
             name: "bool".to_string(),
             size: 1,
+            align: 1,
+            kind: TypeKind::Bool,
         },
         VariableType {
             name: "char".to_string(),
             size: 4,
+            align: target.align_of(4),
+            kind: TypeKind::Char,
+        },
+        VariableType {
+            name: "void".to_string(),
+            size: 0,
+            align: 1,
+            kind: TypeKind::Void,
+        },
+        VariableType {
+            name: "String".to_string(),
+            size: buffer_size,
+            align: buffer_align,
+            kind: TypeKind::String,
+        },
+        VariableType {
+            name: "usize".to_string(),
+            size: ptr,
+            align: target.align_of(ptr),
+            kind: TypeKind::Integer { signed: false },
+        },
+        VariableType {
+            name: "isize".to_string(),
+            size: ptr,
+            align: target.align_of(ptr),
+            kind: TypeKind::Integer { signed: true },
         },
     ];
+    let variable_name: Vec<Vec<VariableName>> = vec![Vec::new()];
+    let function_name: Vec<FunctionName> = Vec::new();
 
     return (variable_type, variable_name, function_name);
 }
 
-trait ParseCheck {
-    fn is_variable_type(self, v: Vec<VariableType>) -> Option<usize>;
-    fn is_variable_name(self, v: Vec<VariableName>) -> Option<usize>;
-    fn is_function_name(self, v: Vec<FunctionName>) -> Option<usize>;
+pub trait ParseCheck {
+    fn is_variable_type(&self, v: &[VariableType]) -> Option<usize>;
+    fn is_variable_name(&self, v: &[VariableName]) -> Option<usize>;
+    fn is_function_name(&self, v: &[FunctionName]) -> Option<usize>;
 }
 
-impl ParseCheck for String {
-    fn is_variable_type(self, v: Vec<VariableType>) -> Option<usize> {
+impl ParseCheck for str {
+    fn is_variable_type(&self, v: &[VariableType]) -> Option<usize> {
         return v.iter().position(|x| x.name == self);
     }
 
-    fn is_variable_name(self, v: Vec<VariableName>) -> Option<usize> {
-        return v.iter().position(|x| x.name == self);
+    fn is_variable_name(&self, v: &[VariableName]) -> Option<usize> {
+        return v.iter().rposition(|x| x.name == self);
     }
 
-    fn is_function_name(self, v: Vec<FunctionName>) -> Option<usize> {
-        return v.iter().position(|x| x.name == self);
+    fn is_function_name(&self, v: &[FunctionName]) -> Option<usize> {
+        return v.iter().position(|x| x.name == self && x.free);
+    }
+}
+
+impl SymbolTable {
+    pub fn for_target(target: Target) -> Self {
+        let (variable_type, variable_name, function_name) = init(&target);
+        SymbolTable {
+            variable_type,
+            variable_name,
+            function_name,
+            //`Drop` is built in: `fn drop(self)` runs when a value goes away
+            trait_name: vec![TraitName {
+                name: "Drop".to_string(),
+                functions: vec!["drop".to_string()],
+            }],
+            alias: Vec::new(),
+            drop_impl: Vec::new(),
+            target,
+            files: Vec::new(),
+        }
+    }
+
+    //where t is, as "file:line:col", or "line:col" if its module has no name
+    pub fn location(&self, t: &Token) -> String {
+        return match self.files.get(t.file) {
+            Some(f) if !f.is_empty() => format!("{}:{}:{}", f, t.line, t.col),
+            _ => format!("{}:{}", t.line, t.col),
+        };
+    }
+
+    pub fn kind(&self, tp: usize) -> &TypeKind {
+        return &self.variable_type[tp].kind;
+    }
+
+    pub fn type_name(&self, tp: usize) -> &str {
+        return &self.variable_type[tp].name;
+    }
+
+    pub fn is_integer(&self, tp: usize) -> bool {
+        return matches!(self.kind(tp), TypeKind::Integer { .. });
+    }
+
+    pub fn find_type(&self, name: &str) -> Option<usize> {
+        if let Some((_, tp)) = self.alias.iter().find(|(n, _)| n == name) {
+            return Some(*tp);
+        }
+        return name.is_variable_type(&self.variable_type);
+    }
+
+    //find or add Vec<elem>
+    pub fn vec_of(&mut self, elem: usize) -> usize {
+        let name = format!("Vec<{}>", self.variable_type[elem].name);
+        if let Some(x) = name.is_variable_type(&self.variable_type) {
+            return x;
+        }
+
+        let (size, align) = buffer_layout(&self.target);
+        self.variable_type.push(VariableType {
+            name,
+            size,
+            align,
+            kind: TypeKind::Vec(elem),
+        });
+        return self.variable_type.len() - 1;
+    }
+
+    //inclusive value range of an integer, char or bool type
+    pub fn value_range(&self, tp: usize) -> Option<(i128, i128)> {
+        let v = &self.variable_type[tp];
+        return match v.kind {
+            TypeKind::Integer { signed: true } => {
+                let bits = v.size as u32 * 8;
+                Some((-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1))
+            }
+            TypeKind::Integer { signed: false } => Some((0, (1i128 << (v.size as u32 * 8)) - 1)),
+            TypeKind::Bool => Some((0, 1)),
+            TypeKind::Char => Some((0, 0x10FFFF)),
+            _ => None,
+        };
+    }
+
+    pub fn drop_impl(&self, tp: usize) -> Option<usize> {
+        return self
+            .drop_impl
+            .iter()
+            .find(|(t, _)| *t == tp)
+            .map(|(_, f)| *f);
+    }
+
+    //true if values of the type own memory or have a `Drop` impl, so they are
+    //moved rather than copied and dropped when they go away
+    pub fn is_owned(&self, tp: usize) -> bool {
+        return match self.kind(tp) {
+            TypeKind::String | TypeKind::Vec(_) => true,
+            TypeKind::Struct(fields) => {
+                self.drop_impl(tp).is_some() || fields.iter().any(|x| self.is_owned(x.tp))
+            }
+            TypeKind::Enum(variants) => {
+                self.drop_impl(tp).is_some()
+                    || variants
+                        .iter()
+                        .any(|v| v.fields.iter().any(|x| self.is_owned(*x)))
+            }
+            _ => false,
+        };
+    }
+
+    pub fn field(&self, tp: usize, name: &str) -> Option<&VariableName> {
+        return match self.kind(tp) {
+            TypeKind::Struct(fields) => fields.iter().find(|x| x.name == name),
+            _ => None,
+        };
+    }
+
+    pub fn find_variant(&self, tp: usize, name: &str) -> Option<usize> {
+        return match self.kind(tp) {
+            TypeKind::Enum(variants) => variants.iter().position(|x| x.name == name),
+            _ => None,
+        };
+    }
+
+    pub fn variant(&self, tp: usize, i: usize) -> &EnumVariant {
+        return match self.kind(tp) {
+            TypeKind::Enum(variants) => &variants[i],
+            _ => panic!("{} is not an enum", self.type_name(tp)),
+        };
+    }
+
+    //fill size and align of a struct or enum the way a C compiler lays it out.
+    //an enum is a u32 tag followed by a union of the payloads.
+    //false if the type contains itself by value
+    pub fn layout(&mut self, tp: usize, visiting: &mut Vec<usize>) -> bool {
+        let fields: Vec<Vec<usize>> = match self.kind(tp) {
+            TypeKind::Struct(fields) => vec![fields.iter().map(|x| x.tp).collect()],
+            TypeKind::Enum(variants) => variants.iter().map(|x| x.fields.clone()).collect(),
+            _ => {
+                return true;
+            }
+        };
+        if visiting.contains(&tp) {
+            return false;
+        }
+
+        visiting.push(tp);
+        for f in fields.iter().flatten() {
+            if !self.layout(*f, visiting) {
+                return false;
+            }
+        }
+        visiting.pop();
+
+        //(size, align) of each field list as a C struct
+        let mut parts: Vec<(usize, usize)> = Vec::new();
+        for list in fields.iter() {
+            let mut size: usize = 0;
+            let mut align: usize = 1;
+            for f in list.iter() {
+                let v = &self.variable_type[*f];
+                size = size.next_multiple_of(v.align) + v.size;
+                align = align.max(v.align);
+            }
+            parts.push((size.next_multiple_of(align), align));
+        }
+
+        let (size, align) = match self.kind(tp) {
+            //an empty C struct gets one dummy byte
+            TypeKind::Struct(_) => (parts[0].0.max(1), parts[0].1),
+            _ => {
+                let payload: Vec<&(usize, usize)> =
+                    parts.iter().filter(|(size, _)| *size > 0).collect();
+                let usize = payload.iter().map(|(s, _)| *s).max().unwrap_or(0);
+                let ualign = payload.iter().map(|(_, a)| *a).max().unwrap_or(1);
+                let align = ualign.max(4);
+                match usize {
+                    0 => (4, 4),
+                    _ => (
+                        (4usize.next_multiple_of(ualign) + usize).next_multiple_of(align),
+                        align,
+                    ),
+                }
+            }
+        };
+        self.variable_type[tp].size = size;
+        self.variable_type[tp].align = align;
+        return true;
+    }
+
+    pub fn push_scope(&mut self) {
+        self.variable_name.push(Vec::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.variable_name.pop();
+    }
+
+    //false if the name is already declared in the current scope
+    pub fn declare(&mut self, v: VariableName) -> bool {
+        let scope = self.variable_name.last_mut().unwrap();
+        if v.name.is_variable_name(scope).is_some() {
+            return false;
+        }
+        scope.push(v);
+        return true;
+    }
+
+    pub fn find_variable(&self, name: &str) -> Option<&VariableName> {
+        for scope in self.variable_name.iter().rev() {
+            if let Some(x) = name.is_variable_name(scope) {
+                return Some(&scope[x]);
+            }
+        }
+        return None;
+    }
+
+    //index in the module scope, if `name` resolves to a module level variable
+    pub fn find_global(&self, name: &str) -> Option<usize> {
+        for (i, scope) in self.variable_name.iter().enumerate().rev() {
+            if let Some(x) = name.is_variable_name(scope) {
+                return match i {
+                    0 => Some(x),
+                    _ => None,
+                };
+            }
+        }
+        return None;
+    }
+
+    pub fn find_function(&self, name: &str) -> Option<usize> {
+        return name.is_function_name(&self.function_name);
+    }
+
+    pub fn find_method(&self, tp: usize, name: &str) -> Option<usize> {
+        return self
+            .function_name
+            .iter()
+            .position(|x| x.name == name && x.self_tp == Some(tp));
     }
 }
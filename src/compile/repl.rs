@@ -0,0 +1,252 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use std::io::Write;
+
+use super::ast::{ASTtype, AST};
+use super::diag::{self, Diagnostic};
+use super::lex::{Lex, LexStream, TokenType};
+use super::parser::{SymbolTable, TypeKind, TYPE_VOID};
+use super::{check_modules, codegen, interp, syntax, Lints, Target, Warnings};
+
+/*
+rfx repl compiles each entry into a program that first runs the entries
+before it again, so their variables and items are there, prints REPL_MARK,
+then runs the entry. what the program prints after the mark is the entry's.
+the interpreter runs only the entry, with the variables ReplState kept
+*/
+pub const REPL_MARK: &str = "\0rfx repl\0";
+
+//what the interpreter keeps between entries: the values of the module level
+//variables, in the order the session declares them
+#[derive(Default)]
+pub struct ReplState {
+    globals: Vec<Option<interp::Value>>,
+}
+
+//what an entry compiles to, the program to build or interpret
+pub struct ReplProgram {
+    root: AST,
+    table: SymbolTable,
+    offset: u64,            // the lines before the entry
+    pub tp: Option<String>, // the type of an expression entry, its value printed last if print can
+    pub log: String,        // warnings
+}
+
+impl ReplProgram {
+    pub fn c(&self) -> String {
+        return codegen::generate(&self.root, &self.table);
+    }
+
+    //run the entry with the interpreter, from and into state. Err is the
+    //message of a panic, and leaves state as it was
+    pub fn interpret(&self, state: &mut ReplState, out: &mut dyn Write) -> Result<(), String> {
+        let (root, table) = (&self.root, &self.table);
+        return interp::run_entry(root, table, self.offset, &mut state.globals, out);
+    }
+}
+
+//false while src has unclosed brackets, an unterminated string or long
+//comment, so the REPL reads another line
+pub fn is_complete(src: &str) -> bool {
+    let mut lexs = LexStream::from_string(src.to_string());
+    let mut depth: i64 = 0;
+    while let Some(t) = lexs.lex() {
+        match t.tp {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBrack => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBrack => depth -= 1,
+            TokenType::BrokenLeftLongComment => return false,
+            //a string running into the end of the input. after a bad escape
+            //the rest does not lex right, leave it to the compiler to report
+            TokenType::Broken => return !(t.s.starts_with('"') && lexs.peek().is_none()),
+            _ => {}
+        }
+    }
+    return depth <= 0;
+}
+
+//the entry with a `;` if it lacks one, so `1 + 2` may be typed as is
+fn terminated(entry: &str) -> String {
+    let e = entry.trim_end();
+    return match e.ends_with(';') || e.ends_with('}') {
+        true => e.to_string(),
+        false => format!("{};", e),
+    };
+}
+
+fn is_expression(tp: ASTtype) -> bool {
+    return matches!(
+        tp,
+        ASTtype::Literal
+            | ASTtype::Ident
+            | ASTtype::Binary
+            | ASTtype::Unary
+            | ASTtype::CallFunction
+            | ASTtype::CallMethod
+            | ASTtype::Index
+            | ASTtype::Array
+            | ASTtype::Field
+            | ASTtype::StructLiteral
+            | ASTtype::Variant
+    );
+}
+
+//the diagnostics of the entry, with lines counted from its start. those of
+//the earlier entries were shown when they were entered
+fn entry_diags(diags: Vec<Diagnostic>, offset: u64, w: Warnings) -> Vec<Diagnostic> {
+    let diags = diags
+        .into_iter()
+        .filter(|d| d.line > offset)
+        .map(|d| Diagnostic {
+            line: d.line - offset,
+            ..d
+        })
+        .collect();
+    return diag::apply_warnings(diags, w);
+}
+
+fn render(diags: &[Diagnostic]) -> String {
+    return diag::render(&["<repl>".to_string()], diags);
+}
+
+/*
+compile entry after the earlier entries of the session. an entry that is a
+single expression is printed when print takes its type, and the type is
+returned. errors come back rendered
+*/
+pub fn compile_repl(
+    session: &[String],
+    entry: &str,
+    target: Target,
+    warnings: Warnings,
+) -> Result<ReplProgram, String> {
+    let entry = terminated(entry);
+    let mut prelude = String::new();
+    for e in session.iter() {
+        prelude += &terminated(e);
+        prelude.push('\n');
+    }
+    prelude += "print(\"\\0rfx repl\\0\");\n";
+    let offset = prelude.lines().count() as u64;
+    let program = |entry: &str| vec![(String::new(), prelude.clone() + entry)];
+
+    let (checked, diags) = check_modules(&program(&entry), target, 1, Lints::NONE, None);
+    let diags = entry_diags(diags, offset, warnings);
+    let log = render(&diags);
+    let (root, table) = match checked {
+        Some(x) if !diags.iter().any(|d| d.is_error()) => x,
+        _ => return Err(log),
+    };
+
+    //a lone expression is the last statement; typeck gave it its type
+    let (alone, _) = syntax::parse(super::lex::lex_all(&entry));
+    let last = root.next.last().filter(|x| x.token.line > offset);
+    let tp = match (alone.next.len(), last) {
+        (1, Some(x)) if is_expression(x.tp) => x.vtype.filter(|t| *t != TYPE_VOID),
+        _ => None,
+    };
+    let printable = tp.map(|t| {
+        table.is_integer(t)
+            || matches!(
+                table.kind(t),
+                TypeKind::Bool | TypeKind::Char | TypeKind::String
+            )
+    });
+
+    let source = match printable {
+        Some(true) => format!("print({});", entry.trim_end_matches(';')),
+        _ => entry.clone(),
+    };
+    let tp = tp.map(|t| table.type_name(t).to_string());
+    let (checked, diags) = check_modules(&program(&source), target, 1, Lints::NONE, None);
+    let (root, table) = checked.ok_or_else(|| render(&entry_diags(diags, offset, warnings)))?;
+    return Ok(ReplProgram {
+        root,
+        table,
+        offset,
+        tp,
+        log,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile_repl, is_complete, ReplState};
+    use crate::compile::{Target, WarningLevel, Warnings};
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("var x: i64 = 1;"));
+        assert!(is_complete("1 + 2"));
+        assert!(!is_complete("fn f() {"));
+        assert!(!is_complete("print(\"a"));
+        assert!(!is_complete("/* note"));
+        assert!(is_complete("print(\"\\q\");"));
+        assert!(is_complete("fn f() {\n    return;\n}"));
+    }
+
+    #[test]
+    fn test_compile_repl() {
+        let session = vec!["var x: i64 = 2;".to_string()];
+        let p = compile_repl(
+            &session,
+            "x + 1",
+            Target::host(),
+            Warnings::new(WarningLevel::Warn),
+        )
+        .unwrap();
+        assert_eq!(p.tp.as_deref(), Some("i64"));
+        assert!(p.c().contains("rfx_print_i64"));
+        let mut out: Vec<u8> = Vec::new();
+        let mut state = ReplState::default();
+        let w = Warnings::new(WarningLevel::Warn);
+        let first = compile_repl(&[], &session[0], Target::host(), w).unwrap();
+        first.interpret(&mut state, &mut out).unwrap();
+        p.interpret(&mut state, &mut out).unwrap();
+        assert_eq!(out, b"3\n");
+
+        let p = compile_repl(
+            &session,
+            "x = 5;",
+            Target::host(),
+            Warnings::new(WarningLevel::Warn),
+        )
+        .unwrap();
+        assert_eq!(p.tp, None);
+
+        let err = compile_repl(
+            &session,
+            "\ny + 1",
+            Target::host(),
+            Warnings::new(WarningLevel::Warn),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err, "<repl>:2:1: error: unknown variable `y`\n");
+    }
+
+    //entries are interpreted once, the variables kept between them
+    #[test]
+    fn test_interpret_state() {
+        let entries = [
+            "var x: i64 = 2;\nprint(x);",
+            "x = x + 1;",
+            "x",
+            "x = 9;\n[1][x];",
+            "x",
+        ];
+        let want = ["2\n", "", "3\n", "", "3\n"];
+        let mut session: Vec<String> = Vec::new();
+        let mut state = ReplState::default();
+        for (entry, want) in entries.iter().zip(want) {
+            let w = Warnings::new(WarningLevel::Warn);
+            let p = compile_repl(&session, entry, Target::host(), w).unwrap();
+            let mut out: Vec<u8> = Vec::new();
+            match p.interpret(&mut state, &mut out) {
+                Ok(()) => session.push(entry.to_string()),
+                Err(e) => assert!(e.contains("out of bounds"), "{}", e),
+            }
+            assert_eq!(String::from_utf8_lossy(&out), want);
+        }
+    }
+}
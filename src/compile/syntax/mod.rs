@@ -1,217 +1,176 @@
-use std::slice::Iter;
-use std::{fs::File, io::BufReader};
+use std::collections::HashMap;
 
 use super::{
-    ast::ASTtype,
     ast::AST,
-    lex::{Lex, Token, TokenType},
+    diag::Diagnostic,
+    lex::{Token, TokenType},
 };
 
 mod parse;
-/*
-struct SyntaxInfo<'a> {
-    expect: Vec<Option<TokenType>>,
-    result: Vec<Option<AST>>,
-    done: Vec<Option<bool>>,
-    tok_iter: Iter<'a, Token>,
-    err: bool,
-    end: bool,
-}
 
-impl<'a> SyntaxInfo<'a> {
-    fn new(iter: Iter<'a, Token>) -> Self {
-        return Self {
-            expect: Vec::new(),
-            result: Vec::new(),
-            done: Vec::new(),
-            tok_iter: iter,
-            err: false,
-            end: false,
-        };
-    }
-
-    fn gettok(&self) -> Option<&Token> {
-        return self.tok_iter.next();
-    }
-}
+pub use parse::binary_precedence;
 
-//super means cannot determine
-static start_expect_list: &[(TokenType, ASTtype, &[usize])] =
-    &[(TokenType::Fn, ASTtype::Fucntion, &[0])];
-
-type SyntaxASTAdder = fn(&mut AST, Token);
-
-static next_expect_list: &[(TokenType, Option<SyntaxASTAdder>, &[usize])] = &[
-    (TokenType::String, syntax_ast_fn_name_adder, &[1]), //0
-    (TokenType::LeftParen, None, &[2, 3]),               //1
-    (TokenType::RightParen, None, &[5, 7]),              //2
-    (TokenType::String, syntax_ast_fn_arg_adder, &[4]),  //3
-    (TokenType::Comma, None, &[2, 3]),                   //4
-    (TokenType::Arrow, None, &[6]),                      //5
-    (TokenType::String, syntax_ast_fn_return_adder, &[7]), //6
-    (TokenType::LeftBrack, None, &[]),                   //7
-];
-
-trait SyntaxCheck<'a> {
-    fn start(i: Iter<'a, Token>) -> SyntaxInfo<'a>;
-    fn get_next_expect(
-        &'a self,
-        index: usize,
-        out_toktp: &'a mut Vec<Option<TokenType>>,
-        out_ast: &'a mut Vec<Option<AST>>,
-        out_done: &mut Vec<Option<bool>>,
-    );
-    fn check_next(&'a self) -> SyntaxInfo<'a>;
-    fn ignore_next(&'a self) -> SyntaxInfo<'a>;
-    fn peek_next(&'a self) -> SyntaxInfo<'a>;
-    //return result ast. if "self.result.len != 1" return self.result.len
-    fn get_result(&self) -> Result<AST, u64>;
+pub struct Parser {
+    toks: Vec<Token>,
+    pos: usize,
+    no_struct: bool, //struct literals are not allowed in `if x {` and the like
+    docs: HashMap<(u64, u64), String>, // by the (line, col) of the token after
+    pub diags: Vec<Diagnostic>,
 }
 
-impl<'a> SyntaxCheck<'a> for SyntaxInfo<'a> {
-    fn start(i: Iter<'a, Token>) -> SyntaxInfo<'a> {
-        return SyntaxInfo::new(i);
-    }
-    fn get_next_expect(
-        &'a self,
-        index: usize,
-        out_toktp: &mut Vec<Option<TokenType>>,
-        out_ast: &mut Vec<Option<AST>>,
-        out_done: &mut Vec<Option<bool>>,
-    ) {
-        let now_ast_root = self.result.iter().nth(index).unwrap();
-
-        if now_ast_root.is_none() {
-            return;
-        }
-
-        let now_ast = now_ast_root.unwrap();
-        match now_ast.tp {
-            _ => {
-                return;
-            }
-        }
-    }
-    fn check_next(&'a self) -> SyntaxInfo<'a> {
-        let tok = self.gettok();
-        let del: Vec<usize> = Vec::new();
-        if tok.is_none() {
-            self.end = true;
-            return *self;
-        }
-
-        let tok = tok.unwrap();
-        let new_expect: Vec<Option<TokenType>> = Vec::new();
-        let new_result: Vec<Option<AST>> = Vec::new();
-        let new_done: Vec<Option<bool>> = Vec::new();
-        for ((i, expect), done) in self.expect.iter().enumerate().zip(self.done.iter()) {
-            if expect.is_none() {
+impl Parser {
+    //doc comments are taken out of the tokens, for the item they come before
+    pub fn new(toks: Vec<Token>) -> Self {
+        let mut docs: HashMap<(u64, u64), String> = HashMap::new();
+        let mut pending: Vec<String> = Vec::new();
+        let mut rest: Vec<Token> = Vec::new();
+        for t in toks {
+            if t.tp == TokenType::DocComment {
+                pending.push(t.s);
                 continue;
             }
-            if expect.unwrap() != tok.tp || done.unwrap() {
-                *expect = None;
-                continue;
+            if !pending.is_empty() {
+                docs.insert((t.line, t.col), pending.join("\n"));
+                pending.clear();
             }
-
-            self.get_next_expect(i, &mut new_expect, &mut new_result, &mut new_done);
+            rest.push(t);
+        }
+        Parser {
+            toks: rest,
+            pos: 0,
+            no_struct: false,
+            docs,
+            diags: Vec::new(),
         }
-
-        self.expect = new_expect;
-        self.result = new_result;
-        self.done = new_done;
-
-        return *self;
     }
-    fn ignore_next(&'a self) -> SyntaxInfo<'a> {}
-    fn peek_next(&'a self) -> SyntaxInfo<'a> {}
-    //return result ast. if "self.result.len != 1" return self.result.len
-    fn get_result(&self) -> Result<AST, u64> {}
-}
-
-/*
-fn try_parse_func(v: Iter<Token>) -> Option<AST> {
-    let ret: AST = AST::new();
 
-    ret.tp = ASTtype::Fucntion;
+    //the doc comments before the next token
+    fn doc(&self) -> Option<String> {
+        let t = self.peek()?;
+        return self.docs.get(&(t.line, t.col)).cloned();
+    }
 
-    let tok = v.next();
-    let expect = TokenType::Fn;
-    if tok.is_none() || tok.unwrap().tp == expect {
-        return None;
+    fn peek(&self) -> Option<&Token> {
+        return self.toks.get(self.pos);
     }
 
-    let tok = v.next();
-    let expect = TokenType::String;
-    if tok.is_none() || tok.unwrap().tp == expect {
-        return None;
-    } else {
-        //push name
-        let name_ast = AST::new();
-        name_ast.tp = ASTtype::FnIdent;
-        name_ast.token = tok.unwrap().clone();
-        ret.next.push(name_ast);
+    //Null on end of file
+    fn peek_tp(&self) -> TokenType {
+        return self.peek_nth_tp(0);
     }
 
-    let tok = v.next();
-    let expect = TokenType::LeftParen;
-    if tok.is_none() || tok.unwrap().tp == expect {
-        return None;
+    fn peek_nth_tp(&self, n: usize) -> TokenType {
+        return match self.toks.get(self.pos + n) {
+            None => TokenType::Null,
+            Some(t) => t.tp,
+        };
     }
 
-    //push arg
-    let args = try_parse_func_arg(v);
-    ret.next.push(args);
+    fn gettok(&mut self) -> Option<Token> {
+        let ret = self.toks.get(self.pos).cloned();
+        if ret.is_some() {
+            self.pos += 1;
+        }
+        return ret;
+    }
 
-    //fn abc(...) -> type {
-    // or
-    //fn abc(...) {
-    let tok = v.next();
-    if tok.is_none() {
-        return None;
+    //the last token, for reporting errors at end of file
+    fn last_tok(&self) -> Token {
+        return match self.toks.last() {
+            None => {
+                let mut t = Token::new();
+                t.line = 1;
+                t.col = 1;
+                t
+            }
+            Some(t) => t.clone(),
+        };
     }
-    let tok = tok.unwrap();
-    let rettype_ast = AST::new();
-    rettype_ast.tp = ASTtype::FnRetType;
-    ret.next.push(rettype_ast);
-    if tok.tp == TokenType::Arrow {
-        //push return type
-        let rettype_tok = v.next();
-        if rettype_tok.is_none() {
-            return None;
-        }
-        let rettype_tok = rettype_tok.unwrap();
-        if rettype_tok.tp != TokenType::String {
-            return None;
+
+    //split a '>>' token into two '>', for closing nested generic types
+    fn split_shift(&mut self) {
+        if self.peek_tp() != TokenType::RightBShift {
+            return;
         }
-        rettype_ast.token = *rettype_tok;
-    } else if tok.tp == TokenType::LeftBrace {
-        //push dummy return type
-        rettype_ast.token = Token::new();
-        rettype_ast.token.tp = TokenType::Null;
-    }
 
-    return Some(ret);
+        let mut second = self.toks[self.pos].clone();
+        second.tp = TokenType::RightABrack;
+        second.s = ">".to_string();
+        second.col += 1;
+        self.toks[self.pos].tp = TokenType::RightABrack;
+        self.toks[self.pos].s = ">".to_string();
+        self.toks.insert(self.pos + 1, second);
+    }
 }
 
-//none means EOF
-pub fn get_AST_block(r: BufReader<File>) -> Option<AST> {
-    let tok_vec: Vec<Token> = Vec::new();
+pub fn parse(toks: Vec<Token>) -> (AST, Vec<Diagnostic>) {
+    let mut p = Parser::new(toks);
+    let mut root = AST::new();
 
-    loop {
-        let tok_wrap = r.lex();
-        if tok_wrap.is_none() {
-            break;
-        }
+    parse::main_parse(&mut p, &mut root);
+    return (root, p.diags);
+}
 
-        let tok = tok_wrap.unwrap();
-        if tok.tp == TokenType::LeftBrace || tok.tp == TokenType::Semicolon {
-            tok_vec.push(tok);
-            break;
-        }
-        tok_vec.push(tok);
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::compile::ast::ASTtype;
+    use crate::compile::lex::lex_all;
+
+    type Bodies = Vec<Vec<ASTtype>>;
+
+    //(line, col) of each error, and the types of the items and the
+    //statements of the function bodies
+    fn recovered(src: &str) -> (Vec<(u64, u64)>, Vec<ASTtype>, Bodies) {
+        let (root, diags) = parse(lex_all(src));
+        let at = diags.iter().map(|d| (d.line, d.col)).collect();
+        let items = root.next.iter().map(|x| x.tp).collect();
+        let bodies = root
+            .next
+            .iter()
+            .filter(|x| x.tp == ASTtype::Function && x.next.len() > 4)
+            .map(|f| f.next[4].next.iter().map(|x| x.tp).collect())
+            .collect();
+        return (at, items, bodies);
     }
 
-    try_parse_func(tok_vec.iter());
+    #[test]
+    fn test_recovery() {
+        use ASTtype::*;
+
+        //a bad statement is skipped to its `;`, the body goes on
+        let (at, items, bodies) =
+            recovered("fn f() {\n    var a = ;\n    a = 1 +* 2;\n    print(a);\n}\nfn g() {}\n");
+        assert_eq!(at, [(2, 13), (3, 12)]);
+        assert_eq!(items, [Function, Function]);
+        assert_eq!(bodies[0], [Error, Error, CallFunction]);
+
+        //the braces a bad statement opened are skipped with it
+        let (at, _, bodies) = recovered("fn f() {\n    if x + { y = 1; }\n    print(1);\n}\n");
+        assert_eq!(at.len(), 1);
+        assert_eq!(bodies[0], [Error, CallFunction]);
+
+        //a block left open ends at the next item, or the end of the file
+        let (at, items, bodies) = recovered("fn f() {\n    print(1);\nfn g() {\n    var x = 1;\n");
+        assert_eq!(at, [(3, 1), (4, 14)]);
+        assert_eq!(items, [Function, Function]);
+        assert_eq!(bodies, [vec![CallFunction], vec![Variable]]);
+
+        //a bad item is skipped to the next, invalid tokens to the next `;`
+        let (at, items, _) = recovered("struct S { a: }\nvar s = 1 $ 2;\nimpl for {}\nuse m;\n}");
+        assert_eq!(at, [(1, 15), (2, 11), (3, 6), (5, 1)]);
+        assert_eq!(items, [Error, Error, Error, Use, Error]);
+
+        //`pub` goes before any item but impl and use, and before module level
+        //var and const, `private` only before fn
+        let (root, diags) = parse(lex_all(
+            "pub struct S {}\npub enum E {}\nstruct T {}\npub const K: i64 = 1;\npub var v = 2;\nvar w = 3;\n",
+        ));
+        assert!(diags.is_empty());
+        let public: Vec<bool> = root.next.iter().map(|x| x.public).collect();
+        assert_eq!(public, [true, true, false, true, true, false]);
+        let (at, items, _) = recovered("pub use m;\nprivate struct S {}\npub type T = S;\n");
+        assert_eq!(at, [(1, 5), (2, 9)]);
+        assert_eq!(items, [Error, Use, Error, Struct, TypeAlias]);
+    }
 }
- */
-*/
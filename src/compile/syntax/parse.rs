@@ -1,11 +1,9 @@
 use crate::compile::ast::{ASTtype, AST};
-use std::cell::RefCell;
-use std::io::BufReader;
-use std::io::SeekFrom;
-use std::thread_local;
-use std::{fs::File, io::Seek};
+use crate::compile::diag::Diagnostic;
+use crate::compile::is_item;
 
-use super::super::lex::{Lex, TokenType};
+use super::super::lex::{Token, TokenType};
+use super::Parser;
 
 /*
 function naming rule
@@ -13,92 +11,1223 @@ function naming rule
 fn (objective to parse)_(current progress)
 */
 
-enum ParseStatus {
+#[allow(clippy::upper_case_acronyms)]
+#[derive(PartialEq, Debug)]
+pub enum ParseStatus {
     Success,
-    Error,
     EOF,
     EOFError,
     UnexpectedToken,
 }
 
+macro_rules! try_parse {
+    ($e:expr) => {
+        match $e {
+            ParseStatus::Success => {}
+            s => {
+                return s;
+            }
+        }
+    };
+}
+
+macro_rules! expect {
+    ($p:expr, $tp:expr, $what:expr) => {
+        match expect($p, $tp, $what) {
+            Ok(t) => t,
+            Err(s) => {
+                return s;
+            }
+        }
+    };
+}
+
+static BINARY_PRECEDENCE: &[(TokenType, u8)] = &[
+    (TokenType::DotDot, 1),
+    (TokenType::DotDotEqu, 1),
+    (TokenType::LogicOR, 2),
+    (TokenType::LogicAND, 3),
+    (TokenType::LogicEqual, 4),
+    (TokenType::LogicNotEqual, 4),
+    (TokenType::LeftABrack, 5),
+    (TokenType::RightABrack, 5),
+    (TokenType::LessEqual, 5),
+    (TokenType::GreaterEqual, 5),
+    (TokenType::BitOR, 6),
+    (TokenType::BitXOR, 7),
+    (TokenType::BitAND, 8),
+    (TokenType::LeftBShift, 9),
+    (TokenType::RightBShift, 9),
+    (TokenType::Plus, 10),
+    (TokenType::Minus, 10),
+    (TokenType::Multify, 11),
+    (TokenType::Divide, 11),
+    (TokenType::Mod, 11),
+];
+
+static ASSIGN_OPERATOR: &[TokenType] = &[
+    TokenType::Equal,
+    TokenType::PlusEqu,
+    TokenType::MinusEqu,
+    TokenType::MultifyEqu,
+    TokenType::DivideEqu,
+    TokenType::ModEqu,
+    TokenType::LeftBShiftEqu,
+    TokenType::RightBShiftEqu,
+    TokenType::BAndEqu,
+    TokenType::BOrEqu,
+    TokenType::BXorEqu,
+];
+
+fn describe(t: &Token) -> String {
+    return match t.tp {
+        TokenType::StringLiteral => "string literal".to_string(),
+        TokenType::CharLiteral => "char literal".to_string(),
+        _ => format!("`{}`", t.s),
+    };
+}
+
+fn unexpected(p: &mut Parser, expected: &str) -> ParseStatus {
+    let (t, msg, status) = match p.peek().cloned() {
+        None => (
+            p.last_tok(),
+            format!("unexpected end of file, expected {}", expected),
+            ParseStatus::EOFError,
+        ),
+        Some(t) => {
+            let msg = match t.tp {
+                TokenType::Broken => format!("invalid token `{}`", t.s),
+                TokenType::BrokenLeftLongComment => "unterminated comment".to_string(),
+                _ => format!("expected {}, found {}", expected, describe(&t)),
+            };
+            (t, msg, ParseStatus::UnexpectedToken)
+        }
+    };
+    //constructs given up at the same token, e.g. blocks left open at the
+    //end of the file, report it once
+    let again = p
+        .diags
+        .last()
+        .is_some_and(|d| (d.line, d.col) == (t.line, t.col));
+    if !again {
+        p.diags.push(Diagnostic::error(&t, msg));
+    }
+    return status;
+}
+
+fn expect(p: &mut Parser, tp: TokenType, what: &str) -> Result<Token, ParseStatus> {
+    if p.peek_tp() == tp {
+        return Ok(p.gettok().unwrap());
+    }
+    return Err(unexpected(p, what));
+}
+
+//a token standing for something that is not written in the source
+fn implicit_tok(at: &Token) -> Token {
+    let mut t = Token::new();
+    t.line = at.line;
+    t.col = at.col;
+    t.file = at.file;
+    return t;
+}
+
+//tokens that only start items. a statement is never found at one, so
+//parsing starts over there after an error
+fn is_item_keyword(tp: TokenType) -> bool {
+    return matches!(
+        tp,
+        TokenType::Fn
+            | TokenType::Trait
+            | TokenType::Impl
+            | TokenType::Use
+            | TokenType::Struct
+            | TokenType::Enum
+            | TokenType::Type
+            | TokenType::Hash
+    );
+}
+
+//attributes a parsed item may have
+static ATTRIBUTES: &[&str] = &["allow", "test"];
+
 /*
-fn main_parse(f: &mut BufReader<File>) -> (AST, ParseStatus) {
-    let tokbuf = f.lex();
-    let mut retast = AST::new();
-    let mut status = ParseStatus::Success;
-    let save = f.seek(SeekFrom::Current(0));
+recovery from an error in an item or a statement that started at token
+`from`: what was parsed of it is replaced by an Error node and the rest
+skipped. skipping stops past its `;` or the `}` closing the braces it opened,
+or before the `}` of the block it is in or an item keyword. tokens are always
+skipped, so parsing goes on
+*/
+fn recover(p: &mut Parser, from: usize) -> AST {
+    let start = match p.toks.get(from) {
+        None => p.last_tok(),
+        Some(t) => t.clone(),
+    };
+    let mut depth: i64 = 0;
+    for t in p.toks[from..p.pos].iter() {
+        match t.tp {
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace => depth -= 1,
+            _ => {}
+        }
+    }
 
-    if let Some(token) = tokbuf {
-        match token.tp {
-            TokenType::String => {
-                status = parse_string(f, &mut retast);
+    loop {
+        match p.peek_tp() {
+            TokenType::Null => break,
+            TokenType::Semicolon if depth <= 0 => {
+                p.gettok();
+                break;
             }
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace if depth <= 0 => break,
+            TokenType::RightBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    p.gettok();
+                    break;
+                }
+            }
+            tp if is_item_keyword(tp) => break,
             _ => {}
         }
-        return (retast, status);
+        p.gettok();
+    }
+    if p.pos == from {
+        p.gettok();
+    }
+    return AST::with(ASTtype::Error, start);
+}
+
+pub fn main_parse(p: &mut Parser, root: &mut AST) -> ParseStatus {
+    loop {
+        if p.peek().is_none() {
+            return ParseStatus::EOF;
+        }
+
+        let from = p.pos;
+        let mut ast = AST::new();
+        match item_start(p, &mut ast) {
+            ParseStatus::Success => root.next.push(ast),
+            _ => root.next.push(recover(p, from)),
+        }
+    }
+}
+
+fn item_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let doc = p.doc();
+    let mut attrs: Vec<AST> = Vec::new();
+    try_parse!(attributes(p, &mut attrs));
+    if !attrs.is_empty() {
+        match p.peek_tp() {
+            TokenType::Pub | TokenType::Private => {}
+            tp if is_item_keyword(tp) => {}
+            _ => {
+                return unexpected(p, "item after attribute");
+            }
+        }
+    }
+    try_parse!(item_kind(p, ast));
+    ast.attrs = attrs;
+    //a `pub` module level var or const is documented like an item
+    if is_item(ast) || ast.public {
+        ast.doc = doc;
+    }
+    return ParseStatus::Success;
+}
+
+//#[name] or #[name(arg, ...)], any number of them
+fn attributes(p: &mut Parser, attrs: &mut Vec<AST>) -> ParseStatus {
+    while p.peek_tp() == TokenType::Hash {
+        p.gettok();
+        expect!(p, TokenType::LeftBrack, "`[`");
+        let name = expect!(p, TokenType::String, "attribute name");
+        if !ATTRIBUTES.contains(&name.s.as_str()) {
+            let msg = format!("unknown attribute `{}`", name.s);
+            p.diags.push(Diagnostic::error(&name, msg));
+        }
+        let mut attr = AST::with(ASTtype::Attribute, name);
+
+        if p.peek_tp() == TokenType::LeftParen {
+            p.gettok();
+            loop {
+                let arg = expect!(p, TokenType::String, "name");
+                attr.push_next(ASTtype::Ident, arg);
+                match p.peek_tp() {
+                    TokenType::Comma => {
+                        p.gettok();
+                        if p.peek_tp() == TokenType::RightParen {
+                            p.gettok();
+                            break;
+                        }
+                    }
+                    TokenType::RightParen => {
+                        p.gettok();
+                        break;
+                    }
+                    _ => {
+                        return unexpected(p, "`,` or `)`");
+                    }
+                }
+            }
+        }
+        expect!(p, TokenType::RightBrack, "`]`");
+        attrs.push(attr);
+    }
+    return ParseStatus::Success;
+}
+
+fn item_kind(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let first = p.peek().unwrap().clone();
+
+    match first.tp {
+        TokenType::Pub | TokenType::Private => {
+            p.gettok();
+            let status = match (first.tp, p.peek_tp()) {
+                (_, TokenType::Fn) => return function_start(p, ast, first, false),
+                (TokenType::Pub, TokenType::Trait) => trait_start(p, ast),
+                (TokenType::Pub, TokenType::Type) => type_alias_start(p, ast),
+                (TokenType::Pub, TokenType::Struct) => struct_start(p, ast),
+                (TokenType::Pub, TokenType::Enum) => enum_start(p, ast),
+                (TokenType::Pub, TokenType::Var | TokenType::Const) => variable_start(p, ast),
+                (TokenType::Pub, _) => {
+                    return unexpected(
+                        p,
+                        "`fn`, `struct`, `enum`, `trait`, `type`, `var` or `const`",
+                    );
+                }
+                _ => return unexpected(p, "`fn`"),
+            };
+            ast.public = true;
+            return status;
+        }
+        TokenType::Fn => {
+            return function_start(p, ast, implicit_tok(&first), false);
+        }
+        TokenType::Trait => {
+            return trait_start(p, ast);
+        }
+        TokenType::Impl => {
+            return impl_start(p, ast);
+        }
+        TokenType::Use => {
+            return use_start(p, ast);
+        }
+        TokenType::Type => {
+            return type_alias_start(p, ast);
+        }
+        TokenType::Struct => {
+            return struct_start(p, ast);
+        }
+        TokenType::Enum => {
+            return enum_start(p, ast);
+        }
+        _ => {
+            return statement_start(p, ast);
+        }
+    }
+}
+
+fn function_start(p: &mut Parser, ast: &mut AST, vis: Token, signature_only: bool) -> ParseStatus {
+    let fn_tok = expect!(p, TokenType::Fn, "`fn`");
+    *ast = AST::with(ASTtype::Function, fn_tok);
+    ast.push_next(ASTtype::FnVisibility, vis);
+
+    let name = expect!(p, TokenType::String, "function name");
+    ast.push_next(ASTtype::FnIdent, name);
+
+    let paren = expect!(p, TokenType::LeftParen, "`(`");
+    let args = ast.push_next(ASTtype::FnArgs, paren);
+    try_parse!(function_args(p, args));
+
+    let ret = match p.peek_tp() {
+        TokenType::Arrow => p.gettok().unwrap(),
+        _ => implicit_tok(p.peek().unwrap_or(&ast.token)),
+    };
+    let has_ret = ret.tp == TokenType::Arrow;
+    let ret_ast = ast.push_next(ASTtype::FnRetType, ret);
+    if has_ret {
+        let mut tp = AST::new();
+        try_parse!(type_start(p, &mut tp));
+        ret_ast.next.push(tp);
+    }
+
+    return function_body(p, ast, signature_only);
+}
+
+fn function_args(p: &mut Parser, args: &mut AST) -> ParseStatus {
+    if p.peek_tp() == TokenType::RightParen {
+        p.gettok();
+        return ParseStatus::Success;
+    }
+
+    loop {
+        let name = expect!(p, TokenType::String, "argument name");
+        let arg = args.push_next(ASTtype::FnArg, name.clone());
+        arg.push_next(ASTtype::VariableName, name.clone());
+
+        if p.peek_tp() == TokenType::Colon {
+            p.gettok();
+            let mut tp = AST::new();
+            try_parse!(type_start(p, &mut tp));
+            arg.next.push(tp);
+        } else if name.s == "self" {
+            //`self` alone is `self: self`
+            arg.push_next(ASTtype::VariableType, name);
+        } else {
+            return unexpected(p, "`:`");
+        }
+
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+                if p.peek_tp() == TokenType::RightParen {
+                    p.gettok();
+                    return ParseStatus::Success;
+                }
+            }
+            TokenType::RightParen => {
+                p.gettok();
+                return ParseStatus::Success;
+            }
+            _ => {
+                return unexpected(p, "`,` or `)`");
+            }
+        }
+    }
+}
+
+fn function_body(p: &mut Parser, ast: &mut AST, signature_only: bool) -> ParseStatus {
+    match p.peek_tp() {
+        TokenType::LeftBrace => {
+            let mut block = AST::new();
+            try_parse!(codeblock_start(p, &mut block));
+            ast.next.push(block);
+            return ParseStatus::Success;
+        }
+        TokenType::Semicolon if signature_only => {
+            p.gettok();
+            return ParseStatus::Success;
+        }
+        _ => {
+            return unexpected(p, "`{`");
+        }
+    }
+}
+
+fn type_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let name = expect!(p, TokenType::String, "type");
+    *ast = AST::with(ASTtype::VariableType, name);
+
+    if ast.token.s == "template" && p.peek_tp() == TokenType::LeftBrace {
+        //template{Trait, ...}
+        p.gettok();
+        loop {
+            let bound = expect!(p, TokenType::String, "trait name");
+            ast.push_next(ASTtype::VariableType, bound);
+            match p.peek_tp() {
+                TokenType::Comma => {
+                    p.gettok();
+                }
+                TokenType::RightBrace => {
+                    p.gettok();
+                    return ParseStatus::Success;
+                }
+                _ => {
+                    return unexpected(p, "`,` or `}`");
+                }
+            }
+        }
+    }
+
+    if p.peek_tp() != TokenType::LeftABrack {
+        return ParseStatus::Success;
+    }
+    p.gettok();
+    loop {
+        let mut arg = AST::new();
+        try_parse!(type_start(p, &mut arg));
+        ast.next.push(arg);
+
+        p.split_shift();
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+            }
+            TokenType::RightABrack => {
+                p.gettok();
+                return ParseStatus::Success;
+            }
+            _ => {
+                return unexpected(p, "`,` or `>`");
+            }
+        }
+    }
+}
+
+fn trait_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    p.gettok();
+    let name = expect!(p, TokenType::String, "trait name");
+    *ast = AST::with(ASTtype::Trait, name);
+    expect!(p, TokenType::LeftBrace, "`{`");
+
+    loop {
+        match p.peek_tp() {
+            TokenType::RightBrace => {
+                p.gettok();
+                return ParseStatus::Success;
+            }
+            TokenType::Fn | TokenType::Hash => {
+                let doc = p.doc();
+                let mut attrs: Vec<AST> = Vec::new();
+                try_parse!(attributes(p, &mut attrs));
+                if p.peek_tp() != TokenType::Fn {
+                    return unexpected(p, "`fn`");
+                }
+                let vis = implicit_tok(p.peek().unwrap());
+                let mut f = AST::new();
+                try_parse!(function_start(p, &mut f, vis, true));
+                f.attrs = attrs;
+                f.doc = doc;
+                ast.next.push(f);
+            }
+            _ => {
+                return unexpected(p, "`fn` or `}`");
+            }
+        }
+    }
+}
+
+fn impl_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let impl_tok = p.gettok().unwrap();
+    let mut tp = AST::new();
+    try_parse!(type_start(p, &mut tp));
+
+    if p.peek_tp() == TokenType::For {
+        //impl Trait for Type
+        p.gettok();
+        *ast = AST::with(ASTtype::Impl, tp.token);
+        let mut target = AST::new();
+        try_parse!(type_start(p, &mut target));
+        ast.next.push(target);
     } else {
-        return (retast, ParseStatus::EOF);
+        *ast = AST::with(ASTtype::Impl, implicit_tok(&impl_tok));
+        ast.next.push(tp);
+    }
+    expect!(p, TokenType::LeftBrace, "`{`");
+
+    loop {
+        match p.peek_tp() {
+            TokenType::RightBrace => {
+                p.gettok();
+                return ParseStatus::Success;
+            }
+            TokenType::Pub | TokenType::Private | TokenType::Fn | TokenType::Hash => {
+                let doc = p.doc();
+                let mut attrs: Vec<AST> = Vec::new();
+                try_parse!(attributes(p, &mut attrs));
+                let vis = match p.peek_tp() {
+                    TokenType::Pub | TokenType::Private => p.gettok().unwrap(),
+                    TokenType::Fn => implicit_tok(p.peek().unwrap()),
+                    _ => {
+                        return unexpected(p, "`fn`");
+                    }
+                };
+                let mut f = AST::new();
+                try_parse!(function_start(p, &mut f, vis, false));
+                f.attrs = attrs;
+                f.doc = doc;
+                ast.next.push(f);
+            }
+            _ => {
+                return unexpected(p, "`fn` or `}`");
+            }
+        }
+    }
+}
+
+fn use_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    p.gettok();
+    let mut path = expect!(p, TokenType::String, "module name");
+
+    while p.peek_tp() == TokenType::DblColon {
+        p.gettok();
+        let next = expect!(p, TokenType::String, "module name");
+        path.s = path.s + "::" + &next.s;
+    }
+    expect!(p, TokenType::Semicolon, "`;`");
+
+    *ast = AST::with(ASTtype::Use, path);
+    return ParseStatus::Success;
+}
+
+fn type_alias_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    p.gettok();
+    let name = expect!(p, TokenType::String, "type name");
+    *ast = AST::with(ASTtype::TypeAlias, name);
+    expect!(p, TokenType::Equal, "`=`");
+
+    let mut tp = AST::new();
+    try_parse!(type_start(p, &mut tp));
+    ast.next.push(tp);
+    expect!(p, TokenType::Semicolon, "`;`");
+    return ParseStatus::Success;
+}
+
+fn struct_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    p.gettok();
+    let name = expect!(p, TokenType::String, "struct name");
+    *ast = AST::with(ASTtype::Struct, name);
+    expect!(p, TokenType::LeftBrace, "`{`");
+
+    loop {
+        if p.peek_tp() == TokenType::RightBrace {
+            p.gettok();
+            return ParseStatus::Success;
+        }
+
+        let name = expect!(p, TokenType::String, "field name");
+        expect!(p, TokenType::Colon, "`:`");
+        let mut tp = AST::new();
+        try_parse!(type_start(p, &mut tp));
+        ast.push_next(ASTtype::StructField, name).next.push(tp);
+
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+            }
+            TokenType::RightBrace => {}
+            _ => {
+                return unexpected(p, "`,` or `}`");
+            }
+        }
+    }
+}
+
+fn enum_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    p.gettok();
+    let name = expect!(p, TokenType::String, "enum name");
+    *ast = AST::with(ASTtype::Enum, name);
+    expect!(p, TokenType::LeftBrace, "`{`");
+
+    loop {
+        if p.peek_tp() == TokenType::RightBrace {
+            p.gettok();
+            return ParseStatus::Success;
+        }
+
+        let name = expect!(p, TokenType::String, "variant name");
+        let variant = ast.push_next(ASTtype::EnumVariant, name);
+        if p.peek_tp() == TokenType::LeftParen {
+            //Variant(Type, ...)
+            p.gettok();
+            loop {
+                let mut tp = AST::new();
+                try_parse!(type_start(p, &mut tp));
+                variant.next.push(tp);
+                match p.peek_tp() {
+                    TokenType::Comma => {
+                        p.gettok();
+                        if p.peek_tp() == TokenType::RightParen {
+                            p.gettok();
+                            break;
+                        }
+                    }
+                    TokenType::RightParen => {
+                        p.gettok();
+                        break;
+                    }
+                    _ => {
+                        return unexpected(p, "`,` or `)`");
+                    }
+                }
+            }
+        }
+
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+            }
+            TokenType::RightBrace => {}
+            _ => {
+                return unexpected(p, "`,` or `}`");
+            }
+        }
     }
-    return (retast, ParseStatus::Error);
 }
 
-fn parse_string(f: &mut BufReader<File>, ast: &mut AST) -> ParseStatus {
-    let tokbuf = f.lex();
+fn codeblock_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let brace = expect!(p, TokenType::LeftBrace, "`{`");
+    *ast = AST::with(ASTtype::CodeBlock, brace);
 
-    if let Some(token) = tokbuf {
-        match token.tp {
-            TokenType::LeftParen => {
-                ast.tp = ASTtype::CallFunction;
-                ast.token = token;
-                return callfunction_arg_start(f, ast);
+    loop {
+        match p.peek_tp() {
+            TokenType::RightBrace => {
+                p.gettok();
+                return ParseStatus::Success;
+            }
+            //left open. the error is reported, the block taken as closed so
+            //what it holds is kept
+            tp if tp == TokenType::Null || is_item_keyword(tp) => {
+                unexpected(p, "`}`");
+                return ParseStatus::Success;
             }
             _ => {
-                return ParseStatus::UnexpectedToken;
+                let from = p.pos;
+                let mut stmt = AST::new();
+                match statement_start(p, &mut stmt) {
+                    ParseStatus::Success => ast.next.push(stmt),
+                    _ => ast.next.push(recover(p, from)),
+                }
+            }
+        }
+    }
+}
+
+fn statement_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    match p.peek_tp() {
+        TokenType::Var | TokenType::Const => {
+            return variable_start(p, ast);
+        }
+        TokenType::Return => {
+            let ret = p.gettok().unwrap();
+            *ast = AST::with(ASTtype::Return, ret);
+            if p.peek_tp() != TokenType::Semicolon {
+                let mut value = AST::new();
+                try_parse!(expr_start(p, &mut value));
+                ast.next.push(value);
+            }
+            expect!(p, TokenType::Semicolon, "`;`");
+            return ParseStatus::Success;
+        }
+        TokenType::Break | TokenType::Continue => {
+            let t = p.gettok().unwrap();
+            let tp = match t.tp {
+                TokenType::Break => ASTtype::Break,
+                _ => ASTtype::Continue,
+            };
+            *ast = AST::with(tp, t);
+            expect!(p, TokenType::Semicolon, "`;`");
+            return ParseStatus::Success;
+        }
+        TokenType::If => {
+            return if_start(p, ast);
+        }
+        TokenType::While => {
+            return while_start(p, ast);
+        }
+        TokenType::For => {
+            return for_start(p, ast);
+        }
+        TokenType::Match => {
+            try_parse!(match_start(p, ast));
+            if p.peek_tp() == TokenType::Semicolon {
+                p.gettok();
             }
+            return ParseStatus::Success;
+        }
+        TokenType::LeftBrace => {
+            return codeblock_start(p, ast);
         }
+        _ => {
+            return expr_statement_start(p, ast);
+        }
+    }
+}
+
+fn variable_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let attr = p.gettok().unwrap();
+    *ast = AST::with(ASTtype::Variable, attr.clone());
+    ast.push_next(ASTtype::VariableAttr, attr);
+
+    let name = expect!(p, TokenType::String, "variable name");
+    ast.push_next(ASTtype::VariableName, name);
+
+    if p.peek_tp() == TokenType::Colon {
+        p.gettok();
+        let mut tp = AST::new();
+        try_parse!(type_start(p, &mut tp));
+        ast.next.push(tp);
     } else {
-        return ParseStatus::EOFError;
+        let at = implicit_tok(p.peek().unwrap_or(&ast.token));
+        ast.push_next(ASTtype::VariableType, at);
+    }
+
+    expect!(p, TokenType::Equal, "`=`");
+    let mut value = AST::new();
+    try_parse!(expr_start(p, &mut value));
+    ast.next.push(value);
+    expect!(p, TokenType::Semicolon, "`;`");
+    return ParseStatus::Success;
+}
+
+fn expr_statement_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    try_parse!(expr_start(p, ast));
+
+    if ASSIGN_OPERATOR.contains(&p.peek_tp()) {
+        let op = p.gettok().unwrap();
+        let place = std::mem::replace(ast, AST::with(ASTtype::Assign, op));
+        let mut value = AST::new();
+        try_parse!(expr_start(p, &mut value));
+        ast.next.push(place);
+        ast.next.push(value);
+    }
+    expect!(p, TokenType::Semicolon, "`;`");
+    return ParseStatus::Success;
+}
+
+fn if_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let if_tok = p.gettok().unwrap();
+    *ast = AST::with(ASTtype::If, if_tok);
+
+    let mut cond = AST::new();
+    try_parse!(expr_struct_ctx(p, &mut cond, false));
+    ast.next.push(cond);
+
+    let mut code = AST::new();
+    try_parse!(codeblock_start(p, &mut code));
+    ast.next.push(code);
+
+    if p.peek_tp() != TokenType::Else {
+        return ParseStatus::Success;
+    }
+    p.gettok();
+
+    let mut other = AST::new();
+    match p.peek_tp() {
+        TokenType::If => {
+            try_parse!(if_start(p, &mut other));
+        }
+        _ => {
+            try_parse!(codeblock_start(p, &mut other));
+        }
     }
+    ast.next.push(other);
+    return ParseStatus::Success;
+}
+
+fn while_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let while_tok = p.gettok().unwrap();
+    *ast = AST::with(ASTtype::While, while_tok);
+
+    let mut cond = AST::new();
+    try_parse!(expr_struct_ctx(p, &mut cond, false));
+    ast.next.push(cond);
+
+    let mut code = AST::new();
+    try_parse!(codeblock_start(p, &mut code));
+    ast.next.push(code);
+    return ParseStatus::Success;
+}
+
+fn for_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let for_tok = p.gettok().unwrap();
+    *ast = AST::with(ASTtype::For, for_tok);
+
+    let name = expect!(p, TokenType::String, "variable name");
+    ast.push_next(ASTtype::VariableName, name);
+    expect!(p, TokenType::In, "`in`");
+
+    let mut iter = AST::new();
+    try_parse!(expr_struct_ctx(p, &mut iter, false));
+    ast.next.push(iter);
+
+    let mut code = AST::new();
+    try_parse!(codeblock_start(p, &mut code));
+    ast.next.push(code);
+    return ParseStatus::Success;
 }
 
-fn callfunction_arg_start(f: &mut BufReader<File>, ast: &mut AST) -> ParseStatus {
-    let tokbuf = f.lex();
+fn match_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let match_tok = p.gettok().unwrap();
+    *ast = AST::with(ASTtype::Match, match_tok);
+
+    let mut value = AST::new();
+    try_parse!(expr_struct_ctx(p, &mut value, false));
+    ast.next.push(value);
+    expect!(p, TokenType::LeftBrace, "`{`");
+
+    loop {
+        if p.peek_tp() == TokenType::RightBrace {
+            p.gettok();
+            return ParseStatus::Success;
+        }
+
+        let mut case = AST::new();
+        try_parse!(match_case(p, &mut case));
+        let block_body = case.next[1].tp == ASTtype::CodeBlock;
+        ast.next.push(case);
 
-    if let Some(token) = tokbuf {
-        match token.tp {
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+            }
+            TokenType::RightBrace => {}
+            _ if block_body => {}
+            _ => {
+                return unexpected(p, "`,` or `}`");
+            }
+        }
+    }
+}
+
+fn match_case(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let at = implicit_tok(p.peek().unwrap_or(&p.last_tok()));
+    *ast = AST::with(ASTtype::MatchCase, at);
+
+    let mut pat = AST::new();
+    try_parse!(pattern_start(p, &mut pat));
+    ast.next.push(pat);
+
+    let arrow = expect!(p, TokenType::FatArrow, "`=>`");
+    ast.token = arrow;
+
+    let mut code = AST::new();
+    match p.peek_tp() {
+        TokenType::LeftBrace => {
+            try_parse!(codeblock_start(p, &mut code));
+        }
+        _ => {
+            try_parse!(expr_start(p, &mut code));
+        }
+    }
+    ast.next.push(code);
+    return ParseStatus::Success;
+}
+
+fn pattern_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    try_parse!(pattern_single(p, ast));
+    if p.peek_tp() != TokenType::BitOR {
+        return ParseStatus::Success;
+    }
+
+    let first = std::mem::replace(ast, AST::with(ASTtype::PatOr, p.peek().unwrap().clone()));
+    ast.next.push(first);
+    while p.peek_tp() == TokenType::BitOR {
+        p.gettok();
+        let mut alt = AST::new();
+        try_parse!(pattern_single(p, &mut alt));
+        ast.next.push(alt);
+    }
+    return ParseStatus::Success;
+}
+
+fn pattern_single(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    match p.peek_tp() {
+        TokenType::String if p.peek_nth_tp(1) == TokenType::DblColon => {
+            return pattern_variant(p, ast);
+        }
+        TokenType::String => {
+            let name = p.gettok().unwrap();
+            let tp = match name.s.as_str() {
+                "_" => ASTtype::PatWildcard,
+                _ => ASTtype::PatBinding,
+            };
+            *ast = AST::with(tp, name);
+            return ParseStatus::Success;
+        }
+        _ => {
+            try_parse!(pattern_literal(p, ast));
+        }
+    }
+
+    match p.peek_tp() {
+        TokenType::DotDot | TokenType::DotDotEqu => {
+            let op = p.gettok().unwrap();
+            let lo = std::mem::replace(ast, AST::with(ASTtype::PatRange, op));
+            let mut hi = AST::new();
+            try_parse!(pattern_literal(p, &mut hi));
+            ast.next.push(lo);
+            ast.next.push(hi);
+        }
+        _ => {}
+    }
+    return ParseStatus::Success;
+}
+
+//Enum::Variant or Enum::Variant(pattern, ...)
+fn pattern_variant(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    let mut path = p.gettok().unwrap();
+    while p.peek_tp() == TokenType::DblColon {
+        p.gettok();
+        let next = expect!(p, TokenType::String, "variant name");
+        path.s = path.s + "::" + &next.s;
+    }
+    *ast = AST::with(ASTtype::PatVariant, path);
+
+    if p.peek_tp() != TokenType::LeftParen {
+        return ParseStatus::Success;
+    }
+    p.gettok();
+    loop {
+        let mut sub = AST::new();
+        try_parse!(pattern_start(p, &mut sub));
+        ast.next.push(sub);
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+                if p.peek_tp() == TokenType::RightParen {
+                    p.gettok();
+                    return ParseStatus::Success;
+                }
+            }
             TokenType::RightParen => {
-                return callfunction_end(f, ast);
+                p.gettok();
+                return ParseStatus::Success;
             }
-            TokenType::String => {
-                ast.push_next(ASTtype::CallFunction_arg, token);
-                return callfunction_arg_comma(f, ast);
+            _ => {
+                return unexpected(p, "`,` or `)`");
             }
+        }
+    }
+}
+
+fn pattern_literal(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    match p.peek_tp() {
+        TokenType::Minus if p.peek_nth_tp(1) == TokenType::Number => {
+            let minus = p.gettok().unwrap();
+            let mut num = p.gettok().unwrap();
+            num.s = "-".to_string() + &num.s;
+            num.line = minus.line;
+            num.col = minus.col;
+            *ast = AST::with(ASTtype::PatLiteral, num);
+        }
+        TokenType::Number
+        | TokenType::StringLiteral
+        | TokenType::CharLiteral
+        | TokenType::True
+        | TokenType::False => {
+            *ast = AST::with(ASTtype::PatLiteral, p.gettok().unwrap());
+        }
+        _ => {
+            return unexpected(p, "pattern");
+        }
+    }
+    return ParseStatus::Success;
+}
+
+pub fn expr_start(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    return expr_binary(p, ast, 1);
+}
+
+//struct literals are not allowed where a `{` opens a block, e.g. `if x {`.
+//inside parentheses and brackets they are allowed again
+fn expr_struct_ctx(p: &mut Parser, ast: &mut AST, allow: bool) -> ParseStatus {
+    let old = p.no_struct;
+    p.no_struct = !allow;
+    let ret = expr_start(p, ast);
+    p.no_struct = old;
+    return ret;
+}
+
+pub fn binary_precedence(tp: TokenType) -> Option<u8> {
+    return BINARY_PRECEDENCE
+        .iter()
+        .find(|(t, _)| *t == tp)
+        .map(|(_, prec)| *prec);
+}
+
+fn expr_binary(p: &mut Parser, ast: &mut AST, min_prec: u8) -> ParseStatus {
+    try_parse!(expr_unary(p, ast));
+
+    loop {
+        let prec = match binary_precedence(p.peek_tp()) {
+            Some(x) if x >= min_prec => x,
             _ => {
-                return ParseStatus::UnexpectedToken;
+                return ParseStatus::Success;
             }
+        };
+
+        let op = p.gettok().unwrap();
+        let mut right = AST::new();
+        try_parse!(expr_binary(p, &mut right, prec + 1));
+
+        let left = std::mem::replace(ast, AST::with(ASTtype::Binary, op));
+        ast.next.push(left);
+        ast.next.push(right);
+    }
+}
+
+fn expr_unary(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    match p.peek_tp() {
+        TokenType::Minus | TokenType::Bang => {
+            let op = p.gettok().unwrap();
+            *ast = AST::with(ASTtype::Unary, op);
+            let mut value = AST::new();
+            try_parse!(expr_unary(p, &mut value));
+            ast.next.push(value);
+            return ParseStatus::Success;
+        }
+        _ => {
+            return expr_postfix(p, ast);
         }
-    } else {
-        return ParseStatus::EOFError;
     }
 }
 
-fn callfunction_arg_comma(f: &mut BufReader<File>, ast: &mut AST) -> ParseStatus {
-    let tokbuf = f.lex();
+fn expr_postfix(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    try_parse!(expr_primary(p, ast));
 
-    if let Some(token) = tokbuf {
-        match token.tp {
+    loop {
+        match p.peek_tp() {
+            TokenType::Dot => {
+                p.gettok();
+                let name = expect!(p, TokenType::String, "field or method name");
+                if p.peek_tp() != TokenType::LeftParen {
+                    let base = std::mem::replace(ast, AST::with(ASTtype::Field, name));
+                    ast.next.push(base);
+                    continue;
+                }
+                p.gettok();
+                let recv = std::mem::replace(ast, AST::with(ASTtype::CallMethod, name));
+                ast.next.push(recv);
+                try_parse!(expr_call_args(p, ast));
+            }
+            TokenType::LeftBrack => {
+                let brack = p.gettok().unwrap();
+                let base = std::mem::replace(ast, AST::with(ASTtype::Index, brack));
+                let mut index = AST::new();
+                try_parse!(expr_struct_ctx(p, &mut index, true));
+                ast.next.push(base);
+                ast.next.push(index);
+                expect!(p, TokenType::RightBrack, "`]`");
+            }
+            _ => {
+                return ParseStatus::Success;
+            }
+        }
+    }
+}
+
+//after '(', push args until ')'
+fn expr_call_args(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    if p.peek_tp() == TokenType::RightParen {
+        p.gettok();
+        return ParseStatus::Success;
+    }
+
+    loop {
+        let mut arg = AST::new();
+        try_parse!(expr_struct_ctx(p, &mut arg, true));
+        ast.next.push(arg);
+
+        match p.peek_tp() {
             TokenType::Comma => {
-                return callfunction_arg_comma(f, ast);
+                p.gettok();
+                if p.peek_tp() == TokenType::RightParen {
+                    p.gettok();
+                    return ParseStatus::Success;
+                }
             }
             TokenType::RightParen => {
-                return callfunction_end(f, ast);
+                p.gettok();
+                return ParseStatus::Success;
             }
             _ => {
-                return ParseStatus::UnexpectedToken;
+                return unexpected(p, "`,` or `)`");
+            }
+        }
+    }
+}
+
+fn expr_primary(p: &mut Parser, ast: &mut AST) -> ParseStatus {
+    match p.peek_tp() {
+        TokenType::Number
+        | TokenType::StringLiteral
+        | TokenType::CharLiteral
+        | TokenType::True
+        | TokenType::False => {
+            *ast = AST::with(ASTtype::Literal, p.gettok().unwrap());
+            return ParseStatus::Success;
+        }
+        TokenType::String => {
+            let mut name = p.gettok().unwrap();
+            while p.peek_tp() == TokenType::DblColon {
+                p.gettok();
+                let next = expect!(p, TokenType::String, "name");
+                name.s = name.s + "::" + &next.s;
+            }
+
+            if p.peek_tp() == TokenType::LeftParen {
+                p.gettok();
+                *ast = AST::with(ASTtype::CallFunction, name);
+                return expr_call_args(p, ast);
+            }
+            if p.peek_tp() == TokenType::LeftBrace && !p.no_struct {
+                return expr_struct_literal(p, ast, name);
+            }
+            *ast = AST::with(ASTtype::Ident, name);
+            return ParseStatus::Success;
+        }
+        TokenType::LeftParen => {
+            p.gettok();
+            try_parse!(expr_struct_ctx(p, ast, true));
+            expect!(p, TokenType::RightParen, "`)`");
+            return ParseStatus::Success;
+        }
+        TokenType::LeftBrack => {
+            let brack = p.gettok().unwrap();
+            *ast = AST::with(ASTtype::Array, brack);
+            if p.peek_tp() == TokenType::RightBrack {
+                p.gettok();
+                return ParseStatus::Success;
+            }
+            loop {
+                let mut elem = AST::new();
+                try_parse!(expr_struct_ctx(p, &mut elem, true));
+                ast.next.push(elem);
+                match p.peek_tp() {
+                    TokenType::Comma => {
+                        p.gettok();
+                        if p.peek_tp() == TokenType::RightBrack {
+                            p.gettok();
+                            return ParseStatus::Success;
+                        }
+                    }
+                    TokenType::RightBrack => {
+                        p.gettok();
+                        return ParseStatus::Success;
+                    }
+                    _ => {
+                        return unexpected(p, "`,` or `]`");
+                    }
+                }
+            }
+        }
+        TokenType::Match => {
+            return match_start(p, ast);
+        }
+        _ => {
+            return unexpected(p, "expression");
+        }
+    }
+}
+
+//Name { field: value, ... }. `field` alone is `field: field`
+fn expr_struct_literal(p: &mut Parser, ast: &mut AST, name: Token) -> ParseStatus {
+    p.gettok();
+    *ast = AST::with(ASTtype::StructLiteral, name);
+
+    loop {
+        if p.peek_tp() == TokenType::RightBrace {
+            p.gettok();
+            return ParseStatus::Success;
+        }
+
+        let field = expect!(p, TokenType::String, "field name");
+        let mut value = AST::with(ASTtype::Ident, field.clone());
+        if p.peek_tp() == TokenType::Colon {
+            p.gettok();
+            try_parse!(expr_struct_ctx(p, &mut value, true));
+        }
+        ast.push_next(ASTtype::FieldInit, field).next.push(value);
+
+        match p.peek_tp() {
+            TokenType::Comma => {
+                p.gettok();
+            }
+            TokenType::RightBrace => {}
+            _ => {
+                return unexpected(p, "`,` or `}`");
             }
         }
-    } else {
-        return ParseStatus::EOFError;
     }
 }
-*/
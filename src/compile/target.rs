@@ -0,0 +1,48 @@
+//the machine the generated C is built for. it decides the size of pointer
+//sized integers and how the C compiler aligns things in memory
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Target {
+    pub bits: usize,      // pointer width
+    pub max_align: usize, // alignment of the widest scalar, e.g. 4 for i64 on i386
+    pub mode: usize,      // the width of the -m flag it is built with
+}
+
+impl Target {
+    //the machine rfx runs on, used when no -m flag is given
+    pub fn host() -> Self {
+        return Self::with_bits(usize::BITS as usize);
+    }
+
+    //the target of -m16, -m32 or -m64. cc -m16 builds i386 code to run in
+    //real mode, so -m16 lays out memory like -m32: 4 byte pointers and i64
+    //aligned to 4
+    pub fn with_bits(mode: usize) -> Self {
+        let bits = match mode {
+            16 => 32,
+            x => x,
+        };
+        return Target {
+            bits,
+            max_align: match bits {
+                32 => 4,
+                _ => 8,
+            },
+            mode,
+        };
+    }
+
+    //size of a pointer, usize and isize in byte
+    pub fn pointer_size(&self) -> usize {
+        return self.bits / 8;
+    }
+
+    //flag asking the C compiler for this width
+    pub fn cc_flag(&self) -> String {
+        return format!("-m{}", self.mode);
+    }
+
+    //alignment of a scalar of `size` byte
+    pub fn align_of(&self, size: usize) -> usize {
+        return size.clamp(1, self.max_align);
+    }
+}
@@ -0,0 +1,1539 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::ast::{ASTtype, AST};
+use super::diag::Diagnostic;
+use super::lex::{number_value, Token, TokenType};
+use super::matching::{check_arms, domain_of, pattern_of, Pat};
+use super::parser::{
+    EnumVariant, FunctionName, SymbolTable, TraitName, TypeKind, VariableName, VariableType,
+    TYPE_BOOL, TYPE_CHAR, TYPE_I64, TYPE_STRING, TYPE_U64, TYPE_VOID,
+};
+
+//functions every program has, their names cannot be taken
+const BUILTINS: &[&str] = &["print", "assert", "assert_eq"];
+
+/*
+type check the AST and fill `vtype` of every expression and `sym` of every
+resolved call. items are collected first, so functions may be used before
+they are declared. top level statements are checked next, in source order;
+they become the program's entry point and their `var`/`const` are module
+level, seen by every function. function bodies are checked last.
+
+module level variables are initialized in source order, so top level code
+must not call a function that uses one that is not initialized yet.
+*/
+
+pub struct Checker<'a> {
+    table: &'a mut SymbolTable,
+    diags: Vec<Diagnostic>,
+    self_tp: Option<usize>,
+    ret_tp: Option<usize>,
+    loop_depth: usize,
+    cur_fn: Option<usize>,
+    uses: Vec<(usize, usize)>,  // (function, module level variable it uses)
+    calls: Vec<(usize, usize)>, // (caller, callee)
+    entry_calls: Vec<(Token, usize, usize)>, // (call, callee, module variables initialized)
+}
+
+pub fn check(root: &mut AST, table: &mut SymbolTable) -> Vec<Diagnostic> {
+    let mut c = Checker {
+        table,
+        diags: Vec::new(),
+        self_tp: None,
+        ret_tp: None,
+        loop_depth: 0,
+        cur_fn: None,
+        uses: Vec::new(),
+        calls: Vec::new(),
+        entry_calls: Vec::new(),
+    };
+
+    c.collect_items(root);
+    for item in root.next.iter_mut() {
+        match item.tp {
+            ASTtype::Function
+            | ASTtype::Impl
+            | ASTtype::Trait
+            | ASTtype::TypeAlias
+            | ASTtype::Struct
+            | ASTtype::Enum
+            | ASTtype::Use => {}
+            _ => c.check_stmt(item),
+        }
+    }
+    for item in root.next.iter_mut() {
+        match item.tp {
+            ASTtype::Function => c.check_function(item),
+            ASTtype::Impl => c.check_impl_body(item),
+            _ => {}
+        }
+    }
+    c.check_init_order();
+    return c.diags;
+}
+
+fn is_int_literal(ast: &AST) -> bool {
+    return match ast.tp {
+        ASTtype::Literal => ast.token.tp == TokenType::Number,
+        ASTtype::Unary => ast.token.tp == TokenType::Minus && is_int_literal(&ast.next[0]),
+        _ => false,
+    };
+}
+
+//the first binding in a pattern
+fn first_binding(pat: &AST) -> Option<&Token> {
+    if pat.tp == ASTtype::PatBinding {
+        return Some(&pat.token);
+    }
+    return pat.next.iter().find_map(first_binding);
+}
+
+//true if the statement never goes on to the next: every path through it
+//returns, breaks or continues
+pub(super) fn diverges(ast: &AST) -> bool {
+    return match ast.tp {
+        ASTtype::Return | ASTtype::Break | ASTtype::Continue => true,
+        ASTtype::CodeBlock => block_diverges(&ast.next),
+        ASTtype::If => ast.next.len() == 3 && diverges(&ast.next[1]) && diverges(&ast.next[2]),
+        ASTtype::Match => ast.next[1..]
+            .iter()
+            .all(|case| case.next[1].tp == ASTtype::CodeBlock && diverges(&case.next[1])),
+        _ => false,
+    };
+}
+
+//true if a statement of the block diverges, the ones after it never run
+pub(super) fn block_diverges(stmts: &[AST]) -> bool {
+    return stmts.iter().any(diverges);
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, t: &Token, msg: String) {
+        self.diags.push(Diagnostic::error(t, msg));
+    }
+
+    fn warning(&mut self, t: &Token, msg: String) {
+        self.diags.push(Diagnostic::warning(t, msg));
+    }
+
+    fn type_name(&self, tp: usize) -> String {
+        return self.table.type_name(tp).to_string();
+    }
+
+    fn mismatch(&mut self, t: &Token, expected: usize, found: usize) {
+        let msg = format!(
+            "mismatched types: expected `{}`, found `{}`",
+            self.type_name(expected),
+            self.type_name(found)
+        );
+        self.error(t, msg);
+    }
+
+    pub fn resolve_type(&mut self, ast: &AST) -> Option<usize> {
+        let name = ast.token.s.as_str();
+
+        if name == "self" {
+            if self.self_tp.is_none() {
+                self.error(
+                    &ast.token,
+                    "`self` type outside of trait or impl".to_string(),
+                );
+            }
+            return self.self_tp;
+        }
+        if name == "template" {
+            self.error(&ast.token, "templates are not supported yet".to_string());
+            return None;
+        }
+        if name == "Vec" {
+            if ast.next.len() != 1 {
+                self.error(&ast.token, "`Vec` takes one type argument".to_string());
+                return None;
+            }
+            let elem = self.resolve_type(&ast.next[0])?;
+            return Some(self.table.vec_of(elem));
+        }
+
+        match self.table.find_type(name) {
+            None => {
+                self.error(&ast.token, format!("unknown type `{}`", name));
+                return None;
+            }
+            Some(x) => {
+                if !ast.next.is_empty() {
+                    self.error(&ast.token, format!("`{}` takes no type arguments", name));
+                    return None;
+                }
+                return Some(x);
+            }
+        }
+    }
+
+    fn collect_items(&mut self, root: &mut AST) {
+        //struct and enum names first, so any type may refer to any other
+        for item in root.next.iter_mut() {
+            let kind = match item.tp {
+                ASTtype::Struct => TypeKind::Struct(Vec::new()),
+                ASTtype::Enum => TypeKind::Enum(Vec::new()),
+                _ => continue,
+            };
+            let name = item.token.s.clone();
+            if self.table.find_type(&name).is_some() || name == "Vec" || name == "self" {
+                self.error(&item.token, format!("type `{}` is already defined", name));
+                continue;
+            }
+            self.table.variable_type.push(VariableType {
+                name,
+                size: 0,
+                align: 1,
+                kind,
+            });
+            item.vtype = Some(self.table.variable_type.len() - 1);
+        }
+
+        for item in root.next.iter() {
+            if item.tp == ASTtype::TypeAlias {
+                if let Some(tp) = self.resolve_type(&item.next[0]) {
+                    self.table.alias.push((item.token.s.clone(), tp));
+                }
+            } else if item.tp == ASTtype::Trait {
+                if self.table.trait_name.iter().any(|x| x.name == item.token.s) {
+                    let msg = format!("trait `{}` is already defined", item.token.s);
+                    self.error(&item.token, msg);
+                    continue;
+                }
+                let functions: Vec<String> = item
+                    .next
+                    .iter()
+                    .map(|f| f.next[1].token.s.clone())
+                    .collect();
+                self.table.trait_name.push(TraitName {
+                    name: item.token.s.clone(),
+                    functions,
+                });
+            }
+        }
+
+        for item in root.next.iter() {
+            if let Some(tp) = item.vtype {
+                self.collect_fields(item, tp);
+            }
+        }
+        for item in root.next.iter() {
+            if let Some(tp) = item.vtype {
+                if !self.table.layout(tp, &mut Vec::new()) {
+                    let msg = format!("recursive type `{}` has infinite size", item.token.s);
+                    self.error(&item.token, msg);
+                }
+            }
+        }
+
+        for item in root.next.iter_mut() {
+            match item.tp {
+                ASTtype::Function => {
+                    let name = item.next[1].token.s.clone();
+                    if self.table.find_function(&name).is_some()
+                        || BUILTINS.contains(&name.as_str())
+                    {
+                        let msg = format!("function `{}` is already defined", name);
+                        self.error(&item.next[1].token, msg);
+                        continue;
+                    }
+                    let cname = format!("rfx_{}", name);
+                    item.sym = self.collect_function(item, None, cname);
+                    let plain = item.next[2].next.is_empty() && item.next[3].next.is_empty();
+                    if item.has_attr("test") && !plain {
+                        let msg = format!(
+                            "test function `{}` must take no arguments and return nothing",
+                            name
+                        );
+                        self.error(&item.next[1].token, msg);
+                    }
+                }
+                ASTtype::Impl => {
+                    self.collect_impl(item);
+                }
+                _ => {}
+            }
+            if matches!(item.tp, ASTtype::Impl | ASTtype::Trait) {
+                for f in item.next.iter().filter(|x| x.has_attr("test")) {
+                    let msg = "`#[test]` is only for functions outside impls and traits";
+                    self.error(&f.next[1].token, msg.to_string());
+                }
+            }
+        }
+    }
+
+    fn field_type(&mut self, ast: &AST) -> Option<usize> {
+        let tp = self.resolve_type(ast)?;
+        if tp == TYPE_VOID {
+            self.error(&ast.token, "field of type `void`".to_string());
+            return None;
+        }
+        return Some(tp);
+    }
+
+    //fill the fields of a struct or the variants of an enum
+    fn collect_fields(&mut self, item: &AST, tp: usize) {
+        let kind = match item.tp {
+            ASTtype::Struct => {
+                let mut fields: Vec<VariableName> = Vec::new();
+                for f in item.next.iter() {
+                    if fields.iter().any(|x| x.name == f.token.s) {
+                        let msg = format!("field `{}` is already declared", f.token.s);
+                        self.error(&f.token, msg);
+                        continue;
+                    }
+                    if let Some(ftp) = self.field_type(&f.next[0]) {
+                        fields.push(VariableName {
+                            name: f.token.s.clone(),
+                            tp: ftp,
+                            constant: false,
+                        });
+                    }
+                }
+                TypeKind::Struct(fields)
+            }
+            _ => {
+                let mut variants: Vec<EnumVariant> = Vec::new();
+                for v in item.next.iter() {
+                    if variants.iter().any(|x| x.name == v.token.s) {
+                        let msg = format!("variant `{}` is already declared", v.token.s);
+                        self.error(&v.token, msg);
+                        continue;
+                    }
+                    let mut fields: Vec<usize> = Vec::new();
+                    for f in v.next.iter() {
+                        if let Some(ftp) = self.field_type(f) {
+                            fields.push(ftp);
+                        }
+                    }
+                    variants.push(EnumVariant {
+                        name: v.token.s.clone(),
+                        fields,
+                    });
+                }
+                TypeKind::Enum(variants)
+            }
+        };
+        self.table.variable_type[tp].kind = kind;
+    }
+
+    //add the signature to the function table. `self_tp` is set for impl methods
+    fn collect_function(
+        &mut self,
+        f: &AST,
+        impl_tp: Option<usize>,
+        cname: String,
+    ) -> Option<usize> {
+        let mut args: Vec<VariableName> = Vec::new();
+        let mut ok = true;
+
+        self.self_tp = impl_tp;
+        for arg in f.next[2].next.iter() {
+            match self.resolve_type(&arg.next[1]) {
+                None => ok = false,
+                Some(tp) => args.push(VariableName {
+                    name: arg.next[0].token.s.clone(),
+                    tp,
+                    constant: false,
+                }),
+            }
+        }
+        let return_tp = match f.next[3].next.first() {
+            None => Some(TYPE_VOID),
+            Some(x) => self.resolve_type(x),
+        };
+        self.self_tp = None;
+
+        if !ok || return_tp.is_none() {
+            return None;
+        }
+
+        //`fn f(self: T)` may also be called as a method of T
+        let self_tp = match impl_tp {
+            Some(_) => impl_tp,
+            None => match args.first() {
+                Some(a) if a.name == "self" => Some(a.tp),
+                _ => None,
+            },
+        };
+
+        self.table.function_name.push(FunctionName {
+            name: f.next[1].token.s.clone(),
+            args,
+            return_tp: return_tp.unwrap(),
+            self_tp,
+            free: impl_tp.is_none(),
+            cname,
+        });
+        return Some(self.table.function_name.len() - 1);
+    }
+
+    fn collect_impl(&mut self, item: &mut AST) {
+        let target = match self.resolve_type(&item.next[0]) {
+            None => return,
+            Some(x) => x,
+        };
+        let tname = self.type_name(target);
+
+        if item.token.tp != TokenType::Null {
+            let trait_name = item.token.s.clone();
+            let expected = match self.table.trait_name.iter().find(|x| x.name == trait_name) {
+                None => {
+                    self.error(&item.token, format!("unknown trait `{}`", trait_name));
+                    return;
+                }
+                Some(x) => x.functions.clone(),
+            };
+
+            let defined: Vec<String> = item.next[1..]
+                .iter()
+                .map(|f| f.next[1].token.s.clone())
+                .collect();
+            for name in expected.iter() {
+                if !defined.contains(name) {
+                    let msg = format!(
+                        "missing `{}` in impl of `{}` for `{}`",
+                        name, trait_name, tname
+                    );
+                    self.error(&item.token, msg);
+                }
+            }
+            for f in item.next[1..].iter() {
+                let name = &f.next[1].token.s;
+                if !expected.contains(name) {
+                    let msg = format!("`{}` is not a member of trait `{}`", name, trait_name);
+                    self.error(&f.next[1].token, msg);
+                }
+            }
+        }
+
+        for f in item.next[1..].iter_mut() {
+            let name = f.next[1].token.s.clone();
+            if self.table.find_method(target, &name).is_some() {
+                let msg = format!("method `{}` is already defined for `{}`", name, tname);
+                self.error(&f.next[1].token, msg);
+                continue;
+            }
+            let cname = format!("rfx_{}_{}", mangle(&tname), name);
+            f.sym = self.collect_function(f, Some(target), cname);
+
+            if item.token.s == "Drop" && name == "drop" {
+                self.collect_drop(f, target);
+            }
+        }
+    }
+
+    fn collect_drop(&mut self, f: &AST, target: usize) {
+        let sym = match f.sym {
+            None => return,
+            Some(x) => x,
+        };
+        if !matches!(
+            self.table.kind(target),
+            TypeKind::Struct(_) | TypeKind::Enum(_)
+        ) {
+            let msg = format!(
+                "`Drop` can only be implemented for structs and enums, not `{}`",
+                self.type_name(target)
+            );
+            self.error(&f.next[1].token, msg);
+            return;
+        }
+
+        let func = &self.table.function_name[sym];
+        if func.args.len() != 1 || func.args[0].tp != target || func.return_tp != TYPE_VOID {
+            self.error(
+                &f.next[1].token,
+                "`drop` must be declared as `fn drop(self)`".to_string(),
+            );
+            return;
+        }
+        self.table.drop_impl.push((target, sym));
+    }
+
+    //`x.drop()` would drop x twice
+    fn check_not_drop(&mut self, t: &Token, sym: usize) -> bool {
+        if self.table.drop_impl.iter().any(|(_, f)| *f == sym) {
+            self.error(
+                t,
+                "explicit calls to `drop` are not allowed; values are dropped when they go out of scope"
+                    .to_string(),
+            );
+            return false;
+        }
+        return true;
+    }
+
+    //note a use of variable `name` if it is module level and we are in a function
+    fn use_variable(&mut self, name: &str) {
+        if let (Some(f), Some(g)) = (self.cur_fn, self.table.find_global(name)) {
+            self.uses.push((f, g));
+        }
+    }
+
+    fn check_init_order(&mut self) {
+        //the variables a function uses, also through the functions it calls
+        let mut uses = self.uses.clone();
+        loop {
+            let mut more: Vec<(usize, usize)> = Vec::new();
+            for (caller, callee) in self.calls.iter() {
+                for (f, g) in uses.iter() {
+                    if f == callee
+                        && !uses.contains(&(*caller, *g))
+                        && !more.contains(&(*caller, *g))
+                    {
+                        more.push((*caller, *g));
+                    }
+                }
+            }
+            if more.is_empty() {
+                break;
+            }
+            uses.extend(more);
+        }
+
+        for (t, callee, ready) in std::mem::take(&mut self.entry_calls) {
+            let late = uses
+                .iter()
+                .filter(|(f, g)| *f == callee && *g >= ready)
+                .map(|(_, g)| *g)
+                .min();
+            if let Some(g) = late {
+                let msg = format!(
+                    "`{}` uses `{}`, which is not initialized yet",
+                    self.table.function_name[callee].name, self.table.variable_name[0][g].name
+                );
+                self.error(&t, msg);
+            }
+        }
+    }
+
+    fn check_impl_body(&mut self, item: &mut AST) {
+        for f in item.next[1..].iter_mut() {
+            self.check_function(f);
+        }
+    }
+
+    fn check_function(&mut self, f: &mut AST) {
+        let sym = match f.sym {
+            None => return,
+            Some(x) => x,
+        };
+        let args = self.table.function_name[sym].args.clone();
+        let ret_tp = self.table.function_name[sym].return_tp;
+
+        self.ret_tp = Some(ret_tp);
+        self.self_tp = self.table.function_name[sym].self_tp;
+        self.cur_fn = Some(sym);
+        self.table.push_scope();
+        for (arg, ast) in args.into_iter().zip(f.next[2].next.iter_mut()) {
+            ast.vtype = Some(arg.tp);
+            if !self.table.declare(arg) {
+                let msg = format!("duplicate argument `{}`", ast.token.s);
+                self.error(&ast.token, msg);
+            }
+        }
+
+        if f.next.len() > 4 {
+            self.check_block(&mut f.next[4]);
+            if ret_tp != TYPE_VOID && !block_diverges(&f.next[4].next) {
+                let msg = format!(
+                    "function `{}` may end without returning a value",
+                    f.next[1].token.s
+                );
+                self.error(&f.next[1].token, msg);
+            }
+        }
+
+        self.table.pop_scope();
+        self.ret_tp = None;
+        self.self_tp = None;
+        self.cur_fn = None;
+    }
+
+    fn check_block(&mut self, ast: &mut AST) {
+        self.table.push_scope();
+        for stmt in ast.next.iter_mut() {
+            self.check_stmt(stmt);
+        }
+        self.table.pop_scope();
+    }
+
+    fn check_stmt(&mut self, ast: &mut AST) {
+        match ast.tp {
+            ASTtype::Variable => self.check_variable(ast),
+            ASTtype::Assign => self.check_assign(ast),
+            ASTtype::CodeBlock => self.check_block(ast),
+            ASTtype::If => {
+                self.check_condition(&mut ast.next[0]);
+                self.check_block(&mut ast.next[1]);
+                if ast.next.len() > 2 {
+                    self.check_stmt(&mut ast.next[2]);
+                }
+            }
+            ASTtype::While => {
+                self.check_condition(&mut ast.next[0]);
+                self.loop_depth += 1;
+                self.check_block(&mut ast.next[1]);
+                self.loop_depth -= 1;
+            }
+            ASTtype::For => self.check_for(ast),
+            ASTtype::Match => {
+                self.check_match(ast, None, true);
+            }
+            ASTtype::Return => self.check_return(ast),
+            ASTtype::Break | ASTtype::Continue => {
+                if self.loop_depth == 0 {
+                    let msg = format!("`{}` outside of a loop", ast.token.s);
+                    self.error(&ast.token, msg);
+                }
+            }
+            _ => {
+                self.check_expr(ast, None);
+            }
+        }
+    }
+
+    fn check_condition(&mut self, ast: &mut AST) {
+        if let Some(tp) = self.check_expr(ast, Some(TYPE_BOOL)) {
+            if tp != TYPE_BOOL {
+                self.mismatch(&ast.token, TYPE_BOOL, tp);
+            }
+        }
+    }
+
+    fn check_variable(&mut self, ast: &mut AST) {
+        let constant = ast.next[0].token.tp == TokenType::Const;
+        let annotated = match ast.next[2].token.tp {
+            TokenType::Null => None,
+            _ => {
+                let tp = self.resolve_type(&ast.next[2]);
+                if tp.is_none() {
+                    return;
+                }
+                tp
+            }
+        };
+
+        let value_tp = self.check_expr(&mut ast.next[3], annotated);
+        let tp = match (annotated, value_tp) {
+            (Some(a), Some(v)) => {
+                if a != v {
+                    self.mismatch(&ast.next[3].token, a, v);
+                }
+                a
+            }
+            (Some(a), None) => a,
+            (None, Some(v)) => v,
+            (None, None) => return,
+        };
+        if tp == TYPE_VOID {
+            self.error(&ast.next[1].token, "variable of type `void`".to_string());
+            return;
+        }
+
+        ast.vtype = Some(tp);
+        ast.next[1].vtype = Some(tp);
+        let name = ast.next[1].token.s.clone();
+        if !self.table.declare(VariableName {
+            name: name.clone(),
+            tp,
+            constant,
+        }) {
+            let msg = format!("`{}` is already declared in this scope", name);
+            self.error(&ast.next[1].token, msg);
+        }
+    }
+
+    //type of an assignable place, or None after reporting why it is not one
+    fn check_place(&mut self, ast: &mut AST) -> Option<usize> {
+        match ast.tp {
+            ASTtype::Ident => {
+                let v = match self.table.find_variable(&ast.token.s) {
+                    None => {
+                        let msg = format!("unknown variable `{}`", ast.token.s);
+                        self.error(&ast.token, msg);
+                        return None;
+                    }
+                    Some(x) => x.clone(),
+                };
+                if v.constant {
+                    let msg = format!("cannot assign to constant `{}`", v.name);
+                    self.error(&ast.token, msg);
+                    return None;
+                }
+                self.use_variable(&v.name);
+                ast.vtype = Some(v.tp);
+                return Some(v.tp);
+            }
+            ASTtype::Index | ASTtype::Field => {
+                self.check_place(&mut ast.next[0])?;
+                return self.check_expr(ast, None);
+            }
+            _ => {
+                self.error(
+                    &ast.token,
+                    "invalid left-hand side of assignment".to_string(),
+                );
+                return None;
+            }
+        }
+    }
+
+    fn check_assign(&mut self, ast: &mut AST) {
+        let tp = match self.check_place(&mut ast.next[0]) {
+            None => return,
+            Some(x) => x,
+        };
+        let value = match self.check_expr(&mut ast.next[1], Some(tp)) {
+            None => return,
+            Some(x) => x,
+        };
+
+        let op = ast.token.tp;
+        if op == TokenType::PlusEqu && tp == TYPE_STRING {
+            if value != TYPE_STRING {
+                self.mismatch(&ast.next[1].token, TYPE_STRING, value);
+            }
+        } else if op != TokenType::Equal && !self.table.is_integer(tp) {
+            let msg = format!(
+                "`{}` cannot be applied to `{}`",
+                ast.token.s,
+                self.type_name(tp)
+            );
+            self.error(&ast.token, msg);
+        } else if value != tp {
+            self.mismatch(&ast.next[1].token, tp, value);
+        }
+        ast.vtype = Some(tp);
+    }
+
+    fn check_for(&mut self, ast: &mut AST) {
+        let iter = &mut ast.next[1];
+        let is_range = iter.tp == ASTtype::Binary
+            && (iter.token.tp == TokenType::DotDot || iter.token.tp == TokenType::DotDotEqu);
+
+        let elem = if is_range {
+            self.check_arith(iter, None)
+        } else {
+            match self.check_expr(iter, None) {
+                None => None,
+                Some(tp) => match self.table.kind(tp).clone() {
+                    TypeKind::Vec(elem) => Some(elem),
+                    _ => {
+                        let msg = format!("cannot iterate over `{}`", self.type_name(tp));
+                        self.error(&iter.token, msg);
+                        None
+                    }
+                },
+            }
+        };
+
+        self.table.push_scope();
+        if let Some(tp) = elem {
+            ast.next[0].vtype = Some(tp);
+            self.table.declare(VariableName {
+                name: ast.next[0].token.s.clone(),
+                tp,
+                constant: true,
+            });
+        }
+        self.loop_depth += 1;
+        self.check_block(&mut ast.next[2]);
+        self.loop_depth -= 1;
+        self.table.pop_scope();
+    }
+
+    fn check_return(&mut self, ast: &mut AST) {
+        let ret_tp = match self.ret_tp {
+            None => {
+                self.error(&ast.token, "`return` outside of a function".to_string());
+                return;
+            }
+            Some(x) => x,
+        };
+
+        if ast.next.is_empty() {
+            if ret_tp != TYPE_VOID {
+                self.mismatch(&ast.token, ret_tp, TYPE_VOID);
+            }
+            return;
+        }
+        if let Some(tp) = self.check_expr(&mut ast.next[0], Some(ret_tp)) {
+            if tp != ret_tp {
+                self.mismatch(&ast.next[0].token, ret_tp, tp);
+            }
+        }
+    }
+
+    pub fn check_expr(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        let tp = match ast.tp {
+            ASTtype::Literal => self.check_literal(ast, expected),
+            ASTtype::Ident if ast.token.s.contains("::") => self.check_path_value(ast),
+            ASTtype::Ident => match self.table.find_variable(&ast.token.s) {
+                None => {
+                    let msg = format!("unknown variable `{}`", ast.token.s);
+                    self.error(&ast.token, msg);
+                    None
+                }
+                Some(v) => {
+                    let tp = v.tp;
+                    self.use_variable(&ast.token.s);
+                    Some(tp)
+                }
+            },
+            ASTtype::Unary => self.check_unary(ast, expected),
+            ASTtype::Binary => self.check_binary(ast, expected),
+            ASTtype::CallFunction => self.check_call(ast, expected),
+            ASTtype::CallMethod => self.check_method(ast),
+            ASTtype::Index => self.check_index(ast),
+            ASTtype::Array => self.check_array(ast, expected),
+            ASTtype::Field => self.check_field(ast),
+            ASTtype::StructLiteral => self.check_struct_literal(ast),
+            ASTtype::Match => self.check_match(ast, expected, false),
+            //reported by the parser
+            ASTtype::Error => None,
+            _ => {
+                self.error(&ast.token, "expected expression".to_string());
+                None
+            }
+        };
+        ast.vtype = tp;
+        return tp;
+    }
+
+    fn check_int_range(&mut self, t: &Token, value: i128, tp: usize) -> bool {
+        let (lo, hi) = self.table.value_range(tp).unwrap();
+        if value < lo || value > hi {
+            let msg = format!(
+                "literal `{}` out of range for `{}`",
+                value,
+                self.type_name(tp)
+            );
+            self.error(t, msg);
+            return false;
+        }
+        return true;
+    }
+
+    fn check_literal(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        return match ast.token.tp {
+            TokenType::Number => {
+                let tp = match expected {
+                    Some(x) if self.table.is_integer(x) => x,
+                    _ => TYPE_I64,
+                };
+                match number_value(&ast.token.s) {
+                    None => {
+                        let msg = format!("invalid number `{}`", ast.token.s);
+                        self.error(&ast.token, msg);
+                        return None;
+                    }
+                    Some(x) => {
+                        self.check_int_range(&ast.token, x, tp);
+                    }
+                }
+                Some(tp)
+            }
+            TokenType::StringLiteral => Some(TYPE_STRING),
+            TokenType::CharLiteral => Some(TYPE_CHAR),
+            _ => Some(TYPE_BOOL),
+        };
+    }
+
+    fn check_unary(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        if ast.token.tp == TokenType::Minus
+            && ast.next[0].tp == ASTtype::Literal
+            && ast.next[0].token.tp == TokenType::Number
+        {
+            //negative literal. check the range of the negated value
+            let tp = match expected {
+                Some(x) if self.table.is_integer(x) => x,
+                _ => TYPE_I64,
+            };
+            let value = -number_value(&ast.next[0].token.s)?;
+            ast.next[0].vtype = Some(tp);
+            if !self.check_int_range(&ast.token, value, tp) {
+                return None;
+            }
+            return Some(tp);
+        }
+
+        let tp = self.check_expr(&mut ast.next[0], expected)?;
+        let ok = match ast.token.tp {
+            TokenType::Minus => matches!(self.table.kind(tp), TypeKind::Integer { signed: true }),
+            _ => tp == TYPE_BOOL || self.table.is_integer(tp),
+        };
+        if !ok {
+            let msg = format!(
+                "`{}` cannot be applied to `{}`",
+                ast.token.s,
+                self.type_name(tp)
+            );
+            self.error(&ast.token, msg);
+            return None;
+        }
+        return Some(tp);
+    }
+
+    //check both sides so that a literal takes the type of the other side
+    fn check_operands(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<(usize, usize)> {
+        let (left, right) = ast.next.split_at_mut(1);
+        let (left, right) = (&mut left[0], &mut right[0]);
+
+        if is_int_literal(left) && !is_int_literal(right) {
+            let r = self.check_expr(right, expected)?;
+            let l = self.check_expr(left, Some(r))?;
+            return Some((l, r));
+        }
+        let l = self.check_expr(left, expected)?;
+        let r = self.check_expr(right, Some(l))?;
+        return Some((l, r));
+    }
+
+    //true if `==` compares values of tp
+    fn comparable(&self, tp: usize) -> bool {
+        return self.table.is_integer(tp)
+            || matches!(
+                self.table.kind(tp),
+                TypeKind::Bool | TypeKind::Char | TypeKind::String
+            );
+    }
+
+    //arithmetic, bitwise and range operators on integers
+    fn check_arith(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        let (l, r) = self.check_operands(ast, expected)?;
+        if !self.table.is_integer(l) {
+            let msg = format!(
+                "`{}` cannot be applied to `{}`",
+                ast.token.s,
+                self.type_name(l)
+            );
+            self.error(&ast.token, msg);
+            return None;
+        }
+        if l != r {
+            self.mismatch(&ast.next[1].token, l, r);
+            return None;
+        }
+        ast.vtype = Some(l);
+        return Some(l);
+    }
+
+    fn check_binary(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        match ast.token.tp {
+            TokenType::DotDot | TokenType::DotDotEqu => {
+                self.error(&ast.token, "a range can only be used in `for`".to_string());
+                return None;
+            }
+            TokenType::LogicAND | TokenType::LogicOR => {
+                for side in ast.next.iter_mut() {
+                    self.check_condition(side);
+                }
+                return Some(TYPE_BOOL);
+            }
+            TokenType::LogicEqual | TokenType::LogicNotEqual => {
+                let (l, r) = self.check_operands(ast, None)?;
+                if l != r {
+                    self.mismatch(&ast.next[1].token, l, r);
+                    return None;
+                }
+                if !self.comparable(l) {
+                    let msg = format!("`{}` cannot be compared", self.type_name(l));
+                    self.error(&ast.token, msg);
+                    return None;
+                }
+                return Some(TYPE_BOOL);
+            }
+            TokenType::LeftABrack
+            | TokenType::RightABrack
+            | TokenType::LessEqual
+            | TokenType::GreaterEqual => {
+                let (l, r) = self.check_operands(ast, None)?;
+                if l != r {
+                    self.mismatch(&ast.next[1].token, l, r);
+                    return None;
+                }
+                if !self.table.is_integer(l) && l != TYPE_CHAR {
+                    let msg = format!("`{}` cannot be ordered", self.type_name(l));
+                    self.error(&ast.token, msg);
+                    return None;
+                }
+                return Some(TYPE_BOOL);
+            }
+            TokenType::Plus => {
+                let (l, r) = self.check_operands(ast, expected)?;
+                if l == TYPE_STRING {
+                    if r != TYPE_STRING {
+                        self.mismatch(&ast.next[1].token, l, r);
+                        return None;
+                    }
+                    return Some(TYPE_STRING);
+                }
+                if !self.table.is_integer(l) {
+                    let msg = format!("`+` cannot be applied to `{}`", self.type_name(l));
+                    self.error(&ast.token, msg);
+                    return None;
+                }
+                if l != r {
+                    self.mismatch(&ast.next[1].token, l, r);
+                    return None;
+                }
+                return Some(l);
+            }
+            _ => {
+                return self.check_arith(ast, expected);
+            }
+        }
+    }
+
+    fn check_args(&mut self, call: &mut AST, first: usize, sym: usize) -> Option<usize> {
+        let f = &self.table.function_name[sym];
+        let arg_tps: Vec<usize> = f.args.iter().skip(first).map(|a| a.tp).collect();
+        let ret = f.return_tp;
+        let name = f.name.clone();
+        let given = call.next.len() - first;
+
+        if given != arg_tps.len() {
+            let msg = format!(
+                "`{}` takes {} argument(s) but {} were given",
+                name,
+                arg_tps.len(),
+                given
+            );
+            self.error(&call.token, msg);
+            return None;
+        }
+        let mut ok = true;
+        for (arg, tp) in call.next[first..].iter_mut().zip(arg_tps) {
+            match self.check_expr(arg, Some(tp)) {
+                Some(x) if x != tp => {
+                    self.mismatch(&arg.token, tp, x);
+                    ok = false;
+                }
+                None => ok = false,
+                _ => {}
+            }
+        }
+
+        call.sym = Some(sym);
+        match self.cur_fn {
+            Some(f) => self.calls.push((f, sym)),
+            None => {
+                let ready = self.table.variable_name[0].len();
+                self.entry_calls.push((call.token.clone(), sym, ready));
+            }
+        }
+        return match ok {
+            true => Some(ret),
+            false => None,
+        };
+    }
+
+    fn check_call(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        let name = ast.token.s.clone();
+
+        match name.as_str() {
+            "assert" => {
+                if ast.next.len() != 1 {
+                    self.error(&ast.token, "`assert` takes 1 argument".to_string());
+                    return None;
+                }
+                self.check_condition(&mut ast.next[0]);
+                return Some(TYPE_VOID);
+            }
+            "assert_eq" => {
+                if ast.next.len() != 2 {
+                    self.error(&ast.token, "`assert_eq` takes 2 arguments".to_string());
+                    return None;
+                }
+                let (l, r) = self.check_operands(ast, None)?;
+                if l != r {
+                    self.mismatch(&ast.next[1].token, l, r);
+                    return None;
+                }
+                if !self.comparable(l) {
+                    let msg = format!("`{}` cannot be compared", self.type_name(l));
+                    self.error(&ast.next[0].token, msg);
+                    return None;
+                }
+                return Some(TYPE_VOID);
+            }
+            "print" => {
+                if ast.next.len() != 1 {
+                    self.error(&ast.token, "`print` takes 1 argument".to_string());
+                    return None;
+                }
+                let tp = self.check_expr(&mut ast.next[0], None)?;
+                if !self.table.is_integer(tp)
+                    && !matches!(
+                        self.table.kind(tp),
+                        TypeKind::Bool | TypeKind::Char | TypeKind::String
+                    )
+                {
+                    let msg = format!("cannot print `{}`", self.type_name(tp));
+                    self.error(&ast.next[0].token, msg);
+                    return None;
+                }
+                return Some(TYPE_VOID);
+            }
+            "Vec::new" | "Vec::new_init" => {
+                let want = if name == "Vec::new" { 0 } else { 1 };
+                if ast.next.len() != want {
+                    let msg = format!("`{}` takes {} argument(s)", name, want);
+                    self.error(&ast.token, msg);
+                    return None;
+                }
+                if want == 1 {
+                    let tp = self.check_expr(&mut ast.next[0], expected)?;
+                    if !matches!(self.table.kind(tp), TypeKind::Vec(_)) {
+                        let msg =
+                            format!("`{}` needs an array, found `{}`", name, self.type_name(tp));
+                        self.error(&ast.next[0].token, msg);
+                        return None;
+                    }
+                    return Some(tp);
+                }
+                return match expected {
+                    Some(x) if matches!(self.table.kind(x), TypeKind::Vec(_)) => Some(x),
+                    _ => {
+                        self.error(
+                            &ast.token,
+                            "cannot infer the element type of `Vec::new()`".to_string(),
+                        );
+                        None
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        if let Some((tname, method)) = name.rsplit_once("::") {
+            //Type::method(self, ...)
+            let tp = match self.table.find_type(tname) {
+                None => {
+                    self.error(&ast.token, format!("unknown type `{}`", tname));
+                    return None;
+                }
+                Some(x) => x,
+            };
+            if let Some(i) = self.table.find_variant(tp, method) {
+                return self.check_variant(ast, tp, i);
+            }
+            return match self.table.find_method(tp, method) {
+                None => {
+                    let msg = format!("no method `{}` on `{}`", method, tname);
+                    self.error(&ast.token, msg);
+                    None
+                }
+                Some(sym) if !self.check_not_drop(&ast.token, sym) => None,
+                Some(sym) => self.check_args(ast, 0, sym),
+            };
+        }
+
+        return match self.table.find_function(&name) {
+            None => {
+                self.error(&ast.token, format!("unknown function `{}`", name));
+                None
+            }
+            Some(sym) => self.check_args(ast, 0, sym),
+        };
+    }
+
+    fn check_method(&mut self, ast: &mut AST) -> Option<usize> {
+        let tp = self.check_expr(&mut ast.next[0], None)?;
+        let name = ast.token.s.clone();
+
+        match (self.table.kind(tp).clone(), name.as_str()) {
+            (TypeKind::String, "len") | (TypeKind::Vec(_), "len") if ast.next.len() == 1 => {
+                return Some(TYPE_U64);
+            }
+            (TypeKind::Vec(elem), "push") => {
+                if ast.next.len() != 2 {
+                    self.error(&ast.token, "`push` takes 1 argument".to_string());
+                    return None;
+                }
+                if !matches!(ast.next[0].tp, ASTtype::Ident | ASTtype::Index) {
+                    self.error(
+                        &ast.next[0].token,
+                        "cannot push to a temporary `Vec`".to_string(),
+                    );
+                    return None;
+                }
+                let x = self.check_expr(&mut ast.next[1], Some(elem))?;
+                if x != elem {
+                    self.mismatch(&ast.next[1].token, elem, x);
+                    return None;
+                }
+                return Some(TYPE_VOID);
+            }
+            _ => {}
+        }
+
+        return match self.table.find_method(tp, &name) {
+            None => {
+                let msg = format!("no method `{}` on `{}`", name, self.type_name(tp));
+                self.error(&ast.token, msg);
+                None
+            }
+            Some(sym) => {
+                if self.table.function_name[sym].args.first().map(|a| a.tp) != Some(tp) {
+                    let msg = format!(
+                        "`{}` is an associated function of `{}`, not a method",
+                        name,
+                        self.type_name(tp)
+                    );
+                    self.error(&ast.token, msg);
+                    return None;
+                }
+                if !self.check_not_drop(&ast.token, sym) {
+                    return None;
+                }
+                self.check_args(ast, 1, sym)
+            }
+        };
+    }
+
+    //Enum::Variant or Enum::Variant(payload...). the node becomes a Variant
+    fn check_variant(&mut self, ast: &mut AST, tp: usize, i: usize) -> Option<usize> {
+        let fields = self.table.variant(tp, i).fields.clone();
+        if fields.len() != ast.next.len() {
+            let msg = format!(
+                "`{}` takes {} value(s) but {} were given",
+                ast.token.s,
+                fields.len(),
+                ast.next.len()
+            );
+            self.error(&ast.token, msg);
+            return None;
+        }
+
+        let mut ok = true;
+        for (x, f) in ast.next.iter_mut().zip(fields) {
+            match self.check_expr(x, Some(f)) {
+                Some(t) if t != f => {
+                    self.mismatch(&x.token, f, t);
+                    ok = false;
+                }
+                None => ok = false,
+                _ => {}
+            }
+        }
+        ast.tp = ASTtype::Variant;
+        ast.sym = Some(i);
+        return match ok {
+            true => Some(tp),
+            false => None,
+        };
+    }
+
+    //a path used as a value, which can only be a variant without payload
+    fn check_path_value(&mut self, ast: &mut AST) -> Option<usize> {
+        let (tname, vname) = ast.token.s.rsplit_once("::").unwrap();
+        let tp = match self.table.find_type(tname) {
+            None => {
+                let msg = format!("unknown type `{}`", tname);
+                self.error(&ast.token, msg);
+                return None;
+            }
+            Some(x) => x,
+        };
+        return match self.table.find_variant(tp, vname) {
+            None => {
+                let msg = format!("no variant `{}` in `{}`", vname, tname);
+                self.error(&ast.token, msg);
+                None
+            }
+            Some(i) => self.check_variant(ast, tp, i),
+        };
+    }
+
+    fn check_field(&mut self, ast: &mut AST) -> Option<usize> {
+        let base = self.check_expr(&mut ast.next[0], None)?;
+        return match self.table.field(base, &ast.token.s) {
+            None => {
+                let msg = format!("no field `{}` on `{}`", ast.token.s, self.type_name(base));
+                self.error(&ast.token, msg);
+                None
+            }
+            Some(f) => Some(f.tp),
+        };
+    }
+
+    fn check_struct_literal(&mut self, ast: &mut AST) -> Option<usize> {
+        let name = ast.token.s.clone();
+        let found = match name.as_str() {
+            "self" => self.self_tp,
+            _ => self.table.find_type(&name),
+        };
+        let (tp, fields) = match found.map(|x| (x, self.table.kind(x).clone())) {
+            None => {
+                self.error(&ast.token, format!("unknown type `{}`", name));
+                return None;
+            }
+            Some((x, TypeKind::Struct(fields))) => (x, fields),
+            Some(_) => {
+                self.error(&ast.token, format!("`{}` is not a struct", name));
+                return None;
+            }
+        };
+
+        let mut ok = true;
+        let mut seen: Vec<String> = Vec::new();
+        for init in ast.next.iter_mut() {
+            let f = match fields.iter().find(|x| x.name == init.token.s) {
+                None => {
+                    let msg = format!("no field `{}` on `{}`", init.token.s, self.type_name(tp));
+                    self.error(&init.token, msg);
+                    ok = false;
+                    continue;
+                }
+                Some(x) => x,
+            };
+            if seen.contains(&f.name) {
+                let msg = format!("field `{}` is given more than once", f.name);
+                self.error(&init.token, msg);
+                ok = false;
+            }
+            seen.push(f.name.clone());
+
+            init.vtype = Some(f.tp);
+            match self.check_expr(&mut init.next[0], Some(f.tp)) {
+                Some(x) if x != f.tp => {
+                    self.mismatch(&init.next[0].token, f.tp, x);
+                    ok = false;
+                }
+                None => ok = false,
+                _ => {}
+            }
+        }
+
+        let missing: Vec<String> = fields
+            .iter()
+            .filter(|x| !seen.contains(&x.name))
+            .map(|x| format!("`{}`", x.name))
+            .collect();
+        if !missing.is_empty() {
+            let msg = format!(
+                "missing field(s) {} in `{}`",
+                missing.join(", "),
+                self.type_name(tp)
+            );
+            self.error(&ast.token, msg);
+            return None;
+        }
+        return match ok {
+            true => Some(tp),
+            false => None,
+        };
+    }
+
+    fn check_index(&mut self, ast: &mut AST) -> Option<usize> {
+        let base = self.check_expr(&mut ast.next[0], None)?;
+        let elem = match self.table.kind(base) {
+            TypeKind::Vec(x) => *x,
+            _ => {
+                let msg = format!("cannot index into `{}`", self.type_name(base));
+                self.error(&ast.token, msg);
+                return None;
+            }
+        };
+
+        let index = self.check_expr(&mut ast.next[1], Some(TYPE_U64))?;
+        if !self.table.is_integer(index) {
+            let msg = format!(
+                "index must be an integer, found `{}`",
+                self.type_name(index)
+            );
+            self.error(&ast.next[1].token, msg);
+            return None;
+        }
+        return Some(elem);
+    }
+
+    fn check_array(&mut self, ast: &mut AST, expected: Option<usize>) -> Option<usize> {
+        let mut elem = match expected.map(|x| self.table.kind(x).clone()) {
+            Some(TypeKind::Vec(x)) => Some(x),
+            _ => None,
+        };
+
+        if ast.next.is_empty() && elem.is_none() {
+            self.error(
+                &ast.token,
+                "cannot infer the element type of `[]`".to_string(),
+            );
+            return None;
+        }
+        for x in ast.next.iter_mut() {
+            let tp = self.check_expr(x, elem)?;
+            match elem {
+                Some(e) if e != tp => {
+                    self.mismatch(&x.token, e, tp);
+                    return None;
+                }
+                _ => elem = Some(tp),
+            }
+        }
+        return Some(self.table.vec_of(elem.unwrap()));
+    }
+
+    //set `vtype` of the pattern and its payloads, and declare its bindings.
+    //a PatVariant gets the variant index in `sym`
+    fn declare_bindings(&mut self, pat: &mut AST, tp: usize) {
+        pat.vtype = Some(tp);
+        match pat.tp {
+            ASTtype::PatBinding => {
+                let v = VariableName {
+                    name: pat.token.s.clone(),
+                    tp,
+                    constant: true,
+                };
+                if !self.table.declare(v) {
+                    let msg = format!("`{}` is bound more than once in the pattern", pat.token.s);
+                    self.error(&pat.token, msg);
+                }
+            }
+            ASTtype::PatOr => {
+                for alt in pat.next.iter_mut() {
+                    if let Some(t) = first_binding(alt) {
+                        let msg = format!("binding `{}` is not allowed in a `|` pattern", t.s);
+                        let t = t.clone();
+                        self.error(&t, msg);
+                        continue;
+                    }
+                    self.declare_bindings(alt, tp);
+                }
+            }
+            ASTtype::PatVariant => {
+                //a bad variant is reported by pattern_of
+                let vname = pat.token.s.rsplit_once("::").unwrap().1;
+                let i = match self.table.find_variant(tp, vname) {
+                    None => return,
+                    Some(x) => x,
+                };
+                let fields = self.table.variant(tp, i).fields.clone();
+                pat.sym = Some(i);
+                for (sub, f) in pat.next.iter_mut().zip(fields) {
+                    self.declare_bindings(sub, f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    //a match statement has no value; a match expression needs expression arms of one type
+    fn check_match(
+        &mut self,
+        ast: &mut AST,
+        expected: Option<usize>,
+        is_stmt: bool,
+    ) -> Option<usize> {
+        let value_tp = self.check_expr(&mut ast.next[0], None)?;
+        let dom = match domain_of(self.table, value_tp) {
+            None => {
+                let msg = format!("cannot match on `{}`", self.type_name(value_tp));
+                self.error(&ast.next[0].token, msg);
+                return None;
+            }
+            Some(x) => x,
+        };
+
+        let mut pats: Vec<Pat> = Vec::new();
+        let mut pats_ok = true;
+        let mut result: Option<usize> = if is_stmt { Some(TYPE_VOID) } else { expected };
+        let mut result_ok = true;
+
+        if ast.next.len() == 1 {
+            self.error(&ast.token, "match has no arms".to_string());
+            return None;
+        }
+        for case in ast.next[1..].iter_mut() {
+            match pattern_of(&case.next[0], &dom, self.table) {
+                Ok(p) => pats.push(p),
+                Err(msg) => {
+                    self.error(&case.next[0].token, msg);
+                    pats_ok = false;
+                }
+            }
+
+            self.table.push_scope();
+            self.declare_bindings(&mut case.next[0], value_tp);
+            let body = &mut case.next[1];
+            if body.tp == ASTtype::CodeBlock {
+                if !is_stmt {
+                    self.error(
+                        &body.token,
+                        "a match used as a value needs expression arms".to_string(),
+                    );
+                    result_ok = false;
+                }
+                self.check_block(body);
+            } else if is_stmt {
+                self.check_expr(body, None);
+            } else {
+                match (self.check_expr(body, result), result) {
+                    (None, _) => result_ok = false,
+                    (Some(tp), None) => result = Some(tp),
+                    (Some(tp), Some(r)) if tp != r => {
+                        self.mismatch(&body.token, r, tp);
+                        result_ok = false;
+                    }
+                    _ => {}
+                }
+            }
+            self.table.pop_scope();
+        }
+
+        if pats_ok {
+            let report = check_arms(&pats, &dom, self.table);
+            for i in report.unreachable.iter() {
+                let t = ast.next[i + 1].next[0].token.clone();
+                self.warning(&t, "unreachable match arm".to_string());
+            }
+            if let Some(missing) = report.missing {
+                let msg = format!("non-exhaustive match: `{}` not covered", missing);
+                self.error(&ast.token, msg);
+            }
+        }
+
+        if !result_ok {
+            return None;
+        }
+        if result == Some(TYPE_VOID) && !is_stmt {
+            self.error(
+                &ast.token,
+                "a match used as a value has no value".to_string(),
+            );
+            return None;
+        }
+        return result;
+    }
+}
+
+//what a failed `assert` or `assert_eq` call panics with, the same for
+//every backend
+pub fn assert_message(table: &SymbolTable, call: &AST) -> String {
+    let at = table.location(&call.token);
+    return match call.token.s.as_str() {
+        "assert" => format!("assertion failed at {}", at),
+        _ => format!("assertion `left == right` failed at {}", at),
+    };
+}
+
+//what a failed `assert_eq` panics with: its message, then the values as
+//print shows them
+pub fn assert_eq_message(msg: &str, left: &str, right: &str) -> String {
+    return format!("{}\n  left: {}\n right: {}", msg, left, right);
+}
+
+//C identifier for a type name, "Vec<String>" -> "Vec_String"
+pub fn mangle(name: &str) -> String {
+    return name
+        .chars()
+        .filter(|c| *c != '>' && *c != ' ')
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+}
@@ -0,0 +1,682 @@
+use std::io::Write;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::bytecode::{CmpOp, Const, Function, Op, Place, PrintKind, Program, Step, TypeDesc, Var};
+use super::interp::{int_op, matches_pat, utf8, wrap_bits, Panic, Value};
+use super::typeck::assert_eq_message;
+
+/*
+the VM running bytecode. each call runs the code of its function over its
+own slots and operand stack, in a frame pushed on the stack of frames; only
+a user `drop` run by dropping a value is a call of the VM into itself. it behaves as the interpreter does,
+values included, so a program prints the same in all three ways of running it
+*/
+
+//the calls deep a program may go before it panics
+const MAX_DEPTH: usize = 1 << 18;
+
+struct Vm<'a> {
+    p: &'a Program,
+    dropping: Vec<bool>, // by type, true if dropping it runs a user `drop`
+    globals: Vec<Option<Value>>,
+    depth: usize, // the frames of all the calls running
+    out: &'a mut dyn Write,
+}
+
+//a call running: its function, slots, operand stack and the next op
+struct Frame<'a> {
+    func: &'a Function,
+    slots: Vec<Option<Value>>,
+    stack: Vec<Value>,
+    pc: usize,
+}
+
+//run the program, writing what it prints to out. Err is the message of a
+//panic
+pub fn run(p: &Program, out: &mut dyn Write) -> Result<(), Panic> {
+    let dropping = (0..p.types.len())
+        .map(|tp| runs_drop(p, tp as u32, &mut Vec::new()))
+        .collect();
+    let mut vm = Vm {
+        p,
+        dropping,
+        globals: vec![None; p.globals.len()],
+        depth: 0,
+        out,
+    };
+    let ended = vm.call(p.entry, Vec::new());
+    vm.out.flush().ok();
+    return ended.map(|_| ());
+}
+
+fn runs_drop(p: &Program, tp: u32, visiting: &mut Vec<u32>) -> bool {
+    let t = &p.types[tp as usize];
+    if t.drop.is_some() {
+        return true;
+    }
+    if visiting.contains(&tp) {
+        return false;
+    }
+    visiting.push(tp);
+    let ret = match &t.desc {
+        TypeDesc::Vec(x) => runs_drop(p, *x, visiting),
+        TypeDesc::Struct(fields) => fields.iter().any(|x| runs_drop(p, *x, visiting)),
+        TypeDesc::Enum(variants) => variants
+            .iter()
+            .flatten()
+            .any(|x| runs_drop(p, *x, visiting)),
+        _ => false,
+    };
+    visiting.pop();
+    return ret;
+}
+
+//the panic of a program whose bytecode does what no compiled program does,
+//e.g. a field of a value that is no struct. the file was damaged or forged;
+//loading it checks the tables and the stack, but not the shape of values
+fn corrupt(what: String) -> Panic {
+    return format!("corrupt bytecode, {}", what);
+}
+
+fn pop(stack: &mut Vec<Value>) -> Result<Value, Panic> {
+    return stack
+        .pop()
+        .ok_or_else(|| corrupt("the operand stack is empty".to_string()));
+}
+
+fn int(v: &Value) -> Result<i128, Panic> {
+    return match v {
+        Value::Int(x) => Ok(*x),
+        Value::Bool(x) => Ok(*x as i128),
+        _ => Err(corrupt(format!("{:?} is not an integer", v))),
+    };
+}
+
+//the value as print shows it, without the newline
+fn shown(v: &Value, kind: PrintKind) -> Result<Vec<u8>, Panic> {
+    return Ok(match (kind, v) {
+        (PrintKind::Char, Value::Int(c)) => utf8(*c as u32),
+        (_, Value::Str(s)) => s.as_bytes().to_vec(),
+        (_, Value::Bool(x)) => x.to_string().into_bytes(),
+        (_, x) => int(x)?.to_string().into_bytes(),
+    });
+}
+
+fn boolean(v: &Value) -> Result<bool, Panic> {
+    return match v {
+        Value::Bool(x) => Ok(*x),
+        _ => Err(corrupt(format!("{:?} is not a bool", v))),
+    };
+}
+
+fn len(v: &Value) -> Result<usize, Panic> {
+    return match v {
+        Value::Str(_) | Value::Vec(_) => Ok(v.len()),
+        _ => Err(corrupt(format!("{:?} has no len", v))),
+    };
+}
+
+//the indices of the Index steps of a place, pushed in order
+fn indices(stack: &mut Vec<Value>, place: &Place) -> Result<Vec<i128>, Panic> {
+    let n = place.steps.iter().filter(|x| **x == Step::Index).count();
+    if n > stack.len() {
+        return Err(corrupt("the operand stack is empty".to_string()));
+    }
+    let at = stack.len() - n;
+    return stack.split_off(at).iter().map(int).collect();
+}
+
+fn cell<'s>(
+    globals: &'s mut [Option<Value>],
+    slots: &'s mut [Option<Value>],
+    v: Var,
+) -> &'s mut Option<Value> {
+    return match v {
+        Var::Local(x) => &mut slots[x as usize],
+        Var::Global(x) => &mut globals[x as usize],
+    };
+}
+
+//the part of a value a place names
+fn walk<'v>(mut v: &'v mut Value, place: &Place, idx: &[i128]) -> Result<&'v mut Value, Panic> {
+    let mut idx = idx.iter();
+    for s in place.steps.iter() {
+        v = match (s, v) {
+            (Step::Field(i), Value::Struct(fields))
+            | (Step::Payload(i), Value::Enum(_, fields)) => match fields.get_mut(*i as usize) {
+                Some(x) => x,
+                None => return Err(corrupt(format!("no part {} in a place", i))),
+            },
+            (Step::Index, Value::Vec(xs)) => {
+                let i = *idx.next().unwrap();
+                if i < 0 || i >= xs.len() as i128 {
+                    return Err("index out of bounds".to_string());
+                }
+                &mut xs[i as usize]
+            }
+            (s, v) => return Err(corrupt(format!("{:?} into {:?}", s, v))),
+        };
+    }
+    return Ok(v);
+}
+
+impl<'a> Vm<'a> {
+    //the value a C variable of the type starts with, all zero
+    fn zero(&self, tp: u32) -> Value {
+        return match &self.p.types[tp as usize].desc {
+            TypeDesc::Void => Value::Void,
+            TypeDesc::Int(_) | TypeDesc::Char => Value::Int(0),
+            TypeDesc::Bool => Value::Bool(false),
+            TypeDesc::Str => Value::Str(String::new()),
+            TypeDesc::Vec(_) => Value::Vec(Vec::new()),
+            TypeDesc::Struct(fields) => {
+                Value::Struct(fields.iter().map(|x| self.zero(*x)).collect())
+            }
+            TypeDesc::Enum(variants) => {
+                let fields = variants.first().cloned().unwrap_or_default();
+                Value::Enum(0, fields.iter().map(|x| self.zero(*x)).collect())
+            }
+        };
+    }
+
+    fn drop(&mut self, v: Value, tp: u32) -> Result<(), Panic> {
+        if !self.dropping[tp as usize] {
+            return Ok(());
+        }
+        return match self.p.types[tp as usize].drop {
+            Some(f) => self.call(f, vec![v]).map(|_| ()),
+            None => self.drop_fields(v, tp),
+        };
+    }
+
+    fn drop_fields(&mut self, v: Value, tp: u32) -> Result<(), Panic> {
+        let types: Vec<u32> = match (&self.p.types[tp as usize].desc, &v) {
+            (TypeDesc::Vec(x), Value::Vec(xs)) => vec![*x; xs.len()],
+            (TypeDesc::Struct(fields), _) => fields.clone(),
+            (TypeDesc::Enum(variants), Value::Enum(i, _)) => match variants.get(*i) {
+                Some(x) => x.clone(),
+                None => {
+                    return Err(corrupt(format!(
+                        "no variant {} in a `{}`",
+                        i, self.p.types[tp as usize].name
+                    )))
+                }
+            },
+            _ => return Ok(()),
+        };
+        let parts = match v {
+            Value::Vec(xs) | Value::Struct(xs) | Value::Enum(_, xs) => xs,
+            _ => return Ok(()),
+        };
+        for (x, t) in parts.into_iter().zip(types) {
+            self.drop(x, t)?;
+        }
+        return Ok(());
+    }
+
+    fn print(&mut self, v: &Value, kind: PrintKind) -> Result<(), Panic> {
+        let mut bytes = shown(v, kind)?;
+        bytes.push(b'\n');
+        self.out.write_all(&bytes).ok();
+        return Ok(());
+    }
+
+    fn var_tp(&self, f: &Function, v: Var) -> u32 {
+        return match v {
+            Var::Local(x) => f.locals[x as usize],
+            Var::Global(x) => self.p.globals[x as usize].1,
+        };
+    }
+
+    //the place, a variable not set yet made zero first as in C
+    fn place<'s>(
+        &'s mut self,
+        slots: &'s mut [Option<Value>],
+        f: &Function,
+        place: &Place,
+        idx: &[i128],
+    ) -> Result<&'s mut Value, Panic> {
+        if cell(&mut self.globals, slots, place.var).is_none() {
+            let z = self.zero(self.var_tp(f, place.var));
+            *cell(&mut self.globals, slots, place.var) = Some(z);
+        }
+        let v = cell(&mut self.globals, slots, place.var).as_mut().unwrap();
+        return walk(v, place, idx);
+    }
+
+    //the value of a variable, zero if not set
+    fn load(&self, slots: &[Option<Value>], f: &Function, v: Var) -> Value {
+        let x = match v {
+            Var::Local(x) => &slots[x as usize],
+            Var::Global(x) => &self.globals[x as usize],
+        };
+        return match x {
+            Some(x) => x.clone(),
+            None => self.zero(self.var_tp(f, v)),
+        };
+    }
+
+    //a frame of function f with args in its first slots, counted in depth
+    fn frame(&mut self, f: u32, args: Vec<Value>) -> Result<Frame<'a>, Panic> {
+        if self.depth == MAX_DEPTH {
+            return Err("stack overflow, too deep a recursion".to_string());
+        }
+        self.depth += 1;
+        let func = &self.p.functions[f as usize];
+        let mut slots: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+        slots.resize(func.locals.len(), None);
+        return Ok(Frame {
+            func,
+            slots,
+            stack: Vec::new(),
+            pc: 0,
+        });
+    }
+
+    //run function f with args in its first slots, to its Return
+    fn call(&mut self, f: u32, args: Vec<Value>) -> Result<Value, Panic> {
+        let depth = self.depth;
+        let ended = self.run_frames(f, args);
+        self.depth = depth;
+        return ended;
+    }
+
+    //the calls f makes push frames on a stack of their own, so recursion in
+    //the program is not recursion of the VM
+    fn run_frames(&mut self, f: u32, args: Vec<Value>) -> Result<Value, Panic> {
+        let p = self.p;
+        let mut frames: Vec<Frame<'a>> = vec![self.frame(f, args)?];
+
+        loop {
+            let fr = frames.last_mut().unwrap();
+            let func = fr.func;
+            let (slots, stack) = (&mut fr.slots, &mut fr.stack);
+            let op = match func.code.get(fr.pc) {
+                Some(x) => *x,
+                None => Op::Return,
+            };
+            if fr.pc == func.code.len() {
+                stack.push(Value::Void);
+            }
+            fr.pc += 1;
+            match op {
+                Op::Const(c) => stack.push(match &p.consts[c as usize] {
+                    Const::Int(x) => Value::Int(*x),
+                    Const::Bool(x) => Value::Bool(*x),
+                    Const::Str(s) => Value::Str(s.clone()),
+                }),
+                Op::Pop => {
+                    pop(stack)?;
+                }
+                Op::Dup => {
+                    let x = stack.last().unwrap().clone();
+                    stack.push(x);
+                }
+                Op::Load(v) => stack.push(self.load(slots, func, v)),
+                Op::Move(v) => {
+                    let x = match cell(&mut self.globals, slots, v).take() {
+                        Some(x) => x,
+                        None => self.zero(self.var_tp(func, v)),
+                    };
+                    stack.push(x);
+                }
+                Op::Store(v) => {
+                    let x = pop(stack)?;
+                    *cell(&mut self.globals, slots, v) = Some(x);
+                }
+                Op::Assign(v) => {
+                    //the old value goes away once the new one is there
+                    let x = pop(stack)?;
+                    if let Some(old) = cell(&mut self.globals, slots, v).replace(x) {
+                        self.drop(old, self.var_tp(func, v))?;
+                    }
+                }
+                Op::Drop(v) => {
+                    if let Some(x) = cell(&mut self.globals, slots, v).take() {
+                        self.drop(x, self.var_tp(func, v))?;
+                    }
+                }
+                Op::DropFields(v) => {
+                    if let Some(x) = cell(&mut self.globals, slots, v).take() {
+                        self.drop_fields(x, self.var_tp(func, v))?;
+                    }
+                }
+                Op::Inc(v) => {
+                    if let Some(Value::Int(x)) = cell(&mut self.globals, slots, v) {
+                        *x += 1;
+                    }
+                }
+                Op::GetPlace(i) => {
+                    let place = &func.places[i as usize];
+                    let idx = indices(stack, place)?;
+                    let x = self.place(slots, func, place, &idx)?.clone();
+                    stack.push(x);
+                }
+                Op::SetPlace(i, tp) => {
+                    let place = &func.places[i as usize];
+                    let idx = indices(stack, place)?;
+                    let x = pop(stack)?;
+                    let old = std::mem::replace(self.place(slots, func, place, &idx)?, x);
+                    self.drop(old, tp)?;
+                }
+                Op::PushPlace(i) => {
+                    let place = &func.places[i as usize];
+                    let idx = indices(stack, place)?;
+                    let x = pop(stack)?;
+                    match self.place(slots, func, place, &idx)? {
+                        Value::Vec(xs) => xs.push(x),
+                        v => return Err(corrupt(format!("push to {:?}", v))),
+                    }
+                    stack.push(Value::Void);
+                }
+                Op::LenPlace(i) => {
+                    let place = &func.places[i as usize];
+                    let idx = indices(stack, place)?;
+                    let n = len(self.place(slots, func, place, &idx)?)?;
+                    stack.push(Value::Int(n as i128));
+                }
+                Op::Zero(tp) => stack.push(self.zero(tp)),
+                Op::SetField(i) => {
+                    let x = pop(stack)?;
+                    match stack.last_mut() {
+                        Some(Value::Struct(fields)) if (i as usize) < fields.len() => {
+                            fields[i as usize] = x
+                        }
+                        v => return Err(corrupt(format!("field {} of {:?}", i, v))),
+                    }
+                }
+                Op::MakeVec(n) => {
+                    let xs = stack.split_off(stack.len() - n as usize);
+                    stack.push(Value::Vec(xs));
+                }
+                Op::MakeEnum(i, n) => {
+                    let xs = stack.split_off(stack.len() - n as usize);
+                    stack.push(Value::Enum(i as usize, xs));
+                }
+                Op::Field(i) => match pop(stack)? {
+                    Value::Struct(mut fields) if (i as usize) < fields.len() => {
+                        stack.push(fields.swap_remove(i as usize))
+                    }
+                    v => return Err(corrupt(format!("field {} of {:?}", i, v))),
+                },
+                Op::Index => {
+                    let i = int(&pop(stack)?)?;
+                    match pop(stack)? {
+                        Value::Vec(mut xs) if i >= 0 && i < xs.len() as i128 => {
+                            stack.push(xs.swap_remove(i as usize))
+                        }
+                        _ => return Err("index out of bounds".to_string()),
+                    }
+                }
+                Op::Len => {
+                    let n = len(&pop(stack)?)?;
+                    stack.push(Value::Int(n as i128));
+                }
+                Op::Bin(b, t) => {
+                    let r = int(&pop(stack)?)?;
+                    let l = int(&pop(stack)?)?;
+                    let x = int_op(b.symbol(), l, r, t.bits as u32, t.signed)?;
+                    stack.push(Value::Int(x));
+                }
+                Op::Cmp(c) => {
+                    let r = pop(stack)?;
+                    let l = pop(stack)?;
+                    let x = match (&l, &r) {
+                        (Value::Str(a), Value::Str(b)) => match c {
+                            CmpOp::Eq => a == b,
+                            _ => a != b,
+                        },
+                        _ => {
+                            let (l, r) = (int(&l)?, int(&r)?);
+                            match c {
+                                CmpOp::Eq => l == r,
+                                CmpOp::Ne => l != r,
+                                CmpOp::Lt => l < r,
+                                CmpOp::Gt => l > r,
+                                CmpOp::Le => l <= r,
+                                CmpOp::Ge => l >= r,
+                            }
+                        }
+                    };
+                    stack.push(Value::Bool(x));
+                }
+                Op::Neg(t) => {
+                    let x = int(&pop(stack)?)?;
+                    let x = wrap_bits(x.wrapping_neg(), t.bits as u32, t.signed);
+                    stack.push(Value::Int(x));
+                }
+                Op::BitNot(t) => {
+                    let x = int(&pop(stack)?)?;
+                    stack.push(Value::Int(wrap_bits(!x, t.bits as u32, t.signed)));
+                }
+                Op::Not => {
+                    let x = boolean(&pop(stack)?)?;
+                    stack.push(Value::Bool(!x));
+                }
+                Op::Concat => match (pop(stack)?, pop(stack)?) {
+                    (Value::Str(r), Value::Str(l)) => stack.push(Value::Str(l + &r)),
+                    v => return Err(corrupt(format!("concat of {:?}", v))),
+                },
+                Op::Test(v, i) => {
+                    let x = self.load(slots, func, v);
+                    stack.push(Value::Bool(matches_pat(&func.pats[i as usize], &x)));
+                }
+                Op::Jump(x) => fr.pc = x as usize,
+                Op::JumpIfFalse(x) => {
+                    if !boolean(&pop(stack)?)? {
+                        fr.pc = x as usize;
+                    }
+                }
+                Op::Call(g, n) => {
+                    let args = stack.split_off(stack.len() - n as usize);
+                    let callee = self.frame(g, args)?;
+                    frames.push(callee);
+                }
+                Op::Return => {
+                    let x = pop(stack)?;
+                    frames.pop();
+                    self.depth -= 1;
+                    match frames.last_mut() {
+                        Some(caller) => caller.stack.push(x),
+                        None => return Ok(x),
+                    }
+                }
+                Op::Print(kind) => {
+                    let x = pop(stack)?;
+                    self.print(&x, kind)?;
+                    stack.push(Value::Void);
+                }
+                Op::Assert(c) => {
+                    if !boolean(&pop(stack)?)? {
+                        return match &p.consts[c as usize] {
+                            Const::Str(s) => Err(s.clone()),
+                            _ => unreachable!(),
+                        };
+                    }
+                    stack.push(Value::Void);
+                }
+                Op::AssertEq(c, kind) => {
+                    let r = pop(stack)?;
+                    let l = pop(stack)?;
+                    let same = match (&l, &r) {
+                        (Value::Str(a), Value::Str(b)) => a == b,
+                        _ => int(&l)? == int(&r)?,
+                    };
+                    if !same {
+                        let msg = match &p.consts[c as usize] {
+                            Const::Str(s) => s,
+                            _ => unreachable!(),
+                        };
+                        let (l, r) = (shown(&l, kind)?, shown(&r, kind)?);
+                        let (l, r) = (String::from_utf8_lossy(&l), String::from_utf8_lossy(&r));
+                        return Err(assert_eq_message(msg, &l, &r));
+                    }
+                    stack.push(Value::Void);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::bytecode::Program;
+    use super::super::{bcgen, check_modules, interp, Lints, Target};
+
+    //what src prints and how it ends run from its bytecode
+    fn vm(src: &str) -> (String, Result<(), String>) {
+        let modules = [(String::new(), src.to_string())];
+        let (checked, diags) = check_modules(&modules, Target::host(), 1, Lints::NONE, None);
+        let (root, table) = checked.unwrap_or_else(|| panic!("{:?}", diags[0].msg));
+
+        //through the file format, as rfx run does
+        let bytes = bcgen::compile(&root, &table).to_bytes();
+        let program = Program::from_bytes(&bytes).unwrap();
+        let mut out: Vec<u8> = Vec::new();
+        let ended = super::run(&program, &mut out);
+        return (String::from_utf8(out).unwrap(), ended);
+    }
+
+    //what src prints and how it ends, interpreted and run from its bytecode
+    fn both(src: &str) -> [(String, Result<(), String>); 2] {
+        let modules = [(String::new(), src.to_string())];
+        let (checked, diags) = check_modules(&modules, Target::host(), 1, Lints::NONE, None);
+        let (root, table) = checked.unwrap_or_else(|| panic!("{:?}", diags[0].msg));
+        let mut out: Vec<u8> = Vec::new();
+        let ended = interp::run(&root, &table, &mut out);
+        let interpreted = (String::from_utf8(out).unwrap(), ended);
+        return [interpreted, vm(src)];
+    }
+
+    #[test]
+    fn test_vm() {
+        let [want, got] = both(
+            r#"
+struct G { id: i64 }
+impl Drop for G {
+    fn drop(self) {
+        print(self.id);
+    }
+}
+fn make(id: i64) -> G {
+    return G { id: id };
+}
+enum Opt { Some(i64), None }
+fn get(i: u64) -> Opt {
+    if i < v.len() {
+        return Opt::Some(v[i]);
+    }
+    return Opt::None;
+}
+var n: i64 = 0;
+while n < 6 {
+    var g: G = make(10 + n);
+    n += 1;
+    if n == 2 {
+        continue;
+    }
+    if n == 4 {
+        break;
+    }
+}
+var v: Vec<i64> = [1, 2];
+v[v.len() - 1] += 40;
+var k: u64 = 3;
+for i in 0..k {
+    var x: i64 = match get(i) {
+        Opt::Some(x) => x,
+        Opt::None => -1,
+    };
+    print(x);
+}
+var w: Vec<G> = [make(1)];
+w[0] = make(2);
+var u: u8 = 0;
+u -= 1;
+print(u);
+"#,
+        );
+        assert_eq!(got, want);
+        assert_eq!(got.0, "10\n11\n12\n13\n1\n42\n-1\n1\n255\n2\n");
+        assert!(got.1.is_ok());
+
+        let [want, got] = both("var v: Vec<i64> = [];\nprint(1);\nv[3] = 1;\nprint(2);");
+        assert_eq!(got, want);
+        assert_eq!(
+            got,
+            ("1\n".to_string(), Err("index out of bounds".to_string()))
+        );
+
+        let [want, got] = both("var min: i64 = -9223372036854775807 - 1;\nvar neg: i64 = -1;\nprint(min / neg);\nprint(min % neg);\nvar z: u8 = 0;\nprint(1 / z);");
+        assert_eq!(got, want);
+        assert_eq!(
+            got,
+            (
+                "-9223372036854775808\n0\n".to_string(),
+                Err("division by zero".to_string())
+            )
+        );
+
+        let [want, got] =
+            both("var c: char = 'x';\nassert_eq(c, 'x');\nvar u: u64 = 0;\nassert_eq(u - 1, 1);");
+        assert_eq!(got, want);
+        let msg =
+            "assertion `left == right` failed at 4:1\n  left: 18446744073709551615\n right: 1";
+        assert_eq!(got, (String::new(), Err(msg.to_string())));
+
+        //recursion takes frames, not the stack of the VM
+        let (out, ended) = vm("fn r(n: u64) -> u64 {\n    if n == 0 {\n        return 0;\n    }\n    return r(n - 1) + 1;\n}\nprint(r(100000));");
+        assert_eq!((out.as_str(), ended), ("100000\n", Ok(())));
+        let (_, ended) = vm("fn r(n: u64) -> u64 {\n    return r(n + 1);\n}\nprint(r(0));");
+        assert_eq!(
+            ended,
+            Err("stack overflow, too deep a recursion".to_string())
+        );
+    }
+
+    #[test]
+    fn test_corrupt() {
+        use super::super::bytecode::Op;
+
+        let src = "struct P { a: i64, b: bool }\nvar p: P = P { a: 1, b: true };\nprint(p.a);\nprint(P { a: 2, b: false }.a);";
+        let modules = [(String::new(), src.to_string())];
+        let (checked, diags) = check_modules(&modules, Target::host(), 1, Lints::NONE, None);
+        let (root, table) = checked.unwrap_or_else(|| panic!("{:?}", diags[0].msg));
+        let bytes = bcgen::compile(&root, &table).to_bytes();
+
+        //a field past the end of the struct, and the field of what is no
+        //struct, pass the checks of loading but end the run with an error
+        let corruptions: [fn(&mut Op) -> bool; 2] = [
+            |op| match op {
+                Op::Field(i) | Op::SetField(i) => {
+                    *i = 99;
+                    true
+                }
+                _ => false,
+            },
+            |op| match op {
+                Op::Field(_) => {
+                    *op = Op::Len;
+                    true
+                }
+                _ => false,
+            },
+        ];
+        for corrupt in corruptions {
+            let mut program = Program::from_bytes(&bytes).unwrap();
+            let mut changed = false;
+            for f in program.functions.iter_mut() {
+                for op in f.code.iter_mut() {
+                    changed |= corrupt(op);
+                }
+            }
+            assert!(changed);
+            let program = Program::from_bytes(&program.to_bytes()).unwrap();
+            let mut out: Vec<u8> = Vec::new();
+            let ended = super::run(&program, &mut out);
+            assert!(ended.unwrap_err().starts_with("corrupt bytecode, "));
+        }
+    }
+}
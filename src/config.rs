@@ -0,0 +1,217 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::port::{home_path, NO_HOME, SETTING_FILE};
+use super::setting::{self, Settings, Value, KEYS};
+
+/*
+`rfx config list | get KEY | set KEY VALUE | unset KEY`. list and get show
+the effective settings and where each comes from; set and unset edit
+~/.rfx/setting.json, or with --project .rfx/setting.json of the project.
+the file is replaced atomically and keys rfx does not know are kept
+*/
+pub fn config(args: &[String], project: bool) -> i32 {
+    let arg = |i: usize| args.get(i).map(|x| x.as_str());
+    let ret = match (arg(0), args.len()) {
+        (Some("list"), 1) => {
+            list();
+            Ok(())
+        }
+        (Some("get"), 2) => get(&args[1]),
+        (Some("set"), 3) => target_file(project).and_then(|f| set(&f, &args[1], &args[2])),
+        (Some("unset"), 2) => target_file(project).and_then(|f| edit(&f, &args[1], None)),
+        _ => Err(
+            "usage: rfx config [--project] list | get KEY | set KEY VALUE | unset KEY".to_string(),
+        ),
+    };
+    return match ret {
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
+        Ok(()) => 0,
+    };
+}
+
+//the file set and unset write
+fn target_file(project: bool) -> Result<PathBuf, String> {
+    if !project {
+        return home_path(SETTING_FILE)
+            .map(PathBuf::from)
+            .ok_or(NO_HOME.to_string());
+    }
+    return Ok(setting::project_file().unwrap_or_else(|| Path::new(".rfx").join("setting.json")));
+}
+
+fn load() -> Settings {
+    let (s, problems) = Settings::load();
+    for p in problems.iter() {
+        warn!("{}", p.msg);
+    }
+    return s;
+}
+
+fn list() {
+    let s = load();
+    for key in KEYS.iter() {
+        match s.get(key.name) {
+            None => println!("{} is not set", key.name),
+            Some((v, origin)) => println!("{} = {}    ({})", key.name, v, origin),
+        }
+    }
+}
+
+fn get(name: &str) -> Result<(), String> {
+    if setting::key(name).is_none() {
+        return Err(format!("unknown key `{}`", name));
+    }
+    return match load().get(name) {
+        None => Err(format!("{} is not set", name)),
+        Some((v, origin)) => {
+            println!("{}", v);
+            info!("{} comes from {}", name, origin);
+            Ok(())
+        }
+    };
+}
+
+fn set(path: &Path, name: &str, value: &str) -> Result<(), String> {
+    let key = setting::key(name).ok_or_else(|| format!("unknown key `{}`", name))?;
+    let v = setting::parse_value(key, value)?;
+    if key.name == "DEFAULT_CC" && find_executable(value).is_none() {
+        return Err(format!("C compiler `{}` not found", value));
+    }
+    edit(path, name, Some(&v))?;
+    for x in shadowing(&setting::files(), path, name) {
+        warn!("{} overrides this setting", x);
+    }
+    return Ok(());
+}
+
+//what sets name over the settings file at path: the files read after it, of
+//layers, that have the key, then the environment variable
+fn shadowing(layers: &[PathBuf], path: &Path, name: &str) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    let after = layers
+        .iter()
+        .position(|x| setting::same_file(x, path))
+        .map_or(layers.len(), |i| i + 1);
+    for f in layers[after..].iter() {
+        if setting::read_file(f).is_ok_and(|v| v.has_key(name)) {
+            ret.push(f.display().to_string());
+        }
+    }
+    let var = format!("RFX_{}", name);
+    if env::var(&var).is_ok() {
+        ret.push(format!("${}", var));
+    }
+    return ret;
+}
+
+//set (Some) or remove a key in the settings file at path
+pub fn edit(path: &Path, name: &str, value: Option<&Value>) -> Result<(), String> {
+    let mut v = setting::read_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    match value {
+        Some(x) => v[name] = x.to_json(),
+        None => {
+            if !v.has_key(name) {
+                return Err(format!("{} is not set in {}", name, path.display()));
+            }
+            v.remove(name);
+        }
+    }
+    return write_atomic(path, &(json::stringify_pretty(v, 4) + "\n"))
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e));
+}
+
+//written aside and renamed, so the file is never seen half written
+fn write_atomic(path: &Path, data: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut part = path.as_os_str().to_owned();
+    part.push(format!(".{}", std::process::id()));
+    fs::write(&part, data)?;
+    if let Err(e) = fs::rename(&part, path) {
+        let _ = fs::remove_file(&part);
+        return Err(e);
+    }
+    return Ok(());
+}
+
+//cmd names an executable file, or a program found in $PATH
+fn find_executable(cmd: &str) -> Option<PathBuf> {
+    let is_exe = |p: &Path| {
+        fs::metadata(p)
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+    if cmd.contains('/') {
+        return match is_exe(Path::new(cmd)) {
+            true => Some(PathBuf::from(cmd)),
+            false => None,
+        };
+    }
+    for dir in env::split_paths(&env::var_os("PATH")?) {
+        let p = dir.join(cmd);
+        if is_exe(&p) {
+            return Some(p);
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit, find_executable, set, shadowing};
+    use std::fs;
+
+    #[test]
+    fn test_edit() {
+        let dir = std::env::temp_dir().join(format!("rfx_config_test_{}", std::process::id()));
+        let path = dir.join(".rfx").join("setting.json");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"{"COLOR": true, "DEFAULT_CC": "gcc"}"#).unwrap();
+
+        set(&path, "CC_FLAGS", "-O2 -g").unwrap();
+        edit(&path, "DEFAULT_CC", None).unwrap();
+        let v = json::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(v["COLOR"], true);
+        assert_eq!(v["CC_FLAGS"][1], "-g");
+        assert!(!v.has_key("DEFAULT_CC"));
+
+        assert!(edit(&path, "DEFAULT_CC", None).is_err());
+        assert!(set(&path, "DEFAULT_TARGET", "12").is_err());
+        assert!(set(&path, "NO_SUCH_KEY", "1").is_err());
+        assert!(set(&path, "DEFAULT_CC", "/no/such/cc").is_err());
+        fs::write(&path, "{ broken").unwrap();
+        assert!(set(&path, "WARNINGS", "deny").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ broken");
+
+        //a file read later with the key shadows the one written
+        let user = dir.join("user.json");
+        let xdg = dir.join("xdg.json");
+        fs::write(&user, "{}").unwrap();
+        fs::write(&xdg, r#"{"CC_FLAGS": ["-O1"]}"#).unwrap();
+        let layers = [user.clone(), xdg.clone(), path.clone()];
+        assert_eq!(
+            shadowing(&layers, &user, "CC_FLAGS"),
+            vec![xdg.display().to_string()]
+        );
+        assert!(shadowing(&layers, &user, "COLOR").is_empty());
+        assert!(shadowing(&layers, &xdg, "CC_FLAGS").is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_executable() {
+        assert!(find_executable("sh").is_some());
+        assert!(find_executable("/bin/sh").is_some());
+        assert!(find_executable("no-such-program-rfx").is_none());
+    }
+}
@@ -0,0 +1,34 @@
+use std::fs;
+use std::path::Path;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::compile::{self, DocFormat};
+
+/*
+`rfx doc`: write a page of documentation per file into outdir, made if
+missing. nothing is written if a file does not parse. returns the exit code
+*/
+pub fn doc(files: &[String], outdir: &Path, format: DocFormat) -> i32 {
+    let (pages, log) = compile::document(files, format);
+    eprint!("{}", log);
+    let pages = match pages {
+        None => return 1,
+        Some(x) => x,
+    };
+
+    if let Err(e) = fs::create_dir_all(outdir) {
+        error!("cannot create {}: {}", outdir.display(), e);
+        return 1;
+    }
+    for (name, page) in pages.iter() {
+        let out = outdir.join(name);
+        if let Err(e) = fs::write(&out, page) {
+            error!("cannot write {}: {}", out.display(), e);
+            return 1;
+        }
+        info!("wrote {}", out.display());
+    }
+    return 0;
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::io::{self, Read};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::compile;
+
+/*
+`rfx fmt`: format the files in place. with check nothing is written, the
+files that would change are listed and make it fail, for CI. without files
+stdin is formatted to stdout. a file that does not parse is left alone and
+its errors printed. returns the exit code
+*/
+pub fn fmt(files: &[String], check: bool, line_width: usize) -> i32 {
+    if files.is_empty() {
+        let mut src = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut src) {
+            error!("cannot read stdin: {}", e);
+            return 1;
+        }
+        return match compile::format_source("<stdin>", &src, line_width) {
+            Err(log) => {
+                eprint!("{}", log);
+                1
+            }
+            Ok(x) if check => match x == src {
+                true => 0,
+                false => {
+                    println!("<stdin>");
+                    1
+                }
+            },
+            Ok(x) => {
+                print!("{}", x);
+                0
+            }
+        };
+    }
+
+    let mut failed = false;
+    for f in files.iter() {
+        let src = match fs::read_to_string(f) {
+            Err(e) => {
+                error!("cannot read {}: {}", f, e);
+                failed = true;
+                continue;
+            }
+            Ok(x) => x,
+        };
+        let formatted = match compile::format_source(f, &src, line_width) {
+            Err(log) => {
+                eprint!("{}", log);
+                failed = true;
+                continue;
+            }
+            Ok(x) => x,
+        };
+        if formatted == src {
+            continue;
+        }
+        if check {
+            println!("{}", f);
+            failed = true;
+            continue;
+        }
+        match fs::write(f, formatted) {
+            Err(e) => {
+                error!("cannot write {}: {}", f, e);
+                failed = true;
+            }
+            Ok(()) => info!("formatted {}", f),
+        }
+    }
+    return failed as i32;
+}
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use json::{object, JsonValue};
+
+use super::compile::{Analysis, Completion, Pos, Symbol, SymbolKind, Target, Warnings};
+
+/*
+`rfx lsp`: a language server speaking LSP over stdin and stdout. an open
+document is checked as it changes and its diagnostics published; it answers
+
+    textDocument/definition      where the name under the cursor is declared
+    textDocument/hover           its declaration or the type of the expression
+    textDocument/documentSymbol  items, their members and the variables
+    textDocument/completion      names in scope and of the symbol table
+
+documents are synced whole. LSP counts characters in UTF-16 units from 0,
+rfx counts chars from 1; positions are converted with the text of the line
+*/
+
+//JSON-RPC errors
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const NOT_INITIALIZED: i32 = -32002;
+
+//a message, or None at the end of the input. Err(msg) if it is not one
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Result<JsonValue, String>>> {
+    let mut len: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse().ok();
+            }
+        }
+    }
+    let len = match len {
+        None => return Ok(Some(Err("no Content-Length".to_string()))),
+        Some(x) => x,
+    };
+    let mut body = vec![0u8; len];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+    return Ok(Some(json::parse(&body).map_err(|e| e.to_string())));
+}
+
+fn write_message(out: &mut dyn Write, msg: &JsonValue) -> io::Result<()> {
+    let body = msg.dump();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return out.flush();
+}
+
+fn response(id: &JsonValue, result: JsonValue) -> JsonValue {
+    return object! { jsonrpc: "2.0", id: id.clone(), result: result };
+}
+
+fn error_response(id: &JsonValue, code: i32, msg: &str) -> JsonValue {
+    return object! {
+        jsonrpc: "2.0",
+        id: id.clone(),
+        error: { code: code, message: msg },
+    };
+}
+
+fn notification(method: &str, params: JsonValue) -> JsonValue {
+    return object! { jsonrpc: "2.0", method: method, params: params };
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn line(&self, line: u64) -> &str {
+        return self
+            .text
+            .split('\n')
+            .nth(line.wrapping_sub(1) as usize)
+            .unwrap_or("");
+    }
+
+    //an LSP position to a rfx one
+    fn pos(&self, p: &JsonValue) -> Pos {
+        let line = p["line"].as_u64().unwrap_or(0) + 1;
+        let mut units = p["character"].as_u64().unwrap_or(0);
+        let mut col = 1;
+        for c in self.line(line).chars() {
+            if units < c.len_utf16() as u64 {
+                break;
+            }
+            units -= c.len_utf16() as u64;
+            col += 1;
+        }
+        return (line, col);
+    }
+
+    //a rfx position to an LSP one
+    fn position(&self, (line, col): Pos) -> JsonValue {
+        let units: usize = self
+            .line(line)
+            .chars()
+            .take(col.saturating_sub(1) as usize)
+            .map(|c| c.len_utf16())
+            .sum();
+        return object! { line: line.saturating_sub(1), character: units };
+    }
+
+    fn range(&self, start: Pos, end: Pos) -> JsonValue {
+        return object! { start: self.position(start), end: self.position(end) };
+    }
+
+    //the word at pos, or the char if it is not in one
+    fn word(&self, (line, col): Pos) -> JsonValue {
+        let chars: Vec<char> = self.line(line).chars().collect();
+        let ident = |c: &char| c.is_alphanumeric() || *c == '_';
+        let from = col.saturating_sub(1) as usize;
+        let len = chars.iter().skip(from).take_while(|c| ident(c)).count();
+        return self.range((line, col), (line, col + len.max(1) as u64));
+    }
+
+    fn diagnostics(&self) -> JsonValue {
+        let mut out = JsonValue::new_array();
+        for d in self.analysis.diags.iter() {
+            let severity = match d.is_error() {
+                true => 1,
+                false => 2,
+            };
+            let range = match d.line {
+                0 => self.range((1, 1), (1, 1)),
+                _ => self.word((d.line, d.col)),
+            };
+            let _ = out.push(object! {
+                range: range,
+                severity: severity,
+                source: "rfx",
+                message: d.msg.clone(),
+            });
+        }
+        return out;
+    }
+
+    fn symbol(&self, s: &Symbol) -> JsonValue {
+        let mut children = JsonValue::new_array();
+        for c in s.children.iter() {
+            let _ = children.push(self.symbol(c));
+        }
+        return object! {
+            name: s.name.clone(),
+            detail: s.detail.clone(),
+            kind: symbol_kind(s.kind),
+            range: self.range(s.start, s.end),
+            selectionRange: self.range(s.at, (s.at.0, s.at.1 + s.len)),
+            children: children,
+        };
+    }
+}
+
+fn symbol_kind(k: SymbolKind) -> u32 {
+    return match k {
+        SymbolKind::Function => 12,
+        SymbolKind::Method => 6,
+        SymbolKind::Trait => 11,
+        SymbolKind::Impl => 5,
+        SymbolKind::Struct => 23,
+        SymbolKind::Enum => 10,
+        SymbolKind::Alias | SymbolKind::Type => 26,
+        SymbolKind::Variable | SymbolKind::Argument => 13,
+        SymbolKind::Constant => 14,
+        SymbolKind::Field => 8,
+        SymbolKind::Variant => 22,
+    };
+}
+
+fn completion_kind(k: SymbolKind) -> u32 {
+    return match k {
+        SymbolKind::Function => 3,
+        SymbolKind::Method => 2,
+        SymbolKind::Trait => 8,
+        SymbolKind::Impl | SymbolKind::Struct => 22,
+        SymbolKind::Enum => 13,
+        SymbolKind::Alias | SymbolKind::Type => 25,
+        SymbolKind::Variable | SymbolKind::Argument => 6,
+        SymbolKind::Constant => 21,
+        SymbolKind::Field => 5,
+        SymbolKind::Variant => 20,
+    };
+}
+
+fn completion_item(c: &Completion) -> JsonValue {
+    return object! {
+        label: c.name.clone(),
+        kind: completion_kind(c.kind),
+        detail: c.detail.clone(),
+    };
+}
+
+struct Server {
+    target: Target,
+    warnings: Warnings,
+    docs: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+}
+
+impl Server {
+    fn open(&mut self, uri: &str, text: String) -> JsonValue {
+        let analysis = Analysis::new(&text, self.target, self.warnings);
+        let doc = Document { text, analysis };
+        let diags = doc.diagnostics();
+        self.docs.insert(uri.to_string(), doc);
+        return notification(
+            "textDocument/publishDiagnostics",
+            object! { uri: uri, diagnostics: diags },
+        );
+    }
+
+    //the answer to a request, Err a JSON-RPC error
+    fn request(&mut self, method: &str, params: &JsonValue) -> Result<JsonValue, (i32, String)> {
+        if method == "initialize" {
+            self.initialized = true;
+            return Ok(object! {
+                capabilities: {
+                    textDocumentSync: 1,
+                    definitionProvider: true,
+                    hoverProvider: true,
+                    documentSymbolProvider: true,
+                    completionProvider: { triggerCharacters: [".", ":"] },
+                },
+                serverInfo: { name: "rfx", version: env!("CARGO_PKG_VERSION") },
+            });
+        }
+        if !self.initialized {
+            return Err((NOT_INITIALIZED, "not initialized".to_string()));
+        }
+        if method == "shutdown" {
+            self.shutdown = true;
+            return Ok(JsonValue::Null);
+        }
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let doc = self.docs.get(uri);
+        let at = |doc: &Document| doc.pos(&params["position"]);
+        return match (method, doc) {
+            ("textDocument/definition", Some(doc)) => Ok(match doc.analysis.definition(at(doc)) {
+                None => JsonValue::Null,
+                Some(pos) => object! { uri: uri, range: doc.word(pos) },
+            }),
+            ("textDocument/hover", Some(doc)) => Ok(match doc.analysis.hover(at(doc)) {
+                None => JsonValue::Null,
+                Some(text) => object! {
+                    contents: {
+                        kind: "markdown",
+                        value: format!("```rfx\n{}\n```", text),
+                    },
+                },
+            }),
+            ("textDocument/documentSymbol", Some(doc)) => {
+                let mut out = JsonValue::new_array();
+                for s in doc.analysis.symbols().iter() {
+                    let _ = out.push(doc.symbol(s));
+                }
+                Ok(out)
+            }
+            ("textDocument/completion", Some(doc)) => {
+                let mut out = JsonValue::new_array();
+                for c in doc.analysis.completions(at(doc)).iter() {
+                    let _ = out.push(completion_item(c));
+                }
+                Ok(out)
+            }
+            //a document that is not open has nothing to tell
+            (
+                "textDocument/definition"
+                | "textDocument/hover"
+                | "textDocument/documentSymbol"
+                | "textDocument/completion",
+                None,
+            ) => Ok(JsonValue::Null),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        };
+    }
+
+    //the messages a notification makes us send
+    fn notify(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                return vec![self.open(uri, text.to_string())];
+            }
+            "textDocument/didChange" => {
+                let changes = &params["contentChanges"];
+                match changes.members().last().and_then(|x| x["text"].as_str()) {
+                    None => return Vec::new(),
+                    Some(text) => return vec![self.open(uri, text.to_string())],
+                }
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(uri);
+                let params = object! { uri: uri, diagnostics: [] };
+                return vec![notification("textDocument/publishDiagnostics", params)];
+            }
+            _ => return Vec::new(),
+        }
+    }
+}
+
+//serve the messages of input until `exit`. returns the exit code: 0 if it
+//was asked to shut down first
+fn serve_on(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    target: Target,
+    warnings: Warnings,
+) -> i32 {
+    let mut server = Server {
+        target,
+        warnings,
+        docs: HashMap::new(),
+        initialized: false,
+        shutdown: false,
+    };
+    loop {
+        let msg = match read_message(input) {
+            Err(e) => {
+                error!("cannot read a message: {}", e);
+                return 1;
+            }
+            Ok(None) => return 1,
+            Ok(Some(Err(e))) => {
+                let reply = error_response(&JsonValue::Null, PARSE_ERROR, &e);
+                if write_message(out, &reply).is_err() {
+                    return 1;
+                }
+                continue;
+            }
+            Ok(Some(Ok(x))) => x,
+        };
+        let method = msg["method"].as_str().unwrap_or("");
+        debug!("lsp: {}", method);
+        if method == "exit" {
+            return match server.shutdown {
+                true => 0,
+                false => 1,
+            };
+        }
+
+        let replies = match msg.has_key("id") {
+            true => match server.request(method, &msg["params"]) {
+                Ok(result) => vec![response(&msg["id"], result)],
+                Err((code, e)) => vec![error_response(&msg["id"], code, &e)],
+            },
+            false => server.notify(method, &msg["params"]),
+        };
+        for r in replies.iter() {
+            if let Err(e) = write_message(out, r) {
+                error!("cannot write a message: {}", e);
+                return 1;
+            }
+        }
+    }
+}
+
+//`rfx lsp`: serve stdin and stdout. returns the exit code
+pub fn serve(target: Target, warnings: Warnings) -> i32 {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    return serve_on(&mut stdin.lock(), &mut stdout.lock(), target, warnings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, serve_on};
+    use crate::compile::{Target, WarningLevel, Warnings};
+    use json::{object, JsonValue};
+
+    fn frame(msgs: &[JsonValue]) -> Vec<u8> {
+        let mut out = String::new();
+        for m in msgs.iter() {
+            let body = m.dump();
+            out += &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        }
+        return out.into_bytes();
+    }
+
+    fn request(id: u32, method: &str, params: JsonValue) -> JsonValue {
+        return object! { jsonrpc: "2.0", id: id, method: method, params: params };
+    }
+
+    #[test]
+    fn test_session() {
+        let uri = "file:///t/main.lang";
+        //the emoji is one char but two UTF-16 units
+        let text = "var s = \"\u{1F600}\"; var x: i64 = 1;\nprint(x);\nvar y: bool = x;\n";
+        let at = |line: u32, character: u32| {
+            object! {
+                textDocument: { uri: uri },
+                position: { line: line, character: character },
+            }
+        };
+        let input = frame(&[
+            request(0, "hover", at(0, 0)),
+            request(1, "initialize", object! { capabilities: {} }),
+            object! { jsonrpc: "2.0", method: "initialized", params: {} },
+            object! {
+                jsonrpc: "2.0",
+                method: "textDocument/didOpen",
+                params: {
+                    textDocument: { uri: uri, languageId: "rfx", version: 1, text: text },
+                },
+            },
+            request(2, "textDocument/hover", at(1, 6)),
+            request(3, "textDocument/definition", at(1, 6)),
+            request(
+                4,
+                "textDocument/documentSymbol",
+                object! { textDocument: { uri: uri } },
+            ),
+            request(5, "textDocument/completion", at(2, 0)),
+            request(6, "no/such", object! {}),
+            request(7, "shutdown", JsonValue::Null),
+            object! { jsonrpc: "2.0", method: "exit" },
+        ]);
+        let mut out: Vec<u8> = Vec::new();
+        let code = serve_on(
+            &mut &input[..],
+            &mut out,
+            Target::with_bits(64),
+            Warnings::new(WarningLevel::Warn),
+        );
+        assert_eq!(code, 0);
+
+        let mut replies: Vec<JsonValue> = Vec::new();
+        let mut rest = &out[..];
+        while let Some(msg) = read_message(&mut rest).unwrap() {
+            replies.push(msg.unwrap());
+        }
+        assert_eq!(replies.len(), 9);
+        assert_eq!(replies[0]["error"]["code"], -32002);
+        assert_eq!(replies[1]["result"]["capabilities"]["hoverProvider"], true);
+
+        let diags = &replies[2]["params"];
+        assert_eq!(diags["uri"], uri);
+        assert_eq!(diags["diagnostics"].len(), 1);
+        let d = &diags["diagnostics"][0];
+        assert_eq!(d["severity"], 1);
+        assert_eq!(d["range"]["start"]["line"], 2);
+        assert_eq!(d["range"]["start"]["character"], 14);
+
+        assert_eq!(
+            replies[3]["result"]["contents"]["value"],
+            "```rfx\nvar x: i64\n```"
+        );
+        let def = &replies[4]["result"]["range"];
+        assert_eq!(def["start"], object! { line: 0, character: 18 });
+        assert_eq!(def["end"], object! { line: 0, character: 19 });
+
+        let symbols: Vec<&str> = replies[5]["result"]
+            .members()
+            .map(|x| x["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(symbols, ["s", "x", "y"]);
+        let labels: Vec<&str> = replies[6]["result"]
+            .members()
+            .map(|x| x["label"].as_str().unwrap())
+            .collect();
+        assert!(labels.contains(&"x") && labels.contains(&"print"));
+        assert_eq!(replies[7]["error"]["code"], -32601);
+        assert!(replies[8]["result"].is_null());
+    }
+}
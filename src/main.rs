@@ -1,69 +1,482 @@
+#![allow(clippy::needless_return, clippy::upper_case_acronyms)]
+
 use std::path::Path;
 
 use env_logger::Env;
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
 
+mod cache;
 mod compile;
+mod config;
+mod doc;
+mod fmt;
+mod lsp;
 mod option;
+mod pool;
 mod port;
+mod project;
+mod repl;
+mod run;
 mod setting;
-
-use port::TMP_DIR;
+mod temp;
 
 fn main() {
-    let mut custom_cc: String = String::new();
-    let mut custom_ofile: String = String::new();
-    let mut source: Vec<String> = Vec::new();
-    let mut flags: option::Flags = option::Flags::INIT;
-
-    setting::init();
-    setting::read_setting(&mut custom_cc, &mut custom_ofile);
+    let mut opt = option::Options::new();
 
-    option::parse(&mut custom_cc, &mut custom_ofile, &mut source, &mut flags);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = option::parse(&args, &mut opt) {
+        eprintln!("rfx: {}\ntry `rfx --help` for the options", e);
+        std::process::exit(2);
+    }
+    if opt.flags.check(option::Flags::HELP) {
+        print!("{}", option::USAGE);
+        return;
+    }
+    if opt.flags.check(option::Flags::VERSION) {
+        println!("rfx {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
 
-    if flags.check(option::Flags::QUIET) {
+    if opt.flags.check(option::Flags::QUIET) {
         env_logger::Builder::from_env(Env::default().default_filter_or("error")).init();
-    } else if flags.check(option::Flags::VERBOSE) {
+    } else if opt.flags.check(option::Flags::VERBOSE) {
         env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     } else {
         env_logger::Builder::from_env(Env::default().default_filter_or("warn")).init();
     }
 
-    for src in source {
+    if opt.flags.check(option::Flags::CONFIG) {
+        let project = opt.flags.check(option::Flags::PROJECT);
+        std::process::exit(config::config(&opt.source, project));
+    }
+
+    //the settings fill in what the command line left out
+    let (settings, problems) = setting::Settings::load();
+    for p in problems.iter() {
+        match p.error {
+            true => error!("{}", p.msg),
+            false => warn!("{}", p.msg),
+        }
+    }
+    if problems.iter().any(|x| x.error) {
+        std::process::exit(1);
+    }
+    if !opt.flags.check(option::Flags::CUSTOM_CC) {
+        opt.cc = settings.str("DEFAULT_CC").unwrap_or_default().to_string();
+    }
+    if !opt.flags.check(option::Flags::CUSTOM_OUTFILE) {
+        opt.ofile = settings
+            .str("DEFAULT_OUT_BINARY")
+            .unwrap_or_default()
+            .to_string();
+    }
+    let quiet = opt.flags.check(option::Flags::QUIET);
+    //-q prints only errors, denied warnings are errors
+    let level = match settings.str("WARNINGS") {
+        Some("allow") => compile::WarningLevel::Allow,
+        Some("deny") => compile::WarningLevel::Deny,
+        _ if quiet => compile::WarningLevel::Allow,
+        _ => compile::WarningLevel::Warn,
+    };
+    let mut warnings = compile::Warnings::new(level);
+    if let Ok(x) = compile::Lints::named(settings.list("LINTS")) {
+        warnings.lints = x;
+    }
+    if !opt.flags.check(option::Flags::BUILD | option::Flags::FMT) {
+        opt.source = opt
+            .source
+            .iter()
+            .map(|x| find_source(x, settings.list("INCLUDE_PATHS")))
+            .collect();
+    }
+
+    let option::Options {
+        cc: custom_cc,
+        ofile: custom_ofile,
+        source,
+        run_args,
+        jobs,
+        line_width,
+        filter,
+        flags,
+    } = opt;
+
+    if flags.check(option::Flags::CLEAN_CACHE) {
+        match cache::clean() {
+            Err(e) => {
+                error!("cannot clean the cache: {}", e);
+                std::process::exit(1);
+            }
+            Ok(n) => info!("removed {} cache entries", n),
+        }
+        return;
+    }
+    let cache = match flags.check(option::Flags::NO_CACHE) {
+        true => None,
+        false => cache::Cache::open(),
+    };
+    //-m16/-m32/-m64 pick the width, then DEFAULT_TARGET, otherwise the
+    //machine rfx runs on
+    let cli_width = if flags.check(option::Flags::BIT16) {
+        Some(compile::Target::with_bits(16))
+    } else if flags.check(option::Flags::BIT32) {
+        Some(compile::Target::with_bits(32))
+    } else if flags.check(option::Flags::BIT64) {
+        Some(compile::Target::with_bits(64))
+    } else {
+        None
+    };
+    let default_width = settings
+        .num("DEFAULT_TARGET")
+        .filter(|x| [16, 32, 64].contains(x))
+        .map(compile::Target::with_bits);
+    let width = cli_width.or(default_width);
+    let target = width.unwrap_or_else(compile::Target::host);
+    let mut cc = match custom_cc.is_empty() {
+        true => compile::Cc::new(compile::DEFAULT_CC, width),
+        false => compile::Cc::new(&custom_cc, width),
+    };
+    cc.flags = settings.list("CC_FLAGS").to_vec();
+    cc.warnings = !quiet;
+
+    if flags.check(option::Flags::BUILD) {
+        if source.len() > 1 {
+            error!("build takes one package directory");
+            std::process::exit(2);
+        }
+        let dir = source.first().map(|x| x.as_str()).unwrap_or(".");
+        let ofile = match flags.check(option::Flags::CUSTOM_OUTFILE) {
+            true => Some(custom_ofile.as_str()),
+            false => None,
+        };
+        let jobs = match jobs {
+            0 => pool::available_jobs(),
+            n => n,
+        };
+        //the target of the manifest comes between the command line and
+        //DEFAULT_TARGET
+        let mut cc = cc.clone();
+        cc.target = cli_width
+            .or_else(|| project::Manifest::load(Path::new(dir)).ok()?.target)
+            .or(default_width);
+        std::process::exit(project::build(
+            Path::new(dir),
+            &cc,
+            ofile,
+            cache.as_ref(),
+            jobs,
+            flags.check(option::Flags::SAVE_TEMPS),
+            warnings,
+        ));
+    }
+    if flags.check(option::Flags::REPL) {
+        if !source.is_empty() {
+            error!("repl takes no files");
+            std::process::exit(2);
+        }
+        std::process::exit(repl::repl(
+            &cc,
+            cache.as_ref(),
+            flags.check(option::Flags::SAVE_TEMPS),
+            flags.check(option::Flags::INTERPRET),
+            warnings,
+        ));
+    }
+    if flags.check(option::Flags::LSP) {
+        if !source.is_empty() {
+            error!("lsp takes no files");
+            std::process::exit(2);
+        }
+        std::process::exit(lsp::serve(target, warnings));
+    }
+    if flags.check(option::Flags::FMT) {
+        let check = flags.check(option::Flags::CHECK);
+        std::process::exit(fmt::fmt(&source, check, line_width));
+    }
+    if source.is_empty() {
+        eprintln!("rfx: no input files\ntry `rfx --help` for the options");
+        std::process::exit(2);
+    }
+    if flags.check(option::Flags::DISASM) {
+        std::process::exit(run::disasm(&source, target, warnings));
+    }
+    if flags.check(option::Flags::DOC) {
+        let outdir = match flags.check(option::Flags::CUSTOM_OUTFILE) {
+            true => custom_ofile.as_str(),
+            false => "doc",
+        };
+        let format = match flags.check(option::Flags::MARKDOWN) {
+            true => compile::DocFormat::Markdown,
+            false => compile::DocFormat::Html,
+        };
+        std::process::exit(doc::doc(&source, Path::new(outdir), format));
+    }
+    if flags.check(option::Flags::TEST) {
+        let jobs = match jobs {
+            0 => pool::available_jobs(),
+            n => n,
+        };
+        std::process::exit(run::test(
+            &source,
+            &cc,
+            &filter,
+            jobs,
+            flags.check(option::Flags::SAVE_TEMPS),
+            warnings,
+        ));
+    }
+    //the sources are linked into one program and run, nothing is built
+    if flags.check(option::Flags::INTERPRET) {
+        let outputs = option::Flags::GENERATE_C
+            | option::Flags::GENERATE_ASM
+            | option::Flags::GENERATE_OBJ
+            | option::Flags::GENERATE_BIN
+            | option::Flags::GENERATE_TOKENS
+            | option::Flags::GENERATE_AST
+            | option::Flags::GENERATE_RFXC
+            | option::Flags::CUSTOM_OUTFILE;
+        if flags.check(outputs) {
+            eprintln!("rfx: --interpret builds nothing, it cannot be given outputs");
+            std::process::exit(2);
+        }
+        let jobs = match jobs {
+            0 => pool::available_jobs(),
+            n => n,
+        };
+        std::process::exit(run::interpret(&source, target, jobs, warnings));
+    }
+
+    //what cc makes of the C. a binary unless only C (-c) is asked for
+    let mut stages: Vec<compile::Stage> = Vec::new();
+    if flags.check(option::Flags::GENERATE_ASM) {
+        stages.push(compile::Stage::Asm);
+    }
+    if flags.check(option::Flags::GENERATE_OBJ) {
+        stages.push(compile::Stage::Obj);
+    }
+    if flags.check(option::Flags::GENERATE_BIN) {
+        stages.push(compile::Stage::Bin);
+    }
+    let front =
+        option::Flags::GENERATE_TOKENS | option::Flags::GENERATE_AST | option::Flags::GENERATE_RFXC;
+    let back = !stages.is_empty() || flags.check(option::Flags::GENERATE_C);
+    if !back && !flags.check(front) {
+        stages.push(compile::Stage::Bin);
+    }
+
+    if flags.check(option::Flags::RUN) {
+        if source.len() != 1 {
+            error!("run needs a source file");
+            std::process::exit(1);
+        }
+        //read once, the file may be a pipe like /dev/stdin
+        let bytes = match std::fs::read(&source[0]) {
+            Err(e) => {
+                error!("cannot read {}: {}", source[0], e);
+                std::process::exit(1);
+            }
+            Ok(x) => x,
+        };
+        if compile::is_bytecode(&bytes) {
+            std::process::exit(run::run_bytecode(&source[0], bytes));
+        }
+        std::process::exit(run::run(
+            &source[0],
+            bytes,
+            &cc,
+            &run_args,
+            cache.as_ref(),
+            flags.check(option::Flags::SAVE_TEMPS),
+            warnings,
+        ));
+    }
+    //sources building one binary named by -o are linked into one program,
+    //otherwise each is built on its own
+    let link = flags.check(option::Flags::CUSTOM_OUTFILE) && stages == [compile::Stage::Bin];
+    let programs: Vec<Vec<String>> = match link {
+        true => vec![source.clone()],
+        false => source.iter().map(|x| vec![x.clone()]).collect(),
+    };
+
+    //-o names the one output; DEFAULT_OUT_BINARY only names a binary
+    let dumps = [
+        option::Flags::GENERATE_TOKENS,
+        option::Flags::GENERATE_AST,
+        option::Flags::GENERATE_RFXC,
+    ]
+    .iter()
+    .filter(|x| flags.check(**x))
+    .count();
+    let mut outputs = source.len() * dumps;
+    if !stages.is_empty() || flags.check(option::Flags::GENERATE_C) {
+        outputs += programs.len() * stages.len().max(1);
+    }
+    if flags.check(option::Flags::CUSTOM_OUTFILE) && outputs > 1 {
+        error!("-o cannot be used with more than one output file");
+        std::process::exit(1);
+    }
+    let custom_bin = !custom_ofile.is_empty() && programs.len() == 1;
+
+    //--emit=tokens/ast/rfxc, written next to each source
+    let mut failed = false;
+    for (flag, ext) in [
+        (option::Flags::GENERATE_TOKENS, "tokens"),
+        (option::Flags::GENERATE_AST, "ast"),
+        (option::Flags::GENERATE_RFXC, compile::BYTECODE_EXTENSION),
+    ] {
+        if !flags.check(flag) {
+            continue;
+        }
+        for src in source.iter() {
+            let (text, log) = match flag == option::Flags::GENERATE_RFXC {
+                true => compile::build_bytecode(std::slice::from_ref(src), target, 1, warnings),
+                false => {
+                    let (text, log) = compile::dump(src, flag == option::Flags::GENERATE_AST);
+                    (text.map(String::into_bytes), log)
+                }
+            };
+            eprint!("{}", log);
+            let text = match text {
+                None => {
+                    failed = true;
+                    continue;
+                }
+                Some(x) => x,
+            };
+            let out = match flags.check(option::Flags::CUSTOM_OUTFILE) {
+                true => custom_ofile.clone(),
+                false => Path::new(src)
+                    .with_extension(ext)
+                    .to_string_lossy()
+                    .to_string(),
+            };
+            if let Err(e) = std::fs::write(&out, text) {
+                error!("cannot write {}: {}", out, e);
+                failed = true;
+            }
+        }
+    }
+    if stages.is_empty() && !flags.check(option::Flags::GENERATE_C) {
+        if failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    //independent programs are built side by side; one program alone gets
+    //the jobs for its modules and cc runs instead
+    let jobs = match jobs {
+        0 => pool::available_jobs(),
+        n => n,
+    };
+    let inner = match programs.len() {
+        1 => jobs,
+        _ => 1,
+    };
+    //builds a program, returns whether it worked and what it has to say
+    let build = |modules: &Vec<String>| -> (bool, String) {
+        let mut temps = match temp::Temps::new(flags.check(option::Flags::SAVE_TEMPS)) {
+            Err(e) => {
+                error!("cannot create the temp directory: {}", e);
+                return (false, String::new());
+            }
+            Ok(x) => x,
+        };
+        //the first source names the outputs
+        let src = modules[0].clone();
         let src_path: &Path = Path::new(&src);
         let src_dir = match src_path.parent() {
             None => {
                 warn!("invalid file name: {}", src);
-                continue;
+                return (true, String::new());
             }
             Some(x) => x.to_str().unwrap().to_string(),
         };
         let src_stem = match src_path.file_stem() {
             None => {
                 warn!("invalid file name: {}", src);
-                continue;
+                return (true, String::new());
             }
             Some(x) => x.to_str().unwrap().to_string(),
         };
-        let src_name = match src_path.file_name() {
+        let (cfile, mut log) = if flags.check(option::Flags::GENERATE_C) {
+            let (dir, name) = match stages.is_empty() && flags.check(option::Flags::CUSTOM_OUTFILE)
+            {
+                true => split_path(&custom_ofile),
+                false => (src_dir.clone(), src_stem.clone() + ".c"),
+            };
+            compile::build_c(modules, dir, name, target, cache.as_ref(), inner, warnings)
+        } else {
+            match temps.create(".c") {
+                Err(e) => {
+                    error!("cannot create a temp file: {}", e);
+                    return (false, String::new());
+                }
+                Ok(x) => {
+                    let (dir, name) = split_path(&x.to_string_lossy());
+                    compile::build_c(modules, dir, name, target, cache.as_ref(), inner, warnings)
+                }
+            }
+        };
+        let cfile = match cfile {
             None => {
-                warn!("invalid file name: {}", src);
-                continue;
+                temps.finish(false);
+                return (false, log);
             }
-            Some(x) => x.to_str().unwrap().to_string(),
+            Some(x) => x,
         };
 
-        if flags.check(option::Flags::GENERATE_C) {
-            let mut cfile = src_stem.clone();
-            cfile.push_str(".c");
-            compile::build_c(src_name, src_dir, cfile);
-        } else {
-            compile::build_c(
-                src_name,
-                TMP_DIR.to_string(),
-                compile::random_cfile(TMP_DIR.to_string() + "/c").expect("rfx tmp dir full"),
-            );
+        let ran = pool::map(&stages, inner, |stage| {
+            let out = if flags.check(option::Flags::CUSTOM_OUTFILE)
+                || (*stage == compile::Stage::Bin && custom_bin)
+            {
+                custom_ofile.clone()
+            } else {
+                let name = src_stem.clone() + stage.extension();
+                Path::new(&src_dir).join(name).to_string_lossy().to_string()
+            };
+            compile::run_cc(&cc, *stage, &cfile, &out, &src, cache.as_ref())
+        });
+        let mut built = true;
+        for (ok, l) in ran {
+            built &= ok;
+            log += &l;
+        }
+        temps.finish(built);
+        return (built, log);
+    };
+
+    //printed in the order of the sources, however the jobs finished
+    for (built, log) in pool::map(&programs, jobs, build) {
+        eprint!("{}", log);
+        failed |= !built;
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+//a source not found where given is looked for in the INCLUDE_PATHS
+fn find_source(src: &str, include: &[String]) -> String {
+    let p = Path::new(src);
+    if p.exists() || p.is_absolute() {
+        return src.to_string();
+    }
+    for dir in include.iter() {
+        let found = Path::new(dir).join(p);
+        if found.exists() {
+            return found.to_string_lossy().to_string();
         }
     }
+    return src.to_string();
+}
+
+//directory and file name of a path given on the command line
+fn split_path(path: &str) -> (String, String) {
+    let p = Path::new(path);
+    let dir = p.parent().map(|x| x.to_string_lossy().to_string());
+    let name = p.file_name().map(|x| x.to_string_lossy().to_string());
+    return (dir.unwrap_or_default(), name.unwrap_or_default());
 }
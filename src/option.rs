@@ -1,9 +1,12 @@
-use std::env;
+use std::fs::{self, File};
+use std::io::Read;
 
 use bitflags::bitflags;
 
+use super::compile;
+
 bitflags! {
-    pub struct Flags: u32 {
+    pub struct Flags: u64 {
         const INIT = 0x0;
         const VERBOSE = 0x1;
         const QUIET = 0x2;
@@ -19,6 +22,30 @@ bitflags! {
         const BIT16 = 0x100;
         const BIT32 = 0x200;
         const BIT64 = 0x400;
+
+        const RUN = 0x800;
+        const NO_CACHE = 0x1000;
+        const CLEAN_CACHE = 0x2000;
+        const SAVE_TEMPS = 0x4000;
+
+        const GENERATE_TOKENS = 0x8000;
+        const GENERATE_AST = 0x10000;
+
+        const HELP = 0x20000;
+        const VERSION = 0x40000;
+        const BUILD = 0x80000;
+        const CONFIG = 0x100000;
+        const PROJECT = 0x200000;
+        const REPL = 0x400000;
+        const INTERPRET = 0x800000;
+        const GENERATE_RFXC = 0x1000000;
+        const DISASM = 0x2000000;
+        const LSP = 0x4000000;
+        const FMT = 0x8000000;
+        const CHECK = 0x10000000;
+        const DOC = 0x20000000;
+        const MARKDOWN = 0x40000000;
+        const TEST = 0x80000000;
     }
 }
 
@@ -28,102 +55,467 @@ impl Flags {
     }
 }
 
-pub fn parse(
-    custom_cc: &mut String,
-    custom_ofile: &mut String,
-    source: &mut Vec<String>,
-    flag: &mut Flags,
-) {
-    //arg withoout binary name
-    let args: Vec<String> = env::args().skip(1).collect();
+//what the command line asks for. cc and ofile may come filled in from the
+//settings, the command line wins
+pub struct Options {
+    pub cc: String,
+    pub ofile: String,
+    pub source: Vec<String>,
+    pub run_args: Vec<String>, // args after the file of `rfx run`
+    pub jobs: usize,           // -j, 0 is one per core
+    pub line_width: usize,     // --line-width of rfx fmt
+    pub filter: String,        // --filter of rfx test
+    pub flags: Flags,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        return Options {
+            cc: String::new(),
+            ofile: String::new(),
+            source: Vec::new(),
+            run_args: Vec::new(),
+            jobs: 1,
+            line_width: compile::DEFAULT_LINE_WIDTH,
+            filter: String::new(),
+            flags: Flags::INIT,
+        };
+    }
+}
+
+pub const USAGE: &str = "\
+usage: rfx [options] file...
+       rfx run [options] file [args...]
+       rfx build [options] [dir]    build the package of dir/rfx.json
+       rfx config [--project] list | get KEY | set KEY VALUE | unset KEY
+       rfx repl [options]           read, build and run entries one by one
+       rfx disasm [options] file... list the bytecode of .rfxc files or sources
+       rfx lsp [options]            serve the language server protocol on
+                                    stdin and stdout
+       rfx fmt [options] [file...]  format the files in place, or stdin to
+                                    stdout
+       rfx doc [options] file...    write the documentation of the files,
+                                    a page each, into doc/ or -o DIR
+       rfx test [options] file...   run the #[test] functions of the files,
+                                    each in a process of its own
+       rfx cache-clean
+
+a file starting with a `#!` line is run unless something is to be built, as
+is a .rfxc bytecode file, which the VM runs without a C compiler
+
+options:
+  -c                    generate C
+  -a                    generate assembly
+  -d                    generate an object file
+  -b                    generate a binary, the default
+      --emit=KIND,...   generate KIND: c, asm, obj, bin, tokens, ast or rfxc
+                        (bytecode)
+  -o, --output=FILE     write the output to FILE. sources building one
+                        binary are linked into one program
+      --cc=CC           C compiler to use
+  -m16, -m32, -m64      pointer width of the target. -m16 is 32 bit code
+                        for real mode, as cc builds it
+      --target=TARGET   16, 32, 64 or an x86 architecture like x86_64 or i386
+  -j, --jobs=N          build N files at once, 0 for one per core
+      --no-cache        neither use nor fill the build cache
+      --save-temps      keep intermediate files
+      --interpret       run the program with the interpreter instead of
+                        building it, also in rfx repl
+      --check           rfx fmt lists the files it would change and
+                        fails if there are any, changing nothing
+      --line-width=N    rfx fmt breaks lines longer than N, default 100
+      --markdown        rfx doc writes Markdown instead of HTML
+      --filter=NAME     rfx test only runs the tests with NAME in their name
+      --project         rfx config edits .rfx/setting.json of the project
+                        instead of ~/.rfx/setting.json
+  -v, --verbose         tell what is being done
+  -q, --quiet           only print errors
+  -h, --help            print this help
+  -V, --version         print the version of rfx
+  --                    everything after is a file
+
+short options can be combined, e.g. -cv or -j4
+";
+
+//true if the file starts with a `#!` line or is bytecode, so it is run
+//unless asked to build. only a regular file is looked into, what is read
+//from a pipe like /dev/stdin would be gone for the build
+fn is_script(path: &str) -> bool {
+    let mut head: Vec<u8> = Vec::new();
+    if !fs::metadata(path).map(|x| x.is_file()).unwrap_or(false) {
+        return false;
+    }
+    return match File::open(path) {
+        Err(_) => false,
+        Ok(f) => {
+            f.take(4).read_to_end(&mut head).is_ok()
+                && (head.starts_with(b"#!") || compile::is_bytecode(&head))
+        }
+    };
+}
+
+//pointer width named by --target. only x86: the width is all that is passed
+//to cc, and the layouts are those of x86
+fn target_flag(target: &str) -> Option<Flags> {
+    return match target {
+        "16" | "i8086" => Some(Flags::BIT16),
+        "32" | "i386" | "i486" | "i586" | "i686" | "x86" => Some(Flags::BIT32),
+        "64" | "x86_64" | "amd64" => Some(Flags::BIT64),
+        _ => None,
+    };
+}
+
+fn emit_flag(kind: &str) -> Option<Flags> {
+    return match kind {
+        "c" => Some(Flags::GENERATE_C),
+        "asm" => Some(Flags::GENERATE_ASM),
+        "obj" => Some(Flags::GENERATE_OBJ),
+        "bin" => Some(Flags::GENERATE_BIN),
+        "tokens" => Some(Flags::GENERATE_TOKENS),
+        "ast" => Some(Flags::GENERATE_AST),
+        "rfxc" => Some(Flags::GENERATE_RFXC),
+        _ => None,
+    };
+}
 
-    *flag = Flags::INIT;
+//only one width, the last one given wins
+fn set_width(opt: &mut Options, width: Flags) {
+    opt.flags.remove(Flags::BIT16 | Flags::BIT32 | Flags::BIT64);
+    opt.flags.insert(width);
+}
 
-    for (i, a) in args.iter().enumerate() {
-        match a.chars().nth(1) {
+//an option that takes a value, given by its name
+fn set_value(opt: &mut Options, name: &str, value: &str) -> Result<(), String> {
+    match name {
+        "o" | "output" => {
+            opt.flags.insert(Flags::CUSTOM_OUTFILE);
+            opt.ofile = value.to_string();
+        }
+        "cc" => {
+            opt.flags.insert(Flags::CUSTOM_CC);
+            opt.cc = value.to_string();
+        }
+        "j" | "jobs" => match value.parse::<usize>() {
+            Err(_) => return Err(format!("invalid job count `{}`", value)),
+            Ok(n) => opt.jobs = n,
+        },
+        "filter" => opt.filter = value.to_string(),
+        "line-width" => match value.parse::<usize>() {
+            Ok(n) if n > 0 => opt.line_width = n,
+            _ => return Err(format!("invalid line width `{}`", value)),
+        },
+        "m" => match value {
+            "16" | "32" | "64" => set_width(opt, target_flag(value).unwrap()),
+            _ => {
+                return Err(format!(
+                    "invalid width `-m{}`, expected 16, 32 or 64",
+                    value
+                ))
+            }
+        },
+        "target" => match target_flag(value) {
             None => {
-                continue;
+                return Err(format!(
+                    "unknown target `{}`, expected 16, 32, 64 or an x86 architecture",
+                    value
+                ))
+            }
+            Some(x) => set_width(opt, x),
+        },
+        "emit" => {
+            for kind in value.split(',') {
+                match emit_flag(kind) {
+                    None => return Err(format!("cannot emit `{}`", kind)),
+                    Some(x) => opt.flags.insert(x),
+                }
             }
+        }
+        _ => unreachable!(),
+    }
+    return Ok(());
+}
 
-            Some('-') => {
-                //option
-                let c = a.chars().nth(1);
-                if c == Some('-') {
-                    //long option
-                    match a[2..].to_lowercase().as_str() {
-                        "verbose" => {
-                            flag.set(Flags::VERBOSE, true);
-                            flag.set(Flags::QUIET, false);
-                        }
-                        "cc" => {
-                            flag.set(Flags::CUSTOM_CC, true);
-                            let cc = args.iter().nth(i + 1);
-                            if cc != None {
-                                *custom_cc = cc.unwrap().clone();
-                            } else {
-                                println!("No CC supplyed with --CC option, ignoring.");
-                            }
-                        }
-                        _ => {
-                            println!("invalid option, ignoring: {}", a);
-                        }
-                    }
-                } else if c == None {
-                    println!("invalid option, ignoring: {}", a);
-                } else {
-                    //short option
-                    match a[1..].to_lowercase().as_str() {
-                        "v" => {
-                            flag.set(Flags::VERBOSE, true);
-                        }
-                        "q" => {
-                            flag.set(Flags::QUIET, true);
-                            flag.set(Flags::VERBOSE, false);
-                        }
-
-                        "c" => {
-                            flag.set(Flags::GENERATE_C, true);
-                        }
-                        "a" => {
-                            flag.set(Flags::GENERATE_ASM, true);
-                        }
-                        "d" => {
-                            flag.set(Flags::GENERATE_OBJ, true);
-                        }
-                        "b" => {
-                            flag.set(Flags::GENERATE_BIN, true);
-                        }
-
-                        "m16" => {
-                            flag.set(Flags::BIT16, true);
-                        }
-                        "m32" => {
-                            flag.set(Flags::BIT32, true);
-                        }
-                        "m64" => {
-                            flag.set(Flags::BIT64, true);
-                        }
-
-                        "o" => {
-                            flag.set(Flags::CUSTOM_OUTFILE, true);
-                            let ofile = args.iter().nth(i + 1);
-                            if ofile != None {
-                                *custom_ofile = ofile.unwrap().clone();
-                            } else {
-                                println!("No output file supplyed with -o option, ignoring.");
-                            }
-                        }
-                        _ => {
-                            println!("invalid option, ignoring: {}", a);
-                        }
+//a switch, given by its name. false if there is no such switch
+fn set_switch(opt: &mut Options, name: &str) -> bool {
+    match name {
+        "v" | "verbose" => {
+            opt.flags.insert(Flags::VERBOSE);
+            opt.flags.remove(Flags::QUIET);
+        }
+        "q" | "quiet" => {
+            opt.flags.insert(Flags::QUIET);
+            opt.flags.remove(Flags::VERBOSE);
+        }
+        "c" => opt.flags.insert(Flags::GENERATE_C),
+        "a" => opt.flags.insert(Flags::GENERATE_ASM),
+        "d" => opt.flags.insert(Flags::GENERATE_OBJ),
+        "b" => opt.flags.insert(Flags::GENERATE_BIN),
+        "h" | "help" => opt.flags.insert(Flags::HELP),
+        "V" | "version" => opt.flags.insert(Flags::VERSION),
+        "no-cache" => opt.flags.insert(Flags::NO_CACHE),
+        "save-temps" => opt.flags.insert(Flags::SAVE_TEMPS),
+        "project" => opt.flags.insert(Flags::PROJECT),
+        "interpret" => opt.flags.insert(Flags::INTERPRET),
+        "check" => opt.flags.insert(Flags::CHECK),
+        "markdown" => opt.flags.insert(Flags::MARKDOWN),
+        _ => return false,
+    }
+    return true;
+}
+
+const SHORT_VALUES: &[char] = &['o', 'j', 'm'];
+const LONG_VALUES: &[&str] = &[
+    "output",
+    "cc",
+    "jobs",
+    "target",
+    "emit",
+    "line-width",
+    "filter",
+];
+
+//a source file, or in run mode the program's own args once it has its file
+fn push_file(opt: &mut Options, a: &str) {
+    if opt.flags.check(Flags::RUN) && !opt.source.is_empty() {
+        opt.run_args.push(a.to_string());
+        return;
+    }
+    let not_run = Flags::GENERATE_C
+        | Flags::GENERATE_ASM
+        | Flags::GENERATE_OBJ
+        | Flags::GENERATE_BIN
+        | Flags::GENERATE_TOKENS
+        | Flags::GENERATE_AST
+        | Flags::GENERATE_RFXC
+        | Flags::BUILD
+        | Flags::CONFIG
+        | Flags::DISASM
+        | Flags::FMT
+        | Flags::DOC
+        | Flags::TEST;
+    if opt.source.is_empty() && !opt.flags.check(not_run) && is_script(a) {
+        opt.flags.insert(Flags::RUN);
+    }
+    opt.source.push(a.to_string());
+}
+
+const SUBCOMMANDS: &[(&str, Flags)] = &[
+    ("run", Flags::RUN),
+    ("cache-clean", Flags::CLEAN_CACHE),
+    ("build", Flags::BUILD),
+    ("config", Flags::CONFIG),
+    ("repl", Flags::REPL),
+    ("disasm", Flags::DISASM),
+    ("lsp", Flags::LSP),
+    ("fmt", Flags::FMT),
+    ("doc", Flags::DOC),
+    ("test", Flags::TEST),
+];
+
+//the subcommand, its index in args and flag, if the first arg that is not
+//an option names one. options may come before it, e.g. `rfx -v build`
+fn subcommand(args: &[String]) -> Option<(usize, Flags)> {
+    let mut i = 0;
+    while i < args.len() {
+        let a = args[i].as_str();
+        if a == "--" {
+            return None;
+        }
+        if !a.starts_with('-') || a == "-" {
+            let flag = SUBCOMMANDS.iter().find(|x| x.0 == a)?.1;
+            return Some((i, flag));
+        }
+        //skip the value of an option that takes the next arg as it
+        let takes_next = match a.strip_prefix("--") {
+            Some(long) => LONG_VALUES.contains(&long),
+            None => a[1..]
+                .char_indices()
+                .find(|(_, c)| SHORT_VALUES.contains(c))
+                .is_some_and(|(k, _)| k + 2 == a.len()),
+        };
+        i += 1 + takes_next as usize;
+    }
+    return None;
+}
+
+/*
+args without the binary name. options may come before the subcommand. `rfx run file args...` or `rfx script args...`:
+the args after the file are the program's own and go to run_args. an option
+taking a value has it after `=` or as the next arg; short ones also right
+after the letter (-ofile, -j4). unknown options are errors
+*/
+pub fn parse(args: &[String], opt: &mut Options) -> Result<(), String> {
+    let mut i = 0;
+    let mut only_files = false;
+
+    let command = subcommand(args);
+    if let Some((_, flag)) = command {
+        opt.flags.insert(flag);
+    }
+
+    while i < args.len() {
+        let a = args[i].as_str();
+        i += 1;
+        if command.is_some_and(|(k, _)| k == i - 1) {
+            continue;
+        }
+        //the program's args, or those of a config action, may look like options
+        let positional = opt.flags.check(Flags::RUN | Flags::CONFIG) && !opt.source.is_empty();
+        if only_files || positional || !a.starts_with('-') || a == "-" {
+            push_file(opt, a);
+            continue;
+        }
+        if a == "--" {
+            only_files = true;
+            continue;
+        }
+
+        if let Some(long) = a.strip_prefix("--") {
+            //long option
+            let (name, value) = match long.split_once('=') {
+                Some((n, v)) => (n, Some(v.to_string())),
+                None => (long, None),
+            };
+            if LONG_VALUES.contains(&name) {
+                let value = match value {
+                    Some(v) => v,
+                    None if i < args.len() => {
+                        i += 1;
+                        args[i - 1].clone()
                     }
-                }
+                    None => return Err(format!("`--{}` needs a value", name)),
+                };
+                set_value(opt, name, &value)?;
+            } else if value.is_some() {
+                return Err(format!("`--{}` takes no value", name));
+            } else if !set_switch(opt, name) {
+                return Err(format!("unknown option `{}`", a));
             }
+            continue;
+        }
 
-            _ => {
-                //source file path
-                source.push(a.to_string());
+        //short options, maybe several in one arg
+        let letters: Vec<char> = a[1..].chars().collect();
+        for (k, c) in letters.iter().enumerate() {
+            if SHORT_VALUES.contains(c) {
+                let rest: String = letters[k + 1..].iter().collect();
+                let value = match rest.is_empty() {
+                    false => rest,
+                    true if i < args.len() => {
+                        i += 1;
+                        args[i - 1].clone()
+                    }
+                    true => return Err(format!("`-{}` needs a value", c)),
+                };
+                set_value(opt, &c.to_string(), &value)?;
+                break;
+            }
+            if !set_switch(opt, &c.to_string()) {
+                return match letters.len() {
+                    1 => Err(format!("unknown option `{}`", a)),
+                    _ => Err(format!("unknown option `-{}` in `{}`", c, a)),
+                };
             }
         }
     }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Flags, Options};
+
+    fn parse_args(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+        let mut opt = Options::new();
+        parse(&args, &mut opt)?;
+        return Ok(opt);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let opt = parse_args(&["-cv", "-j4", "--output=out.c", "m.lang", "--", "-x.lang"]).unwrap();
+        assert!(opt.flags.check(Flags::GENERATE_C) && opt.flags.check(Flags::VERBOSE));
+        assert!(opt.flags.check(Flags::CUSTOM_OUTFILE));
+        assert_eq!((opt.jobs, opt.ofile.as_str()), (4, "out.c"));
+        assert_eq!(opt.source, vec!["m.lang", "-x.lang"]);
+
+        //values are never taken for files
+        let opt = parse_args(&["--cc", "clang", "-o", "m", "-m32", "a.lang"]).unwrap();
+        assert_eq!((opt.cc.as_str(), opt.ofile.as_str()), ("clang", "m"));
+        assert_eq!(opt.source, vec!["a.lang"]);
+        assert!(opt.flags.check(Flags::BIT32));
+
+        let opt = parse_args(&["--emit=tokens,ast", "--target", "x86_64", "a.lang"]).unwrap();
+        assert!(opt.flags.check(Flags::GENERATE_TOKENS) && opt.flags.check(Flags::GENERATE_AST));
+        assert!(opt.flags.check(Flags::BIT64) && !opt.flags.check(Flags::BIT32));
+
+        let opt = parse_args(&["config", "set", "CC_FLAGS", "-O2 -g"]).unwrap();
+        assert!(opt.flags.check(Flags::CONFIG));
+        assert_eq!(opt.source, vec!["set", "CC_FLAGS", "-O2 -g"]);
+
+        let opt = parse_args(&["repl", "-m32", "--interpret"]).unwrap();
+        assert!(opt.flags.check(Flags::REPL) && opt.flags.check(Flags::BIT32));
+        assert!(opt.flags.check(Flags::INTERPRET));
+
+        let opt = parse_args(&["--emit=rfxc", "a.lang"]).unwrap();
+        assert!(opt.flags.check(Flags::GENERATE_RFXC) && !opt.flags.check(Flags::RUN));
+        let opt = parse_args(&["disasm", "a.rfxc"]).unwrap();
+        assert!(opt.flags.check(Flags::DISASM));
+        let opt = parse_args(&["lsp", "-m32"]).unwrap();
+        assert!(opt.flags.check(Flags::LSP) && opt.flags.check(Flags::BIT32));
+        let opt = parse_args(&["fmt", "--check", "--line-width=80", "a.lang"]).unwrap();
+        assert!(opt.flags.check(Flags::FMT) && opt.flags.check(Flags::CHECK));
+        assert_eq!((opt.line_width, opt.source.len()), (80, 1));
+        let opt = parse_args(&["doc", "--markdown", "-o", "out", "a.lang"]).unwrap();
+        assert!(opt.flags.check(Flags::DOC) && opt.flags.check(Flags::MARKDOWN));
+        assert_eq!((opt.ofile.as_str(), opt.source.len()), ("out", 1));
+        let opt = parse_args(&["test", "--filter", "parse", "a.lang"]).unwrap();
+        assert!(opt.flags.check(Flags::TEST) && !opt.flags.check(Flags::RUN));
+        assert_eq!((opt.filter.as_str(), opt.source.len()), ("parse", 1));
+
+        //options before the subcommand
+        let opt = parse_args(&["-v", "build", "dir"]).unwrap();
+        assert!(opt.flags.check(Flags::BUILD) && opt.flags.check(Flags::VERBOSE));
+        assert_eq!(opt.source, vec!["dir"]);
+        let opt = parse_args(&["-q", "-o", "run", "run", "x.lang", "-v"]).unwrap();
+        assert!(opt.flags.check(Flags::RUN) && opt.flags.check(Flags::QUIET));
+        assert_eq!((opt.ofile.as_str(), opt.source.len()), ("run", 1));
+        assert_eq!(opt.run_args, vec!["-v"]);
+        assert!(!parse_args(&["--", "build"])
+            .unwrap()
+            .flags
+            .check(Flags::BUILD));
+
+        assert!(parse_args(&["--target=arm", "a.lang"]).is_err());
+
+        let opt = parse_args(&["run", "-q", "a.lang", "-v", "x"]).unwrap();
+        assert!(opt.flags.check(Flags::RUN) && !opt.flags.check(Flags::VERBOSE));
+        assert_eq!(opt.run_args, vec!["-v", "x"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_args(&["--frobnicate"]).err().unwrap(),
+            "unknown option `--frobnicate`"
+        );
+        assert_eq!(
+            parse_args(&["-cx"]).err().unwrap(),
+            "unknown option `-x` in `-cx`"
+        );
+        assert_eq!(parse_args(&["-o"]).err().unwrap(), "`-o` needs a value");
+        assert_eq!(
+            parse_args(&["--emit=exe"]).err().unwrap(),
+            "cannot emit `exe`"
+        );
+        assert_eq!(
+            parse_args(&["--verbose=1"]).err().unwrap(),
+            "`--verbose` takes no value"
+        );
+        assert_eq!(parse_args(&["-x"]).err().unwrap(), "unknown option `-x`");
+        assert!(parse_args(&["-m8"]).is_err());
+        assert!(parse_args(&["-j", "many"]).is_err());
+        assert!(parse_args(&["--line-width=0"]).is_err());
+    }
 }
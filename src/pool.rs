@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/*
+runs f over items on up to jobs threads (-j). results come back in the order
+of items, whatever order they were finished in, so output made from them is
+the same for any number of jobs
+*/
+pub fn map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|s| {
+        for _ in 0..jobs {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= items.len() {
+                    break;
+                }
+                let r = f(&items[i]);
+                results.lock().unwrap()[i] = Some(r);
+            });
+        }
+    });
+    return results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|x| x.unwrap())
+        .collect();
+}
+
+//-j 0: as many jobs as the machine has cores
+pub fn available_jobs() -> usize {
+    return thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::map;
+
+    #[test]
+    fn test_map_order() {
+        let items: Vec<u64> = (0..50).collect();
+        //later items finish first
+        let slow = |x: &u64| {
+            std::thread::sleep(std::time::Duration::from_micros(50 - x));
+            x * 2
+        };
+        let expect: Vec<u64> = items.iter().map(|x| x * 2).collect();
+        assert_eq!(map(&items, 1, slow), expect);
+        assert_eq!(map(&items, 8, slow), expect);
+        assert!(map(&Vec::<u64>::new(), 4, slow).is_empty());
+    }
+}
@@ -7,3 +7,18 @@ pub const FILENAME_CHARS: [char; 61] = [
     'C', 'D', 'E', 'F', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V',
     'W', 'X', 'Y', 'Z',
 ];
+
+pub const NO_HOME: &str = "cannot find the home directory, HOME is not set";
+
+//`~` at the start of a path is the user's home directory. None if it is
+//asked for but unknown, never a directory literally named `~`
+pub fn home_path(path: &str) -> Option<String> {
+    let rest = match path.strip_prefix('~') {
+        None => return Some(path.to_string()),
+        Some(x) => x,
+    };
+    return match std::env::var("HOME") {
+        Ok(home) if !home.is_empty() => Some(home + rest),
+        _ => None,
+    };
+}
@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::cache::Cache;
+use super::compile::{self, Cc, Stage, Target, Warnings};
+use super::temp::Temps;
+
+pub const MANIFEST_FILE: &str = "rfx.json";
+pub const SOURCE_EXT: &str = "lang";
+
+/*
+a package is a directory with an rfx.json:
+
+{
+    "name": "app",
+    "entry": "src/main.lang",             the top level statements, optional
+    "sources": ["src"],                   directories whose .lang files are modules
+    "dependencies": {"util": "../util"},  other local packages, by directory
+    "cflags": ["-O2"],                    extra flags for the C compiler
+    "target": 64                          pointer width, 16, 32 or 64
+}
+
+paths are relative to the package directory. `rfx build` links the modules of
+the package and of everything it depends on into one binary, build/<name>.
+the entry of a dependency is not linked, only its sources
+*/
+#[derive(Debug)]
+pub struct Manifest {
+    pub dir: PathBuf,
+    pub name: String,
+    pub entry: Option<PathBuf>,
+    pub sources: Vec<PathBuf>,
+    pub dependencies: Vec<(String, PathBuf)>,
+    pub cflags: Vec<String>,
+    pub target: Option<Target>,
+}
+
+fn strings(v: &json::JsonValue, key: &str) -> Result<Vec<String>, String> {
+    let v = &v[key];
+    if v.is_null() {
+        return Ok(Vec::new());
+    }
+    if !v.is_array() {
+        return Err(format!("`{}` must be a list of strings", key));
+    }
+    let mut ret: Vec<String> = Vec::new();
+    for x in v.members() {
+        match x.as_str() {
+            None => return Err(format!("`{}` must be a list of strings", key)),
+            Some(s) => ret.push(s.to_string()),
+        }
+    }
+    return Ok(ret);
+}
+
+impl Manifest {
+    //the manifest of the package in dir
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(MANIFEST_FILE);
+        let raw = fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        return Self::parse(dir, &raw).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+
+    pub fn parse(dir: &Path, raw: &str) -> Result<Self, String> {
+        let v = json::parse(raw).map_err(|e| e.to_string())?;
+        if !v.is_object() {
+            return Err("the manifest must be an object".to_string());
+        }
+        for (key, _) in v.entries() {
+            let known = [
+                "name",
+                "entry",
+                "sources",
+                "dependencies",
+                "cflags",
+                "target",
+            ];
+            if !known.contains(&key) {
+                return Err(format!("unknown key `{}`", key));
+            }
+        }
+
+        let name = match v["name"].as_str() {
+            Some(x) if !x.is_empty() => x.to_string(),
+            _ => return Err("`name` must be a non empty string".to_string()),
+        };
+        let entry = match &v["entry"] {
+            x if x.is_null() => None,
+            x => match x.as_str() {
+                None => return Err("`entry` must be a string".to_string()),
+                Some(s) => Some(dir.join(s)),
+            },
+        };
+        let sources = strings(&v, "sources")?
+            .iter()
+            .map(|x| dir.join(x))
+            .collect();
+
+        let mut dependencies: Vec<(String, PathBuf)> = Vec::new();
+        let deps = &v["dependencies"];
+        if !deps.is_null() && !deps.is_object() {
+            return Err("`dependencies` must map names to directories".to_string());
+        }
+        for (k, x) in deps.entries() {
+            match x.as_str() {
+                None => return Err(format!("dependency `{}` must be a directory", k)),
+                Some(s) => dependencies.push((k.to_string(), dir.join(s))),
+            }
+        }
+
+        let target = match &v["target"] {
+            x if x.is_null() => None,
+            x => match x.as_usize() {
+                Some(bits) if [16, 32, 64].contains(&bits) => Some(Target::with_bits(bits)),
+                _ => return Err("`target` must be 16, 32 or 64".to_string()),
+            },
+        };
+
+        return Ok(Manifest {
+            dir: dir.to_path_buf(),
+            name,
+            entry,
+            sources,
+            dependencies,
+            cflags: strings(&v, "cflags")?,
+            target,
+        });
+    }
+
+    //the .lang files in the source directories, sorted so builds are the
+    //same on every machine
+    fn source_files(&self) -> Result<Vec<PathBuf>, String> {
+        let mut ret: Vec<PathBuf> = Vec::new();
+        let mut dirs: Vec<PathBuf> = self.sources.clone();
+        while let Some(d) = dirs.pop() {
+            let rd = fs::read_dir(&d).map_err(|e| format!("cannot read {}: {}", d.display(), e))?;
+            for entry in rd {
+                let p = entry.map_err(|e| e.to_string())?.path();
+                if p.is_dir() {
+                    dirs.push(p);
+                } else if p.extension().map(|x| x == SOURCE_EXT).unwrap_or(false) {
+                    ret.push(p);
+                }
+            }
+        }
+        ret.sort();
+        return Ok(ret);
+    }
+}
+
+//the package and, depth first, the packages it depends on, each once. a
+//package depending on itself, directly or not, is an error
+pub fn packages(root: Manifest) -> Result<Vec<Manifest>, String> {
+    fn visit(
+        m: Manifest,
+        stack: &mut Vec<PathBuf>,
+        done: &mut Vec<PathBuf>,
+        out: &mut Vec<Manifest>,
+    ) -> Result<(), String> {
+        let id = m.dir.canonicalize().unwrap_or_else(|_| m.dir.clone());
+        if stack.contains(&id) {
+            return Err(format!("package `{}` depends on itself", m.name));
+        }
+        if done.contains(&id) {
+            return Ok(());
+        }
+        stack.push(id.clone());
+        for (name, dir) in m.dependencies.iter() {
+            let dep = Manifest::load(dir)?;
+            if dep.name != *name {
+                return Err(format!(
+                    "`{}` depends on `{}`, but {} is package `{}`",
+                    m.name,
+                    name,
+                    dir.display(),
+                    dep.name
+                ));
+            }
+            visit(dep, stack, done, out)?;
+        }
+        stack.pop();
+        done.push(id);
+        out.push(m);
+        return Ok(());
+    }
+
+    let mut out: Vec<Manifest> = Vec::new();
+    visit(root, &mut Vec::new(), &mut Vec::new(), &mut out)?;
+    //the root package comes last out of the walk, put it first
+    out.rotate_right(1);
+    return Ok(out);
+}
+
+//a path the same however it was reached, e.g. app/../util/x.lang and
+//util/x.lang, as long as it exists
+fn file_id(p: &Path) -> PathBuf {
+    return p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+}
+
+//the modules of the program: the root entry, the root sources, then those of
+//the dependencies. the entry of a dependency is left out, even when it is in
+//one of its source directories
+pub fn modules(pkgs: &[Manifest]) -> Result<Vec<String>, String> {
+    let mut ret: Vec<PathBuf> = Vec::new();
+    let mut seen: Vec<PathBuf> = Vec::new();
+    if let Some(e) = &pkgs[0].entry {
+        ret.push(e.clone());
+        seen.push(file_id(e));
+    }
+    for dep in pkgs[1..].iter() {
+        if let Some(e) = &dep.entry {
+            seen.push(file_id(e));
+        }
+    }
+    for p in pkgs.iter() {
+        for f in p.source_files()? {
+            let id = file_id(&f);
+            if !seen.contains(&id) {
+                ret.push(f);
+                seen.push(id);
+            }
+        }
+    }
+    return Ok(ret
+        .iter()
+        .map(|x| x.to_string_lossy().to_string())
+        .collect());
+}
+
+/*
+`rfx build`: build the package in dir. cc.target, from the command line, wins
+over the target of the manifest, and cc.flags are added to its cflags.
+ofile names the binary instead of build/<name>. returns the exit code
+*/
+pub fn build(
+    dir: &Path,
+    cc: &Cc,
+    ofile: Option<&str>,
+    cache: Option<&Cache>,
+    jobs: usize,
+    save_temps: bool,
+    warnings: Warnings,
+) -> i32 {
+    let (pkgs, modules) = match Manifest::load(dir)
+        .and_then(packages)
+        .and_then(|x| modules(&x).map(|m| (x, m)))
+    {
+        Err(e) => {
+            error!("{}", e);
+            return 1;
+        }
+        Ok(x) => x,
+    };
+    let root = &pkgs[0];
+    if modules.is_empty() {
+        error!("package `{}` has no modules", root.name);
+        return 1;
+    }
+
+    let mut cc = cc.clone();
+    cc.target = cc.target.or(root.target);
+    cc.flags = root.cflags.iter().chain(cc.flags.iter()).cloned().collect();
+    let out = match ofile {
+        Some(x) => PathBuf::from(x),
+        None => {
+            let d = root.dir.join("build");
+            if let Err(e) = fs::create_dir_all(&d) {
+                error!("cannot create {}: {}", d.display(), e);
+                return 1;
+            }
+            d.join(&root.name)
+        }
+    };
+    let noun = match modules.len() {
+        1 => "module",
+        _ => "modules",
+    };
+    info!("building `{}` from {} {}", root.name, modules.len(), noun);
+
+    let mut temps = match Temps::new(save_temps) {
+        Err(e) => {
+            error!("cannot create the temp directory: {}", e);
+            return 1;
+        }
+        Ok(x) => x,
+    };
+    let cpath = match temps.create(".c") {
+        Err(e) => {
+            error!("cannot create a temp file: {}", e);
+            return 1;
+        }
+        Ok(x) => x,
+    };
+    let (cfile, log) = compile::build_c(
+        &modules,
+        cpath.parent().unwrap().to_string_lossy().to_string(),
+        cpath.file_name().unwrap().to_string_lossy().to_string(),
+        cc.target.unwrap_or_else(Target::host),
+        cache,
+        jobs,
+        warnings,
+    );
+    eprint!("{}", log);
+    let cfile = match cfile {
+        None => {
+            temps.finish(false);
+            return 1;
+        }
+        Some(x) => x,
+    };
+    let out = out.to_string_lossy().to_string();
+    let (built, log) = compile::run_cc(&cc, Stage::Bin, &cfile, &out, &modules[0], cache);
+    eprint!("{}", log);
+    temps.finish(built);
+    return match built {
+        true => 0,
+        false => 1,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{modules, packages, Manifest};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_manifest() {
+        let m = Manifest::parse(
+            Path::new("app"),
+            r#"{"name": "app", "entry": "main.lang", "sources": ["src"],
+                "dependencies": {"util": "../util"}, "cflags": ["-O2"], "target": 32}"#,
+        )
+        .unwrap();
+        assert_eq!(m.entry.unwrap(), Path::new("app/main.lang"));
+        assert_eq!(m.sources, vec![Path::new("app/src")]);
+        assert_eq!(m.dependencies[0].1, Path::new("app/../util"));
+        assert_eq!((m.cflags.len(), m.target.unwrap().bits), (1, 32));
+
+        let err = |raw: &str| Manifest::parse(Path::new("."), raw).err().unwrap();
+        assert_eq!(
+            err(r#"{"entry": "m.lang"}"#),
+            "`name` must be a non empty string"
+        );
+        assert_eq!(
+            err(r#"{"name": "a", "target": 8}"#),
+            "`target` must be 16, 32 or 64"
+        );
+        assert_eq!(err(r#"{"name": "a", "main": "m"}"#), "unknown key `main`");
+        assert_eq!(
+            err(r#"{"name": "a", "cflags": "-O2"}"#),
+            "`cflags` must be a list of strings"
+        );
+    }
+
+    #[test]
+    fn test_packages() {
+        let dir = std::env::temp_dir().join(format!("rfx_project_test_{}", std::process::id()));
+        let write = |path: &str, s: &str| {
+            let p = dir.join(path);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, s).unwrap();
+        };
+        write(
+            "app/rfx.json",
+            r#"{"name": "app", "entry": "main.lang", "sources": ["src"],
+                "dependencies": {"util": "../util"}}"#,
+        );
+        write("app/main.lang", "");
+        write("app/src/b.lang", "");
+        write("app/src/a.lang", "");
+        write("app/src/notes.txt", "");
+        write(
+            "util/rfx.json",
+            r#"{"name": "util", "entry": "lib/demo.lang", "sources": ["lib"]}"#,
+        );
+        write("util/lib/u.lang", "");
+        write("util/lib/demo.lang", "print(1);");
+
+        let pkgs = packages(Manifest::load(&dir.join("app")).unwrap()).unwrap();
+        let names: Vec<&str> = pkgs.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["app", "util"]);
+        let mods = modules(&pkgs).unwrap();
+        let mods: Vec<&str> = mods.iter().map(|x| x.rsplit('/').next().unwrap()).collect();
+        assert_eq!(mods, vec!["main.lang", "a.lang", "b.lang", "u.lang"]);
+
+        write(
+            "util/rfx.json",
+            r#"{"name": "util", "dependencies": {"app": "../app"}}"#,
+        );
+        let err = packages(Manifest::load(&dir.join("app")).unwrap());
+        assert_eq!(err.err().unwrap(), "package `app` depends on itself");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
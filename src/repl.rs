@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::cache::Cache;
+use super::compile::{self, Cc, Stage, Target, Warnings};
+use super::temp::Temps;
+
+/*
+`rfx repl`: read an entry, a line or, while brackets, a string or a comment
+are left open, several. an entry is compiled after the entries before it and
+run, or with --interpret interpreted, so what they declared is still there.
+an entry that is an expression has its value and type printed.
+
+    :reset    forget the entries so far
+    :quit     leave, as does the end of the input
+*/
+
+//build and run one entry. Ok is the output of the entry, Err what went wrong
+fn eval(
+    session: &[String],
+    entry: &str,
+    cc: &Cc,
+    cache: Option<&Cache>,
+    save_temps: bool,
+    interpret: Option<&mut compile::ReplState>,
+    warnings: Warnings,
+) -> Result<String, String> {
+    let target = cc.target.unwrap_or_else(Target::host);
+    let program = compile::compile_repl(session, entry, target, warnings)?;
+    eprint!("{}", program.log);
+
+    let (mut shown, status) = match interpret {
+        //the interpreter runs the entry alone
+        Some(state) => {
+            let mut out: Vec<u8> = Vec::new();
+            let ended = program.interpret(state, &mut out);
+            if let Err(msg) = &ended {
+                eprintln!("rfx panic: {}", msg);
+            }
+            (
+                String::from_utf8_lossy(&out).to_string(),
+                ended.map_err(|_| 101),
+            )
+        }
+        None => {
+            let (stdout, status) = build_and_run(&program, cc, cache, save_temps)?;
+            let stdout = String::from_utf8_lossy(&stdout);
+            //what the earlier entries print again is before the mark
+            let shown = match stdout.find(compile::REPL_MARK) {
+                Some(i) => stdout[i + compile::REPL_MARK.len()..].trim_start_matches('\n'),
+                None => "",
+            };
+            (shown.to_string(), status)
+        }
+    };
+    if let Err(code) = status {
+        return Err(format!("{}exited with {}\n", shown, code));
+    }
+
+    //the value, printed last, is followed by its type
+    match program.tp {
+        Some(tp) if shown.ends_with('\n') => {
+            shown.insert_str(shown.len() - 1, &format!(": {}", tp))
+        }
+        Some(tp) => shown += &format!(": {}\n", tp),
+        None => {}
+    }
+    return Ok(shown);
+}
+
+//build the program with cc and run it. its stdout, and Err with the exit
+//code if it failed
+fn build_and_run(
+    program: &compile::ReplProgram,
+    cc: &Cc,
+    cache: Option<&Cache>,
+    save_temps: bool,
+) -> Result<(Vec<u8>, Result<(), i32>), String> {
+    let mut temps =
+        Temps::new(save_temps).map_err(|e| format!("cannot create the temp directory: {}", e))?;
+    let (cpath, bin) = match (temps.create(".c"), temps.create("")) {
+        (Ok(c), Ok(b)) => (c, b),
+        (Err(e), _) | (_, Err(e)) => return Err(format!("cannot create a temp file: {}", e)),
+    };
+    if let Err(e) = fs::write(&cpath, program.c()) {
+        temps.finish(false);
+        return Err(format!("cannot write {}: {}", cpath.display(), e));
+    }
+    let (cfile, bin) = (cpath.to_string_lossy(), bin.to_string_lossy());
+    let (built, log) = compile::run_cc(cc, Stage::Bin, &cfile, &bin, "<repl>", cache);
+    if !built {
+        temps.finish(false);
+        return Err(log);
+    }
+    eprint!("{}", log);
+
+    let out = Command::new(bin.as_ref()).stdin(Stdio::null()).output();
+    temps.finish(true);
+    let out = out.map_err(|e| format!("cannot run {}: {}", bin, e))?;
+    io::stderr().write_all(&out.stderr).ok();
+    if !out.status.success() {
+        let code = out
+            .status
+            .code()
+            .unwrap_or_else(|| 128 + out.status.signal().unwrap_or(0));
+        return Ok((out.stdout, Err(code)));
+    }
+    return Ok((out.stdout, Ok(())));
+}
+
+//the exit code of the REPL
+pub fn repl(
+    cc: &Cc,
+    cache: Option<&Cache>,
+    save_temps: bool,
+    interpret: bool,
+    warnings: Warnings,
+) -> i32 {
+    let mut session: Vec<String> = Vec::new();
+    let mut state = compile::ReplState::default();
+    let mut entry = String::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("{}", if entry.is_empty() { "rfx> " } else { "...> " });
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            None => break,
+            Some(Err(e)) => {
+                error!("cannot read the input: {}", e);
+                return 1;
+            }
+            Some(Ok(x)) => x,
+        };
+        if entry.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" | ":q" => return 0,
+                ":reset" => {
+                    session.clear();
+                    state = compile::ReplState::default();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        entry += &line;
+        entry.push('\n');
+        if !compile::is_complete(&entry) {
+            continue;
+        }
+
+        let state = interpret.then_some(&mut state);
+        match eval(&session, &entry, cc, cache, save_temps, state, warnings) {
+            Ok(out) => {
+                print!("{}", out);
+                session.push(std::mem::take(&mut entry));
+            }
+            Err(e) => {
+                eprint!("{}", e);
+                entry.clear();
+            }
+        }
+    }
+    println!();
+    return 0;
+}
@@ -0,0 +1,359 @@
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::Command;
+use std::thread;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::cache::{self, Cache};
+use super::compile::{self, Cc, Stage, Target, Warnings};
+use super::port::NO_HOME;
+use super::temp::Temps;
+
+//a cached binary is named after everything that goes into it, the path of
+//the source among it: it is in the messages of panics
+fn binary_name(rfxfile: &str, src: &str, cc: &Cc, warnings: Warnings) -> String {
+    let flags = cc.flags.join("\0");
+    let mode = cc.target.map(|t| t.cc_flag()).unwrap_or_default();
+    let warnings = format!("{:?}", warnings);
+    return cache::key(&[
+        rfxfile.as_bytes(),
+        src.as_bytes(),
+        cc.cmd.as_bytes(),
+        flags.as_bytes(),
+        mode.as_bytes(),
+        warnings.as_bytes(),
+    ]);
+}
+
+//build rfxfile, read as bytes, unless its binary is cached, then run it with
+//args on our stdin and stdout. returns the exit code of the program. without
+//a cache (--no-cache) it is always built again. save_temps keeps the C file
+pub fn run(
+    rfxfile: &str,
+    bytes: Vec<u8>,
+    cc: &Cc,
+    args: &[String],
+    cache: Option<&Cache>,
+    save_temps: bool,
+    warnings: Warnings,
+) -> i32 {
+    let src = match String::from_utf8(bytes) {
+        Err(e) => {
+            error!("cannot read {}: {}", rfxfile, e);
+            return 1;
+        }
+        Ok(x) => x,
+    };
+    //in the user's own directory, no one else can put a binary there to run
+    let dir = match cache::run_dir() {
+        None => {
+            error!("cannot build {}: {}", rfxfile, NO_HOME);
+            return 1;
+        }
+        Some(x) => x,
+    };
+    if let Err(e) = fs::create_dir_all(&dir) {
+        error!("cannot create {}: {}", dir.display(), e);
+        return 1;
+    }
+
+    let bin = dir.join(binary_name(rfxfile, &src, cc, warnings));
+    if cache.is_none() || !bin.exists() {
+        let mut temps = match Temps::new(save_temps) {
+            Err(e) => {
+                error!("cannot create the temp directory: {}", e);
+                return 1;
+            }
+            Ok(x) => x,
+        };
+        let cpath = match temps.create(".c") {
+            Err(e) => {
+                error!("cannot create a temp file: {}", e);
+                return 1;
+            }
+            Ok(x) => x,
+        };
+        //built from the text read above, never read again: what is built is
+        //what the binary is named after, and a pipe like /dev/stdin is read
+        //only once
+        let (cfile, log) = compile::build_c_modules(
+            &[(rfxfile.to_string(), src.clone())],
+            cpath.parent().unwrap().to_string_lossy().to_string(),
+            cpath.file_name().unwrap().to_string_lossy().to_string(),
+            cc.target.unwrap_or_else(Target::host),
+            cache,
+            1,
+            warnings,
+        );
+        eprint!("{}", log);
+        let cfile = match cfile {
+            None => {
+                temps.finish(false);
+                return 1;
+            }
+            Some(x) => x,
+        };
+        //built aside and renamed, another run never sees half a binary
+        let part = format!("{}.{}", bin.display(), std::process::id());
+        let (built, log) = compile::run_cc(cc, Stage::Bin, &cfile, &part, rfxfile, cache);
+        eprint!("{}", log);
+        temps.finish(built);
+        if !built {
+            return 1;
+        }
+        if let Err(e) = fs::rename(&part, &bin) {
+            error!("cannot write {}: {}", bin.display(), e);
+            return 1;
+        }
+    }
+
+    return match Command::new(&bin).args(args).status() {
+        Err(e) => {
+            error!("cannot run {}: {}", bin.display(), e);
+            1
+        }
+        //like a shell, a program killed by a signal exits with 128 + signal
+        Ok(x) => x.code().unwrap_or_else(|| 128 + x.signal().unwrap_or(0)),
+    };
+}
+
+//`rfx test`: build the test harness of rfxfiles, linked as one program, and
+//run the #[test] functions with filter in their name. returns the exit code
+//of the harness, 101 if a test failed
+pub fn test(
+    rfxfiles: &[String],
+    cc: &Cc,
+    filter: &str,
+    jobs: usize,
+    save_temps: bool,
+    warnings: Warnings,
+) -> i32 {
+    let target = cc.target.unwrap_or_else(Target::host);
+    let (c, log) = compile::build_tests(rfxfiles, target, jobs, warnings);
+    eprint!("{}", log);
+    let c = match c {
+        None => return 1,
+        Some(x) => x,
+    };
+    let mut temps = match Temps::new(save_temps) {
+        Err(e) => {
+            error!("cannot create the temp directory: {}", e);
+            return 1;
+        }
+        Ok(x) => x,
+    };
+    let (cpath, bin) = match temps.create(".c").and_then(|c| Ok((c, temps.create("")?))) {
+        Err(e) => {
+            error!("cannot create a temp file: {}", e);
+            return 1;
+        }
+        Ok(x) => x,
+    };
+    if let Err(e) = fs::write(&cpath, c) {
+        error!("cannot write {}: {}", cpath.display(), e);
+        temps.finish(false);
+        return 1;
+    }
+    let cfile = cpath.to_string_lossy().to_string();
+    let binfile = bin.to_string_lossy().to_string();
+    let (built, log) = compile::run_cc(cc, Stage::Bin, &cfile, &binfile, &rfxfiles[0], None);
+    eprint!("{}", log);
+    if !built {
+        temps.finish(false);
+        return 1;
+    }
+
+    let code = match Command::new(&bin).arg(filter).status() {
+        Err(e) => {
+            error!("cannot run {}: {}", bin.display(), e);
+            1
+        }
+        Ok(x) => x.code().unwrap_or_else(|| 128 + x.signal().unwrap_or(0)),
+    };
+    temps.finish(true);
+    return code;
+}
+
+//the interpreter and the VM recurse with the program, give them room for
+//deep recursion
+const INTERPRET_STACK: usize = 1 << 28;
+
+//run f on a thread with that stack, its result is the exit code
+fn with_stack(f: impl FnOnce() -> i32 + Send + 'static) -> i32 {
+    return match thread::Builder::new().stack_size(INTERPRET_STACK).spawn(f) {
+        Err(e) => {
+            error!("cannot start the interpreter: {}", e);
+            1
+        }
+        Ok(x) => x.join().unwrap_or(101),
+    };
+}
+
+//check rfxfiles, linked as one program, and run it with the interpreter
+//(--interpret), no C compiler needed. returns the exit code of the program,
+//101 for a panic like the built program
+pub fn interpret(rfxfiles: &[String], target: Target, jobs: usize, warnings: Warnings) -> i32 {
+    let files = rfxfiles.to_vec();
+    return with_stack(move || {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        let (ended, log) = compile::interpret(&files, target, jobs, warnings, &mut out);
+        eprint!("{}", log);
+        out.flush().ok();
+        return match ended {
+            None => 1,
+            Some(Ok(())) => 0,
+            Some(Err(msg)) => {
+                eprintln!("rfx panic: {}", msg);
+                101
+            }
+        };
+    });
+}
+
+//run the bytes of a .rfxc bytecode file in the VM, no C compiler needed.
+//returns the exit code of the program, 101 for a panic
+pub fn run_bytecode(rfxcfile: &str, bytes: Vec<u8>) -> i32 {
+    let file = rfxcfile.to_string();
+    return with_stack(move || {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        let ended = compile::run_bytecode(&bytes, &mut out);
+        out.flush().ok();
+        return match ended {
+            Err(e) => {
+                error!("cannot run {}: {}", file, e);
+                1
+            }
+            Ok(Ok(())) => 0,
+            Ok(Err(msg)) => {
+                eprintln!("rfx panic: {}", msg);
+                101
+            }
+        };
+    });
+}
+
+//`rfx disasm`: print the listing of the bytecode of each file, a .rfxc file
+//or a source compiled for it. returns the exit code
+pub fn disasm(files: &[String], target: Target, warnings: Warnings) -> i32 {
+    let mut failed = false;
+    for f in files.iter() {
+        let bytes = match fs::read(f) {
+            Err(e) => {
+                error!("cannot read {}: {}", f, e);
+                failed = true;
+                continue;
+            }
+            Ok(x) => x,
+        };
+        let bytes = match compile::is_bytecode(&bytes) {
+            true => bytes,
+            false => {
+                let (bytes, log) =
+                    compile::build_bytecode(std::slice::from_ref(f), target, 1, warnings);
+                eprint!("{}", log);
+                match bytes {
+                    None => {
+                        failed = true;
+                        continue;
+                    }
+                    Some(x) => x,
+                }
+            }
+        };
+        match compile::disassemble(&bytes) {
+            Err(e) => {
+                error!("{}: {}", f, e);
+                failed = true;
+            }
+            Ok(text) if files.len() > 1 => print!("{}:\n{}\n", f, text),
+            Ok(text) => print!("{}", text),
+        }
+    }
+    return failed as i32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::binary_name;
+    use crate::compile::{Cc, Target, WarningLevel, Warnings};
+
+    #[test]
+    fn test_binary_name() {
+        let cc = Cc::new("cc", None);
+        let a = binary_name(
+            "a.lang",
+            "print(1);",
+            &cc,
+            Warnings::new(WarningLevel::Warn),
+        );
+        assert_eq!(
+            a,
+            binary_name(
+                "a.lang",
+                "print(1);",
+                &cc,
+                Warnings::new(WarningLevel::Warn)
+            )
+        );
+        assert_ne!(
+            a,
+            binary_name(
+                "b.lang",
+                "print(1);",
+                &cc,
+                Warnings::new(WarningLevel::Warn)
+            )
+        );
+        assert_ne!(
+            a,
+            binary_name(
+                "a.lang",
+                "print(2);",
+                &cc,
+                Warnings::new(WarningLevel::Warn)
+            )
+        );
+        assert_ne!(
+            a,
+            binary_name(
+                "a.lang",
+                "print(1);",
+                &Cc::new("clang", None),
+                Warnings::new(WarningLevel::Warn)
+            )
+        );
+        let m32 = Cc::new("cc", Some(Target::with_bits(32)));
+        assert_ne!(
+            a,
+            binary_name(
+                "a.lang",
+                "print(1);",
+                &m32,
+                Warnings::new(WarningLevel::Warn)
+            )
+        );
+        let mut o2 = cc.clone();
+        o2.flags.push("-O2".to_string());
+        assert_ne!(
+            a,
+            binary_name(
+                "a.lang",
+                "print(1);",
+                &o2,
+                Warnings::new(WarningLevel::Warn)
+            )
+        );
+        assert_ne!(
+            a,
+            binary_name(
+                "a.lang",
+                "print(1);",
+                &cc,
+                Warnings::new(WarningLevel::Deny)
+            )
+        );
+    }
+}
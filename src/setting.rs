@@ -1,53 +1,446 @@
-use fs::File;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
-use std::fs;
-use std::io::Read;
-use std::path::Path;
 
-use super::port::{RFX_HOME_DIR, SETTING_FILE};
+use super::compile::LINT_RULES;
+use super::port::{home_path, SETTING_FILE};
+
+/*
+settings come from, each overriding the one before:
+  the defaults
+  ~/.rfx/setting.json
+  $XDG_CONFIG_HOME/rfx/setting.json
+  .rfx/setting.json of the project, the nearest one from the working directory up
+  RFX_<KEY> environment variables, e.g. RFX_DEFAULT_CC
+and the command line overrides them all. every file is an object of KEYS;
+a value of the wrong type is an error and an unknown key a warning
+*/
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Str,
+    Width, // 16, 32 or 64
+    Warnings,
+    List,  // of strings. in the environment `:` separated, spaces for CC_FLAGS
+    Lints, // a List of names of LINT_RULES
+}
+
+pub struct Key {
+    pub name: &'static str,
+    pub kind: Kind,
+}
+
+pub const KEYS: &[Key] = &[
+    //C compiler used without --cc
+    Key {
+        name: "DEFAULT_CC",
+        kind: Kind::Str,
+    },
+    //binary built without -o
+    Key {
+        name: "DEFAULT_OUT_BINARY",
+        kind: Kind::Str,
+    },
+    //pointer width without -m
+    Key {
+        name: "DEFAULT_TARGET",
+        kind: Kind::Width,
+    },
+    //"allow" drops warnings, "deny" makes them errors
+    Key {
+        name: "WARNINGS",
+        kind: Kind::Warnings,
+    },
+    //the lints checked, all of them by default
+    Key {
+        name: "LINTS",
+        kind: Kind::Lints,
+    },
+    //searched for source files not found where given
+    Key {
+        name: "INCLUDE_PATHS",
+        kind: Kind::List,
+    },
+    //extra flags for the C compiler
+    Key {
+        name: "CC_FLAGS",
+        kind: Kind::List,
+    },
+];
 
-fn create_rfx_dir() -> std::io::Result<()> {
-    fs::create_dir(RFX_HOME_DIR)?;
-    return Ok(());
+pub fn key(name: &str) -> Option<&'static Key> {
+    return KEYS.iter().find(|x| x.name == name);
 }
 
-fn create_setting_file() -> std::io::Result<()> {
-    File::create(SETTING_FILE)?;
-    return Ok(());
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Str(String),
+    Num(usize),
+    List(Vec<String>),
 }
 
-pub fn init() {
-    if !Path::new(RFX_HOME_DIR).exists() {
-        create_rfx_dir().unwrap();
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Num(n) => write!(f, "{}", n),
+            Value::List(l) => write!(f, "{}", l.join(" ")),
+        };
     }
+}
+
+impl Value {
+    pub fn to_json(&self) -> json::JsonValue {
+        return match self {
+            Value::Str(s) => json::JsonValue::from(s.as_str()),
+            Value::Num(n) => json::JsonValue::from(*n),
+            Value::List(l) => json::JsonValue::from(l.clone()),
+        };
+    }
+}
 
-    if !Path::new(SETTING_FILE).exists() {
-        create_setting_file().unwrap();
+//where a value came from
+#[derive(Clone, PartialEq, Debug)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Env(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(p) => write!(f, "{}", p.display()),
+            Origin::Env(v) => write!(f, "${}", v),
+        };
     }
 }
 
-pub fn read_setting(custom_cc: &mut String, custom_ofile: &mut String) {
-    let mut sfile = File::open(SETTING_FILE).unwrap();
-    let mut setting_raw: String = String::new();
-    let setting_parsed: json::JsonValue;
+//a problem with a settings file or variable, error or warning
+pub struct Problem {
+    pub error: bool,
+    pub msg: String,
+}
 
-    sfile.read_to_string(&mut setting_raw).unwrap();
-    setting_parsed = match json::parse(setting_raw.as_str()) {
-        Err(_e) => {
-            return;
+//check a json value against the kind of key
+pub fn validate(key: &Key, v: &json::JsonValue) -> Result<Value, String> {
+    let bad = |want: &str| Err(format!("`{}` must be {}", key.name, want));
+    return match key.kind {
+        Kind::Str => match v.as_str() {
+            Some(s) if !s.is_empty() => Ok(Value::Str(s.to_string())),
+            _ => bad("a non empty string"),
+        },
+        Kind::Width => match v.as_usize() {
+            Some(n) if [16, 32, 64].contains(&n) => Ok(Value::Num(n)),
+            _ => bad("16, 32 or 64"),
+        },
+        Kind::Warnings => match v.as_str() {
+            Some(s) if ["allow", "warn", "deny"].contains(&s) => Ok(Value::Str(s.to_string())),
+            _ => bad("\"allow\", \"warn\" or \"deny\""),
+        },
+        Kind::List => {
+            let mut l: Vec<String> = Vec::new();
+            for x in v.members() {
+                match x.as_str() {
+                    None => return bad("a list of strings"),
+                    Some(s) => l.push(s.to_string()),
+                }
+            }
+            match v.is_array() {
+                true => Ok(Value::List(l)),
+                false => bad("a list of strings"),
+            }
+        }
+        Kind::Lints => {
+            let mut l: Vec<String> = Vec::new();
+            for x in v.members() {
+                match x.as_str() {
+                    Some(s) if LINT_RULES.iter().any(|(n, _)| *n == s) => l.push(s.to_string()),
+                    Some(s) => return bad(&format!("a list of lints, `{}` is not one", s)),
+                    None => return bad("a list of lints"),
+                }
+            }
+            match v.is_array() {
+                true => Ok(Value::List(l)),
+                false => bad("a list of lints"),
+            }
         }
+    };
+}
+
+//a value given as text, by an environment variable or `rfx config set`
+pub fn parse_value(key: &Key, s: &str) -> Result<Value, String> {
+    let v = match key.kind {
+        Kind::Width => match s.parse::<usize>() {
+            Ok(n) => json::JsonValue::from(n),
+            Err(_) => json::JsonValue::from(s),
+        },
+        Kind::List if key.name == "CC_FLAGS" => {
+            json::JsonValue::from(s.split_whitespace().collect::<Vec<&str>>())
+        }
+        Kind::List | Kind::Lints => json::JsonValue::from(
+            s.split(':')
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>(),
+        ),
+        _ => json::JsonValue::from(s),
+    };
+    return validate(key, &v);
+}
+
+//the settings files in the order they are read, whether or not they exist
+pub fn files() -> Vec<PathBuf> {
+    //without a home directory there are no user settings
+    let user = home_path(SETTING_FILE).map(PathBuf::from);
+    let mut ret: Vec<PathBuf> = user.iter().cloned().collect();
+    if let Ok(x) = env::var("XDG_CONFIG_HOME") {
+        if !x.is_empty() {
+            ret.push(Path::new(&x).join("rfx").join("setting.json"));
+        }
+    }
+    if let Some(p) = project_file() {
+        //from within the home directory, ~/.rfx is not a project
+        if !user.as_ref().is_some_and(|u| same_file(&p, u)) {
+            ret.push(p);
+        }
+    }
+    return ret;
+}
 
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    return match (a.canonicalize(), b.canonicalize()) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => a == b,
+    };
+}
+
+//.rfx/setting.json of the nearest directory up from the working directory
+pub fn project_file() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    for d in cwd.ancestors() {
+        let p = d.join(".rfx").join("setting.json");
+        if p.exists() {
+            return Some(p);
+        }
+    }
+    return None;
+}
+
+//the key and value pairs of a settings file, every key, known or not
+pub fn read_file(path: &Path) -> Result<json::JsonValue, String> {
+    let raw = match fs::read_to_string(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(json::object! {}),
+        Err(e) => return Err(format!("cannot read: {}", e)),
         Ok(x) => x,
     };
+    //an empty file, as older rfx made them, has no settings
+    if raw.trim().is_empty() {
+        return Ok(json::object! {});
+    }
+    let v = json::parse(&raw).map_err(|e| format!("invalid JSON: {}", e))?;
+    if !v.is_object() {
+        return Err("settings must be a JSON object".to_string());
+    }
+    return Ok(v);
+}
+
+pub struct Settings {
+    values: BTreeMap<&'static str, (Value, Origin)>,
+}
+
+impl Settings {
+    pub fn defaults() -> Self {
+        let mut values = BTreeMap::new();
+        values.insert(
+            "WARNINGS",
+            (Value::Str("warn".to_string()), Origin::Default),
+        );
+        let lints = LINT_RULES.iter().map(|(n, _)| n.to_string()).collect();
+        values.insert("LINTS", (Value::List(lints), Origin::Default));
+        return Settings { values };
+    }
+
+    //read every source. problems are returned, not printed, since the logger
+    //may not be up yet
+    pub fn load() -> (Self, Vec<Problem>) {
+        let mut s = Self::defaults();
+        let mut problems: Vec<Problem> = Vec::new();
+        for f in files() {
+            s.read(&f, &mut problems);
+        }
+        s.read_env(&mut problems);
+        return (s, problems);
+    }
+
+    fn read(&mut self, path: &Path, problems: &mut Vec<Problem>) {
+        let v = match read_file(path) {
+            Err(e) => {
+                problems.push(Problem {
+                    error: true,
+                    msg: format!("{}: {}", path.display(), e),
+                });
+                return;
+            }
+            Ok(x) => x,
+        };
+        for (k, x) in v.entries() {
+            let key = match key(k) {
+                None => {
+                    problems.push(Problem {
+                        error: false,
+                        msg: format!("{}: unknown key `{}`", path.display(), k),
+                    });
+                    continue;
+                }
+                Some(x) => x,
+            };
+            match validate(key, x) {
+                Err(e) => problems.push(Problem {
+                    error: true,
+                    msg: format!("{}: {}", path.display(), e),
+                }),
+                Ok(x) => {
+                    let origin = Origin::File(path.to_path_buf());
+                    self.values.insert(key.name, (x, origin));
+                }
+            }
+        }
+    }
 
-    if !setting_parsed["DEFAULT_CC"].is_null() {
-        *custom_cc = setting_parsed["DEFAULT_CC"].as_str().unwrap().to_string();
+    fn read_env(&mut self, problems: &mut Vec<Problem>) {
+        for key in KEYS.iter() {
+            let var = format!("RFX_{}", key.name);
+            let s = match env::var(&var) {
+                Err(_) => continue,
+                Ok(x) => x,
+            };
+            match parse_value(key, &s) {
+                Err(e) => problems.push(Problem {
+                    error: true,
+                    msg: format!("${}: {}", var, e),
+                }),
+                Ok(x) => {
+                    self.values.insert(key.name, (x, Origin::Env(var)));
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&(Value, Origin)> {
+        return self.values.get(name);
+    }
+
+    pub fn str(&self, name: &str) -> Option<&str> {
+        return match self.get(name) {
+            Some((Value::Str(s), _)) => Some(s),
+            _ => None,
+        };
+    }
+
+    pub fn num(&self, name: &str) -> Option<usize> {
+        return match self.get(name) {
+            Some((Value::Num(n), _)) => Some(*n),
+            _ => None,
+        };
+    }
+
+    pub fn list(&self, name: &str) -> &[String] {
+        return match self.get(name) {
+            Some((Value::List(l), _)) => l,
+            _ => &[],
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key, parse_value, validate, Origin, Problem, Settings, Value};
+    use std::fs;
+
+    #[test]
+    fn test_validate() {
+        let cc = key("DEFAULT_CC").unwrap();
+        assert_eq!(
+            validate(cc, &json::parse(r#""clang""#).unwrap()),
+            Ok(Value::Str("clang".to_string()))
+        );
+        assert_eq!(
+            validate(cc, &json::parse("1").unwrap()).err().unwrap(),
+            "`DEFAULT_CC` must be a non empty string"
+        );
+        let width = key("DEFAULT_TARGET").unwrap();
+        assert_eq!(parse_value(width, "32"), Ok(Value::Num(32)));
+        assert!(parse_value(width, "48").is_err());
+        assert!(parse_value(key("WARNINGS").unwrap(), "loud").is_err());
+        assert_eq!(
+            parse_value(key("CC_FLAGS").unwrap(), "-O2  -g"),
+            Ok(Value::List(vec!["-O2".to_string(), "-g".to_string()]))
+        );
+        assert_eq!(
+            parse_value(key("INCLUDE_PATHS").unwrap(), "lib:/usr/rfx"),
+            Ok(Value::List(vec!["lib".to_string(), "/usr/rfx".to_string()]))
+        );
+        let lints = key("LINTS").unwrap();
+        assert_eq!(
+            parse_value(lints, "shadowing:unused_import"),
+            Ok(Value::List(vec![
+                "shadowing".to_string(),
+                "unused_import".to_string()
+            ]))
+        );
+        assert_eq!(
+            parse_value(lints, "shadowing:loud").err().unwrap(),
+            "`LINTS` must be a list of lints, `loud` is not one"
+        );
     }
-    if !setting_parsed["DEFAULT_OUT_BINARY"].is_null() {
-        *custom_ofile = setting_parsed["DEFAULT_OUT_BINARY"]
-            .as_str()
-            .unwrap()
-            .to_string();
+
+    #[test]
+    fn test_read_precedence() {
+        let dir = std::env::temp_dir().join(format!("rfx_setting_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.json");
+        let project = dir.join("project.json");
+        fs::write(
+            &user,
+            r#"{"DEFAULT_CC": "gcc", "CC_FLAGS": ["-O2"], "COLOR": 1}"#,
+        )
+        .unwrap();
+        fs::write(&project, r#"{"DEFAULT_CC": "clang", "DEFAULT_TARGET": 8}"#).unwrap();
+
+        let mut s = Settings::defaults();
+        let mut problems: Vec<Problem> = Vec::new();
+        s.read(&user, &mut problems);
+        s.read(&project, &mut problems);
+        s.read(&dir.join("missing.json"), &mut problems);
+        assert_eq!(s.str("DEFAULT_CC"), Some("clang"));
+        assert_eq!(
+            s.get("DEFAULT_CC").unwrap().1,
+            Origin::File(project.clone())
+        );
+        assert_eq!(s.list("CC_FLAGS"), ["-O2"]);
+        assert_eq!(s.str("WARNINGS"), Some("warn"));
+        assert_eq!(s.num("DEFAULT_TARGET"), None);
+
+        let msgs: Vec<(bool, String)> = problems.into_iter().map(|x| (x.error, x.msg)).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                (false, format!("{}: unknown key `COLOR`", user.display())),
+                (
+                    true,
+                    format!(
+                        "{}: `DEFAULT_TARGET` must be 16, 32 or 64",
+                        project.display()
+                    )
+                ),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,120 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, warn};
+
+use super::port::{FILENAME_CHARS, TMP_DIR};
+
+/*
+intermediate files of a build, e.g. the generated C handed to cc. each is
+created with O_EXCL under TMP_DIR/c, so concurrent rfx processes never share
+one, and removed again once the build succeeded. --save-temps keeps them
+*/
+pub struct Temps {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+    keep: bool,
+}
+
+//tells names apart within one process, the clock and pid do across processes
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+//ten chars of FILENAME_CHARS, different on every call
+fn random_name() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_nanos() as u64)
+        .unwrap_or(0);
+    let mut x = nanos ^ ((std::process::id() as u64) << 32);
+    x = x.wrapping_add(
+        COUNTER
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_mul(0x9e3779b97f4a7c15),
+    );
+    let mut name = String::new();
+    for _ in 0..10 {
+        //splitmix64
+        x = x.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = x;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        name.push(FILENAME_CHARS[(z % FILENAME_CHARS.len() as u64) as usize]);
+    }
+    return name;
+}
+
+impl Temps {
+    pub fn new(keep: bool) -> io::Result<Self> {
+        return Self::in_dir(Path::new(TMP_DIR).join("c"), keep);
+    }
+
+    pub fn in_dir(dir: PathBuf, keep: bool) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        return Ok(Temps {
+            dir,
+            files: Vec::new(),
+            keep,
+        });
+    }
+
+    //a new empty file ending in ext that no one else has
+    pub fn create(&mut self, ext: &str) -> io::Result<PathBuf> {
+        loop {
+            let path = self.dir.join(random_name() + ext);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+                Ok(_) => {
+                    self.files.push(path.clone());
+                    return Ok(path);
+                }
+            }
+        }
+    }
+
+    //remove the files after a successful build. a failed one keeps them to
+    //look at, as does --save-temps
+    pub fn finish(self, success: bool) {
+        if self.keep || !success {
+            for f in self.files.iter() {
+                info!("kept {}", f.display());
+            }
+            return;
+        }
+        for f in self.files.iter() {
+            if let Err(e) = fs::remove_file(f) {
+                warn!("cannot remove {}: {}", f.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Temps;
+    use std::fs;
+
+    #[test]
+    fn test_temps() {
+        let dir = std::env::temp_dir().join(format!("rfx_temp_test_{}", std::process::id()));
+        let mut temps = Temps::in_dir(dir.join("c"), false).unwrap();
+        let a = temps.create(".c").unwrap();
+        let b = temps.create(".c").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.file_name().unwrap().len(), 12);
+        assert!(a.exists() && b.exists());
+        temps.finish(true);
+        assert!(!a.exists() && !b.exists());
+
+        let mut temps = Temps::in_dir(dir.join("c"), true).unwrap();
+        let a = temps.create(".c").unwrap();
+        temps.finish(true);
+        assert!(a.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    const char *ptr;
+    uint64_t len;
+    uint64_t cap; // 0 if ptr is not owned, e.g. a literal
+} rfx_String;
+
+static inline void rfx_panic(const char *msg) {
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n", msg);
+    exit(101);
+}
+
+static inline void rfx_assert(bool ok, const char *msg) {
+    if (!ok)
+        rfx_panic(msg);
+}
+
+// integer division, checked as the interpreter does it. MIN / -1 wraps
+static inline int64_t rfx_div_i64(int64_t a, int64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    if (b == -1)
+        return (int64_t)(0 - (uint64_t)a);
+    return a / b;
+}
+
+static inline int64_t rfx_rem_i64(int64_t a, int64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    if (b == -1)
+        return 0;
+    return a % b;
+}
+
+static inline uint64_t rfx_div_u64(uint64_t a, uint64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    return a / b;
+}
+
+static inline uint64_t rfx_rem_u64(uint64_t a, uint64_t b) {
+    if (b == 0)
+        rfx_panic("division by zero");
+    return a % b;
+}
+
+static inline rfx_String rfx_string_lit(const char *s, uint64_t len) {
+    rfx_String r = {s, len, 0};
+    return r;
+}
+
+static inline void rfx_drop_String(rfx_String s) {
+    if (s.cap)
+        free((char *)s.ptr);
+}
+
+static inline rfx_String rfx_clone_String(rfx_String s) {
+    if (s.cap == 0)
+        return s;
+    char *p = malloc(s.len + 1);
+    if (p == NULL)
+        rfx_panic("out of memory");
+    memcpy(p, s.ptr, s.len);
+    p[s.len] = '\0';
+    rfx_String r = {p, s.len, s.len + 1};
+    return r;
+}
+
+static inline rfx_String rfx_string_concat(rfx_String a, rfx_String b) {
+    char *p = malloc(a.len + b.len + 1);
+    if (p == NULL)
+        rfx_panic("out of memory");
+    memcpy(p, a.ptr, a.len);
+    memcpy(p + a.len, b.ptr, b.len);
+    p[a.len + b.len] = '\0';
+    rfx_String r = {p, a.len + b.len, a.len + b.len + 1};
+    return r;
+}
+
+static inline bool rfx_string_eq(rfx_String a, rfx_String b) {
+    return a.len == b.len && memcmp(a.ptr, b.ptr, a.len) == 0;
+}
+
+static inline void rfx_print_String(rfx_String s) {
+    fwrite(s.ptr, 1, s.len, stdout);
+    putchar('\n');
+}
+
+static inline void rfx_print_i64(int64_t x) {
+    printf("%lld\n", (long long)x);
+}
+
+static inline void rfx_print_u64(uint64_t x) {
+    printf("%llu\n", (unsigned long long)x);
+}
+
+static inline void rfx_print_bool(bool x) {
+    puts(x ? "true" : "false");
+}
+
+// c as utf-8 into buf, which ends with a NUL
+static inline void rfx_utf8(uint32_t c, char buf[5]) {
+    memset(buf, 0, 5);
+    if (c < 0x80) {
+        buf[0] = (char)c;
+    } else if (c < 0x800) {
+        buf[0] = (char)(0xC0 | (c >> 6));
+        buf[1] = (char)(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        buf[0] = (char)(0xE0 | (c >> 12));
+        buf[1] = (char)(0x80 | ((c >> 6) & 0x3F));
+        buf[2] = (char)(0x80 | (c & 0x3F));
+    } else {
+        buf[0] = (char)(0xF0 | (c >> 18));
+        buf[1] = (char)(0x80 | ((c >> 12) & 0x3F));
+        buf[2] = (char)(0x80 | ((c >> 6) & 0x3F));
+        buf[3] = (char)(0x80 | (c & 0x3F));
+    }
+}
+
+static inline void rfx_print_char(uint32_t c) {
+    char buf[5];
+    rfx_utf8(c, buf);
+    puts(buf);
+}
+
+// a failed assert_eq panics with its message and the values as print shows
+// them, the same as rfx_panic
+static inline void rfx_assert_eq_i64(int64_t l, int64_t r, const char *msg) {
+    if (l == r)
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %lld\n right: %lld\n", msg, (long long)l,
+            (long long)r);
+    exit(101);
+}
+
+static inline void rfx_assert_eq_u64(uint64_t l, uint64_t r, const char *msg) {
+    if (l == r)
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %llu\n right: %llu\n", msg, (unsigned long long)l,
+            (unsigned long long)r);
+    exit(101);
+}
+
+static inline void rfx_assert_eq_bool(bool l, bool r, const char *msg) {
+    if (l == r)
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %s\n right: %s\n", msg, l ? "true" : "false",
+            r ? "true" : "false");
+    exit(101);
+}
+
+static inline void rfx_assert_eq_char(uint32_t l, uint32_t r, const char *msg) {
+    if (l == r)
+        return;
+    char a[5], b[5];
+    rfx_utf8(l, a);
+    rfx_utf8(r, b);
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: %s\n right: %s\n", msg, a, b);
+    exit(101);
+}
+
+static inline void rfx_assert_eq_String(rfx_String l, rfx_String r, const char *msg) {
+    if (rfx_string_eq(l, r))
+        return;
+    fflush(stdout);
+    fprintf(stderr, "rfx panic: %s\n  left: ", msg);
+    fwrite(l.ptr, 1, l.len, stderr);
+    fprintf(stderr, "\n right: ");
+    fwrite(r.ptr, 1, r.len, stderr);
+    fprintf(stderr, "\n");
+    exit(101);
+}
+typedef struct rfx_Point rfx_Point;
+
+struct rfx_Point {
+    int64_t f_x;
+    int64_t f_y;
+};
+
+int64_t rfx_Point_sum(rfx_Point v_p);
+
+static int64_t v_total;
+static int64_t v_i;
+static rfx_Point v_p;
+
+int64_t rfx_Point_sum(rfx_Point v_p) {
+    return ((int64_t)((uint64_t)((v_p).f_x) + (uint64_t)((v_p).f_y)));
+}
+
+int main(void) {
+    v_total = ((int64_t)0LL);
+    v_i = ((int64_t)0LL);
+    while ((v_i < ((int64_t)3LL))) {
+        v_total = ((int64_t)((uint64_t)(v_total) + (uint64_t)(v_i)));
+        v_i = ((int64_t)((uint64_t)(v_i) + (uint64_t)(((int64_t)1LL))));
+    }
+    v_p = ((rfx_Point){.f_x = v_total, .f_y = ((int64_t)2LL)});
+    rfx_print_i64((int64_t)rfx_Point_sum(v_p));
+    return 0;
+}
@@ -0,0 +1,28 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+what the golden tests in src/compile/golden.rs and the end-to-end tests in
+tests/e2e.rs share: where the sources under test are
+*/
+
+//the directory of Cargo.toml, tests do not depend on the working directory
+pub fn root() -> PathBuf {
+    return PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+}
+
+//the .lang files under dir, sorted
+pub fn sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Err(_) => return,
+        Ok(x) => x.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+    };
+    entries.sort();
+    for p in entries {
+        if p.is_dir() {
+            sources(&p, out);
+        } else if p.extension().is_some_and(|x| x == "lang") {
+            out.push(p);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+#![allow(clippy::needless_return)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+use common::{root, sources};
+
+/*
+end-to-end tests of the rfx binary. every .lang file under tests/ and in
+language/ is compiled to C, and must compile unless a trailing comment says
+what error it fails with:
+
+    var x: i64 = true; // expect-error: mismatched types
+
+a file that says what it does when run is then run with `rfx run`. its
+stdout must be the lines of its `// expect: LINE` comments, in order, and its
+exit code that of `// expect-exit: N`, 0 if there is none:
+
+    print(fib(10)); // expect: 55
+
+the comments count only after the code of a line, not in a string. without
+the C compiler the files are compiled but not run
+*/
+
+const DEFAULT_CC: &str = "cc";
+
+//what a file says of itself
+#[derive(Default)]
+struct Expect {
+    errors: Vec<String>,
+    stdout: String,
+    code: Option<i32>,
+    run: bool,
+}
+
+//the text of the `//` comment ending a line, None if it has none. a `//`
+//inside a string or char literal starts no comment
+fn comment(line: &str) -> Option<&str> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '/' && prev == '/' => return Some(&line[i + 1..]),
+            None => {}
+        }
+        prev = c;
+    }
+    return None;
+}
+
+fn expectations(src: &str) -> Expect {
+    let mut e = Expect::default();
+    for line in src.lines() {
+        let c = match comment(line) {
+            None => continue,
+            Some(x) => x.trim_start(),
+        };
+        if let Some(x) = c.strip_prefix("expect:") {
+            e.stdout += x.strip_prefix(' ').unwrap_or(x);
+            e.stdout.push('\n');
+            e.run = true;
+        } else if let Some(x) = c.strip_prefix("expect-exit:") {
+            e.code = Some(x.trim().parse::<i32>().unwrap());
+            e.run = true;
+        } else if let Some(x) = c.strip_prefix("expect-error:") {
+            e.errors.push(x.trim().to_string());
+        }
+    }
+    return e;
+}
+
+//the home of the binary under test, new for each run of the tests, so
+//neither the settings and cache of the user nor what an earlier build of rfx
+//left in its cache are used
+fn home() -> PathBuf {
+    let dir = format!("e2e_home_{}", std::process::id());
+    return Path::new(env!("CARGO_TARGET_TMPDIR")).join(dir);
+}
+
+//the binary under test, run in the root
+fn rfx(args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_rfx_new"))
+        .args(args)
+        .current_dir(root())
+        .env("HOME", home())
+        .env_remove("XDG_CONFIG_HOME")
+        .output()
+        .unwrap();
+}
+
+//why the file named name does not do what it says, None if it does
+fn check(name: &str, e: &Expect, cfile: &Path, has_cc: bool) -> Option<String> {
+    let built = rfx(&["-q", "-c", "-o", &cfile.to_string_lossy(), name]);
+    let stderr = String::from_utf8_lossy(&built.stderr);
+    if !e.errors.is_empty() {
+        let missing: Vec<&String> = e
+            .errors
+            .iter()
+            .filter(|x| !stderr.contains(x.as_str()))
+            .collect();
+        return match (built.status.success(), missing.is_empty()) {
+            (true, _) => Some("compiles, but errors are expected".to_string()),
+            (false, false) => Some(format!("want the errors {:?}, got\n{}", missing, stderr)),
+            (false, true) => None,
+        };
+    }
+    if !built.status.success() {
+        return Some(format!("does not compile\n{}", stderr));
+    }
+    if !e.run || !has_cc {
+        return None;
+    }
+
+    let ran = rfx(&["-q", "run", name]);
+    let stdout = String::from_utf8_lossy(&ran.stdout);
+    let code = ran.status.code().unwrap_or(-1);
+    let want_code = e.code.unwrap_or(0);
+    if stdout != e.stdout || code != want_code {
+        return Some(format!(
+            "want exit {} and stdout\n{}got exit {} and stdout\n{}{}",
+            want_code,
+            e.stdout,
+            code,
+            stdout,
+            String::from_utf8_lossy(&ran.stderr)
+        ));
+    }
+    return None;
+}
+
+#[test]
+fn test_e2e() {
+    let has_cc = Command::new(DEFAULT_CC).arg("--version").output().is_ok();
+    if !has_cc {
+        eprintln!("no `{}`, the programs are compiled but not run", DEFAULT_CC);
+    }
+    let mut files = Vec::new();
+    sources(&root().join("tests"), &mut files);
+    sources(&root().join("language"), &mut files);
+    assert!(!files.is_empty());
+
+    let out = home().join("c");
+    fs::create_dir_all(&out).unwrap();
+    let mut failures = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let name = path.strip_prefix(root()).unwrap().to_string_lossy();
+        let e = expectations(&fs::read_to_string(path).unwrap());
+        let cfile = out.join(format!("{}.c", i));
+        if let Some(why) = check(&name, &e, &cfile, has_cc) {
+            failures.push(format!("{}: {}", name, why));
+        }
+    }
+    fs::remove_dir_all(home()).unwrap();
+    assert!(
+        failures.is_empty(),
+        "{} of {} programs failed:\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_expectations() {
+    let e = expectations(
+        "print(\"// expect: no\"); // expect: yes\nprint('/'); // expect-exit: 3\n// expect-error: bad",
+    );
+    assert_eq!(e.stdout, "yes\n");
+    assert_eq!((e.code, e.run), (Some(3), true));
+    assert_eq!(e.errors, ["bad"]);
+    assert!(!expectations("var s = \"a // expect: b\";").run);
+}